@@ -1,122 +1,189 @@
 use plotters::prelude::*;
-use std::collections::BTreeMap;
 
-use crate::rand::boxmuller::BoxMullerGaussian701;
-use crate::rand::clt_gaussian::CentralLimitTheoremGaussian701;
-use crate::rand::exponential::Exponential701;
-use crate::rand::inverse_transform::InverseTransform701;
-use crate::rand::uniform::Uniform701;
+use crate::output::Output;
+use cos_701::rand::boxmuller::BoxMullerGaussian701;
+use cos_701::rand::clt_gaussian::CentralLimitTheoremGaussian701;
+use cos_701::rand::exponential::Exponential701;
+use cos_701::rand::inverse_transform::InverseTransform701;
+use cos_701::rand::polar::MarsagliaPolarGaussian701;
+use cos_701::rand::sampler::{log_moments, Sampler701};
+use cos_701::rand::uniform::Uniform701;
+use cos_701::stats::goodness_of_fit::GoodnessOfFit;
+use cos_701::stats::histogram::Histogram;
 use std::error::Error;
-use std::f64::consts::PI;
 use std::ops::Range;
 
-const NUM_POINTS: i32 = 1_000_000;
+const NUM_POINTS: usize = 1_000_000;
+const N_BINS: usize = 1_000;
+
+/// Histograms of the random number generators, compared against their analytic distributions
+#[derive(clap::Parser)]
+pub struct DistributionsArgs {
+    /// The number of samples drawn from each generator
+    #[clap(long, default_value_t = NUM_POINTS)]
+    pub n_points: usize,
+    /// The number of bins in each histogram
+    #[clap(long, default_value_t = N_BINS)]
+    pub n_bins: usize,
+}
 
 /// Entry-point for assignment 1
-pub fn do_assignment_1() -> Result<(), Box<dyn Error>> {
+pub fn do_assignment_1(output: &Output, args: &DistributionsArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing assignment 1");
 
-    generate_uniform_histogram(Uniform701::new())?;
-    part_1a(&mut Exponential701::new(Uniform701::new(), 1.0, 1.0))?;
-    part_1b(&mut BoxMullerGaussian701::new(Uniform701::new()))?;
-    part_1c(&mut CentralLimitTheoremGaussian701::new(
-        Uniform701::new(),
-        8,
-    ))?;
-    part_1d(&mut InverseTransform701::new(Uniform701::new(), 1.0))?;
+    let mut uni = Uniform701::seeded(output.seed());
+
+    generate_uniform_histogram(output, args, uni.fork())?;
+    part_1a(output, args, &mut Exponential701::new(uni.fork(), 1.0, 1.0))?;
+    part_1b(
+        output,
+        args,
+        &mut BoxMullerGaussian701::new(uni.fork(), 0.5, 0.1),
+        "box_muller.png",
+        "The Box-Müller Method",
+    )?;
+    part_1b(
+        output,
+        args,
+        &mut MarsagliaPolarGaussian701::new(uni.fork(), 0.5, 0.1),
+        "marsaglia_polar.png",
+        "The Marsaglia Polar Method",
+    )?;
+    part_1c(
+        output,
+        args,
+        &mut CentralLimitTheoremGaussian701::new(uni.fork(), 8),
+    )?;
+    part_1d(output, args, &mut InverseTransform701::new(uni.fork(), 1.0))?;
+
+    test_goodness_of_fit(output, args, &mut uni)?;
 
     Ok(())
 }
 
-/// Sample from the supplied uniform random number generator, bin the results, and plot the bins
-fn generate_uniform_histogram(mut uni: Uniform701) -> Result<(), Box<dyn Error>> {
-    log::info!("Generating a histogram using the uniform distribution");
+/// Test every generator against its analytic distribution and tabulate the p-values
+fn test_goodness_of_fit(
+    output: &Output,
+    args: &DistributionsArgs,
+    uni: &mut Uniform701,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Testing the goodness of fit of each generator");
+
+    let results = vec![
+        GoodnessOfFit::test("Uniform", &mut uni.fork(), args.n_points, 0.0..1.0, 100),
+        GoodnessOfFit::test(
+            "Exponential, a = 1, b = 1",
+            &mut Exponential701::new(uni.fork(), 1.0, 1.0),
+            args.n_points,
+            0.0..5.0,
+            100,
+        ),
+        GoodnessOfFit::test(
+            "Box-Müller, μ = 0.5, σ = 0.1",
+            &mut BoxMullerGaussian701::new(uni.fork(), 0.5, 0.1),
+            args.n_points,
+            0.0..1.0,
+            100,
+        ),
+        GoodnessOfFit::test(
+            "Marsaglia polar, μ = 0.5, σ = 0.1",
+            &mut MarsagliaPolarGaussian701::new(uni.fork(), 0.5, 0.1),
+            args.n_points,
+            0.0..1.0,
+            100,
+        ),
+        GoodnessOfFit::test(
+            "Central limit theorem, n = 8",
+            &mut CentralLimitTheoremGaussian701::new(uni.fork(), 8),
+            args.n_points,
+            0.0..1.0,
+            100,
+        ),
+        GoodnessOfFit::test(
+            "Inverse transform (Rayleigh), σ = 1",
+            &mut InverseTransform701::new(uni.fork(), 1.0),
+            args.n_points,
+            0.0..5.0,
+            100,
+        ),
+    ]
+    .into_iter()
+    .collect::<Result<Vec<GoodnessOfFit>, Box<dyn Error>>>()?;
+
+    results
+        .iter()
+        .filter(|result| result.rejects(0.01))
+        .for_each(|result| log::warn!("Rejected at the 1% level: {}", result));
+
+    GoodnessOfFit::print_csv(&results, &output.path("goodness_of_fit.csv"))?;
+    output.write_seed("goodness_of_fit.csv")?;
 
-    let mut bins: BTreeMap<String, i32> = BTreeMap::new();
+    Ok(())
+}
 
-    (0..NUM_POINTS)
-        .map(|_| uni.next())
-        .map(|v| format!("{:0.3}", v))
-        .for_each(|k| {
-            bins.entry(k).and_modify(|v| *v += 1).or_insert(1);
-        });
+/// Sample from the supplied uniform random number generator, bin the results, and plot the bins
+fn generate_uniform_histogram(
+    output: &Output,
+    args: &DistributionsArgs,
+    mut uni: Uniform701,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Generating a histogram using the uniform distribution");
 
-    let max_y = bins.values().max().unwrap().to_owned() as f64;
+    let histogram = bin_samples(&mut uni, 0.0..1.0, args)?;
 
     plot_histogram(
-        "output/assignment1/uniform.png",
+        output,
+        "uniform.png",
         "The Uniform Distribution, system supplied",
-        0.0..1.0,
-        0.001,
-        max_y,
-        bins,
-        Some((Box::new(|&x| (x, 1000.0)), "y = μ")),
+        &histogram,
+        Some((Box::new(|&x| (x, uni.pdf(x).unwrap())), "y = μ")),
     )?;
 
     Ok(())
 }
 
 /// Sample from the supplied exponential random number generator, bin the results, and plot the bins
-fn part_1a(exp: &mut Exponential701) -> Result<(), Box<dyn Error>> {
+fn part_1a(
+    output: &Output,
+    args: &DistributionsArgs,
+    exp: &mut Exponential701,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Generating a histogram using the exponential distribution");
 
-    let mut bins: BTreeMap<String, i32> = BTreeMap::new();
-    (0..1_000_000)
-        .map(|_| exp.next())
-        .map(|v| format!("{:0.3}", v))
-        .for_each(|k| {
-            bins.entry(k).and_modify(|v| *v += 1).or_insert(1);
-        });
-
-    let max_y = bins.values().max().unwrap().to_owned() as f64;
+    let histogram = bin_samples(exp, 0.0..1.0, args)?;
 
     plot_histogram(
-        "output/assignment1/exponential.png",
+        output,
+        "exponential.png",
         &format!("Exponential distribution: a = {}, b = {}", exp.a(), exp.b()),
-        0.0..1.0,
-        0.001,
-        max_y,
-        bins,
-        Some((
-            Box::new(|&x| (x, exp.a() * (-exp.b() * x).exp() * 1_000.0)),
-            "ae^(-bx)",
-        )),
+        &histogram,
+        Some((Box::new(|&x| (x, exp.pdf(x).unwrap())), "ae^(-bx)")),
     )?;
 
     Ok(())
 }
 
-/// Sample from the supplied box muller random number generator, bin the results, and plot the bins
-fn part_1b(bm: &mut BoxMullerGaussian701) -> Result<(), Box<dyn Error>> {
-    log::info!("Generating a histogram using the Box-Müller method");
-
-    let mut bins: BTreeMap<String, i32> = BTreeMap::new();
-
-    (0..NUM_POINTS)
-        .map(|_| bm.next())
-        .map(|v| format!("{:0.3}", v))
-        .for_each(|k| {
-            bins.entry(k).and_modify(|v| *v += 1).or_insert(1);
-        });
+/// Sample from the supplied gaussian random number generator, bin the results, and plot the bins
+/// against the analytic density
+fn part_1b<G: Sampler701>(
+    output: &Output,
+    args: &DistributionsArgs,
+    gaussian: &mut G,
+    file: &str,
+    caption: &str,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Generating a histogram using '{}'", caption);
 
-    let max_y = bins.values().max().unwrap().to_owned() as f64;
+    let histogram = bin_samples(gaussian, 0.0..1.0, args)?;
 
     plot_histogram(
-        "output/assignment1/box_muller.png",
-        "The Box-Müller Method",
-        0.0..1.0,
-        0.001,
-        max_y,
-        bins,
+        output,
+        file,
+        caption,
+        &histogram,
         Some((
-            Box::new(|&x| {
-                let sigma: f64 = 10.0;
-                let a = 1.0 / (2.0 * PI * sigma.powf(2.0)).sqrt();
-                let b = -(x - 0.5).powf(2.0) / 2.0 * sigma.powf(2.0);
-                let y = a * b.exp() * 100_000.0;
-                (x, y)
-            }),
-            "y = (1 / √(2πσ^2)) * e^( x^2 / 2σ^2 )",
+            Box::new(|&x| (x, gaussian.pdf(x).unwrap())),
+            "y = (1 / √(2πσ^2)) * e^( -(x-μ)^2 / 2σ^2 )",
         )),
     )?;
 
@@ -125,36 +192,23 @@ fn part_1b(bm: &mut BoxMullerGaussian701) -> Result<(), Box<dyn Error>> {
 
 /// Sample from the supplied central limit theorem gaussian random number generator,
 /// bin the results, and plot the bins
-fn part_1c(clt: &mut CentralLimitTheoremGaussian701) -> Result<(), Box<dyn Error>> {
+fn part_1c(
+    output: &Output,
+    args: &DistributionsArgs,
+    clt: &mut CentralLimitTheoremGaussian701,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Generating a histogram using the central limit theorem method");
 
-    let mut bins: BTreeMap<String, i32> = BTreeMap::new();
-
-    (0..NUM_POINTS)
-        .map(|_| clt.next())
-        .map(|v| format!("{:0.3}", v))
-        .for_each(|k| {
-            bins.entry(k).and_modify(|v| *v += 1).or_insert(1);
-        });
-
-    let max_y = bins.values().max().unwrap().to_owned() as f64;
+    let histogram = bin_samples(clt, 0.0..1.0, args)?;
 
     plot_histogram(
-        "output/assignment1/clt_muller.png",
+        output,
+        "clt_muller.png",
         "The Central Limit Theorem Method",
-        0.0..1.0,
-        0.001,
-        max_y,
-        bins,
+        &histogram,
         Some((
-            Box::new(|&x| {
-                let sigma: f64 = 10.25;
-                let a = 1.0 / (2.0 * PI * sigma.powf(2.0)).sqrt();
-                let b = -(x - 0.5).powf(2.0) / 2.0 * sigma.powf(2.0);
-                let y = a * b.exp() * 100_000.0;
-                (x, y)
-            }),
-            "y = 1 / √(2πσ^2) * e^( (x-0.5)^2 / 2σ^2 )",
+            Box::new(|&x| (x, clt.pdf(x).unwrap())),
+            "Bates distribution, n = 8",
         )),
     )?;
 
@@ -163,34 +217,22 @@ fn part_1c(clt: &mut CentralLimitTheoremGaussian701) -> Result<(), Box<dyn Error
 
 /// Sample from the supplied inverse transform random number generator,
 /// bin the results, and plot the bins
-fn part_1d(inv: &mut InverseTransform701) -> Result<(), Box<dyn Error>> {
+fn part_1d(
+    output: &Output,
+    args: &DistributionsArgs,
+    inv: &mut InverseTransform701,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Generating a histogram using the inverse transform method");
 
-    let mut bins: BTreeMap<String, i32> = BTreeMap::new();
-
-    (0..NUM_POINTS)
-        .map(|_| inv.next())
-        .map(|v| format!("{:0.3}", v))
-        .for_each(|k| {
-            bins.entry(k).and_modify(|v| *v += 1).or_insert(1);
-        });
-
-    let max_y = bins.values().max().unwrap().to_owned() as f64;
+    let histogram = bin_samples(inv, 0.0..5.0, args)?;
 
     plot_histogram(
-        "output/assignment1/inv_trans.png",
+        output,
+        "inv_trans.png",
         &format!("The Inverse Transform Method, σ = {}", inv.sigma()),
-        0.0..5.0,
-        0.005,
-        max_y,
-        bins,
+        &histogram,
         Some((
-            Box::new(|&x| {
-                let y = (x / inv.sigma().powf(2.0))
-                    * (-1.0 * x.powf(2.0) / (2.0 * inv.sigma().powf(2.0))).exp()
-                    * 1_050.0;
-                (x, y)
-            }),
+            Box::new(|&x| (x, inv.pdf(x).unwrap())),
             "y = (x/σ^2) * e^( -(x^2)/(2σ^2) )",
         )),
     )?;
@@ -198,22 +240,48 @@ fn part_1d(inv: &mut InverseTransform701) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Sample `args.n_points` values from the supplied generator into `args.n_bins` equal bins
+/// over `range`.  The sample moments are logged for comparison with the analytic ones.
+fn bin_samples<S: Sampler701 + ?Sized>(
+    sampler: &mut S,
+    range: Range<f64>,
+    args: &DistributionsArgs,
+) -> Result<Histogram, Box<dyn Error>> {
+    // The moments are logged for the very samples that are binned
+    let samples = sampler.samples().take(args.n_points).collect::<Vec<f64>>();
+    let mut histogram = Histogram::uniform(range, args.n_bins)?;
+    histogram.fill_all(samples.iter().copied());
+
+    log::info!(
+        "{} samples fell below and {} above the histogram",
+        histogram.underflow(),
+        histogram.overflow()
+    );
+    log_moments(std::any::type_name::<S>(), sampler, &samples);
+
+    Ok(histogram)
+}
+
 type OptionalCurve<'a, 'b> = Option<(Box<dyn Fn(&f64) -> (f64, f64) + 'a>, &'b str)>;
 
-/// Plot a histogram for the supplied bins where the key represents the x-axis and the value
-/// represents the y-axis.  Optionally take a function to plot a curve.
+/// Plot the density of the supplied histogram, scaled so that the tallest bin has height 1.
+/// Optionally take a function to plot a curve, e.g., the analytic density, on the same scale.
 fn plot_histogram(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
-    x_range: Range<f64>,
-    x_step: f64,
-    max_y: f64,
-    bins: BTreeMap<String, i32>,
+    histogram: &Histogram,
     optional_curve: OptionalCurve,
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting '{}'.", caption);
 
-    let root = BitMapBackend::new(path, (1440, 900)).into_drawing_area();
+    let edges = histogram.edges();
+    let x_range = edges[0]..edges[edges.len() - 1];
+    let density = histogram.density();
+    let max_y = density.iter().cloned().fold(f64::MIN_POSITIVE, f64::max);
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -221,26 +289,21 @@ fn plot_histogram(
         .margin(32)
         .x_label_area_size(32)
         .y_label_area_size(32)
-        .build_cartesian_2d(x_range.to_owned().step(x_step).into_segmented(), 0.0..1.0)?
-        .set_secondary_coord(x_range.to_owned(), 0.0..1.0);
+        .build_cartesian_2d(x_range.to_owned(), 0.0..1.0)?;
     chart.configure_mesh().disable_mesh().draw()?;
 
-    chart.draw_series(bins.iter().map(|(k, v)| {
-        let x = k.parse::<f64>().unwrap();
-        let y = *v as f64 / max_y;
-        let x0 = SegmentValue::CenterOf(x);
-        let x1 = SegmentValue::CenterOf(x + x_step);
-        Rectangle::new([(x0, 0.0), (x1, y)], RED.filled())
+    chart.draw_series(histogram.bins().zip(&density).map(|((bin, _), d)| {
+        Rectangle::new([(bin.start, 0.0), (bin.end, d / max_y)], RED.filled())
     }))?;
 
     if let Some((curve_fn, curve_label)) = optional_curve {
         chart
-            .draw_secondary_series(LineSeries::new(
+            .draw_series(LineSeries::new(
                 x_range
-                    .step(x_step)
-                    .key_points(1_000_000)
-                    .iter()
-                    .map(curve_fn)
+                    .to_owned()
+                    .step((x_range.end - x_range.start) / 10_000.0)
+                    .values()
+                    .map(|x| curve_fn(&x))
                     .map(|(x, y)| (x, y / max_y)),
                 BLUE.stroke_width(4),
             ))?
@@ -253,6 +316,8 @@ fn plot_histogram(
             .draw()?;
     }
 
+    output.write_seed(file)?;
+
     log::info!("Done drawing '{}'", caption);
     Ok(())
 }
@@ -1,84 +1,119 @@
 use plotters::prelude::*;
 
-use crate::data::point::{Point2d, Point3d};
-use crate::data::points_in_grid::{
+use crate::output::Output;
+use cos_701::data::point::{Point2d, Point3d};
+use cos_701::data::points_in_grid::{
     gen_points_in_box, gen_points_in_cube, gen_spaced_points_in_box,
 };
-use crate::rand::uniform::Uniform701;
+use cos_701::rand::uniform::Uniform701;
 use std::error::Error;
 use std::ops::Range;
 
 const L: f64 = 20.0;
 
+/// Uniformly distributed points in a box and a cube, with and without a minimum spacing
+#[derive(clap::Parser)]
+pub struct UniformArgs {
+    /// The number of points to generate
+    #[clap(long, default_value_t = 500)]
+    pub n: usize,
+    /// The side length of the box and the cube
+    #[clap(long, default_value_t = L)]
+    pub box_len: f64,
+    /// The minimum distance between points in the box
+    #[clap(long, default_value_t = 0.7)]
+    pub r_min: f64,
+    /// The minimum distance between points in the cube
+    #[clap(long, default_value_t = 2.0)]
+    pub r_min_3d: f64,
+}
+
 /// Entry-point for assignment 2
-pub fn do_assignment_2() -> Result<(), Box<dyn Error>> {
+pub fn do_assignment_2(output: &Output, args: &UniformArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing assignment 2");
 
-    let mut uni = Uniform701::new();
+    let mut uni = Uniform701::seeded(output.seed());
 
-    part_2a(&mut uni, 500)?;
-    part_2b(&mut uni, 500, 0.7)?;
-    part_2c(&mut uni, 500, 2.0)?;
+    part_2a(output, &mut uni, args.box_len, args.n)?;
+    part_2b(output, &mut uni, args.box_len, args.n, args.r_min)?;
+    part_2c(output, &mut uni, args.box_len, args.n, args.r_min_3d)?;
 
     Ok(())
 }
 
 /// Generate a set of random numbers in a two-dimensional box of length L. Assume L = 20
 /// and the number of points, n = 500.
-fn part_2a(uni: &mut Uniform701, n: usize) -> Result<(), Box<dyn Error>> {
+fn part_2a(output: &Output, uni: &mut Uniform701, l: f64, n: usize) -> Result<(), Box<dyn Error>> {
     log::info!("Doing part a");
 
     scatter_2d(
-        "output/assignment2/part_2a.png",
-        "Assignment 2a, L = 20, n = 500",
-        0.0..L,
-        0.0..L,
-        &gen_points_in_box(uni, L, n),
+        output,
+        "part_2a.png",
+        &format!("Assignment 2a, L = {}, n = {}", l, n),
+        0.0..l,
+        0.0..l,
+        &gen_points_in_box(uni, l, n),
     )?;
     Ok(())
 }
 
 /// Modify the code in (2a) such that the minimum distance between any two random points is
 /// greater or equal to rmin. Choose n and rmin as 500 and 1.0, respectively, in your program.
-fn part_2b(uni: &mut Uniform701, n: usize, r_min: f64) -> Result<(), Box<dyn Error>> {
+fn part_2b(
+    output: &Output,
+    uni: &mut Uniform701,
+    l: f64,
+    n: usize,
+    r_min: f64,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Doing part b");
 
     scatter_2d(
-        "output/assignment2/part_2b.png",
-        &format!("Assignment 2b, L = 20, n = {}, r_min = {}", n, r_min),
-        0.0..L,
-        0.0..L,
-        &gen_spaced_points_in_box(uni, L, n, r_min),
+        output,
+        "part_2b.png",
+        &format!("Assignment 2b, L = {}, n = {}, r_min = {}", l, n, r_min),
+        0.0..l,
+        0.0..l,
+        &gen_spaced_points_in_box(uni, l, n, r_min)?,
     )?;
     Ok(())
 }
 
 /// Repeat the computation in (2b) in three dimensions by changing rmin from 1 to 2.
 /// Show your results graphically.
-fn part_2c(uni: &mut Uniform701, n: usize, r_min: f64) -> Result<(), Box<dyn Error>> {
+fn part_2c(
+    output: &Output,
+    uni: &mut Uniform701,
+    l: f64,
+    n: usize,
+    r_min: f64,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Doing part c");
 
     // scatter_4d(
     animated_3d(
-        "output/assignment2/part_2c.gif",
-        &format!("Assignment 2c, L = 20, n = {}, r_min = {}", n, r_min),
-        0.0..L,
-        0.0..L,
-        0.0..L,
-        &gen_points_in_cube(uni, L, n, r_min),
+        output,
+        "part_2c.gif",
+        &format!("Assignment 2c, L = {}, n = {}, r_min = {}", l, n, r_min),
+        0.0..l,
+        0.0..l,
+        0.0..l,
+        &gen_points_in_cube(uni, l, n, r_min)?,
     )?;
     Ok(())
 }
 
 /// Draw a scatter plot for the supplied 2-dimensional points
 fn scatter_2d(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     x_range: Range<f64>,
     y_range: Range<f64>,
     points: &[Point2d],
 ) -> Result<(), Box<dyn Error>> {
-    let root = BitMapBackend::new(path, (1440, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -95,19 +130,23 @@ fn scatter_2d(
             .map(|&coord| Circle::new(coord.into(), 2, RED.filled())),
     )?;
 
+    output.write_seed(file)?;
     Ok(())
 }
 
 /// Draw a scatter plot for the supplied 3-dimensional points
+#[allow(dead_code)]
 fn scatter_3d(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     x_range: Range<f64>,
     y_range: Range<f64>,
     z_range: Range<f64>,
     points: &[Point3d],
 ) -> Result<(), Box<dyn Error>> {
-    let root = BitMapBackend::new(path, (1440, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -124,6 +163,7 @@ fn scatter_3d(
             .map(|&coord| Circle::new(coord.into(), 2, RED.filled())),
     )?;
 
+    output.write_seed(file)?;
     Ok(())
 }
 
@@ -131,14 +171,17 @@ fn scatter_3d(
 /// The animation adjusts the matrix perspective's yaw, generating a gif instead of a png.
 /// This function is slow.
 fn animated_3d(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     x_range: Range<f64>,
     y_range: Range<f64>,
     z_range: Range<f64>,
     points: &[Point3d],
 ) -> Result<(), Box<dyn Error>> {
-    let root = BitMapBackend::gif(path, (1440, 900), 1_000)?.into_drawing_area();
+    let path = output.path(file);
+    let root =
+        BitMapBackend::gif(&path, output.image_size((1440, 900)), 1_000)?.into_drawing_area();
 
     for i in 0..10 {
         let frame = format!("Frame {}", i);
@@ -170,5 +213,6 @@ fn animated_3d(
         root.present()?;
     }
 
+    output.write_seed(file)?;
     Ok(())
 }
@@ -1,30 +1,44 @@
 use plotters::prelude::*;
 
-use crate::data::point::{Point2d, Point3d};
-use crate::data::random_vec::RandomVec;
-use crate::rand::boxmuller::BoxMullerGaussian701;
-use crate::rand::uniform::Uniform701;
-use crate::util;
+use crate::output::Output;
+use cos_701::data::point::{Point2d, Point3d};
+use cos_701::data::random_vec::RandomVec;
+use cos_701::rand::boxmuller::BoxMullerGaussian701;
+use cos_701::rand::uniform::Uniform701;
+use cos_701::util;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::error::Error;
 use std::f64::consts::PI;
 use std::ops::Range;
 
-pub fn do_assignment_3() -> Result<(), Box<dyn Error>> {
+/// Points in a hypersphere, by accept-reject and by normalizing gaussian vectors
+#[derive(clap::Parser)]
+pub struct SphereArgs {
+    /// The number of points drawn for the 2- and 3-dimensional plots
+    #[clap(long, default_value_t = 10_000)]
+    pub n: usize,
+    /// The largest dimension for which accept rates are computed
+    #[clap(long, default_value_t = 10)]
+    pub max_dim: usize,
+}
+
+/// Entry-point for assignment 3
+pub fn do_assignment_3(output: &Output, args: &SphereArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing assignment 3");
 
-    let mut uni = Uniform701::new();
-    let mut gau = BoxMullerGaussian701::new(Uniform701::new());
+    let mut uni = Uniform701::seeded(output.seed());
+    let mut gau = BoxMullerGaussian701::standard(uni.fork());
 
-    draw_2d_and_3d_naive(&mut uni, 10_000)?;
-    draw_2d_and_3d_efficient(&mut uni, 10_000)?;
+    draw_2d_and_3d_naive(output, &mut uni, args.n)?;
+    draw_2d_and_3d_efficient(output, &mut uni, args.n)?;
 
     let mut accept_rates: Vec<(BTreeMap<usize, f64>, String, RGBColor)> = Vec::new();
 
     // Compute accept rates for the accept-reject method
-    for (num_iter, color) in vec![(100, CYAN), (1000, GREEN), (10_000, RED)] {
+    for (num_iter, color) in [(100, CYAN), (1000, GREEN), (10_000, RED)] {
         accept_rates.push((
-            (2..=10)
+            (2..=args.max_dim)
                 .map(|dim| (dim, part_3b_nd(&mut uni, dim, num_iter)))
                 .map(|(dim, num_accepted)| (dim, num_accepted as f64 / num_iter as f64))
                 .collect(),
@@ -35,7 +49,7 @@ pub fn do_assignment_3() -> Result<(), Box<dyn Error>> {
 
     // Compute accept rates for the efficient method; expecting 100% accept rate
     accept_rates.push((
-        (2..=10)
+        (2..=args.max_dim)
             .map(|dim| (dim, part_3c(&mut uni, &mut gau, dim, 10_000)))
             .map(|(dim, num_accepted)| (dim, num_accepted as f64 / 10_000.0))
             .collect(),
@@ -45,7 +59,7 @@ pub fn do_assignment_3() -> Result<(), Box<dyn Error>> {
 
     // Compute expected accept rates for the accept-reject method using the gamma function
     accept_rates.push((
-        (2..=10)
+        (2..=args.max_dim)
             .map(|d| {
                 (
                     d,
@@ -59,37 +73,38 @@ pub fn do_assignment_3() -> Result<(), Box<dyn Error>> {
     ));
 
     plot_accept_rates(
-        "output/assignment3/3b_3c_accept_rates.png",
-        "Accept rates for d = 2..10",
+        output,
+        "3b_3c_accept_rates.png",
+        &format!("Accept rates for d = 2..{}", args.max_dim),
         &accept_rates,
     )?;
 
     Ok(())
 }
 
-fn draw_2d_and_3d_naive(uni: &mut Uniform701, n_iter: usize) -> Result<(), Box<dyn Error>> {
+fn draw_2d_and_3d_naive(
+    output: &Output,
+    uni: &mut Uniform701,
+    n_iter: usize,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Doing part 3a for 2-dimensions, naively");
     scatter_2d(
-        "output/assignment3/3a_2D_naive.png",
+        output,
+        "3a_2D_naive.png",
         "Naive, sphere r = 1, dimension = 2",
         -1.0..1.0,
         -1.0..1.0,
         &(0..n_iter)
             .map(|_| RandomVec::naive_scaled(uni, 2, 2.0, -1.0))
             .filter(|v| v.is_in_sphere(1.0))
-            .map(|v| {
-                let points = v.get().to_owned();
-                Point2d {
-                    x: points.get(0).unwrap().to_owned(),
-                    y: points.get(1).unwrap().to_owned(),
-                }
-            })
-            .collect::<Vec<Point2d>>(),
+            .map(|v| Point2d::try_from(&v))
+            .collect::<Result<Vec<Point2d>, Box<dyn Error>>>()?,
     )?;
 
     log::info!("Doing part 3a for 3-dimensions, naively");
     animate_3d(
-        "output/assignment3/3a_3D_naive.gif",
+        output,
+        "3a_3D_naive.gif",
         "Naive sphere r = 1, dimension = 3",
         -1.0..1.0,
         -1.0..1.0,
@@ -97,60 +112,46 @@ fn draw_2d_and_3d_naive(uni: &mut Uniform701, n_iter: usize) -> Result<(), Box<d
         &(0..n_iter)
             .map(|_| RandomVec::naive_scaled(uni, 3, 2.0, -1.0))
             .filter(|v| v.is_in_sphere(1.0))
-            .map(|v| {
-                let points = v.get().to_owned();
-                Point3d {
-                    x: points.get(0).unwrap().to_owned(),
-                    y: points.get(1).unwrap().to_owned(),
-                    z: points.get(2).unwrap().to_owned(),
-                }
-            })
-            .collect::<Vec<Point3d>>(),
+            .map(|v| Point3d::try_from(&v))
+            .collect::<Result<Vec<Point3d>, Box<dyn Error>>>()?,
     )?;
 
     Ok(())
 }
 
-fn draw_2d_and_3d_efficient(uni: &mut Uniform701, n_iter: usize) -> Result<(), Box<dyn Error>> {
+fn draw_2d_and_3d_efficient(
+    output: &Output,
+    uni: &mut Uniform701,
+    n_iter: usize,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Doing part 3a for 2-dimensions, efficiently");
-    let mut gaussian = BoxMullerGaussian701::new(Uniform701::new());
+    let mut gaussian = BoxMullerGaussian701::standard(uni.fork());
     scatter_2d(
-        "output/assignment3/3a_2D_efficient.png",
+        output,
+        "3a_2D_efficient.png",
         "Efficient sphere r = 1, dimension = 2",
         -1.0..1.0,
         -1.0..1.0,
         &(0..n_iter)
-            .map(|_| RandomVec::efficient_scaled(uni, &mut gaussian, 2, 2.0, -1.0))
+            .map(|_| RandomVec::efficient(uni, &mut gaussian, 2))
             .filter(|v| v.is_in_sphere(1.0))
-            .map(|v| {
-                let points = v.get().to_owned();
-                Point2d {
-                    x: points.get(0).unwrap().to_owned(),
-                    y: points.get(1).unwrap().to_owned(),
-                }
-            })
-            .collect::<Vec<Point2d>>(),
+            .map(|v| Point2d::try_from(&v))
+            .collect::<Result<Vec<Point2d>, Box<dyn Error>>>()?,
     )?;
 
     log::info!("Doing part 3a for 3-dimensions, efficiently");
     animate_3d(
-        "output/assignment3/3a_3D_efficient.gif",
+        output,
+        "3a_3D_efficient.gif",
         "Efficient sphere r = 1, dimension = 3",
         -1.0..1.0,
         -1.0..1.0,
         -1.0..1.0,
         &(0..n_iter)
-            .map(|_| RandomVec::efficient_scaled(uni, &mut gaussian, 3, 2.0, -1.0))
+            .map(|_| RandomVec::efficient(uni, &mut gaussian, 3))
             .filter(|v| v.is_in_sphere(1.0))
-            .map(|v| {
-                let points = v.get().to_owned();
-                Point3d {
-                    x: points.get(0).unwrap().to_owned(),
-                    y: points.get(1).unwrap().to_owned(),
-                    z: points.get(2).unwrap().to_owned(),
-                }
-            })
-            .collect::<Vec<Point3d>>(),
+            .map(|v| Point3d::try_from(&v))
+            .collect::<Result<Vec<Point3d>, Box<dyn Error>>>()?,
     )?;
 
     Ok(())
@@ -174,20 +175,22 @@ fn part_3c(
     log::info!("Doing part 3c for {}-dimensions", dim);
 
     (2..n_iter)
-        .map(|_| RandomVec::efficient_scaled(uni, gaussian, dim, 2.0, -1.0))
+        .map(|_| RandomVec::efficient(uni, gaussian, dim))
         .filter(|v| v.is_in_sphere(1.0))
         .count()
 }
 
 /// Draw a scatter plot for the supplied 2-dimensional points
 fn scatter_2d(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     x_range: Range<f64>,
     y_range: Range<f64>,
     points: &[Point2d],
 ) -> Result<(), Box<dyn Error>> {
-    let root = BitMapBackend::new(path, (900, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((900, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -204,19 +207,23 @@ fn scatter_2d(
             .map(|&coord| Circle::new(coord.into(), 2, RED.filled())),
     )?;
 
+    output.write_seed(file)?;
     Ok(())
 }
 
 /// Draw a scatter plot for the supplied 3-dimensional points
+#[allow(dead_code)]
 fn scatter_3d(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     x_range: Range<f64>,
     y_range: Range<f64>,
     z_range: Range<f64>,
     points: &[Point3d],
 ) -> Result<(), Box<dyn Error>> {
-    let root = BitMapBackend::new(path, (900, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((900, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -233,6 +240,7 @@ fn scatter_3d(
             .map(|&coord| Circle::new(coord.into(), 2, RED.filled())),
     )?;
 
+    output.write_seed(file)?;
     Ok(())
 }
 
@@ -240,14 +248,17 @@ fn scatter_3d(
 /// The animation adjusts the matrix perspective's yaw, generating a gif instead of a png.
 /// This function is slow.
 fn animate_3d(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     x_range: Range<f64>,
     y_range: Range<f64>,
     z_range: Range<f64>,
     points: &[Point3d],
 ) -> Result<(), Box<dyn Error>> {
-    let root = BitMapBackend::gif(path, (1440, 900), 1_000)?.into_drawing_area();
+    let path = output.path(file);
+    let root =
+        BitMapBackend::gif(&path, output.image_size((1440, 900)), 1_000)?.into_drawing_area();
 
     for i in 0..10 {
         let frame = format!("Frame {}", i);
@@ -280,17 +291,20 @@ fn animate_3d(
         root.present()?;
     }
 
+    output.write_seed(file)?;
     Ok(())
 }
 
 fn plot_accept_rates(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     to_plot: &[(BTreeMap<usize, f64>, String, RGBColor)],
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting accept rates for parts 3a-3b.");
 
-    let root = BitMapBackend::new(path, (1440, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -307,9 +321,7 @@ fn plot_accept_rates(
     for (accept_rate, curve_label, color) in to_plot {
         chart
             .draw_series(LineSeries::new(
-                accept_rate
-                    .iter()
-                    .map(|entry| (*entry.0 as f64, (*entry.1 as f64))),
+                accept_rate.iter().map(|entry| (*entry.0 as f64, *entry.1)),
                 ShapeStyle::from(color),
             ))?
             .label(curve_label.to_owned())
@@ -324,5 +336,6 @@ fn plot_accept_rates(
         .background_style(&WHITE.mix(0.8))
         .draw()?;
 
+    output.write_seed(file)?;
     Ok(())
 }
@@ -1,58 +1,255 @@
-use crate::data::neighbors::{AdjacencyMatrix, NearestNeighborMap};
-use crate::data::point::Point3d;
-use crate::data::points_in_grid::gen_points_in_cube;
-use crate::rand::uniform::Uniform701;
+use crate::output::Output;
+use cos_701::data::neighbors::{AdjacencyMatrix, NearestNeighborMap};
+use cos_701::data::point::{Point3d, ORIGIN_3D};
+use cos_701::data::points_in_grid::gen_spaced_points_in;
+use cos_701::data::simulation_box::SimulationBox;
+use cos_701::data::voronoi_3d::VoronoiDiagram3d;
+use cos_701::rand::uniform::Uniform701;
+use cos_701::stats::rdf::RadialDistribution;
 use std::error::Error;
+use std::fs::File;
+use std::io::Write;
 
 const L: f64 = 20.0;
 
-pub fn do_assignment_4() -> Result<(), Box<dyn Error>> {
+/// First and second nearest neighbors of spaced points in a cube
+#[derive(clap::Parser)]
+pub struct NeighborsArgs {
+    /// The number of points to generate
+    #[clap(long, default_value_t = 500)]
+    pub n: usize,
+    /// The side length of the cube
+    #[clap(long, default_value_t = L)]
+    pub box_len: f64,
+    /// The minimum distance between points
+    #[clap(long, default_value_t = 2.0)]
+    pub r_min: f64,
+    /// The distance within which two points are first neighbors
+    #[clap(long, default_value_t = 3.0)]
+    pub r_neighbor: f64,
+    /// Wrap the cube around each axis, so that sites near a face neighbor those near the opposite face
+    #[clap(long)]
+    pub periodic: bool,
+    /// The number of bins of the radial distribution function
+    #[clap(long, default_value_t = 100)]
+    pub rdf_bins: usize,
+}
+
+/// Entry-point for assignment 4
+pub fn do_assignment_4(output: &Output, args: &NeighborsArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing assignment 4");
 
-    let mut uni = Uniform701::new();
+    let mut uni = Uniform701::seeded(output.seed());
 
-    let mut sites = gen_points_in_cube(&mut uni, L, 500, 2.0);
-    sites.sort_unstable_by(|l, r| l.magnitude().partial_cmp(&r.magnitude()).unwrap());
+    let sim_box = SimulationBox::cube(args.box_len, args.periodic)?;
+    let mut sites = gen_spaced_points_in(&sim_box, &mut uni, args.n, args.r_min)?;
+    sites.sort_unstable_by(|l, r| l.magnitude().total_cmp(&r.magnitude()));
 
-    let first_neighbors = part_4a(&sites)?;
-    let first_adj = part_4b(&first_neighbors)?;
-    let second_neighbors = part_4c(&first_neighbors)?;
+    let first_neighbors = part_4a(output, &sim_box, &sites, args.r_neighbor)?;
+    part_4b(output, &first_neighbors)?;
+    let second_neighbors = part_4c(output, &first_neighbors)?;
 
     NearestNeighborMap::print_txt(
         &sites,
         &first_neighbors,
         &second_neighbors,
-        "output/assignment4/sites_first_second.txt",
+        &output.path("sites_first_second.txt"),
     )?;
+    output.write_seed("sites_first_second.txt")?;
+
+    part_4d(output, args, &sites, &first_neighbors, &second_neighbors)?;
+    part_4e(output, args, &sites, &first_neighbors, &second_neighbors)?;
+
+    RadialDistribution::compute(&sim_box, &sites, args.box_len / 2.0, args.rdf_bins)?
+        .print_csv(&output.path("rdf.csv"))?;
+    output.write_seed("rdf.csv")?;
 
     Ok(())
 }
 
-fn part_4a(sites: &[Point3d]) -> Result<NearestNeighborMap, Box<dyn Error>> {
+fn part_4a(
+    output: &Output,
+    sim_box: &SimulationBox<3>,
+    sites: &[Point3d],
+    r_neighbor: f64,
+) -> Result<NearestNeighborMap, Box<dyn Error>> {
     log::info!("Doing part a");
 
-    let nn_map = NearestNeighborMap::first_3d(sites, 3.0);
-    nn_map.print_latex("output/assignment4/first_neighbors.tex")?;
-    nn_map.print_csv("output/assignment4/first_neighbors.csv")?;
+    let nn_map = NearestNeighborMap::first_in(sim_box, sites, r_neighbor);
+    nn_map.print_latex(&output.path("first_neighbors.tex"))?;
+    output.write_seed("first_neighbors.tex")?;
+    nn_map.print_csv(&output.path("first_neighbors.csv"))?;
+    output.write_seed("first_neighbors.csv")?;
 
     Ok(nn_map)
 }
 
-fn part_4b(nn_map: &NearestNeighborMap) -> Result<AdjacencyMatrix, Box<dyn Error>> {
+fn part_4b(
+    output: &Output,
+    nn_map: &NearestNeighborMap,
+) -> Result<AdjacencyMatrix, Box<dyn Error>> {
     log::info!("Doing part b");
 
     let adj_mat = AdjacencyMatrix::from(nn_map);
-    adj_mat.print_csv("output/assignment4/adjacency_matrix.csv")?;
+    adj_mat.print_csv(&output.path("adjacency_matrix.csv"))?;
+    output.write_seed("adjacency_matrix.csv")?;
 
     Ok(adj_mat)
 }
 
-fn part_4c(first_neighbors: &NearestNeighborMap) -> Result<NearestNeighborMap, Box<dyn Error>> {
+fn part_4c(
+    output: &Output,
+    first_neighbors: &NearestNeighborMap,
+) -> Result<NearestNeighborMap, Box<dyn Error>> {
     log::info!("Doing part c");
 
     let second_neighbors = NearestNeighborMap::second(first_neighbors);
-    second_neighbors.print_latex("output/assignment4/second_neighbors.tex")?;
-    second_neighbors.print_csv("output/assignment4/second_neighbors.csv")?;
+    second_neighbors.print_latex(&output.path("second_neighbors.tex"))?;
+    output.write_seed("second_neighbors.tex")?;
+    second_neighbors.print_csv(&output.path("second_neighbors.csv"))?;
+    output.write_seed("second_neighbors.csv")?;
 
     Ok(second_neighbors)
 }
+
+/// Compare the natural neighbors of the sites, from their Delaunay tetrahedralization,
+/// with the first and second neighbors found with the cutoff
+fn part_4d(
+    output: &Output,
+    args: &NeighborsArgs,
+    sites: &[Point3d],
+    first_neighbors: &NearestNeighborMap,
+    second_neighbors: &NearestNeighborMap,
+) -> Result<NearestNeighborMap, Box<dyn Error>> {
+    log::info!("Doing part d");
+    if args.periodic {
+        log::warn!("The natural neighbors are found without wrapping around the cube");
+    }
+
+    let natural_neighbors = NearestNeighborMap::natural_3d(sites)?;
+    natural_neighbors.print_csv(&output.path("natural_neighbors.csv"))?;
+    output.write_seed("natural_neighbors.csv")?;
+
+    let mut of = File::create(output.path("natural_vs_cutoff.csv"))?;
+    of.write_all(
+        "Site Index, Natural Neighbors, Also First, Also Second, Neither, First Not Natural\n"
+            .as_ref(),
+    )?;
+    let (mut n_natural, mut n_first, mut n_also_first, mut n_also_second) = (0, 0, 0, 0);
+    for (i, natural_i) in natural_neighbors.neighbors.iter().enumerate() {
+        let first_i = &first_neighbors.neighbors[i];
+        let also_first = natural_i.iter().filter(|j| first_i.contains(j)).count();
+        let also_second = natural_i
+            .iter()
+            .filter(|j| second_neighbors.neighbors[i].contains(j))
+            .count();
+        of.write_all(
+            format!(
+                "{}, {}, {}, {}, {}, {}\n",
+                i,
+                natural_i.len(),
+                also_first,
+                also_second,
+                natural_i.len() - also_first - also_second,
+                first_i.len() - also_first
+            )
+            .as_ref(),
+        )?;
+        n_natural += natural_i.len();
+        n_first += first_i.len();
+        n_also_first += also_first;
+        n_also_second += also_second;
+    }
+    output.write_seed("natural_vs_cutoff.csv")?;
+
+    let n = sites.len() as f64;
+    log::info!(
+        "On average, a site has {:.3} natural neighbors and {:.3} first neighbors",
+        n_natural as f64 / n,
+        n_first as f64 / n
+    );
+    log::info!(
+        "{:.1}% of natural neighbors are first neighbors and {:.1}% are second neighbors",
+        100.0 * n_also_first as f64 / n_natural as f64,
+        100.0 * n_also_second as f64 / n_natural as f64
+    );
+    log::info!(
+        "{:.1}% of first neighbors are natural neighbors",
+        100.0 * n_also_first as f64 / n_first.max(1) as f64
+    );
+
+    Ok(natural_neighbors)
+}
+
+/// Compute the Voronoi cells of the sites within the cube, and report the local density around
+/// each site alongside its numbers of neighbors
+fn part_4e(
+    output: &Output,
+    args: &NeighborsArgs,
+    sites: &[Point3d],
+    first_neighbors: &NearestNeighborMap,
+    second_neighbors: &NearestNeighborMap,
+) -> Result<VoronoiDiagram3d, Box<dyn Error>> {
+    log::info!("Doing part e");
+
+    let l = args.box_len;
+    let diagram = VoronoiDiagram3d::new(sites, ORIGIN_3D, Point3d::new(l, l, l))?;
+    diagram.print_obj(&output.path("voronoi_cells.obj"))?;
+    output.write_seed("voronoi_cells.obj")?;
+
+    let mut of = File::create(output.path("voronoi_cells.csv"))?;
+    of.write_all(
+        "Site Index, First Neighbors, Second Neighbors, Faces, Volume, Surface Area, \
+         Local Density, Touches Bounds\n"
+            .as_ref(),
+    )?;
+    for (i, cell) in diagram.cells().iter().enumerate() {
+        let volume = cell.volume();
+        of.write_all(
+            format!(
+                "{}, {}, {}, {}, {}, {}, {}, {}\n",
+                i,
+                first_neighbors.neighbors[i].len(),
+                second_neighbors.neighbors[i].len(),
+                cell.faces().len(),
+                volume,
+                cell.surface_area(),
+                1.0 / volume,
+                cell.touches_bounds()
+            )
+            .as_ref(),
+        )?;
+    }
+    output.write_seed("voronoi_cells.csv")?;
+
+    let interior = diagram
+        .cells()
+        .iter()
+        .filter(|cell| !cell.touches_bounds())
+        .collect::<Vec<_>>();
+    log::info!(
+        "The cells fill a volume of {:.6}, out of the cube's {}",
+        diagram
+            .cells()
+            .iter()
+            .map(|cell| cell.volume())
+            .sum::<f64>(),
+        l * l * l
+    );
+    if !interior.is_empty() {
+        let n = interior.len() as f64;
+        log::info!(
+            "The {} cells clear of the faces of the cube have {:.3} faces and a volume of {:.4} \
+             on average",
+            interior.len(),
+            interior
+                .iter()
+                .map(|cell| cell.faces().len())
+                .sum::<usize>() as f64
+                / n,
+            interior.iter().map(|cell| cell.volume()).sum::<f64>() / n
+        );
+    }
+
+    Ok(diagram)
+}
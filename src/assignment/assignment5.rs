@@ -1,34 +1,67 @@
 use plotters::prelude::*;
 
-use crate::data::delaunay::{dealunay_2d, to_lines_2d};
-use crate::data::point::Point2d;
-use crate::data::points_in_grid::gen_spaced_points_in_box;
-use crate::rand::uniform::Uniform701;
+use crate::output::Output;
+use cos_701::data::delaunay::delaunay_2d;
+use cos_701::data::point::Point2d;
+use cos_701::data::points_in_grid::gen_spaced_points_in_box;
+use cos_701::rand::uniform::Uniform701;
 use std::error::Error;
 
-pub fn do_assignment_5() -> Result<(), Box<dyn Error>> {
+/// Delaunay triangulation of spaced points in a box
+#[derive(clap::Parser)]
+pub struct DelaunayArgs {
+    /// The number of points to triangulate
+    #[clap(long, default_value_t = 500)]
+    pub n: usize,
+    /// The side length of the box
+    #[clap(long, default_value_t = 20.0)]
+    pub box_len: f64,
+    /// The minimum distance between points
+    #[clap(long, default_value_t = 0.7)]
+    pub r_min: f64,
+}
+
+/// Entry-point for assignment 5
+pub fn do_assignment_5(output: &Output, args: &DelaunayArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing assignment 5");
 
-    let mut uni = Uniform701::new();
+    let mut uni = Uniform701::seeded(output.seed());
 
-    let sites = gen_spaced_points_in_box(&mut uni, 20.0, 500, 0.7);
+    let sites = gen_spaced_points_in_box(&mut uni, args.box_len, args.n, args.r_min)?;
 
-    do_part_a(&sites)?;
-    do_part_b(&sites)?;
+    let library_lines = do_part_a(output, &sites, args.box_len)?;
+    let homemade_lines = do_part_b(output, &sites, args.box_len)?;
+
+    if library_lines == homemade_lines {
+        log::info!(
+            "Both triangulations have the same {} edges",
+            homemade_lines.len()
+        );
+    } else {
+        log::warn!(
+            "The triangulations differ: the library has {} edges, the homemade one {}",
+            library_lines.len(),
+            homemade_lines.len()
+        );
+    }
 
     Ok(())
 }
 
-fn do_part_a(sites: &[Point2d]) -> Result<(), Box<dyn Error>> {
+fn do_part_a(
+    output: &Output,
+    sites: &[Point2d],
+    l: f64,
+) -> Result<Vec<(usize, usize)>, Box<dyn Error>> {
     log::info!("Doing part a");
 
     let wrapped_points = &sites
         .iter()
-        .map(|&v| delaunator::Point { x: v.x, y: v.y })
+        .map(|&v| delaunator::Point { x: v.x(), y: v.y() })
         .collect::<Vec<delaunator::Point>>();
 
     let mut lines = delaunator::triangulate(wrapped_points)
-        .expect("No triangulation for the points exists!")
+        .ok_or("No triangulation for the points exists")?
         .triangles
         .chunks(3)
         .flat_map(|t| vec![(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
@@ -39,39 +72,50 @@ fn do_part_a(sites: &[Point2d]) -> Result<(), Box<dyn Error>> {
     lines.dedup();
 
     plot_triangulation(
-        "output/assignment5/part_5a.png",
+        output,
+        "part_5a.png",
         "Using Delaunay triangulation library",
+        l,
         sites,
         &lines,
     )?;
 
-    Ok(())
+    Ok(lines)
 }
 
-fn do_part_b(sites: &[Point2d]) -> Result<(), Box<dyn Error>> {
+fn do_part_b(
+    output: &Output,
+    sites: &[Point2d],
+    l: f64,
+) -> Result<Vec<(usize, usize)>, Box<dyn Error>> {
     log::info!("Doing part b");
 
-    let lines = to_lines_2d(&dealunay_2d(&sites, 4.0, false));
+    let lines = delaunay_2d(sites)?.edges();
 
     plot_triangulation(
-        "output/assignment5/part_5b.png",
+        output,
+        "part_5b.png",
         "Homemade Delaunay Triangulation",
+        l,
         sites,
         &lines,
     )?;
 
-    Ok(())
+    Ok(lines)
 }
 
 fn plot_triangulation(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
+    l: f64,
     sites: &[Point2d],
     lines: &[(usize, usize)],
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting {}", &caption);
 
-    let root = BitMapBackend::new(path, (1440, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -79,7 +123,7 @@ fn plot_triangulation(
         .margin(32)
         .x_label_area_size(32)
         .y_label_area_size(32)
-        .build_cartesian_2d(-0.5..20.5, -0.5..20.5)?;
+        .build_cartesian_2d(-0.5..l + 0.5, -0.5..l + 0.5)?;
     chart.configure_mesh().draw()?;
 
     if let Err(err) = chart.draw_series(
@@ -102,5 +146,6 @@ fn plot_triangulation(
             }
         });
 
+    output.write_seed(file)?;
     Ok(())
 }
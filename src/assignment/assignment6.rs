@@ -1,54 +1,89 @@
 use ordered_float::OrderedFloat;
 use plotters::prelude::*;
 
-use crate::data::delaunay::dealunay_2d;
-use crate::data::line::Line2d;
-use crate::data::point::Point2d;
-use crate::data::points_in_grid::gen_spaced_points_in_box;
-use crate::data::voronoi::voronoi_701;
-use crate::rand::uniform::Uniform701;
+use crate::output::Output;
+use cos_701::data::line::Line2d;
+use cos_701::data::point::{Point2d, ORIGIN_2D};
+use cos_701::data::points_in_grid::{gen_points_in_box, gen_spaced_points_in_box};
+use cos_701::data::voronoi::VoronoiDiagram;
+use cos_701::rand::uniform::Uniform701;
+use cos_701::stats::cell_stats::CellStatistics;
+use cos_701::stats::fit::LinearFit;
+use cos_701::stats::histogram::Histogram;
+use cos_701::util;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::str::FromStr;
 
-pub fn do_assignment_6() -> Result<(), Box<dyn Error>> {
+/// Voronoi diagram of spaced points in a box
+#[derive(clap::Parser)]
+pub struct VoronoiArgs {
+    /// The number of sites
+    #[clap(long, default_value_t = 500)]
+    pub n: usize,
+    /// The side length of the box
+    #[clap(long, default_value_t = 20.0)]
+    pub box_len: f64,
+    /// The minimum distance between sites
+    #[clap(long, default_value_t = 0.7)]
+    pub r_min: f64,
+    /// The number of diagrams of each kind of site whose cells are pooled for statistics
+    #[clap(long, default_value_t = 20)]
+    pub n_realizations: usize,
+    /// The number of bins of the distribution of cell areas
+    #[clap(long, default_value_t = 30)]
+    pub area_bins: usize,
+}
+
+/// The largest cell area binned, relative to the mean
+const MAX_RELATIVE_AREA: f64 = 3.0;
+
+/// Points to plot, with their label and color
+type Series<'a> = (Vec<(f64, f64)>, String, &'a RGBColor);
+/// Points to plot, with the line fit to them, their label and color
+type FitSeries<'a> = (Vec<(f64, f64)>, LinearFit, String, &'a RGBColor);
+
+/// Entry-point for assignment 6
+pub fn do_assignment_6(output: &Output, args: &VoronoiArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing assignment 6");
 
     // let sites = use_saved_sites();
 
-    let mut uni = Uniform701::new();
+    let mut uni = Uniform701::seeded(output.seed());
 
-    let sites = gen_spaced_points_in_box(&mut uni, 20.0, 500, 0.7);
+    let sites = gen_spaced_points_in_box(&mut uni, args.box_len, args.n, args.r_min)?;
 
-    do_part_a(&sites)?;
-    do_part_b(&sites)?;
+    do_part_a(output, &sites, args.box_len)?;
+    do_part_b(output, &sites, args.box_len)?;
+    do_part_c(output, args, &mut uni)?;
 
     Ok(())
 }
 
-fn do_part_a(sites: &[Point2d]) -> Result<(), Box<dyn Error>> {
+fn do_part_a(output: &Output, sites: &[Point2d], l: f64) -> Result<(), Box<dyn Error>> {
     log::info!("Doing part a");
 
     plot_voronoi_diagram(
-        "output/assignment6/part_6a.png",
+        output,
+        "part_6a.png",
         "Using voronoi library",
+        l,
         sites,
         &voronoi::make_polygons(&voronoi::voronoi(
             sites
                 .iter()
-                .map(|&v| voronoi::Point::new(v.x, v.y))
+                .map(|&v| voronoi::Point::new(v.x(), v.y()))
                 .collect::<Vec<voronoi::Point>>(),
             800.0,
         ))
         .iter()
-        .map(|polygon| {
+        .flat_map(|polygon| {
             polygon
                 .windows(2)
                 .map(|line| [(line[0].x, line[0].y), (line[1].x, line[1].y)])
                 .collect::<Vec<[(OrderedFloat<f64>, OrderedFloat<f64>); 2]>>()
         })
-        .flatten()
         .map(|line| {
             let src = line[0];
             let dst = line[1];
@@ -64,31 +99,371 @@ fn do_part_a(sites: &[Point2d]) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn do_part_b(sites: &[Point2d]) -> Result<(), Box<dyn Error>> {
+fn do_part_b(output: &Output, sites: &[Point2d], l: f64) -> Result<(), Box<dyn Error>> {
     log::info!("Doing part b");
 
-    let triangulation = dealunay_2d(sites, 4.0, false);
-
-    let lines = voronoi_701(&sites, &triangulation);
+    let diagram = VoronoiDiagram::new(sites, ORIGIN_2D, Point2d::new(l, l))?;
+    let lines = diagram.edges();
 
     plot_voronoi_diagram(
-        "output/assignment6/part_6b.png",
+        output,
+        "part_6b.png",
         "Homemade Voronoi Diagram",
+        l,
         sites,
         &lines,
     )?;
     Ok(())
 }
 
+fn do_part_c(
+    output: &Output,
+    args: &VoronoiArgs,
+    uni: &mut Uniform701,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Doing part c");
+
+    let l = args.box_len;
+    let mut poisson = CellStatistics::default();
+    let mut hard_core = CellStatistics::default();
+    for i in 0..args.n_realizations {
+        let poisson_sites = gen_points_in_box(uni, l, args.n);
+        let poisson_diagram = VoronoiDiagram::new(&poisson_sites, ORIGIN_2D, Point2d::new(l, l))?;
+        poisson.merge(&CellStatistics::from_diagram(&poisson_diagram));
+
+        let hard_core_sites = gen_spaced_points_in_box(uni, l, args.n, args.r_min)?;
+        let hard_core_diagram =
+            VoronoiDiagram::new(&hard_core_sites, ORIGIN_2D, Point2d::new(l, l))?;
+        hard_core.merge(&CellStatistics::from_diagram(&hard_core_diagram));
+
+        if i == 0 {
+            plot_voronoi_diagram(
+                output,
+                "part_6c_poisson.png",
+                "Voronoi Diagram of Poisson Sites",
+                l,
+                &poisson_sites,
+                &poisson_diagram.edges(),
+            )?;
+        }
+    }
+
+    let kinds = vec![
+        ("poisson", "Poisson sites", &poisson, RED),
+        ("hard_core", "Hard-core sites", &hard_core, BLUE),
+    ];
+    let mut summary = File::create(output.path("cell_summary.csv"))?;
+    summary.write_all(
+        "Sites, Cells, Mean Sides, mu_2, Aboav-Weaire a, Aboav-Weaire Intercept, 6a + mu_2, \
+         Lewis Slope, Lewis Intercept\n"
+            .as_ref(),
+    )?;
+    let mut areas = vec![];
+    let mut sides = vec![];
+    let mut aboav_weaire = vec![];
+    let mut lewis = vec![];
+    for &(name, label, stats, ref color) in &kinds {
+        if stats.n_cells() == 0 {
+            return Err(format!("No cell of the {} lies inside the box", label).into());
+        }
+        let aw_fit = stats.aboav_weaire()?;
+        let lewis_fit = stats.lewis()?;
+        let a = 6.0 - aw_fit.slope;
+        log::info!(
+            "{}: {} cells with {:.4} sides on average, mu_2 = {:.4}",
+            label,
+            stats.n_cells(),
+            stats.mean_sides(),
+            stats.sides_variance()
+        );
+        log::info!(
+            "{}: Aboav-Weaire fit {}, so a = {:.4} and 6a + mu_2 = {:.4}",
+            label,
+            aw_fit,
+            a,
+            6.0 * a + stats.sides_variance()
+        );
+        log::info!("{}: Lewis fit {}", label, lewis_fit);
+        summary.write_all(
+            format!(
+                "{}, {}, {}, {}, {}, {}, {}, {}, {}\n",
+                label,
+                stats.n_cells(),
+                stats.mean_sides(),
+                stats.sides_variance(),
+                a,
+                aw_fit.intercept,
+                6.0 * a + stats.sides_variance(),
+                lewis_fit.slope,
+                lewis_fit.intercept
+            )
+            .as_ref(),
+        )?;
+
+        let sides_file = format!("cell_sides_{}.csv", name);
+        stats.print_csv(&output.path(&sides_file))?;
+        output.write_seed(&sides_file)?;
+        let areas_file = format!("cell_areas_{}.csv", name);
+        let histogram = stats.area_histogram(MAX_RELATIVE_AREA, args.area_bins)?;
+        histogram.print_csv(&output.path(&areas_file))?;
+        output.write_seed(&areas_file)?;
+
+        let rows = stats.by_sides();
+        areas.push((histogram, label.to_string(), color));
+        sides.push((
+            rows.iter()
+                .map(|row| (row.sides as f64, row.fraction))
+                .collect(),
+            label.to_string(),
+            color,
+        ));
+        aboav_weaire.push((
+            rows.iter()
+                .filter_map(|row| {
+                    row.mean_neighbor_sides
+                        .map(|m| (row.sides as f64, row.sides as f64 * m))
+                })
+                .collect(),
+            aw_fit,
+            label.to_string(),
+            color,
+        ));
+        lewis.push((
+            rows.iter()
+                .map(|row| (row.sides as f64, row.mean_relative_area))
+                .collect(),
+            lewis_fit,
+            label.to_string(),
+            color,
+        ));
+    }
+    output.write_seed("cell_summary.csv")?;
+
+    plot_cell_areas(output, "cell_areas.png", &areas)?;
+    plot_side_fractions(output, "cell_sides.png", &sides)?;
+    plot_law(
+        output,
+        "aboav_weaire.png",
+        "Aboav-Weaire's Law",
+        "n m(n)",
+        &aboav_weaire,
+    )?;
+    plot_law(
+        output,
+        "lewis.png",
+        "Lewis's Law",
+        "Mean relative area",
+        &lewis,
+    )?;
+
+    Ok(())
+}
+
+/// Plot the distributions of the relative areas of the cells, along with the gamma distribution
+/// which Ferenc and Neda found to fit the cells of Poisson sites
+fn plot_cell_areas(
+    output: &Output,
+    file: &str,
+    to_plot: &[(Histogram, String, &RGBColor)],
+) -> Result<(), Box<dyn Error>> {
+    let caption = "Distribution of Relative Cell Areas";
+    log::info!("Plotting {}", caption);
+
+    let densities = to_plot
+        .iter()
+        .map(|(histogram, _, _)| histogram.density())
+        .collect::<Vec<Vec<f64>>>();
+    let y_max = densities
+        .iter()
+        .flatten()
+        .cloned()
+        .fold(f64::MIN_POSITIVE, f64::max)
+        * 1.1;
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(64)
+        .y_label_area_size(64)
+        .build_cartesian_2d(0.0..MAX_RELATIVE_AREA, 0.0..y_max)?;
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc("Cell area / mean cell area")
+        .y_desc("Density")
+        .draw()?;
+
+    for ((histogram, label, color), density) in to_plot.iter().zip(&densities) {
+        chart
+            .draw_series(LineSeries::new(
+                histogram
+                    .bins()
+                    .zip(density)
+                    .flat_map(|((bin, _), &d)| vec![(bin.start, d), (bin.end, d)]),
+                color.stroke_width(2),
+            ))?
+            .label(label.to_owned())
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+            });
+    }
+
+    // f(y) = (7/2)^(7/2) / gamma(7/2) y^(5/2) e^(-7y/2)
+    let k: f64 = 3.5;
+    let norm = k.powf(k) / util::gamma_half(7);
+    chart
+        .draw_series(LineSeries::new(
+            (1..=1000)
+                .map(|i| i as f64 * MAX_RELATIVE_AREA / 1000.0)
+                .map(|y| (y, norm * y.powf(k - 1.0) * (-k * y).exp())),
+            BLACK.stroke_width(2),
+        ))?
+        .label("Gamma fit for Poisson sites (Ferenc and Neda)")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK.stroke_width(2)));
+
+    chart
+        .configure_series_labels()
+        .border_style(&BLACK)
+        .background_style(&WHITE.mix(0.8))
+        .draw()?;
+
+    output.write_seed(file)?;
+    Ok(())
+}
+
+/// Plot the fraction of cells with each number of sides, as bars side by side
+fn plot_side_fractions(
+    output: &Output,
+    file: &str,
+    to_plot: &[Series],
+) -> Result<(), Box<dyn Error>> {
+    let caption = "Number of Sides of the Cells";
+    log::info!("Plotting {}", caption);
+
+    let all = to_plot.iter().flat_map(|(points, _, _)| points);
+    let (x_min, x_max, y_max) = all.fold((f64::MAX, f64::MIN, 0.0), |(lo, hi, top), &(x, y)| {
+        (lo.min(x), hi.max(x), f64::max(top, y))
+    });
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(64)
+        .y_label_area_size(64)
+        .build_cartesian_2d(x_min - 1.0..x_max + 1.0, 0.0..y_max * 1.1)?;
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc("Number of sides")
+        .y_desc("Fraction of cells")
+        .draw()?;
+
+    let width = 0.8 / to_plot.len() as f64;
+    for (i, (points, label, color)) in to_plot.iter().enumerate() {
+        let offset = -0.4 + i as f64 * width;
+        chart
+            .draw_series(points.iter().map(|&(x, y)| {
+                Rectangle::new([(x + offset, 0.0), (x + offset + width, y)], color.filled())
+            }))?
+            .label(label.to_owned())
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.filled()));
+    }
+
+    chart
+        .configure_series_labels()
+        .border_style(&BLACK)
+        .background_style(&WHITE.mix(0.8))
+        .draw()?;
+
+    output.write_seed(file)?;
+    Ok(())
+}
+
+/// Plot the mean of some quantity over the cells with each number of sides,
+/// along with the line fit to it
+fn plot_law(
+    output: &Output,
+    file: &str,
+    caption: &str,
+    y_desc: &str,
+    to_plot: &[FitSeries],
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Plotting {}", caption);
+
+    let all = to_plot.iter().flat_map(|(points, _, _, _)| points);
+    let (x_min, x_max, y_min, y_max) = all.fold(
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+        |(x_lo, x_hi, y_lo, y_hi), &(x, y)| (x_lo.min(x), x_hi.max(x), y_lo.min(y), y_hi.max(y)),
+    );
+    let y_pad = (y_max - y_min).max(1.0) * 0.1;
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(64)
+        .y_label_area_size(64)
+        .build_cartesian_2d(x_min - 0.5..x_max + 0.5, y_min - y_pad..y_max + y_pad)?;
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_desc("Number of sides, n")
+        .y_desc(y_desc)
+        .draw()?;
+
+    for (points, fit, label, color) in to_plot {
+        chart.draw_series(
+            points
+                .iter()
+                .map(|&(x, y)| Circle::new((x, y), 5, color.filled())),
+        )?;
+        chart
+            .draw_series(LineSeries::new(
+                vec![
+                    (x_min - 0.5, fit.at(x_min - 0.5)),
+                    (x_max + 0.5, fit.at(x_max + 0.5)),
+                ],
+                color.stroke_width(2),
+            ))?
+            .label(format!("{}: {}", label, fit))
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+            });
+    }
+
+    chart
+        .configure_series_labels()
+        .border_style(&BLACK)
+        .background_style(&WHITE.mix(0.8))
+        .draw()?;
+
+    output.write_seed(file)?;
+    Ok(())
+}
+
 fn plot_voronoi_diagram(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
+    l: f64,
     sites: &[Point2d],
     lines: &[Line2d],
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting {}", &caption);
 
-    let root = BitMapBackend::new(path, (1440, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -96,7 +471,7 @@ fn plot_voronoi_diagram(
         .margin(32)
         .x_label_area_size(32)
         .y_label_area_size(32)
-        .build_cartesian_2d(-0.5..20.5, -0.5..20.5)?;
+        .build_cartesian_2d(-0.5..l + 0.5, -0.5..l + 0.5)?;
     chart.configure_mesh().draw()?;
 
     if let Err(err) = chart.draw_series(
@@ -119,9 +494,11 @@ fn plot_voronoi_diagram(
             }
         });
 
+    output.write_seed(file)?;
     Ok(())
 }
 
+#[allow(dead_code)]
 fn use_saved_sites() -> Vec<Point2d> {
     let fin = BufReader::new(File::open("output/scratch/a6_pts.txt").unwrap());
 
@@ -1,143 +1,267 @@
 use plotters::prelude::*;
 
-use crate::data::line::Line2d;
-use crate::data::point::{Point2d, ORIGIN_2D};
-use crate::data::points_in_grid::gen_points_in_box;
-use crate::rand::uniform::Uniform701;
+use crate::output::Output;
+use cos_701::data::hull::HullAlgorithm;
+use cos_701::data::hull_3d::convex_hull_3d;
+use cos_701::data::line::Line2d;
+use cos_701::data::point::{Point2d, Point3d};
+use cos_701::data::points_in_grid::{gen_points_in_box, gen_points_in_cube};
+use cos_701::data::random_vec::RandomVec;
+use cos_701::rand::boxmuller::BoxMullerGaussian701;
+use cos_701::rand::uniform::Uniform701;
+use std::convert::TryFrom;
 use std::error::Error;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::Write;
 use std::time::Instant;
 
 const BOX_LEN: f64 = 100.0;
 
-pub fn do_assignment_7() -> Result<(), Box<dyn Error>> {
+/// Convex hulls of increasingly many points in a box, timed for each algorithm and a library
+#[derive(clap::Parser)]
+pub struct HullArgs {
+    /// The number of points in the first and the step between each further grid
+    #[clap(long, default_value_t = 500)]
+    pub n_step: usize,
+    /// The number of points in the largest grid
+    #[clap(long, default_value_t = 10_000)]
+    pub n_max: usize,
+    /// The side length of the box
+    #[clap(long, default_value_t = BOX_LEN)]
+    pub box_len: f64,
+    /// The number of times each hull is computed, of which the fastest is kept
+    #[clap(long, default_value_t = 5)]
+    pub repeats: usize,
+}
+
+/// The color each algorithm is plotted in, with the library last
+const COLORS: [&RGBColor; 5] = [&BLUE, &GREEN, &MAGENTA, &CYAN, &RED];
+
+/// Entry-point for assignment 7
+pub fn do_assignment_7(output: &Output, args: &HullArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing assignment 7");
 
-    let mut uni = Uniform701::new();
+    let mut uni = Uniform701::seeded(output.seed());
+    let repeats = args.repeats.max(1);
 
-    let mut grids: Vec<Vec<Point2d>> = (500..=10_000)
-        .step_by(500)
-        .map(|n| gen_points_in_box(&mut uni, BOX_LEN, n))
+    let grids: Vec<Vec<Point2d>> = (args.n_step..=args.n_max)
+        .step_by(args.n_step)
+        .map(|n| gen_points_in_box(&mut uni, args.box_len, n))
         .collect();
 
-    let hulls: Vec<(u128, Vec<Line2d>)> = grids
-        .iter_mut()
-        .map(Vec::as_mut_slice)
-        .map(compute_convex_hull)
-        .collect();
+    let mut runtimes = vec![];
+    for grid in &grids {
+        let n = grid.len();
+        let hulls = HullAlgorithm::ALL
+            .iter()
+            .map(|&algorithm| compute_convex_hull(grid, algorithm, repeats))
+            .collect::<Result<Vec<(u128, Vec<Line2d>)>, Box<dyn Error>>>()?;
+        if let Some(at) = hulls.iter().position(|(_, hull)| hull != &hulls[0].1) {
+            return Err(format!(
+                "{} and {} found different hulls for n={}",
+                HullAlgorithm::ALL[0],
+                HullAlgorithm::ALL[at],
+                n
+            )
+            .into());
+        }
+        let (time_lib, hull_lib) = compute_convex_hull_from_library(grid, repeats);
+        if corners(&hull_lib) != corners(&hulls[0].1) {
+            log::warn!("The library found a different hull for n={}", n);
+        }
 
-    hulls.iter().enumerate().try_for_each(|(i, (time, hull))| {
-        let n = 500 + i * 500;
+        let (fastest, &(time, ref hull)) = HullAlgorithm::ALL
+            .iter()
+            .zip(&hulls)
+            .min_by_key(|(_, &(time, _))| time)
+            .unwrap();
         plot_hull(
-            &*format!("output/assignment7/hull_{}.png", n),
-            &*format!("Convex Hull for n={}, computed in {}ns.", n, time),
-            &grids[i],
+            output,
+            &format!("hull_{}.png", n),
+            &format!(
+                "Convex Hull for n={}, computed by {} in {}ns.",
+                n, fastest, time
+            ),
+            args.box_len,
+            grid,
             hull,
-        )
-    })?;
+        )?;
 
-    let from_lib: Vec<(u128, Vec<Line2d>)> = grids
+        let mut times = hulls.iter().map(|&(time, _)| time).collect::<Vec<u128>>();
+        times.push(time_lib);
+        runtimes.push((n, times));
+    }
+
+    let mut labels = HullAlgorithm::ALL
         .iter()
-        .map(Vec::as_slice)
-        .map(compute_convex_hull_from_library)
-        .collect();
+        .map(|algorithm| algorithm.to_string())
+        .collect::<Vec<String>>();
+    labels.push("Library".to_string());
 
+    print_runtimes(output, "runtimes.csv", &labels, &runtimes)?;
     plot_runtimes(
-        "output/assignment7/runtimes.png",
+        output,
+        "runtimes.png",
         "Convex Hull Runtimes",
-        &hulls
-            .iter()
-            .map(|&(time, _)| time)
-            .zip(from_lib)
-            .map(|(time_me, (time_lib, _))| (time_me, time_lib))
-            .collect::<Vec<(u128, u128)>>(),
+        &labels,
+        &runtimes,
     )?;
 
+    do_hulls_3d(output, args, &mut uni)?;
+
     Ok(())
 }
 
-pub fn compute_convex_hull_from_library(grid: &[Point2d]) -> (u128, Vec<Line2d>) {
-    let now = Instant::now();
-
-    let wrapped_points = &grid
-        .iter()
-        .map(|&v| delaunator::Point { x: v.x, y: v.y })
-        .collect::<Vec<delaunator::Point>>();
-
-    let hull = delaunator::triangulate(wrapped_points)
-        .expect("No triangulation for the points exists!")
-        .hull
-        .windows(2)
-        .map(|points| (grid[points[0]], grid[points[1]]).into())
-        .collect::<Vec<Line2d>>();
-
-    let elapsed = now.elapsed().as_nanos();
-    (elapsed, hull)
-}
+/// Compute the convex hulls of points in a cube and in a unit ball, for the same numbers of
+/// points as in the plane, and compare their volumes and areas with those of the cube and ball
+fn do_hulls_3d(
+    output: &Output,
+    args: &HullArgs,
+    uni: &mut Uniform701,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Computing convex hulls in three dimensions");
+
+    let l = args.box_len;
+    let mut gaussian = BoxMullerGaussian701::standard(uni.fork());
+    let mut of = File::create(output.path("hulls_3d.csv"))?;
+    of.write_all(
+        "Points, Shape, Vertices, Facets, Volume, Shape Volume, Surface Area, Shape Area, \
+         Runtime\n"
+            .as_ref(),
+    )?;
 
-pub fn compute_convex_hull(grid: &mut [Point2d]) -> (u128, Vec<Line2d>) {
-    let now = Instant::now();
+    let mut runtimes = vec![];
+    let mut largest = vec![];
+    for n in (args.n_step..=args.n_max).step_by(args.n_step) {
+        let in_cube = gen_points_in_cube(uni, l, n, 0.0)?;
+        let in_ball = (0..n)
+            .map(|_| Point3d::try_from(&RandomVec::efficient(uni, &mut gaussian, 3)))
+            .collect::<Result<Vec<Point3d>, Box<dyn Error>>>()?;
+        let shapes = [
+            ("Cube", in_cube, l.powi(3), 6.0 * l * l),
+            ("Ball", in_ball, 4.0 * PI / 3.0, 4.0 * PI),
+        ];
+
+        let mut times = vec![];
+        largest.clear();
+        for (shape, points, volume, area) in shapes {
+            let now = Instant::now();
+            let hull = convex_hull_3d(&points)?;
+            let time = now.elapsed().as_nanos();
+            of.write_all(
+                format!(
+                    "{}, {}, {}, {}, {}, {}, {}, {}, {}\n",
+                    n,
+                    shape,
+                    hull.vertices().len(),
+                    hull.facets().len(),
+                    hull.volume(),
+                    volume,
+                    hull.surface_area(),
+                    area,
+                    time
+                )
+                .as_ref(),
+            )?;
+            times.push(time);
+            largest.push((shape, hull, volume, area));
+        }
+        runtimes.push((n, times));
+    }
+    output.write_seed("hulls_3d.csv")?;
+
+    let n_largest = runtimes.last().map_or(0, |&(n, _)| n);
+    for (shape, hull, volume, area) in &largest {
+        log::info!(
+            "The hull of {} points in the {} has {} vertices and covers {:.2}% of its volume \
+             and {:.2}% of its area",
+            n_largest,
+            shape.to_lowercase(),
+            hull.vertices().len(),
+            100.0 * hull.volume() / volume,
+            100.0 * hull.surface_area() / area
+        );
+        let file = format!("hull_3d_{}.obj", shape.to_lowercase());
+        hull.print_obj(&output.path(&file))?;
+        output.write_seed(&file)?;
+    }
 
-    // Sort by magnitude so that element 0 is in the bottom-left
-    grid.sort_unstable_by(|l, r| l.x.partial_cmp(&r.x).unwrap());
+    plot_runtimes(
+        output,
+        "runtimes_3d.png",
+        "3D Convex Hull Runtimes",
+        &["Cube".to_string(), "Ball".to_string()],
+        &runtimes,
+    )?;
 
-    // Start with element 0 since we forced it to be in the bottom-left
-    let starting_i = 0;
-    // Assigned at the start of each iteration, represents the current index
-    let mut i;
-    // Assigned at the end of each iteration, represents the best line
-    let mut k = 0;
+    Ok(())
+}
 
-    // Assigned at the end of each iteration.
-    // For each iteration, represents the previous line against which we want a minimum angle.
-    // For the first iteration, this is a line arbitrarily from the origin,
-    // but thereafter, it will be a line included in the hull.
-    let mut reference_line = Line2d {
-        src: grid[0],
-        dst: ORIGIN_2D,
-    };
+/// The corners of a hull, in ascending order along the x-axis then the y-axis
+fn corners(hull: &[Line2d]) -> Vec<(f64, f64)> {
+    let mut corners = hull
+        .iter()
+        .map(|line| line.src.into())
+        .collect::<Vec<(f64, f64)>>();
+    corners.sort_unstable_by(|l, r| l.0.total_cmp(&r.0).then(l.1.total_cmp(&r.1)));
+    corners
+}
 
+/// Compute the hull with the library's Delaunay triangulation `repeats` times,
+/// returning the fastest time in nanoseconds along with the hull
+pub fn compute_convex_hull_from_library(grid: &[Point2d], repeats: usize) -> (u128, Vec<Line2d>) {
+    let mut fastest = u128::MAX;
     let mut hull = vec![];
+    for _ in 0..repeats {
+        let now = Instant::now();
 
-    loop {
-        i = k;
-        let src = grid[i];
-        let next = grid
+        let wrapped_points = &grid
             .iter()
-            .enumerate()
-            .filter_map(|(j, &pt)| {
-                if j == i || j == k {
-                    None
-                } else {
-                    let line = Line2d { src, dst: grid[j] };
-                    Some((j, reference_line.angle_between(&line)))
-                }
-            })
-            .reduce(|l, r| if l.1 < r.1 { l } else { r })
-            .unwrap();
-        k = next.0;
-        reference_line = Line2d {
-            src: grid[i],
-            dst: grid[k],
-        };
-        hull.push(reference_line);
-        if k == starting_i {
-            break;
-        }
+            .map(|&v| delaunator::Point { x: v.x(), y: v.y() })
+            .collect::<Vec<delaunator::Point>>();
+
+        let corners = delaunator::triangulate(wrapped_points)
+            .expect("No triangulation for the points exists!")
+            .hull;
+        hull = (0..corners.len())
+            .map(|i| (grid[corners[i]], grid[corners[(i + 1) % corners.len()]]).into())
+            .collect::<Vec<Line2d>>();
+
+        fastest = fastest.min(now.elapsed().as_nanos());
     }
+    (fastest, hull)
+}
 
-    let elapsed = now.elapsed().as_nanos();
-    (elapsed, hull)
+/// Compute the hull with `algorithm` `repeats` times,
+/// returning the fastest time in nanoseconds along with the hull
+pub fn compute_convex_hull(
+    grid: &[Point2d],
+    algorithm: HullAlgorithm,
+    repeats: usize,
+) -> Result<(u128, Vec<Line2d>), Box<dyn Error>> {
+    let mut fastest = u128::MAX;
+    let mut hull = vec![];
+    for _ in 0..repeats {
+        let now = Instant::now();
+        hull = algorithm.compute(grid)?;
+        fastest = fastest.min(now.elapsed().as_nanos());
+    }
+    Ok((fastest, hull))
 }
 
 pub fn plot_hull(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
+    box_len: f64,
     sites: &[Point2d],
     hull: &[Line2d],
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting '{}'", caption);
 
-    let root = BitMapBackend::new(path, (1440, 900)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((1440, 900))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -145,7 +269,7 @@ pub fn plot_hull(
         .margin(32)
         .x_label_area_size(32)
         .y_label_area_size(32)
-        .build_cartesian_2d(-10.0..BOX_LEN + 10.0, -10.0..BOX_LEN + 10.0)?;
+        .build_cartesian_2d(-10.0..box_len + 10.0, -10.0..box_len + 10.0)?;
     chart.configure_mesh().disable_mesh().draw()?;
 
     chart.draw_series(
@@ -163,30 +287,63 @@ pub fn plot_hull(
                 log::error!("Error occurred drawing a line!  Details: {:?}", err);
             }
         });
+    output.write_seed(file)?;
+    Ok(())
+}
+
+fn print_runtimes(
+    output: &Output,
+    file: &str,
+    labels: &[String],
+    runtimes: &[(usize, Vec<u128>)],
+) -> Result<(), Box<dyn Error>> {
+    let mut of = File::create(output.path(file))?;
+    of.write_all(format!("Points, {}\n", labels.join(", ")).as_ref())?;
+    for (n, times) in runtimes {
+        of.write_all(
+            format!(
+                "{}, {}\n",
+                n,
+                times
+                    .iter()
+                    .map(|time| time.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .as_ref(),
+        )?;
+    }
+    output.write_seed(file)?;
     Ok(())
 }
 
 fn plot_runtimes(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
-    to_plot: &[(u128, u128)],
+    labels: &[String],
+    to_plot: &[(usize, Vec<u128>)],
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting runtimes");
 
-    let root = BitMapBackend::new(path, (800, 600)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
     root.fill(&WHITE)?;
 
-    let y_max = u128::max(
-        to_plot.iter().map(|&(v, _)| v).max().unwrap(),
-        to_plot.iter().map(|&(_, v)| v).max().unwrap(),
-    );
+    let x_range = to_plot.first().unwrap().0..to_plot.last().unwrap().0;
+    let y_max = to_plot
+        .iter()
+        .flat_map(|(_, times)| times)
+        .cloned()
+        .max()
+        .unwrap();
 
     let mut chart = ChartBuilder::on(&root)
         .caption(caption, ("sans-serif", 50).into_font())
         .margin(32)
         .x_label_area_size(64)
         .y_label_area_size(64)
-        .build_cartesian_2d(500_usize..10_000_usize, 0..y_max)?;
+        .build_cartesian_2d(x_range, 0..y_max)?;
     chart
         .configure_mesh()
         .disable_x_mesh()
@@ -196,27 +353,17 @@ fn plot_runtimes(
         .y_label_formatter(&|y| format!("{:E}", y))
         .draw()?;
 
-    chart
-        .draw_series(LineSeries::new(
-            to_plot
-                .iter()
-                .enumerate()
-                .map(|(i, &(v, _))| (500 + i * 500, v)),
-            BLUE.stroke_width(2),
-        ))?
-        .label("My implementation")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE.stroke_width(2)));
-
-    chart
-        .draw_series(LineSeries::new(
-            to_plot
-                .iter()
-                .enumerate()
-                .map(|(i, &(_, v))| (500 + i * 500, v)),
-            RED.stroke_width(2),
-        ))?
-        .label("Library implementation")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED.stroke_width(2)));
+    for (k, (label, &color)) in labels.iter().zip(COLORS.iter().cycle()).enumerate() {
+        chart
+            .draw_series(LineSeries::new(
+                to_plot.iter().map(|(n, times)| (*n, times[k])),
+                color.stroke_width(2),
+            ))?
+            .label(label.to_owned())
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+            });
+    }
 
     chart
         .configure_series_labels()
@@ -224,5 +371,6 @@ fn plot_runtimes(
         .background_style(&WHITE.mix(0.8))
         .draw()?;
 
+    output.write_seed(file)?;
     Ok(())
 }
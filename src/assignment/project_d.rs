@@ -1,58 +1,212 @@
 use plotters::prelude::*;
 
-use crate::data::lattice::{Clusters, Lattice};
-use crate::rand::uniform::Uniform701;
+use crate::output::Output;
+use cos_701::data::burning::Burning;
+use cos_701::data::lattice::{
+    ClusterLabels, Clusters, Connectivity, Criterion, Lattice, Percolation,
+};
+use cos_701::data::newman_ziff::{NewmanZiff, Observables, Sweep};
+use cos_701::rand::uniform::Uniform701;
+use cos_701::stats::cluster_stats::{ClusterGeometry, ClusterNumbers, FractalFit};
+use cos_701::stats::finite_size::{Estimate, Exponents, FiniteSizeScaling, Scaled};
 use std::error::Error;
+use std::fs::File;
+use std::io::Write;
 use std::ops::RangeInclusive;
 use std::time::Instant;
 
 const BOX_LEN: usize = 50;
 const N_ITER: usize = 20;
+const N_SWEEPS: usize = 1000;
+/// The number of values of p at which the Newman-Ziff observables are evaluated
+const N_SMOOTH_P: usize = 400;
+/// The side lengths of the lattices compared by finite-size scaling
+const FSS_SIZES: [usize; 4] = [16, 32, 64, 128];
+const FSS_SWEEPS: usize = 500;
+/// The largest scaled distance from the threshold, (p - p_c) L^(1/nu), of the collapsed curves
+const COLLAPSE_X_MAX: f64 = 2.0;
+/// The side length of the lattice whose largest cluster at the threshold has its fractal
+/// dimension measured
+const FRACTAL_LEN: usize = 512;
+/// The fractal dimension of the incipient infinite cluster in two dimensions
+const EXACT_FRACTAL_DIMENSION: f64 = 91.0 / 48.0;
+/// The number of logarithmic bins of the cluster numbers
+const N_SIZE_BINS: usize = 24;
+/// The number of lattices populated at the threshold in search of one whose percolating cluster
+/// can be burned
+const N_BURN_TRIES: usize = 1000;
 const P_RANGE: RangeInclusive<usize> = 20..=75;
+/// The occupation probabilities, in percent, for which cluster sizes and lattices are plotted
+const REPRESENTATIVE_P: [usize; 3] = [25, 50, 70];
+/// The side lengths of the lattices which are timed
+const RUNTIME_L_RANGE: RangeInclusive<usize> = 100..=1000;
+const COLORS: [&RGBColor; 5] = [&BLUE, &GREEN, &MAGENTA, &CYAN, &RED];
 
-pub fn do_project_d() -> Result<(), Box<dyn Error>> {
+/// Which parts of the lattice are randomly present
+#[derive(clap::ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Model {
+    /// Each site is occupied with probability p
+    Site,
+    /// Each bond is open with probability p, and every site is occupied
+    Bond,
+    /// Each bond is open with probability p, and each site is occupied with probability `p_site`
+    Mixed,
+}
+
+/// Site, bond or mixed percolation on a square lattice
+#[derive(clap::Parser)]
+pub struct PercolationArgs {
+    /// The percolation model
+    #[clap(long, value_enum, default_value_t = Model::Site)]
+    pub model: Model,
+    /// The number of neighbors of each site: 4, 8 or 12
+    #[clap(long, default_value_t = Connectivity::Four)]
+    pub connectivity: Connectivity,
+    /// The occupation probability of each site in mixed percolation
+    #[clap(long, default_value_t = 0.8)]
+    pub p_site: f64,
+    /// Wrap the lattice around, so clusters may also wrap around it
+    #[clap(long)]
+    pub periodic: bool,
+    /// The side length of the lattice
+    #[clap(long, default_value_t = BOX_LEN)]
+    pub box_len: usize,
+    /// The number of lattices populated for each occupation probability
+    #[clap(long, default_value_t = N_ITER)]
+    pub n_iter: usize,
+    /// The smallest occupation probability, in percent
+    #[clap(long, default_value_t = *P_RANGE.start())]
+    pub p_min: usize,
+    /// The largest occupation probability, in percent
+    #[clap(long, default_value_t = *P_RANGE.end())]
+    pub p_max: usize,
+    /// The number of lattices filled one site or bond at a time for the Newman-Ziff estimates
+    #[clap(long, default_value_t = N_SWEEPS)]
+    pub n_sweeps: usize,
+    /// The side lengths of the lattices compared by finite-size scaling
+    #[clap(long, value_delimiter = ',', default_values_t = FSS_SIZES.to_vec())]
+    pub fss_sizes: Vec<usize>,
+    /// The number of Newman-Ziff sweeps of each lattice compared by finite-size scaling
+    #[clap(long, default_value_t = FSS_SWEEPS)]
+    pub fss_sweeps: usize,
+    /// The side length of the lattice whose largest cluster at the threshold has its fractal
+    /// dimension measured
+    #[clap(long, default_value_t = FRACTAL_LEN)]
+    pub fractal_len: usize,
+}
+
+/// Entry-point for project D
+pub fn do_project_d(output: &Output, args: &PercolationArgs) -> Result<(), Box<dyn Error>> {
     log::info!("Doing Project D");
 
-    let mut uni = Uniform701::new();
+    let mut uni = Uniform701::seeded(output.seed());
+    let p_range = args.p_min..=args.p_max;
 
     log::info!(
-        "Generating clusters for p range {}-{}, with {} iterations each.",
-        *P_RANGE.start() as f64 * 0.01,
-        *P_RANGE.end() as f64 * 0.01,
-        N_ITER
+        "Generating clusters for {:?} percolation with {} neighbors, for p range {}-{}, with {} iterations each.",
+        args.model,
+        args.connectivity,
+        *p_range.start() as f64 * 0.01,
+        *p_range.end() as f64 * 0.01,
+        args.n_iter
     );
 
-    let lattices = P_RANGE
+    let lattices = p_range
+        .clone()
         .map(|i| {
             let p = i as f64 * 0.01;
-            (0..N_ITER)
-                .map(|j| Lattice::populate(p, BOX_LEN, &mut uni))
-                .collect::<Vec<Lattice>>()
+            (0..args.n_iter)
+                .map(|_| populate(args, p, args.box_len, &mut uni))
+                .collect::<Result<Vec<Lattice>, Box<dyn Error>>>()
         })
-        .collect::<Vec<Vec<Lattice>>>();
+        .collect::<Result<Vec<Vec<Lattice>>, Box<dyn Error>>>()?;
 
-    let mut clusters = lattices
+    let labels = lattices
         .iter()
         .map(|lattices_for_p| {
             lattices_for_p
                 .iter()
-                .map(|lattice| lattice.create_clusters())
+                .map(|lattice| lattice.label_clusters())
+                .collect::<Vec<ClusterLabels>>()
+        })
+        .collect::<Vec<Vec<ClusterLabels>>>();
+
+    let clusters = labels
+        .iter()
+        .map(|labels_for_p| {
+            labels_for_p
+                .iter()
+                .map(|l| l.to_clusters())
                 .collect::<Vec<Clusters>>()
         })
         .collect::<Vec<Vec<Clusters>>>();
 
-    log::info!("Calculating cluster sizes for p=0.25, p=0.5, and p=7");
-    let sizes = vec![
-        (0.25, calculate_spread(&mut clusters[5])),
-        (0.5, calculate_spread(&mut clusters[30])),
-        (0.7, calculate_spread(&mut clusters[50])),
-    ];
+    let representative = REPRESENTATIVE_P
+        .iter()
+        .filter(|pct| p_range.contains(pct))
+        .map(|&pct| (pct as f64 / 100.0, pct - args.p_min))
+        .collect::<Vec<(f64, usize)>>();
+
+    log::info!("Calculating cluster sizes for p={:?}", REPRESENTATIVE_P);
+    let sizes = representative
+        .iter()
+        .filter_map(|&(p, i)| {
+            let spread = calculate_spread(&clusters[i]);
+            if spread.is_none() {
+                log::warn!("No clusters to size at p={}", p);
+            }
+            spread.map(|spread| (p, spread))
+        })
+        .collect::<Vec<(f64, (f64, f64, f64))>>();
+
+    plot_cluster_sizes(output, "cluster_sizes.png", "Cluster Sizes", &sizes)?;
+
+    // The cluster standing in for the infinite cluster, left out of the finite-cluster statistics
+    let infinite = percolation_criterion(args);
+
+    log::info!("Counting clusters by size for p={:?}", REPRESENTATIVE_P);
+    let cluster_numbers = representative
+        .iter()
+        .map(|&(p, i)| {
+            let mut numbers = ClusterNumbers::new(args.box_len * args.box_len, N_SIZE_BINS)?;
+            for l in &labels[i] {
+                numbers.add(l, infinite);
+            }
+            numbers.print_csv(&output.path(&format!("cluster_numbers_p_{}.csv", p)))?;
+            output.write_seed(&format!("cluster_numbers_p_{}.csv", p))?;
+            Ok((p, numbers.n_s()))
+        })
+        .collect::<Result<Vec<(f64, Vec<(f64, f64)>)>, Box<dyn Error>>>()?;
+    plot_cluster_numbers(
+        output,
+        "cluster_numbers.png",
+        "Cluster numbers n_s(p)",
+        &cluster_numbers,
+    )?;
 
-    plot_cluster_sizes("output/projectD/cluster_sizes.png", "Cluster Sizes", &sizes)?;
+    plot_observable(
+        output,
+        "mean_cluster_size.png",
+        "Mean finite cluster size",
+        &format!("Sites, excluding clusters which {}", infinite),
+        &p_range
+            .clone()
+            .zip(&labels)
+            .map(|(pct, labels_for_p)| {
+                let total = labels_for_p
+                    .iter()
+                    .map(|l| l.mean_size_excluding(infinite))
+                    .sum::<f64>();
+                (pct as f64 * 0.01, total / labels_for_p.len() as f64)
+            })
+            .collect::<Vec<(f64, f64)>>(),
+    )?;
 
     plot_percolating_cluster_rates(
-        "output/projectD/percolating_clusters.png",
+        output,
+        "percolating_clusters.png",
         "Average percolating clusters",
+        args.p_min,
         &clusters
             .iter()
             .map(|p_v| {
@@ -63,45 +217,673 @@ pub fn do_project_d() -> Result<(), Box<dyn Error>> {
             .collect::<Vec<Vec<usize>>>(),
     )?;
 
-    draw_lattice(
-        "output/projectD/lattice_p_0.25.png",
-        "Representative lattice for p=0.25",
-        &lattices[5][0],
+    let criteria = if args.periodic {
+        [&Criterion::SPANNING[..], &Criterion::WRAPPING[..]].concat()
+    } else {
+        Criterion::SPANNING.to_vec()
+    };
+    let probabilities = p_range
+        .clone()
+        .zip(&clusters)
+        .map(|(pct, clusters_for_p)| {
+            (
+                pct as f64 * 0.01,
+                percolation_probabilities(&criteria, clusters_for_p),
+            )
+        })
+        .collect::<Vec<(f64, Vec<f64>)>>();
+    print_percolation_probabilities(
+        output,
+        "percolation_probabilities.csv",
+        &criteria,
+        &probabilities,
     )?;
-    draw_lattice(
-        "output/projectD/lattice_p_0.5.png",
-        "Representative lattice for p=0.5",
-        &lattices[30][0],
+    plot_percolation_probabilities(
+        output,
+        "percolation_probabilities.png",
+        "Percolation probabilities R_L(p)",
+        &criteria,
+        &probabilities,
     )?;
-    draw_lattice(
-        "output/projectD/lattice_p_0.7.png",
-        "Representative lattice for p=0.7",
-        &lattices[50][0],
+
+    let burned = p_range
+        .clone()
+        .zip(lattices.iter().zip(&clusters))
+        .map(|(pct, (lattices_for_p, clusters_for_p))| {
+            let burnings = lattices_for_p
+                .iter()
+                .zip(clusters_for_p)
+                .flat_map(|(lattice, c)| {
+                    c.get_percolating_clusters()
+                        .clusters
+                        .iter()
+                        .filter_map(|cluster| Burning::new(lattice, cluster))
+                        .collect::<Vec<Burning>>()
+                })
+                .collect::<Vec<Burning>>();
+            (pct as f64 * 0.01, burnings)
+        })
+        .collect::<Vec<(f64, Vec<Burning>)>>();
+    print_burning(output, "burning.csv", &burned)?;
+
+    for &(p, i) in &representative {
+        draw_lattice(
+            output,
+            &format!("lattice_p_{}.png", p),
+            &format!("Representative lattice for p={}", p),
+            &lattices[i][0],
+            args.model != Model::Site,
+            &[],
+        )?;
+        let file = format!("cluster_shapes_p_{}.csv", p);
+        ClusterGeometry::new(&lattices[i][0], &labels[i][0]).print_csv(&output.path(&file))?;
+        output.write_seed(&file)?;
+    }
+
+    do_newman_ziff(output, args, &criteria, &mut uni)?;
+
+    let p_c = do_finite_size_scaling(output, args, &mut uni)?;
+
+    calculate_fractal_dimension(output, args, p_c.value, &mut uni)?;
+
+    draw_burning(output, args, p_c.value, &mut uni)?;
+
+    calculate_runtimes(output, args, &mut uni)?;
+
+    Ok(())
+}
+
+/// Populate a lattice for the model and connectivity in `args`
+fn populate(
+    args: &PercolationArgs,
+    p: f64,
+    box_len: usize,
+    uni: &mut Uniform701,
+) -> Result<Lattice, Box<dyn Error>> {
+    match args.model {
+        Model::Site => {
+            Lattice::populate_mixed(p, 1.0, box_len, args.connectivity, args.periodic, uni)
+        }
+        Model::Bond => Lattice::populate_bonds(p, box_len, args.connectivity, args.periodic, uni),
+        Model::Mixed => Lattice::populate_mixed(
+            args.p_site,
+            p,
+            box_len,
+            args.connectivity,
+            args.periodic,
+            uni,
+        ),
+    }
+}
+
+/// Fill lattices one site or bond at a time to find the observables at every p, and estimate the
+/// threshold for each criterion
+fn do_newman_ziff(
+    output: &Output,
+    args: &PercolationArgs,
+    criteria: &[Criterion],
+    uni: &mut Uniform701,
+) -> Result<(), Box<dyn Error>> {
+    log::info!(
+        "Running {} Newman-Ziff sweeps of a {}x{} lattice",
+        args.n_sweeps,
+        args.box_len,
+        args.box_len
+    );
+    let experiment = NewmanZiff::run(
+        sweep(args),
+        args.box_len,
+        args.connectivity,
+        args.periodic,
+        args.n_sweeps,
+        uni,
     )?;
 
-    calculate_runtimes(&mut uni)?;
+    let observables = (0..=N_SMOOTH_P)
+        .map(|k| experiment.at_p(k as f64 / N_SMOOTH_P as f64))
+        .collect::<Vec<Observables>>();
+    print_observables(output, "newman_ziff.csv", criteria, &observables)?;
+    plot_percolation_probabilities(
+        output,
+        "newman_ziff_probabilities.png",
+        "Newman-Ziff R_L(p)",
+        criteria,
+        &observables
+            .iter()
+            .map(|o| (o.p, criteria.iter().map(|&c| o.probability(c)).collect()))
+            .collect::<Vec<(f64, Vec<f64>)>>(),
+    )?;
+    plot_observable(
+        output,
+        "newman_ziff_largest.png",
+        "Largest cluster",
+        "Fraction of the sites",
+        &observables
+            .iter()
+            .map(|o| (o.p, o.largest))
+            .collect::<Vec<(f64, f64)>>(),
+    )?;
+    plot_observable(
+        output,
+        "newman_ziff_mean_size.png",
+        "Mean cluster size",
+        "Sites, excluding the largest cluster",
+        &observables
+            .iter()
+            .map(|o| (o.p, o.mean_size))
+            .collect::<Vec<(f64, f64)>>(),
+    )?;
 
+    let file = "newman_ziff_thresholds.csv";
+    let mut of = File::create(output.path(file))?;
+    of.write_all("Criterion, p_c, Standard Error\n".as_ref())?;
+    for &criterion in criteria {
+        if let Some((p_c, error)) = experiment.threshold(criterion) {
+            log::info!("{}: p_c = {:.5} +/- {:.5}", criterion, p_c, error);
+            of.write_all(format!("{}, {}, {}\n", criterion, p_c, error).as_ref())?;
+        }
+    }
+    output.write_seed(file)?;
     Ok(())
 }
 
-fn calculate_runtimes(uni: &mut Uniform701) -> Result<(), Box<dyn Error>> {
+/// The criterion by which a cluster stands in for the infinite cluster.
+/// Wrapping is the sharper test where the lattice allows it.
+fn percolation_criterion(args: &PercolationArgs) -> Criterion {
+    if args.periodic {
+        Criterion::WrapsEither
+    } else {
+        Criterion::SpansVertically
+    }
+}
+
+/// The Newman-Ziff sweep for the model in `args`
+fn sweep(args: &PercolationArgs) -> Sweep {
+    match args.model {
+        Model::Site => Sweep::Sites { p_bond: 1.0 },
+        Model::Bond => Sweep::Bonds { p_site: 1.0 },
+        Model::Mixed => Sweep::Bonds {
+            p_site: args.p_site,
+        },
+    }
+}
+
+/// Compare lattices of several sizes to estimate the threshold from where their percolation
+/// probabilities cross, then the critical exponents both from how the observables at the
+/// threshold grow with the lattice and from collapsing the curves of every size onto one.
+/// Return the threshold.
+fn do_finite_size_scaling(
+    output: &Output,
+    args: &PercolationArgs,
+    uni: &mut Uniform701,
+) -> Result<Estimate, Box<dyn Error>> {
+    let criterion = percolation_criterion(args);
+    log::info!(
+        "Finite-size scaling of '{}' for lattices of sizes {:?}",
+        criterion,
+        args.fss_sizes
+    );
+    let scaling = FiniteSizeScaling::run(
+        sweep(args),
+        &args.fss_sizes,
+        args.connectivity,
+        args.periodic,
+        args.fss_sweeps,
+        criterion,
+        uni,
+    )?;
+
+    let file = "fss_crossings.csv";
+    let mut of = File::create(output.path(file))?;
+    of.write_all("L1, L2, Crossing\n".as_ref())?;
+    for (small, large, p) in scaling.crossings() {
+        of.write_all(format!("{}, {}, {}\n", small, large, p).as_ref())?;
+    }
+    output.write_seed(file)?;
+
+    let p_c = scaling.threshold()?;
+    log::info!("p_c from the crossings = {}", p_c);
+    let fitted = scaling.fit_exponents(p_c.value)?;
+    let collapsed = scaling.fit_collapse(p_c.value, &fitted, COLLAPSE_X_MAX)?;
+
+    let file = "fss_exponents.csv";
+    let mut of = File::create(output.path(file))?;
+    of.write_all("Method, Exponent, Value, Error, Exact (2D)\n".as_ref())?;
+    of.write_all(format!("Crossing, p_c, {}, {}, \n", p_c.value, p_c.error).as_ref())?;
+    for (method, exponents) in [("Log-log fit", &fitted), ("Data collapse", &collapsed)] {
+        let estimates = [
+            exponents.nu,
+            exponents.beta_over_nu,
+            exponents.gamma_over_nu,
+        ];
+        for (&(name, exact), estimate) in Exponents::EXACT_2D.iter().zip(&estimates) {
+            log::info!("{}: {} = {} (exactly {:.4})", method, name, estimate, exact);
+            of.write_all(
+                format!(
+                    "{}, {}, {}, {}, {}\n",
+                    method, name, estimate.value, estimate.error, exact
+                )
+                .as_ref(),
+            )?;
+        }
+    }
+    output.write_seed(file)?;
+
+    // The unscaled curves around the threshold, which cross there
+    let window = 0.1;
+    let around = (0..=N_SMOOTH_P)
+        .map(|i| p_c.value - window + 2.0 * window * i as f64 / N_SMOOTH_P as f64)
+        .map(|p| (p, scaling.at_p(p)))
+        .collect::<Vec<(f64, Vec<(usize, Observables)>)>>();
+    let curves = scaling
+        .sizes()
+        .into_iter()
+        .enumerate()
+        .map(|(k, l)| {
+            let points = around
+                .iter()
+                .map(|(p, by_size)| (*p, by_size[k].1.probability(criterion)))
+                .collect();
+            (l, points)
+        })
+        .collect::<Vec<(usize, Vec<(f64, f64)>)>>();
+    plot_by_size(
+        output,
+        "fss_probabilities.png",
+        &format!("{} by lattice size", criterion),
+        "p-value",
+        "R_L(p)",
+        &curves,
+    )?;
+
+    for (scaled, exponent, file, y_desc) in [
+        (
+            Scaled::Probability,
+            0.0,
+            "collapse_probability.png",
+            "R_L(p)".to_string(),
+        ),
+        (
+            Scaled::Largest,
+            collapsed.beta_over_nu.value,
+            "collapse_largest.png",
+            format!("P L^{:.3}", collapsed.beta_over_nu.value),
+        ),
+        (
+            Scaled::MeanSize,
+            collapsed.gamma_over_nu.value,
+            "collapse_mean_size.png",
+            format!("S L^-{:.3}", collapsed.gamma_over_nu.value),
+        ),
+    ] {
+        plot_by_size(
+            output,
+            file,
+            &format!("{}, collapsed", scaled),
+            &format!("(p - p_c) L^(1/{:.3})", collapsed.nu.value),
+            &y_desc,
+            &scaling.collapse(
+                scaled,
+                p_c.value,
+                collapsed.nu.value,
+                exponent,
+                COLLAPSE_X_MAX,
+            ),
+        )?;
+    }
+    Ok(p_c)
+}
+
+/// Measure the fractal dimension of the largest cluster of a lattice populated at the threshold,
+/// both from its mass within growing radii and by counting the boxes which cover it
+fn calculate_fractal_dimension(
+    output: &Output,
+    args: &PercolationArgs,
+    p_c: f64,
+    uni: &mut Uniform701,
+) -> Result<(), Box<dyn Error>> {
+    log::info!(
+        "Measuring the fractal dimension of the largest cluster of a {}x{} lattice at p={:.4}",
+        args.fractal_len,
+        args.fractal_len,
+        p_c
+    );
+    let lattice = populate(args, p_c, args.fractal_len, uni)?;
+    let labels = lattice.label_clusters();
+    let largest = labels
+        .largest()
+        .ok_or("The lattice at the threshold is empty")?;
+    let geometry = ClusterGeometry::new(&lattice, &labels);
+    let fits = [
+        ("Mass-radius", geometry.mass_radius(largest)?),
+        ("Box counting", geometry.box_counting(largest)?),
+    ];
+
+    let file = "fractal_dimension.csv";
+    let mut of = File::create(output.path(file))?;
+    of.write_all("Method, Dimension, Error, Exact (2D)\n".as_ref())?;
+    for (method, fit) in &fits {
+        log::info!(
+            "{}: D = {:.4} +/- {:.4} (exactly {:.4})",
+            method,
+            fit.dimension(),
+            fit.fit.slope_error,
+            EXACT_FRACTAL_DIMENSION
+        );
+        of.write_all(
+            format!(
+                "{}, {}, {}, {}\n",
+                method,
+                fit.dimension(),
+                fit.fit.slope_error,
+                EXACT_FRACTAL_DIMENSION
+            )
+            .as_ref(),
+        )?;
+    }
+    output.write_seed(file)?;
+
+    plot_fractal_fits(
+        output,
+        "fractal_dimension.png",
+        &format!("Fractal dimension, {} sites", labels.sizes()[largest]),
+        &fits,
+    )
+}
+
+/// Write the mean chemical distance, backbone, dangling ends and red bonds of the percolating
+/// clusters burned at each p
+fn print_burning(
+    output: &Output,
+    file: &str,
+    burned: &[(f64, Vec<Burning>)],
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Writing burned percolating clusters");
+
+    let mut of = File::create(output.path(file))?;
+    of.write_all(
+        "p, Clusters Burned, Chemical Distance, Backbone Sites, Dangling Sites, Red Bonds\n"
+            .as_ref(),
+    )?;
+    for (p, burnings) in burned {
+        let mean = |f: &dyn Fn(&Burning) -> usize| {
+            burnings.iter().map(f).sum::<usize>() as f64 / burnings.len().max(1) as f64
+        };
+        of.write_all(
+            format!(
+                "{:.2}, {}, {}, {}, {}, {}\n",
+                p,
+                burnings.len(),
+                mean(&|b| b.chemical_distance()),
+                mean(&|b| b.backbone_size()),
+                mean(&|b| b.dangling_size()),
+                mean(&|b| b.red_bonds().len())
+            )
+            .as_ref(),
+        )?;
+    }
+    output.write_seed(file)?;
+    Ok(())
+}
+
+/// Burn the percolating cluster of a lattice populated at the threshold, and draw its backbone,
+/// dangling ends, shortest path and red bonds
+fn draw_burning(
+    output: &Output,
+    args: &PercolationArgs,
+    p_c: f64,
+    uni: &mut Uniform701,
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Burning a percolating cluster at p={:.4}", p_c);
+    for _ in 0..N_BURN_TRIES {
+        let lattice = populate(args, p_c, args.box_len, uni)?;
+        let percolating = lattice.create_clusters().get_percolating_clusters();
+        let burning = match percolating
+            .clusters
+            .iter()
+            .find_map(|cluster| Burning::new(&lattice, cluster))
+        {
+            Some(burning) => burning,
+            None => continue,
+        };
+        log::info!(
+            "Chemical distance {}, backbone of {} sites, {} sites in dangling ends, {} red bonds",
+            burning.chemical_distance(),
+            burning.backbone_size(),
+            burning.dangling_size(),
+            burning.red_bonds().len()
+        );
+        return draw_lattice(
+            output,
+            "burning.png",
+            &format!("Burned cluster for p={:.4}", p_c),
+            &lattice,
+            args.model != Model::Site,
+            &[
+                ("Dangling ends", burning.dangling_ends(), &CYAN),
+                ("Backbone", burning.backbone(), &BLUE),
+                ("Shortest path", &burning.shortest_path_mask(), &MAGENTA),
+                ("Red bonds", &burning.red_sites(), &RED),
+            ],
+        );
+    }
+    log::warn!(
+        "No percolating cluster in {} lattices at p={:.4}",
+        N_BURN_TRIES,
+        p_c
+    );
+    Ok(())
+}
+
+/// Plot the points of each fractal dimension fit on a log-log scale with the fitted line
+fn plot_fractal_fits(
+    output: &Output,
+    file: &str,
+    caption: &str,
+    fits: &[(&str, FractalFit)],
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Plotting fractal dimension fits");
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let points = fits.iter().flat_map(|(_, f)| f.points.iter());
+    let (x_min, x_max, y_max) = points.fold(
+        (f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        |(x_min, x_max, y_max), &(x, y)| (x_min.min(x), x_max.max(x), y_max.max(y)),
+    );
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(32)
+        .y_label_area_size(46)
+        .build_cartesian_2d(x_min - 0.5..x_max + 0.5, 0.0..y_max * 1.05)?;
+    chart
+        .configure_mesh()
+        .x_desc("ln(length scale)")
+        .y_desc("ln(amount of the cluster)")
+        .draw()?;
+
+    for (k, (method, f)) in fits.iter().enumerate() {
+        let color = COLORS[k % COLORS.len()];
+        chart
+            .draw_series(
+                f.points
+                    .iter()
+                    .map(|&(x, y)| Circle::new((x, y), 4, color.filled())),
+            )?
+            .label(format!("{}: D = {:.3}", method, f.dimension()))
+            .legend(move |(x, y)| Circle::new((x + 10, y), 4, color.filled()));
+        let (first, last) = (f.points[0].0, f.points[f.points.len() - 1].0);
+        chart.draw_series(LineSeries::new(
+            [first, last]
+                .iter()
+                .map(|&x| (x, f.fit.intercept + f.fit.slope * x)),
+            color.stroke_width(2),
+        ))?;
+    }
+
+    chart
+        .configure_series_labels()
+        .border_style(&BLACK)
+        .background_style(&WHITE.mix(0.8))
+        .draw()?;
+
+    output.write_seed(file)?;
+    Ok(())
+}
+
+fn plot_by_size(
+    output: &Output,
+    file: &str,
+    caption: &str,
+    x_desc: &str,
+    y_desc: &str,
+    curves: &[(usize, Vec<(f64, f64)>)],
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Plotting {}", caption.to_lowercase());
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let points = || curves.iter().flat_map(|(_, c)| c.iter());
+    let x_min = points().map(|&(x, _)| x).fold(f64::INFINITY, f64::min);
+    let x_max = points().map(|&(x, _)| x).fold(f64::NEG_INFINITY, f64::max);
+    let y_max = points().map(|&(_, y)| y).fold(0.0, f64::max) * 1.05;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(32)
+        .y_label_area_size(64)
+        .build_cartesian_2d(x_min..x_max, 0.0..y_max.max(f64::MIN_POSITIVE))?;
+    chart
+        .configure_mesh()
+        .x_desc(x_desc)
+        .y_desc(y_desc)
+        .draw()?;
+
+    for ((l, curve), &color) in curves.iter().zip(COLORS.iter().cycle()) {
+        chart
+            .draw_series(LineSeries::new(
+                curve.iter().cloned(),
+                color.stroke_width(2),
+            ))?
+            .label(format!("L = {}", l))
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+            });
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .border_style(&BLACK)
+        .background_style(&WHITE.mix(0.8))
+        .draw()?;
+
+    output.write_seed(file)?;
+    Ok(())
+}
+
+fn print_observables(
+    output: &Output,
+    file: &str,
+    criteria: &[Criterion],
+    observables: &[Observables],
+) -> Result<(), Box<dyn Error>> {
+    let mut of = File::create(output.path(file))?;
+    of.write_all(
+        format!(
+            "p, Largest Cluster, Mean Cluster Size, {}\n",
+            criteria
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+        .as_ref(),
+    )?;
+    for o in observables {
+        of.write_all(
+            format!(
+                "{}, {}, {}, {}\n",
+                o.p,
+                o.largest,
+                o.mean_size,
+                criteria
+                    .iter()
+                    .map(|&c| o.probability(c).to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .as_ref(),
+        )?;
+    }
+    output.write_seed(file)?;
+    Ok(())
+}
+
+fn plot_observable(
+    output: &Output,
+    file: &str,
+    caption: &str,
+    y_desc: &str,
+    to_plot: &[(f64, f64)],
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Plotting {}", caption.to_lowercase());
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let y_max = to_plot.iter().map(|&(_, y)| y).fold(0.0, f64::max) * 1.05;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(32)
+        .y_label_area_size(64)
+        .build_cartesian_2d(0.0..1.0, 0.0..y_max.max(f64::MIN_POSITIVE))?;
+    chart
+        .configure_mesh()
+        .x_desc("p-value")
+        .y_desc(y_desc)
+        .draw()?;
+
+    chart.draw_series(LineSeries::new(
+        to_plot.iter().cloned(),
+        BLUE.stroke_width(2),
+    ))?;
+
+    output.write_seed(file)?;
+    Ok(())
+}
+
+fn calculate_runtimes(
+    output: &Output,
+    args: &PercolationArgs,
+    uni: &mut Uniform701,
+) -> Result<(), Box<dyn Error>> {
     log::info!("Calculating runtimes");
 
-    let l_vals = (10..=50).step_by(5);
+    let l_vals = RUNTIME_L_RANGE.step_by(100);
 
     let mut runtimes = vec![];
 
     for l in l_vals {
         let now = Instant::now();
-        let average_pc = (0..10)
-            .map(|_| {
-                let lattice = Lattice::populate(0.6, l, uni);
-                let clusters = lattice.create_clusters();
-                let pc = clusters.get_percolating_clusters();
-                pc.clusters.len()
-            })
-            .sum::<usize>() as f64
-            / 10.0;
+        let mut n_pc = 0;
+        for _ in 0..10 {
+            let lattice = populate(args, 0.6, l, uni)?;
+            let clusters = lattice.create_clusters();
+            let pc = clusters.get_percolating_clusters();
+            n_pc += pc.clusters.len();
+        }
+        let average_pc = n_pc as f64 / 10.0;
         log::trace!("Average num pc for l={} is {}", l, average_pc);
         let elapsed = now.elapsed().as_nanos();
         runtimes.push((l, elapsed));
@@ -111,7 +893,8 @@ fn calculate_runtimes(uni: &mut Uniform701) -> Result<(), Box<dyn Error>> {
 
     let y_max = runtimes.iter().map(|&(_, v)| v).max().unwrap();
 
-    let root = BitMapBackend::new("output/projectD/runtimes.png", (800, 600)).into_drawing_area();
+    let path = output.path("runtimes.png");
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -122,7 +905,7 @@ fn calculate_runtimes(uni: &mut Uniform701) -> Result<(), Box<dyn Error>> {
         .margin(32)
         .x_label_area_size(32)
         .y_label_area_size(46)
-        .build_cartesian_2d(10_usize..50_usize, 0..y_max)?;
+        .build_cartesian_2d(*RUNTIME_L_RANGE.start()..*RUNTIME_L_RANGE.end(), 0..y_max)?;
     chart
         .configure_mesh()
         // .disable_mesh()
@@ -132,40 +915,148 @@ fn calculate_runtimes(uni: &mut Uniform701) -> Result<(), Box<dyn Error>> {
         .draw()?;
 
     chart.draw_series(LineSeries::new(
-        runtimes.into_iter(),
+        runtimes,
         // .map(|&(l, t)| (SegmentValue::CenterOf(l), t)),
         BLUE.stroke_width(2),
     ))?;
 
+    output.write_seed("runtimes.png")?;
     Ok(())
 }
 
-fn calculate_spread(data: &mut [Clusters]) -> (f64, f64, f64) {
-    let n = data.len();
-    let mut sizes: Vec<usize> = data
+/// The fraction of the lattices in which some cluster meets each criterion
+fn percolation_probabilities(criteria: &[Criterion], clusters: &[Clusters]) -> Vec<f64> {
+    let crossings = clusters
         .iter()
-        .flat_map(|c| {
-            c.clusters
+        .map(|c| {
+            c.percolation()
                 .iter()
-                .map(|cluster| cluster.len())
-                .collect::<Vec<usize>>()
+                .fold(Percolation::default(), |all, &crossing| all | crossing)
+        })
+        .collect::<Vec<Percolation>>();
+    criteria
+        .iter()
+        .map(|criterion| {
+            crossings.iter().filter(|c| criterion.is_met_by(c)).count() as f64
+                / crossings.len() as f64
         })
+        .collect()
+}
+
+fn print_percolation_probabilities(
+    output: &Output,
+    file: &str,
+    criteria: &[Criterion],
+    probabilities: &[(f64, Vec<f64>)],
+) -> Result<(), Box<dyn Error>> {
+    let mut of = File::create(output.path(file))?;
+    of.write_all(
+        format!(
+            "p, {}\n",
+            criteria
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+        .as_ref(),
+    )?;
+    for (p, r) in probabilities {
+        of.write_all(
+            format!(
+                "{:.2}, {}\n",
+                p,
+                r.iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+            .as_ref(),
+        )?;
+    }
+    output.write_seed(file)?;
+    Ok(())
+}
+
+fn plot_percolation_probabilities(
+    output: &Output,
+    file: &str,
+    caption: &str,
+    criteria: &[Criterion],
+    probabilities: &[(f64, Vec<f64>)],
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Plotting percolation probabilities");
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(32)
+        .y_label_area_size(46)
+        .build_cartesian_2d(0.0..1.0, 0.0..1.0)?;
+    chart
+        .configure_mesh()
+        .x_desc("p-value")
+        .y_desc("R_L(p)")
+        .draw()?;
+
+    // The spanning and wrapping criteria each take the colors in turn, marking the wrapping
+    // criteria with points
+    for (k, criterion) in criteria.iter().enumerate() {
+        let (color, marked) = match Criterion::SPANNING.iter().position(|c| c == criterion) {
+            Some(i) => (COLORS[i], false),
+            None => (COLORS[k - Criterion::SPANNING.len()], true),
+        };
+        chart
+            .draw_series(
+                LineSeries::new(
+                    probabilities.iter().map(|(p, r)| (*p, r[k])),
+                    color.stroke_width(2),
+                )
+                .point_size(if marked { 3 } else { 0 }),
+            )?
+            .label(criterion.to_string())
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+            });
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .border_style(&BLACK)
+        .background_style(&WHITE.mix(0.8))
+        .draw()?;
+
+    output.write_seed(file)?;
+    Ok(())
+}
+
+/// The smallest, mean and largest sizes of the clusters, or `None` if there are none
+fn calculate_spread(data: &[Clusters]) -> Option<(f64, f64, f64)> {
+    let sizes: Vec<usize> = data
+        .iter()
+        .flat_map(|c| c.clusters.iter().map(|cluster| cluster.len()))
         .collect();
-    sizes.sort_unstable();
-    let min = sizes[0] as f64;
-    let max = sizes[sizes.len() - 1] as f64;
-    let avg = sizes.iter().sum::<usize>() as f64 / (sizes.len() - 1) as f64;
-    (min, avg, max)
+    let min = *sizes.iter().min()? as f64;
+    let max = *sizes.iter().max()? as f64;
+    let avg = sizes.iter().sum::<usize>() as f64 / sizes.len() as f64;
+    Some((min, avg, max))
 }
 
 fn plot_cluster_sizes(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
     sizes: &[(f64, (f64, f64, f64))],
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting cluster size distribution");
 
-    let root = BitMapBackend::new(path, (800, 600)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let x_range = sizes.iter().map(|v| v.0).collect::<Vec<f64>>();
@@ -195,17 +1086,81 @@ fn plot_cluster_sizes(
         )
     }))?;
 
+    output.write_seed(file)?;
+    Ok(())
+}
+
+/// Plot n_s against s on a log-log scale for each occupation probability
+fn plot_cluster_numbers(
+    output: &Output,
+    file: &str,
+    caption: &str,
+    to_plot: &[(f64, Vec<(f64, f64)>)],
+) -> Result<(), Box<dyn Error>> {
+    log::info!("Plotting cluster numbers");
+
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let nonzero = to_plot
+        .iter()
+        .flat_map(|(_, n_s)| n_s.iter().filter(|&&(_, n)| n > 0.0))
+        .collect::<Vec<&(f64, f64)>>();
+    let s_max = nonzero.iter().map(|&&(s, _)| s).fold(2.0, f64::max);
+    let n_min = nonzero.iter().map(|&&(_, n)| n).fold(1.0, f64::min);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(caption, ("sans-serif", 50).into_font())
+        .margin(32)
+        .x_label_area_size(32)
+        .y_label_area_size(64)
+        .build_cartesian_2d((1.0..s_max).log_scale(), (n_min..1.0).log_scale())?;
+    chart
+        .configure_mesh()
+        .x_label_formatter(&|x| format!("{}", x))
+        .y_label_formatter(&|y| format!("{:.0e}", y))
+        .x_desc("Cluster size s")
+        .y_desc("n_s")
+        .draw()?;
+
+    for (k, (p, n_s)) in to_plot.iter().enumerate() {
+        let color = COLORS[k % COLORS.len()];
+        chart
+            .draw_series(
+                LineSeries::new(
+                    n_s.iter().filter(|&&(_, n)| n > 0.0).cloned(),
+                    color.stroke_width(2),
+                )
+                .point_size(3),
+            )?
+            .label(format!("p={}", p))
+            .legend(move |(x, y)| {
+                PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2))
+            });
+    }
+
+    chart
+        .configure_series_labels()
+        .border_style(&BLACK)
+        .background_style(&WHITE.mix(0.8))
+        .draw()?;
+
+    output.write_seed(file)?;
     Ok(())
 }
 
 fn plot_percolating_cluster_rates(
-    path: &str,
+    output: &Output,
+    file: &str,
     caption: &str,
+    p_min: usize,
     to_plot: &[Vec<usize>],
 ) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting average number of percolating clusters");
 
-    let root = BitMapBackend::new(path, (800, 600)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 600))).into_drawing_area();
     root.fill(&WHITE)?;
 
     let mut chart = ChartBuilder::on(&root)
@@ -225,46 +1180,103 @@ fn plot_percolating_cluster_rates(
         to_plot.iter().enumerate().map(|(i, vals)| {
             let n_vals = vals.len() as f64;
             (
-                (i + 20) as f64 * 0.01,
+                (i + p_min) as f64 * 0.01,
                 vals.iter().sum::<usize>() as f64 / n_vals,
             )
         }),
         BLUE.stroke_width(2),
     ))?;
 
+    output.write_seed(file)?;
     Ok(())
 }
 
-fn draw_lattice(path: &str, caption: &str, lattice: &Lattice) -> Result<(), Box<dyn Error>> {
+/// Draw the occupied sites of the lattice, then the sites of each named mask in its color over
+/// them, in order
+fn draw_lattice(
+    output: &Output,
+    file: &str,
+    caption: &str,
+    lattice: &Lattice,
+    show_bonds: bool,
+    highlights: &[(&str, &[Vec<bool>], &RGBColor)],
+) -> Result<(), Box<dyn Error>> {
     log::info!("Plotting lattice: '{}'", caption);
 
-    let root = BitMapBackend::new(path, (800, 800)).into_drawing_area();
+    let path = output.path(file);
+    let root = BitMapBackend::new(&path, output.image_size((800, 800))).into_drawing_area();
     root.fill(&WHITE)?;
 
+    let len = lattice.grid.len() as f64;
     let mut chart = ChartBuilder::on(&root)
         .caption(caption, ("sans-serif", 50).into_font())
         .margin(32)
         .x_label_area_size(32)
         .y_label_area_size(32)
-        .build_cartesian_2d(0..BOX_LEN, 0..BOX_LEN)?;
+        .build_cartesian_2d(0.0..len, 0.0..len)?;
     chart
         .configure_mesh()
         // .disable_mesh()
         .draw()?;
 
-    chart.draw_series(
-        lattice
-            .grid
-            .iter()
-            .enumerate()
-            .flat_map(|(i, row)| {
-                row.iter()
-                    .enumerate()
-                    .map(move |(j, &occupied)| if occupied { Some((i, j)) } else { None })
-            })
-            .filter(Option::is_some)
-            .map(Option::unwrap)
-            .map(|(i, j)| Rectangle::new([(i, j), (i + 1, j + 1)], BLACK.filled())),
-    )?;
+    let occupied = lattice
+        .grid
+        .iter()
+        .enumerate()
+        .flat_map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(j, &occupied)| if occupied { Some((i, j)) } else { None })
+        })
+        .flatten();
+
+    if show_bonds {
+        // Draw each site as a dot, joined to its neighbors by the open bonds
+        let center = |(i, j): (usize, usize)| (i as f64 + 0.5, j as f64 + 0.5);
+        chart.draw_series(
+            occupied
+                .clone()
+                .flat_map(|site| {
+                    (0..lattice.bonds.len())
+                        .filter_map(move |k| lattice.joined(site, k).map(|to| (site, to)))
+                })
+                .map(|(from, to)| PathElement::new(vec![center(from), center(to)], &BLUE)),
+        )?;
+        chart.draw_series(occupied.map(|site| Circle::new(center(site), 2, BLACK.filled())))?;
+    } else {
+        chart.draw_series(occupied.map(|(i, j)| {
+            let (x, y) = (i as f64, j as f64);
+            Rectangle::new([(x, y), (x + 1.0, y + 1.0)], BLACK.filled())
+        }))?;
+    }
+
+    for &(name, mask, color) in highlights {
+        let masked = mask.iter().enumerate().flat_map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(_, &masked)| masked)
+                .map(move |(j, _)| (i as f64, j as f64))
+        });
+        if show_bonds {
+            chart.draw_series(
+                masked.map(|(x, y)| Circle::new((x + 0.5, y + 0.5), 3, color.filled())),
+            )
+        } else {
+            chart.draw_series(
+                masked.map(|(x, y)| Rectangle::new([(x, y), (x + 1.0, y + 1.0)], color.filled())),
+            )
+        }?
+        .label(name)
+        .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.filled()));
+    }
+    if !highlights.is_empty() {
+        chart
+            .configure_series_labels()
+            .border_style(&BLACK)
+            .background_style(&WHITE.mix(0.8))
+            .draw()?;
+    }
+
+    output.write_seed(file)?;
     Ok(())
 }
@@ -0,0 +1,410 @@
+//! The burning algorithm, which sets fire to the top row of a cluster joining the top and the
+//! bottom of a lattice to find the shortest path across it, the backbone which would carry a
+//! current across it, the dangling ends hanging off the backbone and the red bonds whose cutting
+//! would break the cluster in two
+
+use crate::data::lattice::{Lattice, Site};
+use std::collections::VecDeque;
+
+/// The parts of a cluster joining the top and the bottom rows of a lattice, each as a mask over
+/// the sites of the lattice indexed by row and column.
+/// The top and bottom rows act as electrodes: a current enters the cluster through its sites in
+/// the top row and leaves through its sites in the bottom row, and no current runs along either
+/// row.  On a periodic lattice the lattice is cut open between its bottom and top rows, so the
+/// paths run down the lattice, though they may wrap sideways.
+pub struct Burning {
+    /// The number of bonds on the shortest path from the top row to the bottom row
+    chemical_distance: usize,
+    /// The number of bonds on the shortest path from the top row to each site
+    burn_times: Vec<Vec<Option<usize>>>,
+    shortest_path: Vec<Site>,
+    backbone: Vec<Vec<bool>>,
+    dangling_ends: Vec<Vec<bool>>,
+    red_bonds: Vec<(Site, Site)>,
+}
+
+impl Burning {
+    /// Burn the cluster with the sites, spreading the fire one bond per step from its sites in
+    /// the top row.  Return `None` if the fire never reaches the bottom row, or if the lattice
+    /// has a single row, so that there is nothing to cross.
+    pub fn new(lattice: &Lattice, cluster: &[Site]) -> Option<Self> {
+        let n = lattice.box_len;
+        if n < 2 {
+            return None;
+        }
+        let mut index = vec![vec![None; n]; n];
+        for (v, &(i, j)) in cluster.iter().enumerate() {
+            index[i][j] = Some(v);
+        }
+
+        // Each bond once, by the offset back from the later of its sites
+        let mut bonds = Vec::new();
+        for (v, &(i, j)) in cluster.iter().enumerate() {
+            for (k, &(di, _)) in lattice.connectivity.bonds().iter().enumerate() {
+                let across = !(0..n as isize).contains(&(i as isize + di));
+                if let Some((a, b)) = lattice.joined((i, j), k).filter(|_| !across) {
+                    if let Some(w) = index[a][b] {
+                        bonds.push((v, w));
+                    }
+                }
+            }
+        }
+
+        let (steps, reached_by) = burn(
+            &adjacency(cluster.len(), &bonds),
+            (0..cluster.len()).filter(|&v| cluster[v].0 == 0),
+        );
+        let mut burn_times = vec![vec![None; n]; n];
+        for (v, &(i, j)) in cluster.iter().enumerate() {
+            burn_times[i][j] = steps[v];
+        }
+        let last = (0..cluster.len())
+            .filter(|&v| cluster[v].0 == n - 1)
+            .filter(|&v| steps[v].is_some())
+            .min_by_key(|&v| steps[v])?;
+        let chemical_distance = steps[last]?;
+        let mut shortest_path = vec![cluster[last]];
+        let mut v = last;
+        while let Some(from) = reached_by[v] {
+            shortest_path.push(cluster[from]);
+            v = from;
+        }
+        shortest_path.reverse();
+
+        // Join the top row into a source and the bottom row into a sink, leaving out the bonds
+        // along either row.  Closing the circuit from the sink back to the source then puts
+        // every bond which some path from the source to the sink passes along in one
+        // biconnected block with the closing bond.
+        let (source, sink) = (cluster.len(), cluster.len() + 1);
+        let vertex = |v: usize| match cluster[v].0 {
+            0 => source,
+            i if i == n - 1 => sink,
+            _ => v,
+        };
+        let mut edges = bonds
+            .iter()
+            .map(|&(v, w)| (vertex(v), vertex(w)))
+            .collect::<Vec<(usize, usize)>>();
+        let circuit = edges.len();
+        edges.push((source, sink));
+        let circuit_adjacency = adjacency(cluster.len() + 2, &edges);
+
+        let in_backbone = blocks(&circuit_adjacency, source, |_| true)
+            .into_iter()
+            .find(|block| block.contains(&circuit))
+            .unwrap_or_default();
+        let mut backbone_bonds = vec![false; edges.len()];
+        let mut backbone = vec![vec![false; n]; n];
+        for &e in in_backbone.iter().filter(|&&e| e != circuit) {
+            backbone_bonds[e] = true;
+            let (v, w) = bonds[e];
+            for &(i, j) in &[cluster[v], cluster[w]] {
+                backbone[i][j] = true;
+            }
+        }
+        let mut dangling_ends = vec![vec![false; n]; n];
+        for &(i, j) in cluster {
+            dangling_ends[i][j] = !backbone[i][j];
+        }
+
+        // Without the closing bond, a bond of the backbone alone in its block is a bridge,
+        // which every path across must cross
+        let red_bonds = blocks(&circuit_adjacency, source, |e| backbone_bonds[e])
+            .into_iter()
+            .filter(|block| block.len() == 1)
+            .map(|block| bonds[block[0]])
+            .map(|(v, w)| (cluster[v], cluster[w]))
+            .collect();
+
+        Some(Self {
+            chemical_distance,
+            burn_times,
+            shortest_path,
+            backbone,
+            dangling_ends,
+            red_bonds,
+        })
+    }
+
+    /// The number of bonds on the shortest path within the cluster from the top row to the
+    /// bottom row
+    pub fn chemical_distance(&self) -> usize {
+        self.chemical_distance
+    }
+
+    /// The number of bonds on the shortest path within the cluster from the top row to the site,
+    /// or `None` if the site is not in the cluster
+    pub fn burn_time(&self, (i, j): Site) -> Option<usize> {
+        self.burn_times[i][j]
+    }
+
+    /// The sites of one of the shortest paths, from the top row to the bottom row
+    pub fn shortest_path(&self) -> &[Site] {
+        &self.shortest_path
+    }
+
+    /// The sites of the shortest path, as a mask
+    pub fn shortest_path_mask(&self) -> Vec<Vec<bool>> {
+        self.mask(self.shortest_path.iter().cloned())
+    }
+
+    /// The sites which some path from the top row to the bottom row passes through without
+    /// visiting any site twice, i.e., the sites which would carry a current across the cluster
+    pub fn backbone(&self) -> &[Vec<bool>] {
+        &self.backbone
+    }
+
+    /// The sites of the cluster which are not on the backbone, and so carry no current
+    pub fn dangling_ends(&self) -> &[Vec<bool>] {
+        &self.dangling_ends
+    }
+
+    /// The bonds which every path from the top row to the bottom row crosses, so that cutting
+    /// any one of them disconnects the top from the bottom
+    pub fn red_bonds(&self) -> &[(Site, Site)] {
+        &self.red_bonds
+    }
+
+    /// The sites at either end of a red bond, as a mask
+    pub fn red_sites(&self) -> Vec<Vec<bool>> {
+        self.mask(self.red_bonds.iter().flat_map(|&(a, b)| [a, b]))
+    }
+
+    /// The number of sites in the backbone
+    pub fn backbone_size(&self) -> usize {
+        count(&self.backbone)
+    }
+
+    /// The number of sites in the dangling ends
+    pub fn dangling_size(&self) -> usize {
+        count(&self.dangling_ends)
+    }
+
+    fn mask(&self, sites: impl Iterator<Item = Site>) -> Vec<Vec<bool>> {
+        let n = self.backbone.len();
+        let mut mask = vec![vec![false; n]; n];
+        for (i, j) in sites {
+            mask[i][j] = true;
+        }
+        mask
+    }
+}
+
+fn count(mask: &[Vec<bool>]) -> usize {
+    mask.iter().flatten().filter(|&&b| b).count()
+}
+
+/// The vertices joined to each of `n` vertices, with the index of the edge joining them.
+/// Edges from a vertex to itself are left out, since no path needs them.
+fn adjacency(n: usize, edges: &[(usize, usize)]) -> Vec<Vec<(usize, usize)>> {
+    let mut adjacency = vec![Vec::new(); n];
+    for (e, &(v, w)) in edges.iter().enumerate().filter(|(_, (v, w))| v != w) {
+        adjacency[v].push((w, e));
+        adjacency[w].push((v, e));
+    }
+    adjacency
+}
+
+/// Spread the fire from the vertices set alight one edge per step.
+/// Return the step at which each vertex burns and the vertex the fire reached it from.
+fn burn(
+    adjacency: &[Vec<(usize, usize)>],
+    alight: impl Iterator<Item = usize>,
+) -> (Vec<Option<usize>>, Vec<Option<usize>>) {
+    let mut steps = vec![None; adjacency.len()];
+    let mut reached_by = vec![None; adjacency.len()];
+    let mut burning = VecDeque::new();
+    for v in alight {
+        steps[v] = Some(0);
+        burning.push_back(v);
+    }
+    while let Some(v) = burning.pop_front() {
+        let step = steps[v].map(|s| s + 1);
+        for &(w, _) in &adjacency[v] {
+            if steps[w].is_none() {
+                steps[w] = step;
+                reached_by[w] = Some(v);
+                burning.push_back(w);
+            }
+        }
+    }
+    (steps, reached_by)
+}
+
+/// Split the edges reached from the start through the kept edges into biconnected blocks, the
+/// largest sets of edges in which any two lie on a cycle, each given as a list of edges
+/// (Hopcroft–Tarjan).  The depth-first search keeps its own stack, so large clusters do not
+/// overflow the call stack.
+fn blocks(
+    adjacency: &[Vec<(usize, usize)>],
+    start: usize,
+    keep: impl Fn(usize) -> bool,
+) -> Vec<Vec<usize>> {
+    let unvisited = usize::MAX;
+    // The order in which each vertex is found, and the earliest vertex reached from its subtree
+    let mut found = vec![unvisited; adjacency.len()];
+    let mut low = vec![unvisited; adjacency.len()];
+    let mut edges = Vec::new();
+    let mut blocks = Vec::new();
+    // Each frame is a vertex, the edge by which it was found and the next of its edges to follow
+    let mut frames = vec![(start, unvisited, 0)];
+    found[start] = 0;
+    low[start] = 0;
+    let mut time = 1;
+    while let Some(frame) = frames.last_mut() {
+        let (v, by, next) = *frame;
+        if let Some(&(w, e)) = adjacency[v].get(next) {
+            frame.2 += 1;
+            if e == by || !keep(e) {
+                continue;
+            }
+            if found[w] == unvisited {
+                found[w] = time;
+                low[w] = time;
+                time += 1;
+                edges.push(e);
+                frames.push((w, e, 0));
+            } else if found[w] < found[v] {
+                edges.push(e);
+                low[v] = low[v].min(found[w]);
+            }
+        } else {
+            frames.pop();
+            if let Some(&(u, _, _)) = frames.last() {
+                low[u] = low[u].min(low[v]);
+                // Nothing below v reaches above u, so u cuts off the block of edges from v on
+                if low[v] >= found[u] {
+                    let first = edges.iter().rposition(|&f| f == by).unwrap_or(0);
+                    blocks.push(edges.split_off(first));
+                }
+            }
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::lattice::Connectivity;
+    use crate::rand::uniform::Uniform701;
+
+    fn spanning(lattice: &Lattice) -> Vec<Site> {
+        let mut clusters = lattice
+            .create_clusters()
+            .get_percolating_clusters()
+            .clusters;
+        assert_eq!(clusters.len(), 1);
+        clusters.remove(0)
+    }
+
+    fn sites(mask: &[Vec<bool>]) -> Vec<Site> {
+        (0..mask.len())
+            .flat_map(|i| (0..mask.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| mask[i][j])
+            .collect()
+    }
+
+    #[test]
+    fn loop_with_a_bridge_and_a_dead_end() {
+        // Two ways down from the top row meet above a single bond to the bottom row, and a
+        // dead end hangs off the right-hand way
+        let lattice = Lattice::drawn(&["###.", "#.##", "###.", ".#.."], Connectivity::Four, false);
+        let burning = Burning::new(&lattice, &spanning(&lattice)).unwrap();
+
+        assert_eq!(burning.chemical_distance(), 4);
+        assert_eq!(burning.burn_time((0, 1)), Some(0));
+        assert_eq!(burning.burn_time((1, 3)), Some(2));
+        assert_eq!(burning.burn_time((2, 1)), Some(3));
+        assert_eq!(burning.burn_time((1, 1)), None);
+        let path = burning.shortest_path();
+        assert_eq!(path.len(), 5);
+        assert_eq!((path[0].0, path[3], path[4]), (0, (2, 1), (3, 1)));
+        assert_eq!(sites(&burning.shortest_path_mask()).len(), 5);
+
+        // No current runs along the top row, so the middle of it hangs off the backbone
+        assert_eq!(
+            sites(burning.backbone()),
+            [
+                (0, 0),
+                (0, 2),
+                (1, 0),
+                (1, 2),
+                (2, 0),
+                (2, 1),
+                (2, 2),
+                (3, 1)
+            ]
+        );
+        assert_eq!(sites(burning.dangling_ends()), [(0, 1), (1, 3)]);
+        assert_eq!((burning.backbone_size(), burning.dangling_size()), (8, 2));
+        assert_eq!(burning.red_bonds(), &[((3, 1), (2, 1))]);
+        assert_eq!(sites(&burning.red_sites()), [(2, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn second_way_to_the_bottom_leaves_no_red_bonds() {
+        let lattice = Lattice::drawn(&["###.", "#.##", "###.", ".##."], Connectivity::Four, false);
+        let burning = Burning::new(&lattice, &spanning(&lattice)).unwrap();
+        assert_eq!(burning.chemical_distance(), 3);
+        assert!(burning.red_bonds().is_empty());
+        assert_eq!(sites(burning.dangling_ends()), [(0, 1), (1, 3)]);
+        assert_eq!(burning.backbone_size(), 9);
+    }
+
+    #[test]
+    fn periodic_lattice_is_cut_open_between_the_bottom_and_top_rows() {
+        // The column wraps around, but the fire must still burn down every row
+        let lattice = Lattice::drawn(&["#...", "#...", "#...", "#..."], Connectivity::Four, true);
+        let burning = Burning::new(&lattice, &spanning(&lattice)).unwrap();
+        assert_eq!(burning.chemical_distance(), 3);
+        assert_eq!(burning.red_bonds().len(), 3);
+        assert_eq!(burning.dangling_size(), 0);
+    }
+
+    #[test]
+    fn clusters_which_do_not_cross_do_not_burn() {
+        let lattice = Lattice::drawn(&["#...", "#...", "....", "...."], Connectivity::Four, false);
+        let cluster = lattice.create_clusters().clusters.remove(0);
+        assert!(Burning::new(&lattice, &cluster).is_none());
+        let single = Lattice::drawn(&["#"], Connectivity::Four, false);
+        assert!(Burning::new(&single, &[(0, 0)]).is_none());
+    }
+
+    #[test]
+    fn parts_of_random_spanning_clusters_fit_together() {
+        let mut uni = Uniform701::seeded(25);
+        let lattice = Lattice::populate(0.62, 40, &mut uni).unwrap();
+        let clusters = lattice
+            .create_clusters()
+            .get_percolating_clusters()
+            .clusters;
+        assert!(!clusters.is_empty());
+        for cluster in &clusters {
+            let burning = Burning::new(&lattice, cluster).unwrap();
+            let backbone = burning.backbone();
+            let dangling = burning.dangling_ends();
+            for &(i, j) in cluster {
+                assert!(backbone[i][j] != dangling[i][j]);
+            }
+            assert_eq!(
+                burning.backbone_size() + burning.dangling_size(),
+                cluster.len()
+            );
+
+            let path = burning.shortest_path();
+            assert_eq!(path.len(), burning.chemical_distance() + 1);
+            for (step, pair) in path.windows(2).enumerate() {
+                let ((a, b), (c, d)) = (pair[0], pair[1]);
+                assert_eq!(a.abs_diff(c) + b.abs_diff(d), 1);
+                assert_eq!(burning.burn_time(pair[1]), Some(step + 1));
+            }
+            let on_path = burning.shortest_path_mask();
+            for &(a, b) in burning.red_bonds() {
+                for (i, j) in [a, b] {
+                    assert!(on_path[i][j] && backbone[i][j]);
+                }
+            }
+        }
+    }
+}
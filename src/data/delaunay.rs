@@ -1,63 +1,470 @@
-use crate::data::neighbors::NearestNeighborMap;
+//! Delaunay triangulation of sites in the plane
+
 use crate::data::point::Point2d;
-use crate::util::{circle_through, point_in_circle};
+use crate::data::predicates::{incircle, orient2d};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// A triangle mesh connecting a set of sites.
+/// Triangles list the indices of their sites in counter-clockwise order.
+/// Edge `e` of a triangle runs from its vertex `e` to its vertex `(e + 1) % 3`.
+pub struct Triangulation {
+    triangles: Vec<[usize; 3]>,
+    neighbors: Vec<[Option<usize>; 3]>,
+    hull: Vec<usize>,
+    n_sites: usize,
+}
+
+impl Triangulation {
+    /// The indices of the sites at the corners of each triangle, counter-clockwise
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    /// The triangle across each edge of each triangle, or `None` if the edge is on the hull
+    pub fn neighbors(&self) -> &[[Option<usize>; 3]] {
+        &self.neighbors
+    }
+
+    /// The indices of the sites on the convex hull, counter-clockwise
+    pub fn hull(&self) -> &[usize] {
+        &self.hull
+    }
 
-/// Compute the Delaunay triangulation of the sites.  `r_cutoof` represents the distance of
-/// neighbors to consider rather than considering the whole space.  Send `f64::MAX` if you
-/// want all points considered.  When `bidirectional` is false, any two points will be connected
-/// in one direction only, e.g., point i will contain j, but j will not contain i.
-pub fn dealunay_2d(sites: &[Point2d], r_cutoff: f64, bidirectional: bool) -> Vec<Vec<usize>> {
-    let n_sites = sites.len();
+    /// The edges of the convex hull, counter-clockwise
+    pub fn hull_edges(&self) -> Vec<(usize, usize)> {
+        (0..self.hull.len())
+            .map(|i| (self.hull[i], self.hull[(i + 1) % self.hull.len()]))
+            .collect()
+    }
 
-    let mut lines = vec![vec![]; n_sites];
+    /// Every edge of the mesh once, as `(i, j)` with `i < j`, in ascending order
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        let mut edges = self
+            .triangles
+            .iter()
+            .flat_map(|t| vec![(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .map(|(i, j)| if i > j { (j, i) } else { (i, j) })
+            .collect::<Vec<(usize, usize)>>();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
 
-    let first_neighbors = NearestNeighborMap::first_2d(sites, r_cutoff);
+    /// List the sites joined to each site by an edge, in ascending order
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        let mut adjacency = vec![vec![]; self.n_sites];
+        for (i, j) in self.edges() {
+            adjacency[i].push(j);
+            adjacency[j].push(i);
+        }
+        adjacency.iter_mut().for_each(|a| a.sort_unstable());
+        adjacency
+    }
+}
+
+/// Compute the Delaunay triangulation of the sites by inserting them one at a time
+/// (the Bowyer-Watson algorithm).  The sites are inserted in order along a Hilbert curve, so that
+/// the search for the triangle holding each new site starts close by.
+/// Repeated sites are left out of the mesh.
+/// Return an error if there are fewer than 3 distinct sites or they all lie on a line.
+pub fn delaunay_2d(sites: &[Point2d]) -> Result<Triangulation, Box<dyn Error>> {
+    if let Some(site) = sites.iter().find(|site| !site.is_finite()) {
+        return Err(format!("Cannot triangulate the non-finite site {}", site).into());
+    }
+    let order = hilbert_order(sites);
+
+    // Start from the first three sites which make a proper triangle
+    let i0 = *order.first().ok_or("Cannot triangulate without sites")?;
+    let i1 = *order
+        .iter()
+        .find(|&&i| sites[i] != sites[i0])
+        .ok_or("Cannot triangulate fewer than 3 distinct sites")?;
+    let i2 = *order
+        .iter()
+        .find(|&&i| orient2d(sites[i0], sites[i1], sites[i]) != 0.0)
+        .ok_or("Cannot triangulate sites which all lie on a line")?;
+
+    let mut mesh = Mesh::new(sites, i0, i1, i2);
+    order
+        .iter()
+        .filter(|&&i| i != i0 && i != i1 && i != i2)
+        .for_each(|&i| mesh.insert(i));
+
+    Ok(mesh.into_triangulation())
+}
 
-    for i in 0..n_sites {
-        let neighbors_i = &first_neighbors.neighbors[i];
-        for &j in neighbors_i {
-            for &k in neighbors_i {
-                if k == j {
+/// Stands in for a vertex at infinity.  Each edge of the hull is closed off by a ghost triangle
+/// joining it to the ghost vertex, so that every triangle has three neighbors and sites
+/// outside the hull are inserted the same way as those inside it.
+const GHOST: usize = usize::MAX;
+
+struct Mesh<'a> {
+    sites: &'a [Point2d],
+    vertices: Vec<[usize; 3]>,
+    /// The triangle across each edge, following the same convention as `Triangulation`
+    adjacent: Vec<[usize; 3]>,
+    alive: Vec<bool>,
+    /// Slots of triangles which have been removed, to be reused
+    free: Vec<usize>,
+    /// A real triangle from which to start looking for the next site
+    last: usize,
+}
+
+impl<'a> Mesh<'a> {
+    /// Construct the mesh of a single triangle and the three ghost triangles around it
+    fn new(sites: &'a [Point2d], i0: usize, i1: usize, i2: usize) -> Self {
+        let (i1, i2) = if orient2d(sites[i0], sites[i1], sites[i2]) > 0.0 {
+            (i1, i2)
+        } else {
+            (i2, i1)
+        };
+        Self {
+            sites,
+            vertices: vec![
+                [i0, i1, i2],
+                [i1, i0, GHOST],
+                [i2, i1, GHOST],
+                [i0, i2, GHOST],
+            ],
+            adjacent: vec![[1, 2, 3], [0, 3, 2], [0, 1, 3], [0, 2, 1]],
+            alive: vec![true; 4],
+            free: vec![],
+            last: 0,
+        }
+    }
+
+    fn is_ghost(&self, t: usize) -> bool {
+        self.vertices[t].contains(&GHOST)
+    }
+
+    /// Whether the site lies strictly within the circumcircle of the triangle.
+    /// The circumcircle of a ghost triangle is the open half-plane beyond its hull edge,
+    /// together with the inside of the edge itself.
+    fn in_conflict(&self, t: usize, p: Point2d) -> bool {
+        let v = self.vertices[t];
+        match v.iter().position(|&i| i == GHOST) {
+            None => incircle(self.sites[v[0]], self.sites[v[1]], self.sites[v[2]], p) > 0.0,
+            Some(g) => {
+                let a = self.sites[v[(g + 1) % 3]];
+                let b = self.sites[v[(g + 2) % 3]];
+                let o = orient2d(a, b, p);
+                o > 0.0 || (o == 0.0 && (a - p).dot(&(b - p)) < 0.0)
+            }
+        }
+    }
+
+    /// Walk from the last triangle towards the site, and return a triangle in conflict with it.
+    /// Return `None` if no triangle is, which happens when the site repeats an earlier one.
+    fn locate(&self, p: Point2d) -> Option<usize> {
+        // Step across any edge with the site on its far side.  Such a walk cannot circle
+        // through a Delaunay mesh, since the orientation tests are exact.
+        let mut t = self.last;
+        while !self.is_ghost(t) {
+            let v = self.vertices[t];
+            let exit =
+                (0..3).find(|&e| orient2d(self.sites[v[e]], self.sites[v[(e + 1) % 3]], p) < 0.0);
+            match exit {
+                Some(e) => t = self.adjacent[t][e],
+                None => break,
+            }
+        }
+        if self.in_conflict(t, p) {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    fn insert(&mut self, i: usize) {
+        let p = self.sites[i];
+        let seed = match self.locate(p) {
+            Some(t) => t,
+            None => return,
+        };
+
+        // Remove every triangle whose circumcircle holds the site, leaving a cavity whose
+        // boundary edges, listed counter-clockwise with the triangle outside them, can all see it
+        let mut boundary = vec![];
+        let mut removed = vec![seed];
+        let mut stack = vec![seed];
+        self.alive[seed] = false;
+        while let Some(t) = stack.pop() {
+            for e in 0..3 {
+                let u = self.adjacent[t][e];
+                if !self.alive[u] {
                     continue;
                 }
-                let (center, r) = circle_through(sites[i], sites[j], sites[k]);
-                if (0..n_sites)
-                    .filter(|&v| !(v == i || v == j || v == k))
-                    .find(|&v| point_in_circle(sites[v], center, r))
-                    .is_none()
-                {
-                    lines[i].push(j);
-                    lines[i].push(k);
-                    lines[j].push(k);
+                if self.in_conflict(u, p) {
+                    self.alive[u] = false;
+                    removed.push(u);
+                    stack.push(u);
+                } else {
+                    boundary.push((self.vertices[t][e], self.vertices[t][(e + 1) % 3], u));
                 }
             }
         }
+        self.free.append(&mut removed);
+
+        // Join the site to each boundary edge
+        let mut starting_at = HashMap::new();
+        let mut created = vec![];
+        for &(a, b, outside) in &boundary {
+            let t = self.allocate([a, b, i], [outside, GHOST, GHOST]);
+            let back = (0..3)
+                .find(|&e| self.vertices[outside][e] == b)
+                .expect("The triangle outside a boundary edge shares its vertices");
+            self.adjacent[outside][back] = t;
+            starting_at.insert(a, t);
+            created.push(t);
+        }
+        // The new triangles fan around the site, each meeting the one which starts where it ends
+        for &t in &created {
+            let after = starting_at[&self.vertices[t][1]];
+            self.adjacent[t][1] = after;
+            self.adjacent[after][2] = t;
+        }
+        if let Some(&t) = created.iter().find(|&&t| !self.is_ghost(t)) {
+            self.last = t;
+        }
+    }
+
+    fn allocate(&mut self, vertices: [usize; 3], adjacent: [usize; 3]) -> usize {
+        match self.free.pop() {
+            Some(t) => {
+                self.vertices[t] = vertices;
+                self.adjacent[t] = adjacent;
+                self.alive[t] = true;
+                t
+            }
+            None => {
+                self.vertices.push(vertices);
+                self.adjacent.push(adjacent);
+                self.alive.push(true);
+                self.vertices.len() - 1
+            }
+        }
     }
 
-    (0..n_sites).for_each(|i| {
-        lines[i].sort_unstable();
-        lines[i].dedup();
-    });
+    /// Drop the ghost triangles and renumber the rest
+    fn into_triangulation(self) -> Triangulation {
+        let kept = (0..self.vertices.len())
+            .filter(|&t| self.alive[t] && !self.is_ghost(t))
+            .collect::<Vec<usize>>();
+        let mut renumbered = vec![None; self.vertices.len()];
+        kept.iter()
+            .enumerate()
+            .for_each(|(new, &old)| renumbered[old] = Some(new));
+        let triangles = kept.iter().map(|&t| self.vertices[t]).collect();
+        let neighbors = kept
+            .iter()
+            .map(|&t| {
+                let [a, b, c] = self.adjacent[t];
+                [renumbered[a], renumbered[b], renumbered[c]]
+            })
+            .collect();
 
-    if bidirectional {
-        let clone = lines.clone();
-        // for i in 0..n_sites {
-        for (i, opposites) in clone.iter().enumerate() {
-            for &j in opposites {
-                lines[j].push(i);
+        // Each ghost triangle lists its hull edge backwards
+        let mut next = HashMap::new();
+        for t in 0..self.vertices.len() {
+            if self.alive[t] {
+                let v = self.vertices[t];
+                if let Some(g) = v.iter().position(|&i| i == GHOST) {
+                    next.insert(v[(g + 2) % 3], v[(g + 1) % 3]);
+                }
             }
         }
+        let start = *next.keys().min().expect("A mesh always has a hull");
+        let mut hull = vec![start];
+        let mut at = next[&start];
+        while at != start {
+            hull.push(at);
+            at = next[&at];
+        }
+
+        Triangulation {
+            triangles,
+            neighbors,
+            hull,
+            n_sites: self.sites.len(),
+        }
     }
-    lines
 }
 
-pub fn to_lines_2d(triangulation: &[Vec<usize>]) -> Vec<(usize, usize)> {
-    let mut lines = triangulation
+/// The number of bits of each coordinate used to place the sites along the Hilbert curve
+const HILBERT_BITS: u32 = 16;
+
+/// Sort the indices of the sites by their position along a Hilbert curve filling their
+/// bounding box, which keeps sites close together in space close together in the order
+fn hilbert_order(sites: &[Point2d]) -> Vec<usize> {
+    let (mut lo, mut hi) = (
+        Point2d::new(f64::MAX, f64::MAX),
+        Point2d::new(f64::MIN, f64::MIN),
+    );
+    for site in sites {
+        for axis in 0..2 {
+            lo[axis] = lo[axis].min(site[axis]);
+            hi[axis] = hi[axis].max(site[axis]);
+        }
+    }
+    let side = (1u32 << HILBERT_BITS) - 1;
+    let scale = |x: f64, axis: usize| {
+        let extent = hi[axis] - lo[axis];
+        if extent > 0.0 {
+            ((x - lo[axis]) / extent * side as f64) as u32
+        } else {
+            0
+        }
+    };
+    let mut keyed = sites
         .iter()
         .enumerate()
-        .flat_map(|(i, others)| others.iter().map(move |&j| (i, j)))
-        .collect::<Vec<(usize, usize)>>();
-    lines.sort_unstable();
-    lines.dedup();
-    lines
+        .map(|(i, site)| (hilbert_index(scale(site.x(), 0), scale(site.y(), 1)), i))
+        .collect::<Vec<(u64, usize)>>();
+    keyed.sort_unstable();
+    keyed.into_iter().map(|(_, i)| i).collect()
+}
+
+/// The distance along the Hilbert curve to the cell (x, y) of a square grid with
+/// `2^HILBERT_BITS` cells on a side
+fn hilbert_index(mut x: u32, mut y: u32) -> u64 {
+    let n = 1u32 << HILBERT_BITS;
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so that the curve through it starts and ends in the right corners
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::points_in_grid::gen_spaced_points_in_box;
+    use crate::data::test_support::{grid, random_sites_2d, with_repeats};
+    use crate::rand::uniform::Uniform701;
+
+    /// The edges of the triangulation from delaunator, as in `Triangulation::edges`
+    fn delaunator_edges(sites: &[Point2d]) -> Vec<(usize, usize)> {
+        let points = sites
+            .iter()
+            .map(|p| delaunator::Point { x: p.x(), y: p.y() })
+            .collect::<Vec<delaunator::Point>>();
+        let mut edges = delaunator::triangulate(&points)
+            .unwrap()
+            .triangles
+            .chunks(3)
+            .flat_map(|t| vec![(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+            .map(|(i, j)| if i > j { (j, i) } else { (i, j) })
+            .collect::<Vec<(usize, usize)>>();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+
+    /// Check that the triangles turn counter-clockwise, that their neighbors agree with each
+    /// other and with the hull, and that Euler's formula holds for the sites in the mesh.
+    /// If `empty_circles`, also check that no site lies inside the circle of any triangle.
+    fn assert_delaunay(sites: &[Point2d], t: &Triangulation, empty_circles: bool) {
+        let hull_edges = t.hull_edges();
+        for (k, &[a, b, c]) in t.triangles().iter().enumerate() {
+            assert!(orient2d(sites[a], sites[b], sites[c]) > 0.0);
+            for e in 0..3 {
+                let (from, to) = (t.triangles()[k][e], t.triangles()[k][(e + 1) % 3]);
+                match t.neighbors()[k][e] {
+                    Some(n) => {
+                        let back = (0..3).find(|&f| t.triangles()[n][f] == to).unwrap();
+                        assert_eq!(t.triangles()[n][(back + 1) % 3], from);
+                        assert_eq!(t.neighbors()[n][back], Some(k));
+                    }
+                    None => assert!(hull_edges.contains(&(from, to))),
+                }
+            }
+            if empty_circles {
+                for &d in sites {
+                    assert!(incircle(sites[a], sites[b], sites[c], d) <= 0.0);
+                }
+            }
+        }
+        for (k, &i) in t.hull().iter().enumerate() {
+            let j = t.hull()[(k + 1) % t.hull().len()];
+            let after = t.hull()[(k + 2) % t.hull().len()];
+            assert!(orient2d(sites[i], sites[j], sites[after]) >= 0.0);
+        }
+
+        let in_mesh = t.adjacency().iter().filter(|a| !a.is_empty()).count();
+        assert_eq!(t.triangles().len(), 2 * in_mesh - 2 - t.hull().len());
+    }
+
+    #[test]
+    fn matches_delaunator_on_assignment_5_sites() {
+        let mut uni = Uniform701::seeded(5);
+        let sites = gen_spaced_points_in_box(&mut uni, 20.0, 500, 0.7).unwrap();
+        let t = delaunay_2d(&sites).unwrap();
+        assert_delaunay(&sites, &t, true);
+        assert_eq!(t.edges(), delaunator_edges(&sites));
+    }
+
+    #[test]
+    fn matches_delaunator_on_uniform_sites() {
+        let sites = random_sites_2d(11, 1.0, 1_000);
+        let t = delaunay_2d(&sites).unwrap();
+        assert_delaunay(&sites, &t, true);
+        assert_eq!(t.edges(), delaunator_edges(&sites));
+    }
+
+    #[test]
+    fn scales_to_100_000_sites() {
+        let sites = random_sites_2d(100, 1.0, 100_000);
+        let t = delaunay_2d(&sites).unwrap();
+        assert_delaunay(&sites, &t, false);
+    }
+
+    #[test]
+    fn rejects_collinear_sites() {
+        let sites = (0..10)
+            .map(|i| Point2d::new(i as f64, 2.0 * i as f64))
+            .collect::<Vec<Point2d>>();
+        assert!(delaunay_2d(&sites).is_err());
+    }
+
+    #[test]
+    fn rejects_fewer_than_3_distinct_sites() {
+        assert!(delaunay_2d(&[]).is_err());
+        assert!(delaunay_2d(&[Point2d::new(1.0, 1.0); 5]).is_err());
+        let two = [Point2d::new(0.0, 0.0), Point2d::new(1.0, 0.0)];
+        assert!(delaunay_2d(&[two[0], two[1], two[0], two[1]]).is_err());
+    }
+
+    #[test]
+    fn triangulates_a_cocircular_grid() {
+        let sites = grid(10, 0.0);
+        let t = delaunay_2d(&sites).unwrap();
+        assert_delaunay(&sites, &t, true);
+        // Every site of the grid's edges is on the hull, and each square is split in two
+        assert_eq!(t.hull().len(), 36);
+        assert_eq!(t.triangles().len(), 162);
+    }
+
+    #[test]
+    fn leaves_out_repeated_sites() {
+        let distinct = random_sites_2d(3, 1.0, 50);
+        let sites = with_repeats(&distinct, 10);
+        let t = delaunay_2d(&sites).unwrap();
+        assert_delaunay(&sites, &t, true);
+        let in_mesh = t.adjacency().iter().filter(|a| !a.is_empty()).count();
+        assert_eq!(in_mesh, distinct.len());
+        assert_eq!(t.edges().len(), delaunator_edges(&distinct).len());
+    }
 }
@@ -0,0 +1,475 @@
+//! Delaunay tetrahedralization of sites in space
+
+use crate::data::point::Point3d;
+use crate::data::predicates::{collinear, insphere, orient3d};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// The corners of the face opposite each corner of a tetrahedron, ordered so that they appear
+/// counter-clockwise from outside the tetrahedron
+const FACES: [[usize; 3]; 4] = [[1, 3, 2], [0, 2, 3], [0, 3, 1], [0, 1, 2]];
+
+/// A tetrahedral mesh connecting a set of sites.
+/// Tetrahedra list the indices of their sites so that `orient3d` of their corners is positive.
+/// Face `f` of a tetrahedron is the one opposite its vertex `f`.
+pub struct Tetrahedralization {
+    tetrahedra: Vec<[usize; 4]>,
+    neighbors: Vec<[Option<usize>; 4]>,
+    hull: Vec<[usize; 3]>,
+    n_sites: usize,
+}
+
+impl Tetrahedralization {
+    /// The indices of the sites at the corners of each tetrahedron
+    pub fn tetrahedra(&self) -> &[[usize; 4]] {
+        &self.tetrahedra
+    }
+
+    /// The tetrahedron across each face of each tetrahedron, or `None` if the face is on the hull
+    pub fn neighbors(&self) -> &[[Option<usize>; 4]] {
+        &self.neighbors
+    }
+
+    /// The faces of the convex hull, each counter-clockwise seen from outside
+    pub fn hull_faces(&self) -> &[[usize; 3]] {
+        &self.hull
+    }
+
+    /// The indices of the sites on the convex hull, in ascending order
+    pub fn hull(&self) -> Vec<usize> {
+        let mut hull = self.hull.iter().flatten().cloned().collect::<Vec<usize>>();
+        hull.sort_unstable();
+        hull.dedup();
+        hull
+    }
+
+    /// Every edge of the mesh once, as `(i, j)` with `i < j`, in ascending order
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        let mut edges = self
+            .tetrahedra
+            .iter()
+            .flat_map(|t| {
+                (0..4)
+                    .flat_map(move |a| ((a + 1)..4).map(move |b| (t[a].min(t[b]), t[a].max(t[b]))))
+            })
+            .collect::<Vec<(usize, usize)>>();
+        edges.sort_unstable();
+        edges.dedup();
+        edges
+    }
+
+    /// List the sites joined to each site by an edge, in ascending order.
+    /// These are the natural neighbors of the sites, whose Voronoi cells share a face.
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        let mut adjacency = vec![vec![]; self.n_sites];
+        for (i, j) in self.edges() {
+            adjacency[i].push(j);
+            adjacency[j].push(i);
+        }
+        adjacency.iter_mut().for_each(|a| a.sort_unstable());
+        adjacency
+    }
+}
+
+/// Compute the Delaunay tetrahedralization of the sites by inserting them one at a time
+/// (the Bowyer-Watson algorithm).  The sites are inserted in order along a Z-order curve, so that
+/// the search for the tetrahedron holding each new site starts close by.
+/// Repeated sites are left out of the mesh.
+/// Return an error if there are fewer than 4 distinct sites or they all lie in a plane.
+pub fn delaunay_3d(sites: &[Point3d]) -> Result<Tetrahedralization, Box<dyn Error>> {
+    if let Some(site) = sites.iter().find(|site| !site.is_finite()) {
+        return Err(format!("Cannot tetrahedralize the non-finite site {}", site).into());
+    }
+    let order = z_order(sites);
+
+    // Start from the first four sites which make a proper tetrahedron
+    let i0 = *order.first().ok_or("Cannot tetrahedralize without sites")?;
+    let i1 = *order
+        .iter()
+        .find(|&&i| sites[i] != sites[i0])
+        .ok_or("Cannot tetrahedralize fewer than 4 distinct sites")?;
+    let i2 = *order
+        .iter()
+        .find(|&&i| !collinear(sites[i0], sites[i1], sites[i]))
+        .ok_or("Cannot tetrahedralize sites which all lie on a line")?;
+    let i3 = *order
+        .iter()
+        .find(|&&i| orient3d(sites[i0], sites[i1], sites[i2], sites[i]) != 0.0)
+        .ok_or("Cannot tetrahedralize sites which all lie in a plane")?;
+
+    let mut mesh = Mesh::new(sites, [i0, i1, i2, i3]);
+    order
+        .iter()
+        .filter(|&&i| i != i0 && i != i1 && i != i2 && i != i3)
+        .for_each(|&i| mesh.insert(i));
+
+    Ok(mesh.into_tetrahedralization())
+}
+
+/// Stands in for a vertex at infinity.  Each face of the hull is closed off by a ghost
+/// tetrahedron joining it to the ghost vertex, so that every tetrahedron has four neighbors and
+/// sites outside the hull are inserted the same way as those inside it.
+const GHOST: usize = usize::MAX;
+
+struct Mesh<'a> {
+    sites: &'a [Point3d],
+    vertices: Vec<[usize; 4]>,
+    /// The tetrahedron across each face, following the same convention as `Tetrahedralization`
+    adjacent: Vec<[usize; 4]>,
+    alive: Vec<bool>,
+    /// Slots of tetrahedra which have been removed, to be reused
+    free: Vec<usize>,
+    /// A real tetrahedron from which to start looking for the next site
+    last: usize,
+}
+
+impl<'a> Mesh<'a> {
+    /// Construct the mesh of a single tetrahedron and the four ghost tetrahedra around it
+    fn new(sites: &'a [Point3d], corners: [usize; 4]) -> Self {
+        let [i0, i1, i2, i3] = corners;
+        let first = if orient3d(sites[i0], sites[i1], sites[i2], sites[i3]) > 0.0 {
+            [i0, i1, i2, i3]
+        } else {
+            [i1, i0, i2, i3]
+        };
+        let mut mesh = Self {
+            sites,
+            vertices: vec![first],
+            adjacent: vec![[1, 2, 3, 4]],
+            alive: vec![true],
+            free: vec![],
+            last: 0,
+        };
+        // The ghost across face f sees the face turning the other way, and meets the ghosts
+        // across the other faces along the edges of face f
+        for face in &FACES {
+            let [a, b, c] = face.map(|k| first[k]);
+            // The ghost across the face opposite a corner, which is the only face without it
+            let ghost_of = |corner: usize| {
+                1 + FACES
+                    .iter()
+                    .position(|other| !other.contains(&face[corner]))
+                    .unwrap()
+            };
+            mesh.vertices.push([a, c, b, GHOST]);
+            mesh.adjacent
+                .push([ghost_of(0), ghost_of(2), ghost_of(1), 0]);
+            mesh.alive.push(true);
+        }
+        mesh
+    }
+
+    fn is_ghost(&self, t: usize) -> bool {
+        self.vertices[t].contains(&GHOST)
+    }
+
+    /// The corners of face `f` of tetrahedron `t`, counter-clockwise seen from outside it
+    fn face(&self, t: usize, f: usize) -> [usize; 3] {
+        FACES[f].map(|k| self.vertices[t][k])
+    }
+
+    /// Whether the site lies strictly within the circumsphere of the tetrahedron.
+    /// The circumsphere of a ghost tetrahedron is the open half-space beyond its hull face,
+    /// together with the inside of the circumcircle of the face itself.
+    fn in_conflict(&self, t: usize, p: Point3d) -> bool {
+        let v = self.vertices[t];
+        match v.iter().position(|&i| i == GHOST) {
+            None => {
+                let [a, b, c, d] = v.map(|i| self.sites[i]);
+                insphere(a, b, c, d, p) > 0.0
+            }
+            Some(g) => {
+                let [a, b, c] = self.face(t, g).map(|i| self.sites[i]);
+                // The ghost vertex lies below the face, seen from the ghost tetrahedron
+                let o = orient3d(a, b, c, p);
+                // Within the plane of the face, the circumsphere of the real tetrahedron on its
+                // other side cuts out the circumcircle of the face
+                o > 0.0 || (o == 0.0 && self.in_conflict(self.adjacent[t][g], p))
+            }
+        }
+    }
+
+    /// Walk from the last tetrahedron towards the site, and return a tetrahedron in conflict
+    /// with it.  Return `None` if no tetrahedron is, which happens when the site repeats an
+    /// earlier one.
+    fn locate(&self, p: Point3d) -> Option<usize> {
+        // Step across any face with the site on its far side.  Such a walk cannot circle
+        // through a Delaunay mesh, since the orientation tests are exact.
+        let mut t = self.last;
+        while !self.is_ghost(t) {
+            let exit = (0..4).find(|&f| {
+                let [a, b, c] = self.face(t, f).map(|i| self.sites[i]);
+                orient3d(a, b, c, p) < 0.0
+            });
+            match exit {
+                Some(f) => t = self.adjacent[t][f],
+                None => break,
+            }
+        }
+        if self.in_conflict(t, p) {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    fn insert(&mut self, i: usize) {
+        let p = self.sites[i];
+        let seed = match self.locate(p) {
+            Some(t) => t,
+            None => return,
+        };
+
+        // Remove every tetrahedron whose circumsphere holds the site, leaving a cavity whose
+        // boundary faces, listed counter-clockwise from outside with the tetrahedron outside
+        // them, can all see it
+        let mut boundary = vec![];
+        let mut removed = vec![seed];
+        let mut stack = vec![seed];
+        self.alive[seed] = false;
+        while let Some(t) = stack.pop() {
+            for f in 0..4 {
+                let u = self.adjacent[t][f];
+                if !self.alive[u] {
+                    continue;
+                }
+                if self.in_conflict(u, p) {
+                    self.alive[u] = false;
+                    removed.push(u);
+                    stack.push(u);
+                } else {
+                    boundary.push((self.face(t, f), u));
+                }
+            }
+        }
+        self.free.append(&mut removed);
+
+        // Join the site to each boundary face, which leaves the site below it
+        let mut around_edge = HashMap::new();
+        let mut created = vec![];
+        for &(face, outside) in &boundary {
+            let [a, b, c] = face;
+            let t = self.allocate([a, b, c, i], [GHOST, GHOST, GHOST, outside]);
+            let back = (0..4)
+                .find(|&f| !face.contains(&self.vertices[outside][f]))
+                .expect("The tetrahedron outside a boundary face shares its vertices");
+            self.adjacent[outside][back] = t;
+            created.push(t);
+
+            // The new tetrahedra meet along the edges of the boundary, each of which is shared
+            // by exactly two boundary faces
+            for k in 0..3 {
+                let (u, w) = (face[(k + 1) % 3], face[(k + 2) % 3]);
+                match around_edge.remove(&(u.min(w), u.max(w))) {
+                    Some((s, g)) => {
+                        self.adjacent[t][k] = s;
+                        self.adjacent[s][g] = t;
+                    }
+                    None => {
+                        around_edge.insert((u.min(w), u.max(w)), (t, k));
+                    }
+                }
+            }
+        }
+        debug_assert!(around_edge.is_empty());
+        if let Some(&t) = created.iter().find(|&&t| !self.is_ghost(t)) {
+            self.last = t;
+        }
+    }
+
+    fn allocate(&mut self, vertices: [usize; 4], adjacent: [usize; 4]) -> usize {
+        match self.free.pop() {
+            Some(t) => {
+                self.vertices[t] = vertices;
+                self.adjacent[t] = adjacent;
+                self.alive[t] = true;
+                t
+            }
+            None => {
+                self.vertices.push(vertices);
+                self.adjacent.push(adjacent);
+                self.alive.push(true);
+                self.vertices.len() - 1
+            }
+        }
+    }
+
+    /// Drop the ghost tetrahedra and renumber the rest
+    fn into_tetrahedralization(self) -> Tetrahedralization {
+        let kept = (0..self.vertices.len())
+            .filter(|&t| self.alive[t] && !self.is_ghost(t))
+            .collect::<Vec<usize>>();
+        let mut renumbered = vec![None; self.vertices.len()];
+        kept.iter()
+            .enumerate()
+            .for_each(|(new, &old)| renumbered[old] = Some(new));
+        let tetrahedra = kept.iter().map(|&t| self.vertices[t]).collect();
+        let neighbors = kept
+            .iter()
+            .map(|&t| self.adjacent[t].map(|u| renumbered[u]))
+            .collect();
+
+        // Each ghost tetrahedron sees its hull face turning the other way
+        let hull = (0..self.vertices.len())
+            .filter(|&t| self.alive[t])
+            .filter_map(|t| {
+                let g = self.vertices[t].iter().position(|&i| i == GHOST)?;
+                let [a, b, c] = self.face(t, g);
+                Some([a, c, b])
+            })
+            .collect();
+
+        Tetrahedralization {
+            tetrahedra,
+            neighbors,
+            hull,
+            n_sites: self.sites.len(),
+        }
+    }
+}
+
+/// The number of bits of each coordinate used to place the sites along the Z-order curve
+const Z_ORDER_BITS: u32 = 21;
+
+/// Sort the indices of the sites by their position along a Z-order (Morton) curve filling
+/// their bounding box, which keeps most sites close together in space close together in the order
+fn z_order(sites: &[Point3d]) -> Vec<usize> {
+    let (mut lo, mut hi) = (Point3d::from([f64::MAX; 3]), Point3d::from([f64::MIN; 3]));
+    for site in sites {
+        for axis in 0..3 {
+            lo[axis] = lo[axis].min(site[axis]);
+            hi[axis] = hi[axis].max(site[axis]);
+        }
+    }
+    let side = (1u64 << Z_ORDER_BITS) - 1;
+    let scale = |x: f64, axis: usize| {
+        let extent = hi[axis] - lo[axis];
+        if extent > 0.0 {
+            ((x - lo[axis]) / extent * side as f64) as u64
+        } else {
+            0
+        }
+    };
+    let mut keyed = sites
+        .iter()
+        .enumerate()
+        .map(|(i, site)| {
+            // Interleave the bits of the coordinates, most significant first
+            let cell = [0, 1, 2].map(|axis| scale(site[axis], axis));
+            let key = (0..Z_ORDER_BITS).rev().fold(0u64, |key, bit| {
+                cell.iter()
+                    .fold(key, |key, &c| (key << 1) | ((c >> bit) & 1))
+            });
+            (key, i)
+        })
+        .collect::<Vec<(u64, usize)>>();
+    keyed.sort_unstable();
+    keyed.into_iter().map(|(_, i)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::test_support::{assert_close, grid, random_sites_3d, with_repeats};
+
+    /// Check that the tetrahedra are positively oriented with empty circumspheres, that their
+    /// neighbors are mutual and share a face, that the faces without a neighbor are the hull, that
+    /// the hull is convex, that the adjacency is symmetric and that the tetrahedra fill the hull
+    fn assert_tetrahedralization(sites: &[Point3d], t: &Tetrahedralization) {
+        let tetrahedra = t.tetrahedra();
+        for (i, &[a, b, c, d]) in tetrahedra.iter().enumerate() {
+            let [a, b, c, d] = [a, b, c, d].map(|v| sites[v]);
+            assert!(orient3d(a, b, c, d) > 0.0);
+            assert!(sites.iter().all(|&e| insphere(a, b, c, d, e) <= 0.0));
+
+            for (f, across) in t.neighbors()[i].iter().enumerate() {
+                let mut face = FACES[f].map(|k| tetrahedra[i][k]);
+                face.sort_unstable();
+                match *across {
+                    Some(j) => {
+                        assert!(t.neighbors()[j].contains(&Some(i)));
+                        assert!(face.iter().all(|v| tetrahedra[j].contains(v)));
+                    }
+                    None => assert!(t.hull_faces().iter().any(|h| {
+                        let mut h = *h;
+                        h.sort_unstable();
+                        h == face
+                    })),
+                }
+            }
+        }
+        let open = t.neighbors().iter().flatten().filter(|n| n.is_none());
+        assert_eq!(open.count(), t.hull_faces().len());
+
+        let adjacency = t.adjacency();
+        for (i, adjacent) in adjacency.iter().enumerate() {
+            assert!(adjacent.iter().all(|&j| adjacency[j].contains(&i)));
+        }
+
+        // The hull faces turn counter-clockwise seen from outside, so by the divergence theorem
+        // they enclose the sum over them of the volumes of the cones they span with the origin
+        for &[a, b, c] in t.hull_faces() {
+            let [a, b, c] = [a, b, c].map(|v| sites[v]);
+            assert!(sites.iter().all(|&p| orient3d(a, b, c, p) >= 0.0));
+        }
+        let enclosed = t
+            .hull_faces()
+            .iter()
+            .map(|&[a, b, c]| sites[a].dot(&sites[b].cross(&sites[c])) / 6.0)
+            .sum::<f64>();
+        let volume = tetrahedra
+            .iter()
+            .map(|&[a, b, c, d]| {
+                let [a, b, c] = [a, b, c].map(|v| sites[v] - sites[d]);
+                a.dot(&b.cross(&c)) / 6.0
+            })
+            .sum::<f64>();
+        assert_close(volume, enclosed, 1e-9 * enclosed);
+    }
+
+    #[test]
+    fn random_sites_are_tetrahedralized() {
+        for &n in &[4, 30, 300] {
+            let sites = random_sites_3d(15, 10.0, n);
+            let t = delaunay_3d(&sites).unwrap();
+            assert_tetrahedralization(&sites, &t);
+            assert!(t.adjacency().iter().all(|a| !a.is_empty()));
+        }
+    }
+
+    #[test]
+    fn cospherical_grid_is_tetrahedralized() {
+        // Each eight neighboring sites of the grid lie on one sphere
+        let sites = grid(3, 0.0);
+        let t = delaunay_3d(&sites).unwrap();
+        assert_tetrahedralization(&sites, &t);
+        assert_eq!(t.hull().len(), 26);
+        let volume = t
+            .tetrahedra()
+            .iter()
+            .map(|&[a, b, c, d]| orient3d(sites[a], sites[b], sites[c], sites[d]) / 6.0)
+            .sum::<f64>();
+        assert_close(volume, 8.0, 1e-9);
+    }
+
+    #[test]
+    fn repeated_sites_are_left_out() {
+        let sites = with_repeats(&random_sites_3d(16, 10.0, 40), 10);
+        let t = delaunay_3d(&sites).unwrap();
+        assert_tetrahedralization(&sites, &t);
+        let used = t.adjacency().iter().filter(|a| !a.is_empty()).count();
+        assert_eq!(used, 40);
+    }
+
+    #[test]
+    fn degenerate_sites_have_no_tetrahedralization() {
+        let p = |x: f64, y: f64, z: f64| Point3d::new(x, y, z);
+        assert!(delaunay_3d(&[]).is_err());
+        assert!(delaunay_3d(&[p(1.0, 2.0, 3.0); 5]).is_err());
+        let line = (0..5).map(|i| p(i as f64, 2.0 * i as f64, 3.0 * i as f64));
+        assert!(delaunay_3d(&line.collect::<Vec<Point3d>>()).is_err());
+        let plane = (0..16).map(|i| p((i % 4) as f64, (i / 4) as f64, (i % 4 + i / 4) as f64));
+        assert!(delaunay_3d(&plane.collect::<Vec<Point3d>>()).is_err());
+        assert!(delaunay_3d(&[p(0.0, 0.0, 0.0), p(1.0, 0.0, 0.0), p(0.0, f64::NAN, 0.0)]).is_err());
+    }
+}
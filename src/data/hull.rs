@@ -0,0 +1,347 @@
+//! Convex hulls of points in the plane, by several algorithms which all give the same hull
+
+use crate::data::line::Line2d;
+use crate::data::point::Point2d;
+use crate::data::predicates::orient2d;
+use std::cmp::Ordering;
+use std::error::Error;
+
+/// The algorithms available to compute a convex hull
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HullAlgorithm {
+    GiftWrap,
+    GrahamScan,
+    MonotoneChain,
+    QuickHull,
+}
+
+impl HullAlgorithm {
+    pub const ALL: [Self; 4] = [
+        Self::GiftWrap,
+        Self::GrahamScan,
+        Self::MonotoneChain,
+        Self::QuickHull,
+    ];
+
+    /// Compute the convex hull of the points with this algorithm
+    pub fn compute(&self, points: &[Point2d]) -> Result<Vec<Line2d>, Box<dyn Error>> {
+        match self {
+            Self::GiftWrap => gift_wrap(points),
+            Self::GrahamScan => graham_scan(points),
+            Self::MonotoneChain => monotone_chain(points),
+            Self::QuickHull => quickhull(points),
+        }
+    }
+}
+
+impl std::fmt::Display for HullAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::GiftWrap => "Gift wrapping",
+            Self::GrahamScan => "Graham scan",
+            Self::MonotoneChain => "Monotone chain",
+            Self::QuickHull => "QuickHull",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Compute the convex hull of the points by gift wrapping, which takes time proportional to
+/// the number of points times the number of corners of the hull.
+/// See `closed_hull` for the form of the hull and the errors returned.
+pub fn gift_wrap(points: &[Point2d]) -> Result<Vec<Line2d>, Box<dyn Error>> {
+    closed_hull(points, |sorted| {
+        let start = sorted[0];
+        let mut corners = vec![];
+        let mut src = start;
+        loop {
+            // Wrap around to the point with no other point to its right, seen from src,
+            // taking the farthest if several lie in that direction
+            let dst = sorted.iter().fold(src, |best, &p| {
+                let turn = orient2d(src, best, p);
+                if best == src
+                    || turn < 0.0
+                    || (turn == 0.0 && src.distance_squared_to(&p) > src.distance_squared_to(&best))
+                {
+                    p
+                } else {
+                    best
+                }
+            });
+            corners.push(src);
+            // Stop once the hull closes, or if it cannot because the points all lie on a line
+            if dst == start || corners.len() > sorted.len() {
+                break;
+            }
+            src = dst;
+        }
+        corners
+    })
+}
+
+/// Compute the convex hull of the points by sorting them by their angle around the lowest point,
+/// then walking around them and backing up at each right turn (the Graham scan).
+/// See `closed_hull` for the form of the hull and the errors returned.
+pub fn graham_scan(points: &[Point2d]) -> Result<Vec<Line2d>, Box<dyn Error>> {
+    closed_hull(points, |sorted| {
+        let pivot = *sorted
+            .iter()
+            .min_by(|l, r| l.y().total_cmp(&r.y()).then(l.x().total_cmp(&r.x())))
+            .unwrap();
+        // Every other point lies at an angle in [0, pi) from the pivot, so the turn between
+        // two points orders them.  Points in the same direction are ordered nearest first.
+        let mut around = sorted
+            .iter()
+            .cloned()
+            .filter(|&p| p != pivot)
+            .collect::<Vec<Point2d>>();
+        around.sort_by(|&a, &b| {
+            0.0.partial_cmp(&orient2d(pivot, a, b))
+                .unwrap_or(Ordering::Equal)
+                .then(
+                    pivot
+                        .distance_squared_to(&a)
+                        .total_cmp(&pivot.distance_squared_to(&b)),
+                )
+        });
+
+        let mut corners = vec![pivot];
+        around
+            .into_iter()
+            .for_each(|p| push_turning_left(&mut corners, 1, p));
+        corners
+    })
+}
+
+/// Compute the convex hull of the points by sorting them along the x-axis, then building the
+/// lower and upper halves of the hull from left to right and right to left (Andrew's monotone
+/// chain algorithm).
+/// See `closed_hull` for the form of the hull and the errors returned.
+pub fn monotone_chain(points: &[Point2d]) -> Result<Vec<Line2d>, Box<dyn Error>> {
+    closed_hull(points, |sorted| {
+        let mut corners = vec![];
+        sorted
+            .iter()
+            .for_each(|&p| push_turning_left(&mut corners, 1, p));
+        // The upper half goes back from the rightmost point, which ends the lower half
+        let lower = corners.len();
+        sorted
+            .iter()
+            .rev()
+            .skip(1)
+            .for_each(|&p| push_turning_left(&mut corners, lower, p));
+        // The upper half ends back at the first corner
+        corners.pop();
+        corners
+    })
+}
+
+/// Compute the convex hull of the points by dividing them at the line between the leftmost and
+/// rightmost points, then recursively finding the farthest point beyond each side and dropping
+/// the points inside the triangle it makes (QuickHull).
+/// See `closed_hull` for the form of the hull and the errors returned.
+pub fn quickhull(points: &[Point2d]) -> Result<Vec<Line2d>, Box<dyn Error>> {
+    closed_hull(points, |sorted| {
+        let (a, b) = (sorted[0], sorted[sorted.len() - 1]);
+        let mut corners = vec![a];
+        let below = right_of(a, b, sorted);
+        hull_beyond(a, b, &below, &mut corners);
+        corners.push(b);
+        let above = right_of(b, a, sorted);
+        hull_beyond(b, a, &above, &mut corners);
+        corners
+    })
+}
+
+/// Add p to the end of a chain of corners, first dropping the corners from which the chain
+/// would not turn left, as long as more than `keep` corners are left
+fn push_turning_left(corners: &mut Vec<Point2d>, keep: usize, p: Point2d) {
+    while corners.len() > keep
+        && orient2d(corners[corners.len() - 2], corners[corners.len() - 1], p) <= 0.0
+    {
+        corners.pop();
+    }
+    corners.push(p);
+}
+
+/// The points strictly to the right of the line from a to b
+fn right_of(a: Point2d, b: Point2d, points: &[Point2d]) -> Vec<Point2d> {
+    points
+        .iter()
+        .cloned()
+        .filter(|&p| orient2d(a, b, p) < 0.0)
+        .collect()
+}
+
+/// Add the corners of the hull between a and b to `corners`, in order, given the points to the
+/// right of the line from a to b
+fn hull_beyond(a: Point2d, b: Point2d, points: &[Point2d], corners: &mut Vec<Point2d>) {
+    let farthest = points
+        .iter()
+        .cloned()
+        .min_by(|&p, &q| orient2d(a, b, p).total_cmp(&orient2d(a, b, q)));
+    if let Some(c) = farthest {
+        hull_beyond(a, c, &right_of(a, c, points), corners);
+        corners.push(c);
+        hull_beyond(c, b, &right_of(c, b, points), corners);
+    }
+}
+
+/// Check the points, sort them along the x-axis then the y-axis and drop repeats, then compute
+/// the corners of their hull with `corners_of`.
+/// Return the lines of the hull counter-clockwise, starting from the point with the smallest x
+/// (then the smallest y) and ending where the first line starts.
+/// Points lying along a side of the hull are skipped over.
+/// Return an error if there are fewer than 3 distinct points, any is not finite,
+/// or they all lie on a line.
+fn closed_hull<F: FnOnce(&[Point2d]) -> Vec<Point2d>>(
+    points: &[Point2d],
+    corners_of: F,
+) -> Result<Vec<Line2d>, Box<dyn Error>> {
+    if !points.iter().all(Point2d::is_finite) {
+        return Err("Cannot compute the convex hull of points which are not finite".into());
+    }
+    let mut sorted = points.to_vec();
+    sorted.sort_unstable_by(|l, r| l.x().total_cmp(&r.x()).then(l.y().total_cmp(&r.y())));
+    sorted.dedup();
+    if sorted.len() < 3 {
+        return Err(format!(
+            "A convex hull needs at least 3 distinct points, got {}",
+            sorted.len()
+        )
+        .into());
+    }
+
+    let mut corners = corners_of(&sorted);
+    if corners.len() < 3 {
+        return Err("Cannot compute the convex hull of points which all lie on a line".into());
+    }
+    let first = corners
+        .iter()
+        .position(|&p| p == sorted[0])
+        .expect("The leftmost point is always on the hull");
+    corners.rotate_left(first);
+
+    Ok((0..corners.len())
+        .map(|i| Line2d {
+            src: corners[i],
+            dst: corners[(i + 1) % corners.len()],
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::points_in_grid::gen_points_in_box;
+    use crate::rand::uniform::Uniform701;
+
+    fn points(coords: &[(f64, f64)]) -> Vec<Point2d> {
+        coords.iter().map(|&(x, y)| Point2d::new(x, y)).collect()
+    }
+
+    /// The hull every algorithm finds, after checking that they all find the same one, that it
+    /// closes and that it turns counter-clockwise at every corner
+    fn agreed_hull(points: &[Point2d]) -> Vec<Point2d> {
+        let hulls = HullAlgorithm::ALL
+            .iter()
+            .map(|algorithm| algorithm.compute(points).unwrap())
+            .collect::<Vec<Vec<Line2d>>>();
+        for (algorithm, hull) in HullAlgorithm::ALL.iter().zip(&hulls) {
+            assert_eq!(hull, &hulls[0], "{} differs", algorithm);
+        }
+        let hull = &hulls[0];
+        for (i, line) in hull.iter().enumerate() {
+            let next = hull[(i + 1) % hull.len()];
+            assert_eq!(line.dst, next.src);
+            assert!(orient2d(line.src, line.dst, next.dst) > 0.0);
+        }
+        hull.iter().map(|line| line.src).collect()
+    }
+
+    fn assert_all_fail(points: &[Point2d]) {
+        for algorithm in HullAlgorithm::ALL.iter() {
+            assert!(
+                algorithm.compute(points).is_err(),
+                "{} succeeded",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn algorithms_agree_on_random_points() {
+        let mut uni = Uniform701::seeded(17);
+        for &n in &[3, 10, 100, 1000] {
+            let points = gen_points_in_box(&mut uni, 10.0, n);
+            let hull = agreed_hull(&points);
+            // Every point lies on or to the left of every side
+            for (i, &a) in hull.iter().enumerate() {
+                let b = hull[(i + 1) % hull.len()];
+                assert!(points.iter().all(|&p| orient2d(a, b, p) >= 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn points_on_the_sides_are_skipped() {
+        // Every point of a 5 by 5 grid lies on or inside the square of its corners
+        let grid = (0..25)
+            .map(|k| ((k % 5) as f64, (k / 5) as f64))
+            .collect::<Vec<(f64, f64)>>();
+        let square = points(&[(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0)]);
+        assert_eq!(agreed_hull(&points(&grid)), square);
+
+        // Points on the hypotenuse and the base of a right triangle
+        let triangle = points(&[
+            (1.0, 3.0),
+            (0.0, 4.0),
+            (2.0, 2.0),
+            (4.0, 0.0),
+            (2.0, 0.0),
+            (0.0, 0.0),
+        ]);
+        assert_eq!(
+            agreed_hull(&triangle),
+            points(&[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)])
+        );
+    }
+
+    #[test]
+    fn repeated_points_are_dropped() {
+        let once = points(&[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (1.0, 1.0)]);
+        let many = (0..10)
+            .flat_map(|_| once.iter().cloned())
+            .collect::<Vec<Point2d>>();
+        assert_eq!(agreed_hull(&many), once[..4].to_vec());
+    }
+
+    #[test]
+    fn points_on_a_line_have_no_hull() {
+        assert_all_fail(&points(&[(0.0, 0.0), (1.0, 0.0), (3.0, 0.0), (2.0, 0.0)]));
+        assert_all_fail(&points(&[(5.0, 1.0), (5.0, -1.0), (5.0, 0.0)]));
+        let diagonal = (0..50)
+            .map(|k| ((k % 7) as f64, (k % 7) as f64 * 3.0))
+            .collect::<Vec<(f64, f64)>>();
+        assert_all_fail(&points(&diagonal));
+    }
+
+    #[test]
+    fn fewer_than_3_distinct_points_have_no_hull() {
+        assert_all_fail(&[]);
+        assert_all_fail(&points(&[(1.0, 2.0)]));
+        assert_all_fail(&points(&[(1.0, 2.0); 10]));
+        assert_all_fail(&points(&[(1.0, 2.0), (3.0, 4.0), (1.0, 2.0), (3.0, 4.0)]));
+    }
+
+    #[test]
+    fn points_which_are_not_finite_have_no_hull() {
+        assert_all_fail(&points(&[
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (0.0, 1.0),
+            (f64::NAN, 0.0),
+        ]));
+        assert_all_fail(&points(&[(0.0, 0.0), (1.0, 0.0), (f64::INFINITY, 1.0)]));
+    }
+}
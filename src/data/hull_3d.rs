@@ -0,0 +1,469 @@
+//! Convex hulls of points in space, as triangle meshes
+
+use crate::data::hull;
+use crate::data::point::{Point2d, Point3d};
+use crate::data::predicates::{collinear, orient3d};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+/// The convex hull of a set of points, as triangles whose corners are counter-clockwise seen
+/// from outside.
+/// If the points all lie in a plane, the hull is a flat polygon, covered by triangles facing
+/// each way.
+pub struct ConvexHull3d {
+    points: Vec<Point3d>,
+    facets: Vec<[usize; 3]>,
+    flat: bool,
+}
+
+impl ConvexHull3d {
+    /// The indices of the points at the corners of each facet, counter-clockwise seen from outside
+    pub fn facets(&self) -> &[[usize; 3]] {
+        &self.facets
+    }
+
+    /// The indices of the points at the corners of the facets, in ascending order.
+    /// Points inside a facet or along one of its edges are left out, but where several facets
+    /// lie in one plane, a point which is not a corner of that face may be a corner of a facet.
+    pub fn vertices(&self) -> Vec<usize> {
+        let mut vertices = self
+            .facets
+            .iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<usize>>();
+        vertices.sort_unstable();
+        vertices.dedup();
+        vertices
+    }
+
+    /// Whether the points all lie in a plane
+    pub fn is_flat(&self) -> bool {
+        self.flat
+    }
+
+    /// The unit normal of each facet, pointing out of the hull
+    pub fn normals(&self) -> Vec<Point3d> {
+        self.facets
+            .iter()
+            .map(|&f| {
+                let n = self.facet_vector(f);
+                n / n.magnitude()
+            })
+            .collect()
+    }
+
+    /// The total area of the facets.  A flat hull counts both of its sides.
+    pub fn surface_area(&self) -> f64 {
+        self.facets
+            .iter()
+            .map(|&f| self.facet_vector(f).magnitude())
+            .sum::<f64>()
+            / 2.0
+    }
+
+    /// Calculate the volume of the hull by splitting it into tetrahedra, each joining a facet to
+    /// the center of the vertices
+    pub fn volume(&self) -> f64 {
+        if self.flat {
+            return 0.0;
+        }
+        let vertices = self.vertices();
+        let center = vertices
+            .iter()
+            .fold(Point3d::default(), |sum, &i| sum + self.points[i])
+            / vertices.len() as f64;
+        self.facets
+            .iter()
+            .map(|&[a, b, c]| {
+                let [a, b, c] = [a, b, c].map(|i| self.points[i] - center);
+                a.dot(&b.cross(&c))
+            })
+            .sum::<f64>()
+            / 6.0
+    }
+
+    /// Write the hull as a Wavefront OBJ mesh, listing every point so that the vertices keep
+    /// their indices
+    pub fn print_obj(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        for p in &self.points {
+            of.write_all(format!("v {} {} {}\n", p.x(), p.y(), p.z()).as_ref())?;
+        }
+        for (f, n) in self.facets.iter().zip(self.normals()) {
+            of.write_all(format!("vn {} {} {}\n", n.x(), n.y(), n.z()).as_ref())?;
+            of.write_all(format!("f {} {} {}\n", f[0] + 1, f[1] + 1, f[2] + 1).as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// The normal of a facet pointing out of the hull, whose length is twice the facet's area
+    fn facet_vector(&self, [a, b, c]: [usize; 3]) -> Point3d {
+        let [a, b, c] = [a, b, c].map(|i| self.points[i]);
+        (b - a).cross(&(c - a))
+    }
+}
+
+/// Compute the convex hull of the points by adding them to a growing hull, each time the point
+/// farthest beyond a facet (the QuickHull algorithm in three dimensions).  Each point outside the
+/// hull is kept in the list of one facet it can see, so that only those points are looked at
+/// again when the facet is replaced.
+/// Return an error if any point is not finite, or the points all lie on a line.
+pub fn convex_hull_3d(points: &[Point3d]) -> Result<ConvexHull3d, Box<dyn Error>> {
+    if !points.iter().all(Point3d::is_finite) {
+        return Err("Cannot compute the convex hull of points which are not finite".into());
+    }
+
+    // Start from the leftmost point and the points farthest from it, its line and its plane
+    let i0 = (0..points.len())
+        .min_by(|&i, &j| points[i].x().total_cmp(&points[j].x()))
+        .ok_or("Cannot compute the convex hull without points")?;
+    let all = (0..points.len()).collect::<Vec<usize>>();
+    let i1 = farthest(points, &all, |p| points[i0].distance_squared_to(&p))
+        .filter(|&i| points[i] != points[i0])
+        .ok_or("Cannot compute the convex hull of identical points")?;
+    let off_line = (0..points.len())
+        .filter(|&i| !collinear(points[i0], points[i1], points[i]))
+        .collect::<Vec<usize>>();
+    let i2 = farthest(points, &off_line, |p| {
+        (points[i1] - points[i0])
+            .cross(&(p - points[i0]))
+            .magnitude_squared()
+    })
+    .ok_or("Cannot compute the convex hull of points which all lie on a line")?;
+    let off_plane = (0..points.len())
+        .filter(|&i| orient3d(points[i0], points[i1], points[i2], points[i]) != 0.0)
+        .collect::<Vec<usize>>();
+    let i3 = farthest(points, &off_plane, |p| {
+        orient3d(points[i0], points[i1], points[i2], p).abs()
+    });
+
+    let facets = match i3 {
+        Some(i3) => {
+            let mut hull = Hull::new(points, [i0, i1, i2, i3]);
+            hull.expand();
+            hull.into_facets()
+        }
+        None => flat_hull(points, [i0, i1, i2])?,
+    };
+    Ok(ConvexHull3d {
+        points: points.to_vec(),
+        facets,
+        flat: i3.is_none(),
+    })
+}
+
+/// The index, among `candidates`, of the point with the greatest `measure`,
+/// taking the first of any ties
+fn farthest<F: Fn(Point3d) -> f64>(
+    points: &[Point3d],
+    candidates: &[usize],
+    measure: F,
+) -> Option<usize> {
+    candidates
+        .iter()
+        .rev()
+        .cloned()
+        .max_by(|&i, &j| measure(points[i]).total_cmp(&measure(points[j])))
+}
+
+/// Compute the hull of points which all lie in the plane through the three corners, as the
+/// polygon of their hull within the plane, split into a fan of triangles facing each way.
+/// The corners are not on a line, so the projected points have a hull, but any error from
+/// finding it is passed on rather than assumed away.
+fn flat_hull(points: &[Point3d], corners: [usize; 3]) -> Result<Vec<[usize; 3]>, Box<dyn Error>> {
+    // Drop the coordinate the normal points most along, so that no two points of the plane
+    // land on the same place
+    let [a, b, c] = corners.map(|i| points[i]);
+    let normal = (b - a).cross(&(c - a));
+    let drop = (1..3).fold(0, |most, i| {
+        if normal[i].abs() > normal[most].abs() {
+            i
+        } else {
+            most
+        }
+    });
+    let (u, v) = ((drop + 1) % 3, (drop + 2) % 3);
+    let projected = points
+        .iter()
+        .map(|p| Point2d::new(p[u], p[v]))
+        .collect::<Vec<Point2d>>();
+
+    // The corners can then be found back from their projections
+    let polygon = hull::monotone_chain(&projected)?
+        .iter()
+        .map(|line| {
+            projected
+                .iter()
+                .position(|&p| p == line.src)
+                .ok_or("A corner of the flat hull is not among the points")
+        })
+        .collect::<Result<Vec<usize>, &str>>()?;
+    let up = (1..polygon.len() - 1).map(|k| [polygon[0], polygon[k], polygon[k + 1]]);
+    let down = (1..polygon.len() - 1).map(|k| [polygon[0], polygon[k + 1], polygon[k]]);
+    Ok(up.chain(down).collect())
+}
+
+/// A facet of the growing hull.
+/// Edge `e` runs from corner `e` to corner `(e + 1) % 3`.
+struct Facet {
+    corners: [usize; 3],
+    /// The facet across each edge
+    adjacent: [usize; 3],
+    /// The points beyond the facet which are not yet inside the hull
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+struct Hull<'a> {
+    points: &'a [Point3d],
+    facets: Vec<Facet>,
+}
+
+impl<'a> Hull<'a> {
+    /// Construct the tetrahedron with the given corners, and sort the other points among the
+    /// facets they lie beyond
+    fn new(points: &'a [Point3d], corners: [usize; 4]) -> Self {
+        let [i0, i1, i2, i3] = corners;
+        // Turn the base so that the apex lies below it, i.e., inside the hull
+        let (i1, i2) = if orient3d(points[i0], points[i1], points[i2], points[i3]) > 0.0 {
+            (i1, i2)
+        } else {
+            (i2, i1)
+        };
+        let corners = [[i0, i1, i2], [i1, i0, i3], [i2, i1, i3], [i0, i2, i3]];
+        let adjacent = [[1, 2, 3], [0, 3, 2], [0, 1, 3], [0, 2, 1]];
+        let mut hull = Self {
+            points,
+            facets: corners
+                .iter()
+                .zip(&adjacent)
+                .map(|(&corners, &adjacent)| Facet {
+                    corners,
+                    adjacent,
+                    outside: vec![],
+                    alive: true,
+                })
+                .collect(),
+        };
+        let others = (0..points.len())
+            .filter(|i| ![i0, i1, i2, i3].contains(i))
+            .collect::<Vec<usize>>();
+        hull.assign(&others, &[0, 1, 2, 3]);
+        hull
+    }
+
+    /// How far beyond the facet the point lies, positive if the facet can see it
+    fn height(&self, f: usize, p: usize) -> f64 {
+        let [a, b, c] = self.facets[f].corners.map(|i| self.points[i]);
+        -orient3d(a, b, c, self.points[p])
+    }
+
+    /// Give each point to the first of the facets which can see it, dropping those which none
+    /// can see since they are inside the hull or on its surface
+    fn assign(&mut self, candidates: &[usize], facets: &[usize]) {
+        for &p in candidates {
+            if let Some(&f) = facets.iter().find(|&&f| self.height(f, p) > 0.0) {
+                self.facets[f].outside.push(p);
+            }
+        }
+    }
+
+    /// Add points to the hull until no point lies beyond any facet.
+    /// A facet gets all the points it will ever have when it is made, and new facets go at the
+    /// end, so a single pass over the facets finds every point.
+    fn expand(&mut self) {
+        let mut f = 0;
+        while f < self.facets.len() {
+            if self.facets[f].alive && !self.facets[f].outside.is_empty() {
+                let eye = *self.facets[f]
+                    .outside
+                    .iter()
+                    .max_by(|&&p, &&q| self.height(f, p).total_cmp(&self.height(f, q)))
+                    .expect("A facet with points outside it has a farthest one");
+                self.add(f, eye);
+            }
+            f += 1;
+        }
+    }
+
+    /// Add a point beyond facet `f` to the hull, replacing the facets which can see it with a
+    /// cone of facets joining it to the edges around them
+    fn add(&mut self, f: usize, eye: usize) {
+        // Find the facets which can see the point, and the edges between them and the rest,
+        // which go around the point counter-clockwise
+        let mut horizon = vec![];
+        let mut visible = vec![f];
+        let mut stack = vec![f];
+        self.facets[f].alive = false;
+        while let Some(g) = stack.pop() {
+            for e in 0..3 {
+                let h = self.facets[g].adjacent[e];
+                if !self.facets[h].alive {
+                    continue;
+                }
+                if self.height(h, eye) > 0.0 {
+                    self.facets[h].alive = false;
+                    visible.push(h);
+                    stack.push(h);
+                } else {
+                    let corners = self.facets[g].corners;
+                    horizon.push((corners[e], corners[(e + 1) % 3], h));
+                }
+            }
+        }
+
+        let mut starting_at = HashMap::new();
+        let mut created = vec![];
+        for &(a, b, beyond) in &horizon {
+            let g = self.facets.len();
+            self.facets.push(Facet {
+                corners: [a, b, eye],
+                adjacent: [beyond, usize::MAX, usize::MAX],
+                outside: vec![],
+                alive: true,
+            });
+            let back = (0..3)
+                .find(|&e| self.facets[beyond].corners[e] == b)
+                .expect("The facet beyond a horizon edge shares its corners");
+            self.facets[beyond].adjacent[back] = g;
+            starting_at.insert(a, g);
+            created.push(g);
+        }
+        // The new facets fan around the point, each meeting the one which starts where it ends
+        for &g in &created {
+            let after = starting_at[&self.facets[g].corners[1]];
+            self.facets[g].adjacent[1] = after;
+            self.facets[after].adjacent[2] = g;
+        }
+
+        let orphans = visible
+            .iter()
+            .flat_map(|&g| std::mem::take(&mut self.facets[g].outside))
+            .filter(|&p| p != eye)
+            .collect::<Vec<usize>>();
+        self.assign(&orphans, &created);
+    }
+
+    fn into_facets(self) -> Vec<[usize; 3]> {
+        self.facets
+            .into_iter()
+            .filter(|facet| facet.alive)
+            .map(|facet| facet.corners)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::test_support::{assert_close, grid, random_sites_3d, with_repeats};
+
+    const TOLERANCE: f64 = 1e-9;
+
+    /// Check that each edge of a facet is crossed the other way by as many facets, so that the
+    /// facets close up, once each for a solid hull, and that no point lies outside its facets
+    fn assert_encloses(points: &[Point3d], hull: &ConvexHull3d) {
+        let mut edges = HashMap::new();
+        for &[a, b, c] in hull.facets() {
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), count) in &edges {
+            assert_eq!(edges.get(&(b, a)), Some(count));
+        }
+        if !hull.is_flat() {
+            assert!(edges.values().all(|&count| count == 1));
+            for &[a, b, c] in hull.facets() {
+                let [a, b, c] = [a, b, c].map(|i| points[i]);
+                assert!(points.iter().all(|&p| orient3d(a, b, c, p) >= 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn random_points_are_enclosed() {
+        for &n in &[4, 50, 1000] {
+            let points = random_sites_3d(18, 10.0, n);
+            let hull = convex_hull_3d(&points).unwrap();
+            assert_encloses(&points, &hull);
+            assert!(!hull.is_flat());
+            assert!(hull.volume() > 0.0 && hull.volume() < 1000.0);
+        }
+    }
+
+    #[test]
+    fn hull_of_a_cube_with_points_on_its_faces_and_edges() {
+        // A 3 by 3 by 3 grid twice, with every point but the corners on a face, an edge or inside
+        let points = with_repeats(&grid(3, 0.0), 27);
+        let hull = convex_hull_3d(&points).unwrap();
+        assert_encloses(&points, &hull);
+        assert_close(hull.volume(), 8.0, TOLERANCE);
+        assert_close(hull.surface_area(), 24.0, TOLERANCE);
+        let vertices = hull.vertices();
+        let corners = [0, 2, 6, 8, 18, 20, 24, 26];
+        assert!(corners
+            .iter()
+            .all(|&i| vertices.contains(&i) || vertices.contains(&(i + 27))));
+        assert!(!vertices.contains(&13) && !vertices.contains(&40));
+    }
+
+    #[test]
+    fn hull_of_a_tetrahedron() {
+        let p = |x: f64, y: f64, z: f64| Point3d::new(x, y, z);
+        let points = [
+            p(0.0, 0.0, 0.0),
+            p(1.0, 0.0, 0.0),
+            p(0.0, 1.0, 0.0),
+            p(0.0, 0.0, 1.0),
+            // On an edge, on a face and inside
+            p(0.5, 0.0, 0.0),
+            p(0.25, 0.25, 0.5),
+            p(0.1, 0.1, 0.1),
+        ];
+        let hull = convex_hull_3d(&points).unwrap();
+        assert_encloses(&points, &hull);
+        assert_eq!(hull.facets().len(), 4);
+        assert_eq!(hull.vertices(), vec![0, 1, 2, 3]);
+        assert_close(hull.volume(), 1.0 / 6.0, TOLERANCE);
+        assert_close(hull.surface_area(), 1.5 + 3.0f64.sqrt() / 2.0, TOLERANCE);
+    }
+
+    #[test]
+    fn coplanar_points_have_a_flat_hull() {
+        // A 5 by 5 grid with each point five times, in the tilted plane z = x + 2y, which covers
+        // a square of area 16 * √6
+        let points = grid(5, 0.0)
+            .into_iter()
+            .map(|p: Point3d| Point3d::new(p.x(), p.y(), p.x() + 2.0 * p.y()))
+            .collect::<Vec<Point3d>>();
+        let hull = convex_hull_3d(&points).unwrap();
+        assert_encloses(&points, &hull);
+        assert!(hull.is_flat());
+        assert_eq!(hull.volume(), 0.0);
+        let area = 16.0 * 6.0f64.sqrt();
+        assert_close(hull.surface_area(), 2.0 * area, TOLERANCE * area);
+        let normals = hull.normals();
+        assert!(normals
+            .iter()
+            .all(|n| n.cross(&normals[0]).magnitude() < TOLERANCE));
+    }
+
+    #[test]
+    fn degenerate_points_have_no_hull() {
+        let p = |x: f64, y: f64, z: f64| Point3d::new(x, y, z);
+        assert!(convex_hull_3d(&[]).is_err());
+        assert!(convex_hull_3d(&[p(1.0, 2.0, 3.0); 4]).is_err());
+        let line = (0..5).map(|i| p(i as f64, 2.0 * i as f64, 3.0 * i as f64));
+        assert!(convex_hull_3d(&line.collect::<Vec<Point3d>>()).is_err());
+        let points = [
+            p(0.0, 0.0, 0.0),
+            p(1.0, 0.0, 0.0),
+            p(0.0, 1.0, f64::INFINITY),
+        ];
+        assert!(convex_hull_3d(&points).is_err());
+    }
+}
@@ -1,106 +1,821 @@
+use crate::data::union_find::UnionFind;
+use crate::rand::sampler::Sampler701;
 use crate::rand::uniform::Uniform701;
+use std::error::Error;
 
-type Site = (usize, usize);
+/// The row and column of a site in the lattice
+pub type Site = (usize, usize);
+
+/// The offsets from a site to the neighbors before it in row-major order, nearest first.
+/// The offsets to the neighbors after it are the negatives of these.
+const BACKWARD: [(isize, isize); 6] = [(-1, 0), (0, -1), (-1, -1), (-1, 1), (-2, 0), (0, -2)];
+
+/// Which sites of the lattice are joined by bonds
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    /// The nearest neighbors, sharing an edge
+    Four,
+    /// The nearest and the next-nearest neighbors, sharing an edge or a corner
+    Eight,
+    /// The nearest, next-nearest and third-nearest neighbors, the last two sites away along a
+    /// row or column
+    Twelve,
+}
+
+impl Connectivity {
+    /// The number of neighbors of each site
+    pub fn degree(&self) -> usize {
+        match self {
+            Self::Four => 4,
+            Self::Eight => 8,
+            Self::Twelve => 12,
+        }
+    }
+
+    /// The offsets of the bonds from each site to its neighbors above it or to its left.
+    /// Every bond of the lattice leads from one site by exactly one of these.
+    pub fn bonds(&self) -> &'static [(isize, isize)] {
+        &BACKWARD[..self.degree() / 2]
+    }
+}
+
+impl std::fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.degree())
+    }
+}
+
+impl std::str::FromStr for Connectivity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" => Ok(Self::Four),
+            "8" => Ok(Self::Eight),
+            "12" => Ok(Self::Twelve),
+            _ => Err(format!("Expected 4, 8 or 12 neighbors, got '{}'", s)),
+        }
+    }
+}
 
 pub struct Lattice {
     pub grid: Vec<Vec<bool>>,
+    /// Whether each bond is open, with one grid for each offset in `Connectivity::bonds`.
+    /// `bonds[k][i][j]` is the bond from site (i, j) by the k-th offset.
+    pub bonds: Vec<Vec<Vec<bool>>>,
+    pub connectivity: Connectivity,
+    /// Whether the lattice wraps around, joining the first row to the last and the first column
+    /// to the last
+    pub periodic: bool,
     pub box_len: usize,
 }
 
 impl Lattice {
-    pub fn populate(p: f64, box_len: usize, uni: &mut Uniform701) -> Self {
-        Self {
-            grid: (0..box_len)
-                .map(|_| (0..box_len).map(|_| uni.next() < p).collect())
-                .collect(),
+    /// Populate a `box_len`x`box_len` open lattice, occupying each site with probability `p`
+    pub fn populate(p: f64, box_len: usize, uni: &mut Uniform701) -> Result<Self, Box<dyn Error>> {
+        Self::populate_mixed(p, 1.0, box_len, Connectivity::Four, false, uni)
+    }
+
+    /// Populate a `box_len`x`box_len` lattice with every site occupied,
+    /// opening each bond with probability `p`
+    pub fn populate_bonds(
+        p: f64,
+        box_len: usize,
+        connectivity: Connectivity,
+        periodic: bool,
+        uni: &mut Uniform701,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::populate_mixed(1.0, p, box_len, connectivity, periodic, uni)
+    }
+
+    /// Populate a `box_len`x`box_len` lattice, occupying each site with probability `p_site`,
+    /// then opening each bond with probability `p_bond`.
+    /// Two neighboring sites are joined if both are occupied and the bond between them is open.
+    pub fn populate_mixed(
+        p_site: f64,
+        p_bond: f64,
+        box_len: usize,
+        connectivity: Connectivity,
+        periodic: bool,
+        uni: &mut Uniform701,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&p_site) {
+            return Err(format!(
+                "The occupation probability must be in [0, 1], got {}",
+                p_site
+            )
+            .into());
+        }
+        if !(0.0..=1.0).contains(&p_bond) {
+            return Err(format!("The bond probability must be in [0, 1], got {}", p_bond).into());
+        }
+        // Nothing is drawn for certain events, so site percolation draws one number per site
+        let mut grid_with = |p: f64| -> Vec<Vec<bool>> {
+            (0..box_len)
+                .map(|_| (0..box_len).map(|_| p == 1.0 || uni.next() < p).collect())
+                .collect()
+        };
+        let grid = grid_with(p_site);
+        let bonds = connectivity
+            .bonds()
+            .iter()
+            .map(|_| grid_with(p_bond))
+            .collect();
+        Ok(Self {
+            grid,
+            bonds,
+            connectivity,
+            periodic,
             box_len,
+        })
+    }
+
+    /// The site reached by moving `(di, dj)` from site (i, j), wrapping around a periodic
+    /// lattice, or `None` if it is off an open lattice
+    fn step(&self, (i, j): Site, (di, dj): (isize, isize)) -> Option<Site> {
+        let n = self.box_len as isize;
+        let (x, y) = (i as isize + di, j as isize + dj);
+        if self.periodic {
+            Some((x.rem_euclid(n) as usize, y.rem_euclid(n) as usize))
+        } else if x < 0 || y < 0 || x >= n || y >= n {
+            None
+        } else {
+            Some((x as usize, y as usize))
         }
     }
 
-    pub fn create_clusters(&self) -> Clusters {
-        let mut clusters = vec![];
+    /// The site at the other end of the bond from the site by the k-th offset in
+    /// `Connectivity::bonds`, whether or not the bond is open, or `None` if there is no such bond
+    pub fn bond_end(&self, site: Site, k: usize) -> Option<Site> {
+        self.step(site, self.connectivity.bonds()[k])
+    }
+
+    /// The site reached from site (i, j) by the k-th offset in `Connectivity::bonds`, if it is
+    /// in the lattice and joined to (i, j): both sites are occupied and the bond is open
+    pub fn joined(&self, (i, j): Site, k: usize) -> Option<Site> {
+        let (x, y) = self.bond_end((i, j), k)?;
+        if self.grid[i][j] && self.grid[x][y] && self.bonds[k][i][j] {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// The sites joined to the site by each of its bonds, with the offset to each.
+    /// On a small periodic lattice, a site may be reached by more than one bond.
+    pub fn neighbors(&self, site: Site) -> impl Iterator<Item = (Site, (isize, isize))> + '_ {
+        self.connectivity
+            .bonds()
+            .iter()
+            .enumerate()
+            .flat_map(move |(k, &(di, dj))| {
+                let before = self.joined(site, k).map(|to| (to, (di, dj)));
+                // The bond to the site after this one leads from that site back to this one
+                let after = self
+                    .step(site, (-di, -dj))
+                    .filter(|&from| self.joined(from, k).is_some())
+                    .map(|from| (from, (-di, -dj)));
+                before.into_iter().chain(after)
+            })
+    }
+
+    /// A lattice drawn row by row, with `#` for an occupied site, and every bond open
+    #[cfg(test)]
+    pub(crate) fn drawn(rows: &[&str], connectivity: Connectivity, periodic: bool) -> Self {
+        let n = rows.len();
+        let grid = rows
+            .iter()
+            .map(|row| row.chars().map(|c| c == '#').collect::<Vec<bool>>())
+            .collect::<Vec<Vec<bool>>>();
+        assert!(grid.iter().all(|row| row.len() == n));
+        Self {
+            grid,
+            bonds: vec![vec![vec![true; n]; n]; connectivity.bonds().len()],
+            connectivity,
+            periodic,
+            box_len: n,
+        }
+    }
+
+    /// Label the clusters of occupied sites joined by open bonds (Hoshen–Kopelman).
+    /// A single pass over the rows joins each site to the sites before it which it is joined to
+    /// with union-find, and a second pass numbers the clusters in the order their first site is
+    /// met, so the whole labelling takes nearly linear time in the number of sites.
+    /// See `Percolation` for how each cluster crosses the lattice.
+    pub fn label_clusters(&self) -> ClusterLabels {
+        let n = self.box_len;
+        let mut sets = UnionFind::new(n * n);
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..self.bonds.len() {
+                    if let Some((x, y)) = self.joined((i, j), k) {
+                        sets.union(i * n + j, x * n + y);
+                    }
+                }
+            }
+        }
 
-        let mut world: Vec<Site> = (0..self.box_len)
-            .flat_map(|i| (0..self.box_len).map(move |j| (i, j)))
+        // Map the root of each cluster to its label as the cluster is first met
+        let mut label_of_root = vec![None; n * n];
+        let mut sizes = vec![];
+        let mut percolation: Vec<Percolation> = vec![];
+        // The rows and columns each cluster touches along the edges of the lattice:
+        // the first row, last row, first column and last column
+        let mut edges: Vec<[bool; 4]> = vec![];
+        let labels = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| {
+                        if !self.grid[i][j] {
+                            return None;
+                        }
+                        let root = sets.find(i * n + j);
+                        let label = *label_of_root[root].get_or_insert_with(|| {
+                            sizes.push(sets.size(root));
+                            percolation.push(Percolation::default());
+                            edges.push([false; 4]);
+                            sizes.len() - 1
+                        });
+                        let touches = &mut edges[label];
+                        touches[0] |= i == 0;
+                        touches[1] |= i == n - 1;
+                        touches[2] |= j == 0;
+                        touches[3] |= j == n - 1;
+                        Some(label)
+                    })
+                    .collect()
+            })
             .collect();
 
-        // Loop while there is some site to be visited
-        while let Some(&(i, j)) = world.last() {
-            // Check if this site is occupied
-            if self.grid[i][j] {
-                // The site is occupied.  Create a new cluster, currently empty.
-                let mut new_cluster: Vec<Site> = vec![];
+        let mut labels = ClusterLabels {
+            labels,
+            sizes,
+            percolation,
+        };
+        if self.periodic {
+            self.find_wrapping(&mut labels);
+        } else {
+            for (crossing, touches) in labels.percolation.iter_mut().zip(&edges) {
+                crossing.spans_vertically = touches[0] && touches[1];
+                crossing.spans_horizontally = touches[2] && touches[3];
+            }
+        }
+        labels
+    }
 
-                // Create a stack of sites to visit, initialized to be the current site.
-                // Other sites will be added as we visit each.
+    /// Find how each cluster crosses the periodic lattice.
+    /// Each cluster is walked from its first site, giving each site its position unwrapped from
+    /// the lattice.  A cluster wraps if a bond leads to a site already given a position other
+    /// than the one across the bond, i.e., if the walk can get back to a site around the lattice.
+    /// A cluster spans the lattice if its unwrapped sites cover as many rows or columns as the
+    /// lattice has, since touching the first and last row only means crossing the boundary.
+    fn find_wrapping(&self, labels: &mut ClusterLabels) {
+        let n = self.box_len;
+        let mut unwrapped: Vec<Option<(isize, isize)>> = vec![None; n * n];
+        for i in 0..n {
+            for j in 0..n {
+                let label = match labels.labels[i][j] {
+                    Some(label) if unwrapped[i * n + j].is_none() => label,
+                    _ => continue,
+                };
+                let crossing = &mut labels.percolation[label];
+                unwrapped[i * n + j] = Some((i as isize, j as isize));
+                let (mut low, mut high) = ((i as isize, j as isize), (i as isize, j as isize));
                 let mut to_visit = vec![(i, j)];
-
-                // Iterate over all the sites in the to_visit stack.
-                while let Some((x, y)) = to_visit.pop() {
-                    // See if we have visited this site before.  If we have, it will not be in `world`.
-                    if let Some(ix) = world.iter().position(|&v| v == (x, y)) {
-                        // We have not visited this site yet
-                        // Remove it from the world since we're visiting it now
-                        world.swap_remove(ix);
-                        // Is it a part of this cluster?
-                        if self.grid[x][y] {
-                            // Add it to the cluster
-                            new_cluster.push((x, y));
-                            // Queue up all neighboring sites to be visited
-                            if y < self.box_len - 1 {
-                                to_visit.push((x, y + 1));
+                while let Some(site) = to_visit.pop() {
+                    let (x, y) = unwrapped[site.0 * n + site.1].unwrap();
+                    low = (low.0.min(x), low.1.min(y));
+                    high = (high.0.max(x), high.1.max(y));
+                    for ((a, b), (di, dj)) in self.neighbors(site) {
+                        let across = (x + di, y + dj);
+                        match unwrapped[a * n + b] {
+                            None => {
+                                unwrapped[a * n + b] = Some(across);
+                                to_visit.push((a, b));
                             }
-                            if x < self.box_len - 1 {
-                                to_visit.push((x + 1, y));
-                            }
-                            if y > 0 {
-                                to_visit.push((x, y - 1));
-                            }
-                            if x > 0 {
-                                to_visit.push((x - 1, y));
+                            Some(found) => {
+                                crossing.wraps_vertically |= found.0 != across.0;
+                                crossing.wraps_horizontally |= found.1 != across.1;
                             }
                         }
                     }
                 }
-                clusters.push(new_cluster);
-            } else {
-                // The site is not occupied.
-                // The .last() method returned a reference to the element but did not remove it.
-                // .pop() will remove the last element.
-                world.pop();
+                crossing.spans_vertically = high.0 - low.0 + 1 >= n as isize;
+                crossing.spans_horizontally = high.1 - low.1 + 1 >= n as isize;
             }
         }
+    }
+
+    /// Collect the sites of each cluster, see `label_clusters`
+    pub fn create_clusters(&self) -> Clusters {
+        self.label_clusters().to_clusters()
+    }
+}
+
+/// How a cluster crosses the lattice
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Percolation {
+    /// The cluster has a site in the first row and in the last row.
+    /// On a periodic lattice, the cluster covers every row once unwrapped.
+    pub spans_vertically: bool,
+    /// The cluster has a site in the first column and in the last column.
+    /// On a periodic lattice, the cluster covers every column once unwrapped.
+    pub spans_horizontally: bool,
+    /// The cluster joins back onto itself around the periodic lattice from top to bottom
+    pub wraps_vertically: bool,
+    /// The cluster joins back onto itself around the periodic lattice from left to right
+    pub wraps_horizontally: bool,
+}
+
+impl std::ops::BitOr for Percolation {
+    type Output = Self;
+
+    /// Combine the crossings of two clusters, e.g., to find how any cluster of a lattice
+    /// crosses it
+    fn bitor(self, rhs: Self) -> Self {
+        Self {
+            spans_vertically: self.spans_vertically || rhs.spans_vertically,
+            spans_horizontally: self.spans_horizontally || rhs.spans_horizontally,
+            wraps_vertically: self.wraps_vertically || rhs.wraps_vertically,
+            wraps_horizontally: self.wraps_horizontally || rhs.wraps_horizontally,
+        }
+    }
+}
+
+/// The standard ways of deciding that a lattice percolates, each giving a probability R_L(p)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Criterion {
+    SpansVertically,
+    SpansHorizontally,
+    SpansEither,
+    SpansBoth,
+    WrapsVertically,
+    WrapsHorizontally,
+    WrapsEither,
+    WrapsBoth,
+    /// Wraps in one direction but not the other
+    WrapsOne,
+}
+
+impl Criterion {
+    pub const ALL: [Self; 9] = [
+        Self::SpansVertically,
+        Self::SpansHorizontally,
+        Self::SpansEither,
+        Self::SpansBoth,
+        Self::WrapsVertically,
+        Self::WrapsHorizontally,
+        Self::WrapsEither,
+        Self::WrapsBoth,
+        Self::WrapsOne,
+    ];
+
+    pub const SPANNING: [Self; 4] = [
+        Self::SpansVertically,
+        Self::SpansHorizontally,
+        Self::SpansEither,
+        Self::SpansBoth,
+    ];
+
+    /// The criteria which can only be met on a periodic lattice
+    pub const WRAPPING: [Self; 5] = [
+        Self::WrapsVertically,
+        Self::WrapsHorizontally,
+        Self::WrapsEither,
+        Self::WrapsBoth,
+        Self::WrapsOne,
+    ];
+
+    pub fn is_met_by(&self, crossing: &Percolation) -> bool {
+        let spans = (crossing.spans_vertically, crossing.spans_horizontally);
+        let wraps = (crossing.wraps_vertically, crossing.wraps_horizontally);
+        match self {
+            Self::SpansVertically => spans.0,
+            Self::SpansHorizontally => spans.1,
+            Self::SpansEither => spans.0 || spans.1,
+            Self::SpansBoth => spans.0 && spans.1,
+            Self::WrapsVertically => wraps.0,
+            Self::WrapsHorizontally => wraps.1,
+            Self::WrapsEither => wraps.0 || wraps.1,
+            Self::WrapsBoth => wraps.0 && wraps.1,
+            Self::WrapsOne => wraps.0 != wraps.1,
+        }
+    }
+}
+
+impl std::fmt::Display for Criterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::SpansVertically => "Spans vertically",
+            Self::SpansHorizontally => "Spans horizontally",
+            Self::SpansEither => "Spans either way",
+            Self::SpansBoth => "Spans both ways",
+            Self::WrapsVertically => "Wraps vertically",
+            Self::WrapsHorizontally => "Wraps horizontally",
+            Self::WrapsEither => "Wraps either way",
+            Self::WrapsBoth => "Wraps both ways",
+            Self::WrapsOne => "Wraps one way only",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The cluster each site of a lattice belongs to
+pub struct ClusterLabels {
+    labels: Vec<Vec<Option<usize>>>,
+    sizes: Vec<usize>,
+    percolation: Vec<Percolation>,
+}
+
+impl ClusterLabels {
+    /// The label of the cluster at each site, indexed like `Lattice::grid`,
+    /// or `None` where the site is empty
+    pub fn labels(&self) -> &[Vec<Option<usize>>] {
+        &self.labels
+    }
+
+    /// The label of the cluster holding the site, or `None` if the site is empty
+    pub fn label(&self, (i, j): Site) -> Option<usize> {
+        self.labels[i][j]
+    }
+
+    /// The number of sites in each cluster, indexed by label
+    pub fn sizes(&self) -> &[usize] {
+        &self.sizes
+    }
+
+    /// How each cluster crosses the lattice, indexed by label
+    pub fn percolation(&self) -> &[Percolation] {
+        &self.percolation
+    }
+
+    /// How any cluster crosses the lattice
+    pub fn lattice_percolation(&self) -> Percolation {
+        self.percolation
+            .iter()
+            .fold(Percolation::default(), |all, &crossing| all | crossing)
+    }
+
+    pub fn n_clusters(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// The mean size of the cluster holding an occupied site, leaving out the clusters which
+    /// meet the criterion, i.e., the sum of the squared sizes over the sum of the sizes.
+    /// Zero if every cluster is left out.
+    pub fn mean_size_excluding(&self, criterion: Criterion) -> f64 {
+        let (squares, sites) = self
+            .sizes
+            .iter()
+            .zip(&self.percolation)
+            .filter(|(_, crossing)| !criterion.is_met_by(crossing))
+            .fold((0.0, 0.0), |(squares, sites), (&size, _)| {
+                (squares + (size * size) as f64, sites + size as f64)
+            });
+        if sites > 0.0 {
+            squares / sites
+        } else {
+            0.0
+        }
+    }
 
+    /// The label of the largest cluster, or `None` if the lattice is empty
+    pub fn largest(&self) -> Option<usize> {
+        (0..self.sizes.len()).max_by_key(|&label| self.sizes[label])
+    }
+
+    /// Collect the sites of each cluster, in order of their labels
+    pub fn to_clusters(&self) -> Clusters {
+        let mut clusters = self
+            .sizes
+            .iter()
+            .map(|&size| Vec::with_capacity(size))
+            .collect::<Vec<Vec<Site>>>();
+        for (i, row) in self.labels.iter().enumerate() {
+            for (j, label) in row.iter().enumerate() {
+                if let Some(label) = *label {
+                    clusters[label].push((i, j));
+                }
+            }
+        }
         Clusters {
             clusters,
-            box_len: self.box_len,
+            percolation: self.percolation.clone(),
         }
     }
 }
 
 pub struct Clusters {
     pub clusters: Vec<Vec<Site>>,
-    box_len: usize,
+    percolation: Vec<Percolation>,
 }
 
 impl Clusters {
+    /// How each cluster crosses the lattice, in the same order as the clusters
+    pub fn percolation(&self) -> &[Percolation] {
+        &self.percolation
+    }
+
+    /// Get a new instance of this type holding only the clusters which meet the criterion
+    pub fn get_clusters_meeting(&self, criterion: Criterion) -> Self {
+        let (clusters, percolation) = self
+            .clusters
+            .iter()
+            .zip(&self.percolation)
+            .filter(|(_, crossing)| criterion.is_met_by(crossing))
+            .map(|(cluster, &crossing)| (cluster.clone(), crossing))
+            .unzip();
+        Self {
+            clusters,
+            percolation,
+        }
+    }
+
     /// Get a new instance of this type where all the elements are percolating clusters
     /// A percolating cluster is a cluster with an element at the top and bottom
     pub fn get_percolating_clusters(&self) -> Self {
-        let mut pc: Vec<Vec<Site>> = vec![];
-        for cluster in &self.clusters {
-            if let Some(entry_element) = cluster.iter().find(|&&site| site.0 == self.box_len - 1) {
-                // We found an element along the top row
-                if let Some(exit_element) = cluster.iter().find(|&&site| site.0 == 0) {
-                    // We also found an element along the bottom row
-                    // Because this is a cluster, these must be connected
-                    pc.push(cluster.clone());
+        self.get_clusters_meeting(Criterion::SpansVertically)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Label the clusters by flooding out from the first site of each in row-major order,
+    /// stepping by every offset either way
+    fn flood_labels(lattice: &Lattice) -> Vec<Vec<Option<usize>>> {
+        let n = lattice.box_len;
+        let mut labels = vec![vec![None; n]; n];
+        let mut next = 0;
+        for i in 0..n {
+            for j in 0..n {
+                if !lattice.grid[i][j] || labels[i][j].is_some() {
+                    continue;
                 }
+                labels[i][j] = Some(next);
+                let mut to_visit = vec![(i as isize, j as isize)];
+                while let Some((x, y)) = to_visit.pop() {
+                    for &(di, dj) in lattice.connectivity.bonds() {
+                        for (a, b) in [(x + di, y + dj), (x - di, y - dj)] {
+                            let (a, b) = if lattice.periodic {
+                                (a.rem_euclid(n as isize), b.rem_euclid(n as isize))
+                            } else if a >= 0 && b >= 0 && a < n as isize && b < n as isize {
+                                (a, b)
+                            } else {
+                                continue;
+                            };
+                            let (a, b) = (a as usize, b as usize);
+                            if lattice.grid[a][b] && labels[a][b].is_none() {
+                                labels[a][b] = Some(next);
+                                to_visit.push((a as isize, b as isize));
+                            }
+                        }
+                    }
+                }
+                next += 1;
             }
         }
-        Self {
-            clusters: pc,
-            box_len: self.box_len,
+        labels
+    }
+
+    #[test]
+    fn clusters_depend_on_the_connectivity() {
+        let rows = ["##..#", "#..##", "..#..", "#...#", "##.##"];
+
+        let labels = Lattice::drawn(&rows, Connectivity::Four, false).label_clusters();
+        assert_eq!(labels.sizes(), &[3, 3, 1, 3, 3]);
+        assert_eq!(labels.label((1, 0)), Some(0));
+        assert_eq!(labels.label((1, 3)), Some(1));
+        assert_eq!(labels.label((2, 2)), Some(2));
+        assert_eq!(labels.label((4, 1)), Some(3));
+        assert_eq!(labels.label((4, 3)), Some(4));
+        assert_eq!(labels.label((0, 2)), None);
+
+        // The site in the middle touches a corner of the cluster above it on the right
+        let labels = Lattice::drawn(&rows, Connectivity::Eight, false).label_clusters();
+        assert_eq!(labels.sizes(), &[3, 4, 3, 3]);
+        assert_eq!(labels.label((2, 2)), Some(1));
+        assert_eq!(labels.label((4, 3)), Some(3));
+
+        // The sites two apart down the first and last columns and along the last row join
+        // every cluster
+        let labels = Lattice::drawn(&rows, Connectivity::Twelve, false).label_clusters();
+        assert_eq!(labels.sizes(), &[13]);
+        assert_eq!(labels.largest(), Some(0));
+        let clusters = labels.to_clusters();
+        assert_eq!(clusters.clusters[0].len(), 13);
+        assert_eq!(clusters.clusters[0][..3], [(0, 0), (0, 1), (0, 4)]);
+    }
+
+    #[test]
+    fn labels_match_a_flood_fill() {
+        let mut uni = Uniform701::seeded(19);
+        for &connectivity in &[
+            Connectivity::Four,
+            Connectivity::Eight,
+            Connectivity::Twelve,
+        ] {
+            for &periodic in &[false, true] {
+                for &p in &[0.3, 0.6, 0.9] {
+                    let mut lattice = Lattice::populate(p, 40, &mut uni).unwrap();
+                    lattice.connectivity = connectivity;
+                    lattice.bonds = vec![vec![vec![true; 40]; 40]; connectivity.bonds().len()];
+                    lattice.periodic = periodic;
+
+                    let labels = lattice.label_clusters();
+                    assert_eq!(labels.labels(), &flood_labels(&lattice)[..]);
+                    let clusters = labels.to_clusters();
+                    for (cluster, &size) in clusters.clusters.iter().zip(labels.sizes()) {
+                        assert_eq!(cluster.len(), size);
+                    }
+                    let occupied = lattice.grid.iter().flatten().filter(|&&s| s).count();
+                    assert_eq!(labels.sizes().iter().sum::<usize>(), occupied);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bonds_decide_which_sites_join() {
+        // Only the bonds down each column open, then only those along each row
+        let mut lattice = Lattice::drawn(&["###", "###", "###"], Connectivity::Four, false);
+        lattice.bonds[1] = vec![vec![false; 3]; 3];
+        let labels = lattice.label_clusters();
+        assert_eq!(labels.sizes(), &[3, 3, 3]);
+        assert_eq!(labels.labels()[2], [Some(0), Some(1), Some(2)]);
+        let vertical = Percolation {
+            spans_vertically: true,
+            ..Percolation::default()
+        };
+        assert!(labels.percolation().iter().all(|&p| p == vertical));
+
+        lattice.bonds.swap(0, 1);
+        let labels = lattice.label_clusters();
+        assert_eq!(labels.sizes(), &[3, 3, 3]);
+        assert_eq!(labels.labels()[2], [Some(2); 3]);
+
+        // Cutting both bonds to a corner leaves it alone
+        let mut lattice = Lattice::drawn(&["###", "###", "###"], Connectivity::Four, false);
+        lattice.bonds[1][0][1] = false;
+        lattice.bonds[0][1][0] = false;
+        assert_eq!(lattice.label_clusters().sizes(), &[1, 8]);
+        // An open bond does not join an empty site
+        lattice.grid[1][1] = false;
+        assert_eq!(lattice.joined((1, 2), 1), None);
+        assert_eq!(lattice.joined((1, 1), 0), None);
+        assert_eq!(lattice.joined((2, 1), 0), None);
+        assert_eq!(lattice.label_clusters().sizes(), &[1, 7]);
+
+        // Only the bonds two apart down each column, which join alternate rows
+        let mut lattice = Lattice::drawn(&["####"; 4], Connectivity::Twelve, false);
+        for k in (0..6).filter(|&k| k != 4) {
+            lattice.bonds[k] = vec![vec![false; 4]; 4];
         }
+        let labels = lattice.label_clusters();
+        assert_eq!(labels.sizes(), &[2; 8]);
+        assert_eq!(labels.labels()[2], labels.labels()[0]);
+        assert_eq!(labels.labels()[3], [Some(4), Some(5), Some(6), Some(7)]);
+    }
+
+    #[test]
+    fn neighbors_are_mutual() {
+        let mut uni = Uniform701::seeded(20);
+        for &connectivity in &[
+            Connectivity::Four,
+            Connectivity::Eight,
+            Connectivity::Twelve,
+        ] {
+            for &periodic in &[false, true] {
+                let lattice =
+                    Lattice::populate_mixed(0.7, 0.6, 12, connectivity, periodic, &mut uni)
+                        .unwrap();
+                for i in 0..12 {
+                    for j in 0..12 {
+                        for ((a, b), (di, dj)) in lattice.neighbors((i, j)) {
+                            assert!(lattice.grid[i][j] && lattice.grid[a][b]);
+                            assert!(lattice
+                                .neighbors((a, b))
+                                .any(|back| back == ((i, j), (-di, -dj))));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bond_probabilities_at_the_extremes() {
+        let mut uni = Uniform701::seeded(21);
+        for &connectivity in &[
+            Connectivity::Four,
+            Connectivity::Eight,
+            Connectivity::Twelve,
+        ] {
+            let closed = Lattice::populate_bonds(0.0, 10, connectivity, true, &mut uni).unwrap();
+            assert_eq!(closed.label_clusters().sizes(), &[1; 100][..]);
+            let open = Lattice::populate_bonds(1.0, 10, connectivity, true, &mut uni).unwrap();
+            assert_eq!(open.label_clusters().sizes(), &[100]);
+        }
+        assert!(Lattice::populate_bonds(1.5, 10, Connectivity::Four, false, &mut uni).is_err());
+        assert!(
+            Lattice::populate_mixed(-0.1, 0.5, 10, Connectivity::Four, false, &mut uni).is_err()
+        );
+    }
+
+    fn crossing(spans: (bool, bool), wraps: (bool, bool)) -> Percolation {
+        Percolation {
+            spans_vertically: spans.0,
+            spans_horizontally: spans.1,
+            wraps_vertically: wraps.0,
+            wraps_horizontally: wraps.1,
+        }
+    }
+
+    #[test]
+    fn strips_can_wrap_without_spanning() {
+        // Two sites two rows apart are joined both ways around a periodic column of 4, so the
+        // cluster wraps, but its unwrapped sites cover only 3 rows
+        let pair = Lattice::drawn(
+            &["#...", "....", "#...", "...."],
+            Connectivity::Twelve,
+            true,
+        );
+        let labels = pair.label_clusters();
+        assert_eq!(labels.sizes(), &[2]);
+        assert_eq!(
+            labels.percolation(),
+            &[crossing((false, false), (true, false))]
+        );
+
+        // Rows 0 and 2 make a strip which wraps and spans along the rows, but only wraps
+        // across them
+        let strip = Lattice::drawn(
+            &["####", "....", "####", "...."],
+            Connectivity::Twelve,
+            true,
+        );
+        let labels = strip.label_clusters();
+        assert_eq!(labels.sizes(), &[8]);
+        let crossed = labels.percolation()[0];
+        assert_eq!(crossed, crossing((false, true), (true, true)));
+        assert!(Criterion::WrapsBoth.is_met_by(&crossed));
+        assert!(!Criterion::SpansBoth.is_met_by(&crossed));
+        assert!(!Criterion::SpansVertically.is_met_by(&crossed));
+        assert!(Criterion::SpansEither.is_met_by(&crossed));
+    }
+
+    #[test]
+    fn strips_can_span_without_wrapping() {
+        // A staircase down a periodic lattice covers every row unwrapped, but its ends are not
+        // joined across the boundary
+        let rows = ["#...", "##..", ".#..", ".#.."];
+        for &periodic in &[false, true] {
+            let labels = Lattice::drawn(&rows, Connectivity::Four, periodic).label_clusters();
+            let crossed = crossing((true, false), (false, false));
+            assert_eq!(labels.percolation(), &[crossed]);
+            assert_eq!(labels.lattice_percolation(), crossed);
+            let clusters = labels.to_clusters();
+            assert_eq!(clusters.get_percolating_clusters().clusters.len(), 1);
+            for &criterion in Criterion::WRAPPING.iter() {
+                assert!(clusters.get_clusters_meeting(criterion).clusters.is_empty());
+            }
+        }
+
+        // Closing the staircase into a loop around the lattice makes it wrap
+        let rows = ["#...", "##..", ".#..", "##.."];
+        let labels = Lattice::drawn(&rows, Connectivity::Four, true).label_clusters();
+        let crossed = crossing((true, false), (true, false));
+        assert_eq!(labels.percolation(), &[crossed]);
+        assert!(Criterion::WrapsOne.is_met_by(&crossed));
+    }
+
+    #[test]
+    fn touching_the_first_and_last_rows_is_not_spanning() {
+        // On a periodic lattice the two sites are one cluster joined across the boundary,
+        // and on an open lattice they are two clusters each touching one edge
+        let rows = ["#...", "....", "....", "#..."];
+        let periodic = Lattice::drawn(&rows, Connectivity::Four, true).label_clusters();
+        assert_eq!(periodic.sizes(), &[2]);
+        assert_eq!(periodic.lattice_percolation(), Percolation::default());
+        let open = Lattice::drawn(&rows, Connectivity::Four, false).label_clusters();
+        assert_eq!(open.sizes(), &[1, 1]);
+        assert_eq!(open.lattice_percolation(), Percolation::default());
+
+        // A full row spans and wraps along itself, and the site apart from it crosses nothing
+        let rows = ["....", "####", "....", "..#."];
+        let labels = Lattice::drawn(&rows, Connectivity::Four, true).label_clusters();
+        assert_eq!(
+            labels.percolation(),
+            &[
+                crossing((false, true), (false, true)),
+                Percolation::default()
+            ]
+        );
+        assert_eq!(labels.mean_size_excluding(Criterion::SpansEither), 1.0);
+        assert_eq!(
+            labels.mean_size_excluding(Criterion::WrapsVertically),
+            17.0 / 5.0
+        );
     }
 }
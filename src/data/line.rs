@@ -1,7 +1,7 @@
 use crate::data::point::Point2d;
 use std::f64::consts::PI;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Line2d {
     pub src: Point2d,
     pub dst: Point2d,
@@ -9,52 +9,32 @@ pub struct Line2d {
 
 impl Line2d {
     /// Calculate a perpendicular bisector for this line.
-    /// The resulting line will have a length of `len` and will be orthogonal to this line
+    /// The resulting line will have a length of 0.4 and will be orthogonal to this line
     pub fn perpendicular_bisector(&self) -> Line2d {
         let midpoint = self.midpoint();
-        // let slope = self.orthogonal_slope();
-        let slope = -1.0 / self.slope();
-        let b = midpoint.y - (slope * midpoint.x);
-
-        let l = Self {
-            src: midpoint,
-            dst: (
-                midpoint.x + 0.2 * (1.0 / (1.0 + slope.powf(2.0)).sqrt()),
-                midpoint.y + (0.2 * (slope / (1.0 + slope.powf(2.0)).sqrt())),
-            )
-                .into(),
-        };
-        let r = Self {
-            src: midpoint,
-            dst: (
-                midpoint.x - 0.2 * (1.0 / (1.0 + slope.powf(2.0)).sqrt()),
-                midpoint.y - (0.2 * (slope / (1.0 + slope.powf(2.0)).sqrt())),
-            )
-                .into(),
-        };
+        let direction = self.dst - self.src;
+        // Rotating the direction a quarter turn clockwise works for vertical lines too
+        let normal = Point2d::new(direction.y(), -direction.x()) * (0.2 / direction.magnitude());
 
         Self {
-            src: l.dst,
-            dst: r.dst,
+            src: midpoint + normal,
+            dst: midpoint - normal,
         }
     }
 
     /// Calculate the midpoint of this line
     pub fn midpoint(&self) -> Point2d {
-        Point2d {
-            x: (self.src.x + self.dst.x) / 2.0,
-            y: (self.src.y + self.dst.y) / 2.0,
-        }
+        (self.src + self.dst) / 2.0
     }
 
     /// Calculate the slope of this line
     pub fn slope(&self) -> f64 {
-        (self.dst.y - self.src.y) / (self.dst.x - self.src.x)
+        (self.dst.y() - self.src.y()) / (self.dst.x() - self.src.x())
     }
 
     /// Calculate the slope of a line orthogonal to this line
     pub fn orthogonal_slope(&self) -> f64 {
-        -(self.dst.x - self.src.x) / (self.dst.y - self.src.y)
+        -(self.dst.x() - self.src.x()) / (self.dst.y() - self.src.y())
     }
 
     /// Get the length of this line.
@@ -65,14 +45,14 @@ impl Line2d {
     /// Determine the point at which the two lines intersect, or if they are parallel,
     /// return None.
     pub fn intersection(&self, that: &Self) -> Option<Point2d> {
-        let x1 = self.src.x;
-        let x2 = self.dst.x;
-        let x3 = that.src.x;
-        let x4 = that.dst.x;
-        let y1 = self.src.y;
-        let y2 = self.dst.y;
-        let y3 = that.src.y;
-        let y4 = that.dst.y;
+        let x1 = self.src.x();
+        let x2 = self.dst.x();
+        let x3 = that.src.x();
+        let x4 = that.dst.x();
+        let y1 = self.src.y();
+        let y2 = self.dst.y();
+        let y3 = that.src.y();
+        let y4 = that.dst.y();
 
         let d = (x1 - x2) * (y3 - y4) - (y1 - y2) * (x3 - x4);
         if d == 0.0 {
@@ -80,7 +60,7 @@ impl Line2d {
         } else {
             let x = ((x1 * y2 - y1 * x2) * (x3 - x4) - (x1 - x2) * (x3 * y4 - y3 * x4)) / d;
             let y = ((x1 * y2 - y1 * x2) * (y3 - y4) - (y1 - y2) * (x3 * y4 - y3 * x4)) / d;
-            Some(Point2d { x, y })
+            Some(Point2d::new(x, y))
         }
     }
 
@@ -88,8 +68,8 @@ impl Line2d {
     /// the positive x direction
     pub fn angle(&self) -> f64 {
         let r = self.length();
-        let x = self.dst.x - self.src.x;
-        let y = self.dst.y - self.src.y;
+        let x = self.dst.x() - self.src.x();
+        let y = self.dst.y() - self.src.y();
 
         if x < 0.0 && y < 0.0 {
             // In quadrant 3
@@ -117,15 +97,10 @@ impl Line2d {
         angle_that - angle_self
     }
 
+    /// Scale this line to a length of 1, keeping its source and direction
     pub fn normalize(mut self) -> Self {
-        let mut v = Line2d {
-            src: (0.0, 0.0).into(),
-            dst: (self.dst.x - self.src.x, self.dst.y - self.src.y).into(),
-        };
-        let len = v.length();
-        v.dst.x /= len;
-        v.dst.y /= len;
-        self.dst = (v.dst.x + self.src.x, v.dst.y + self.src.y).into();
+        let v = self.dst - self.src;
+        self.dst = self.src + v / v.magnitude();
         self
     }
 }
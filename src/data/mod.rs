@@ -1,8 +1,21 @@
-pub(crate) mod delaunay;
-pub(crate) mod lattice;
-pub(crate) mod line;
-pub(crate) mod neighbors;
-pub(crate) mod point;
-pub(crate) mod points_in_grid;
-pub(crate) mod random_vec;
-pub(crate) mod voronoi;
+pub mod burning;
+pub mod delaunay;
+pub mod delaunay_3d;
+pub mod hull;
+pub mod hull_3d;
+pub mod lattice;
+pub mod line;
+pub mod neighbors;
+pub mod newman_ziff;
+pub mod point;
+pub mod points_in_grid;
+pub mod predicates;
+pub mod random_vec;
+pub mod simulation_box;
+pub mod spatial_index;
+pub mod union_find;
+pub mod voronoi;
+pub mod voronoi_3d;
+
+#[cfg(test)]
+mod test_support;
@@ -1,51 +1,117 @@
-use crate::data::point::{Point2d, Point3d};
+use crate::data::delaunay_3d::delaunay_3d;
+use crate::data::point::{Point, Point3d};
+use crate::data::simulation_box::SimulationBox;
+use crate::data::spatial_index::{CellList, KdTree, SpatialIndex};
 use crate::util::{TEX_PREFIX, TEX_SUFFIX};
 use std::error::Error;
 use std::fs::File;
 use std::io::Write;
 
-/// A nearest neighbor map for 3-D points.
-pub(crate) struct NearestNeighborMap {
+/// A nearest neighbor map, listing the indices of the neighbors of each site.
+pub struct NearestNeighborMap {
     pub neighbors: Vec<Vec<usize>>,
 }
 
 impl NearestNeighborMap {
-    pub fn first_2d(sites: &[Point2d], r_cutoff: f64) -> Self {
-        let n = sites.len();
-        let mut neighbors = vec![vec![]; n];
-
-        for i in 0..n - 1 {
-            let site_i = sites[i];
-            for j in i + 1..n {
-                let site_j = sites[j];
-                if site_i.distance_to(&site_j) < r_cutoff {
-                    neighbors[i].push(j);
-                    neighbors[j].push(i);
-                }
-            }
+    /// Map each site to the sites closer to it than `r_cutoff`.
+    /// The neighbors of each site are listed in ascending order.
+    pub fn first<const D: usize>(sites: &[Point<D>], r_cutoff: f64) -> Self {
+        match CellList::with_points(r_cutoff, sites) {
+            Ok(index) => Self::first_from_index(&index, sites, r_cutoff),
+            // No site is closer than a cutoff which is not positive
+            Err(_) => Self {
+                neighbors: vec![vec![]; sites.len()],
+            },
+        }
+    }
+
+    /// Map each site to the sites closer to it than `r_cutoff`, measuring the distance to the
+    /// nearest periodic image along the periodic axes of `sim_box`.
+    /// The neighbors of each site are listed in ascending order.
+    pub fn first_in<const D: usize>(
+        sim_box: &SimulationBox<D>,
+        sites: &[Point<D>],
+        r_cutoff: f64,
+    ) -> Self {
+        match CellList::with_points_in_box(sim_box, r_cutoff, sites) {
+            Ok(index) => Self::first_from_index(&index, sites, r_cutoff),
+            Err(_) => Self {
+                neighbors: vec![vec![]; sites.len()],
+            },
         }
+    }
+
+    fn first_from_index<const D: usize, I: SpatialIndex<D>>(
+        index: &I,
+        sites: &[Point<D>],
+        r_cutoff: f64,
+    ) -> Self {
+        let neighbors = sites
+            .iter()
+            .enumerate()
+            .map(|(i, site)| {
+                let mut neighbors_i = index.within(site, r_cutoff);
+                neighbors_i.retain(|&j| j != i);
+                neighbors_i.sort_unstable();
+                neighbors_i
+            })
+            .collect();
 
         Self { neighbors }
     }
 
-    pub fn first_3d(sites: &[Point3d], r_cutoff: f64) -> Self {
-        let n = sites.len();
-        let mut neighbors = vec![vec![]; n];
-
-        for i in 0..n - 1 {
-            let site_i = sites[i];
-            for j in i + 1..n {
-                let site_j = sites[j];
-                if site_i.distance_to(&site_j) < r_cutoff {
-                    neighbors[i].push(j);
-                    neighbors[j].push(i);
+    /// Map each site to the `k` sites closest to it, nearest first
+    pub fn k_nearest<const D: usize>(sites: &[Point<D>], k: usize) -> Self {
+        Self::k_nearest_from_index(&KdTree::with_points(sites), sites, k)
+    }
+
+    /// Map each site to the `k` sites closest to it, nearest first, measuring the distance to
+    /// the nearest periodic image along the periodic axes of `sim_box`
+    pub fn k_nearest_in<const D: usize>(
+        sim_box: &SimulationBox<D>,
+        sites: &[Point<D>],
+        k: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        // Size the cells to hold about one site each
+        let cell_len = (sim_box.volume() / sites.len().max(1) as f64).powf(1.0 / D as f64);
+        let index = CellList::with_points_in_box(sim_box, cell_len, sites)?;
+        Ok(Self::k_nearest_from_index(&index, sites, k))
+    }
+
+    fn k_nearest_from_index<const D: usize, I: SpatialIndex<D>>(
+        index: &I,
+        sites: &[Point<D>],
+        k: usize,
+    ) -> Self {
+        let neighbors = sites
+            .iter()
+            .enumerate()
+            .map(|(i, site)| {
+                // The site itself is among the closest, unless it shares its location with others
+                let mut neighbors_i = index.nearest(site, k + 1);
+                match neighbors_i.iter().position(|&j| j == i) {
+                    Some(at) => {
+                        neighbors_i.remove(at);
+                    }
+                    None => neighbors_i.truncate(k),
                 }
-            }
-        }
+                neighbors_i
+            })
+            .collect();
 
         Self { neighbors }
     }
 
+    /// Map each site to its natural neighbors, the sites joined to it by an edge of the
+    /// Delaunay tetrahedralization, listed in ascending order.  Unlike the other maps, this
+    /// needs no cutoff.
+    /// Return an error if the sites cannot be tetrahedralized, e.g., if they all lie in a plane.
+    pub fn natural_3d(sites: &[Point3d]) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            neighbors: delaunay_3d(sites)?.adjacency(),
+        })
+    }
+
     pub fn second(first_neighbors: &Self) -> Self {
         let n = first_neighbors.neighbors.len();
         let mut second_neighbors = vec![vec![]; n];
@@ -116,8 +182,8 @@ impl NearestNeighborMap {
         Ok(())
     }
 
-    pub fn print_txt(
-        sites: &[Point3d],
+    pub fn print_txt<const D: usize>(
+        sites: &[Point<D>],
         first_neighbors: &NearestNeighborMap,
         second_neighbors: &NearestNeighborMap,
         to_file: &str,
@@ -129,11 +195,14 @@ impl NearestNeighborMap {
             let second_i = &second_neighbors.neighbors[i];
             of.write_all(
                 format!(
-                    "{} {} {} {} {} {} {} {}\n",
+                    "{} {} {} {} {} {}\n",
                     i,
-                    site_i.x,
-                    site_i.y,
-                    site_i.z,
+                    site_i
+                        .coords
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" "),
                     first_i.len(),
                     first_i
                         .iter()
@@ -155,7 +224,7 @@ impl NearestNeighborMap {
     }
 }
 
-pub(crate) struct AdjacencyMatrix {
+pub struct AdjacencyMatrix {
     m: Vec<Vec<u8>>,
 }
 
@@ -0,0 +1,578 @@
+//! Newman–Ziff sweeps, which fill a lattice one site or bond at a time to measure percolation at
+//! every occupation at once, then weight the occupations binomially to give smooth curves in p
+
+use crate::data::lattice::{Connectivity, Criterion, Lattice, Percolation, Site};
+use crate::rand::sampler::Sampler701;
+use crate::rand::uniform::Uniform701;
+use std::error::Error;
+
+/// The weight, relative to the most likely number added, below which numbers are left out
+const NEGLIGIBLE: f64 = 1e-16;
+
+/// What a sweep adds to the lattice one at a time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sweep {
+    /// Add sites one at a time, with each bond open with probability `p_bond`
+    Sites { p_bond: f64 },
+    /// Add bonds one at a time, with each site occupied with probability `p_site`
+    Bonds { p_site: f64 },
+}
+
+/// The observables of a lattice with p the probability of each site or bond being added
+#[derive(Clone, Debug)]
+pub struct Observables {
+    pub p: f64,
+    /// The mean fraction of the sites in the largest cluster
+    pub largest: f64,
+    /// The mean size of the cluster holding an occupied site outside the largest cluster
+    pub mean_size: f64,
+    /// The probability of some cluster meeting each criterion, in the order of `Criterion::ALL`
+    probabilities: Vec<f64>,
+}
+
+impl Observables {
+    /// The probability R_L(p) of some cluster meeting the criterion
+    pub fn probability(&self, criterion: Criterion) -> f64 {
+        self.probabilities[index(criterion)]
+    }
+}
+
+/// The observables of many sweeps over a lattice, averaged by the number of sites or bonds added
+pub struct NewmanZiff {
+    n_sites: usize,
+    /// The total size of the largest cluster over the sweeps, by the number added
+    largest: Vec<f64>,
+    /// The total mean cluster size over the sweeps, by the number added
+    mean_size: Vec<f64>,
+    /// The number added when each criterion of `Criterion::ALL` was first met in each sweep
+    first_met: Vec<[Option<usize>; 9]>,
+}
+
+impl NewmanZiff {
+    /// Sweep `n_runs` lattices of `box_len`x`box_len` sites, drawing the order the sites or bonds
+    /// are added in, and the part of the lattice which is not swept, afresh for each.
+    /// Return an error if the probability of the part not swept is not in [0, 1], or there are
+    /// no runs.
+    pub fn run(
+        sweep: Sweep,
+        box_len: usize,
+        connectivity: Connectivity,
+        periodic: bool,
+        n_runs: usize,
+        uni: &mut Uniform701,
+    ) -> Result<Self, Box<dyn Error>> {
+        if n_runs == 0 {
+            return Err("A Newman-Ziff experiment needs at least one sweep".into());
+        }
+        let (p_site, p_bond) = match sweep {
+            Sweep::Sites { p_bond } => (1.0, p_bond),
+            Sweep::Bonds { p_site } => (p_site, 1.0),
+        };
+
+        let mut experiment: Option<Self> = None;
+        for _ in 0..n_runs {
+            let mut lattice =
+                Lattice::populate_mixed(p_site, p_bond, box_len, connectivity, periodic, uni)?;
+            let run = match sweep {
+                Sweep::Sites { .. } => sweep_sites(&mut lattice, uni),
+                Sweep::Bonds { .. } => sweep_bonds(&mut lattice, uni),
+            };
+            let experiment = experiment.get_or_insert_with(|| Self {
+                n_sites: box_len * box_len,
+                largest: vec![0.0; run.largest.len()],
+                mean_size: vec![0.0; run.largest.len()],
+                first_met: vec![],
+            });
+            for (n, (&largest, &mean_size)) in run.largest.iter().zip(&run.mean_size).enumerate() {
+                experiment.largest[n] += largest as f64;
+                experiment.mean_size[n] += mean_size;
+            }
+            experiment.first_met.push(run.first_met);
+        }
+        Ok(experiment.unwrap())
+    }
+
+    /// Sweeps whose observables are set by curves in the fraction added rather than measured,
+    /// with one sweep for each of `first_met`, the fraction added when that sweep first meets
+    /// every criterion (never, if over 1)
+    #[cfg(test)]
+    pub(crate) fn from_curves(
+        n_max: usize,
+        first_met: &[f64],
+        largest: impl Fn(f64) -> f64,
+        mean_size: impl Fn(f64) -> f64,
+    ) -> Self {
+        let runs = first_met.len() as f64;
+        let fractions = (0..=n_max).map(|n| n as f64 / n_max as f64);
+        Self {
+            n_sites: 1,
+            largest: fractions.clone().map(|x| runs * largest(x)).collect(),
+            mean_size: fractions.map(|x| runs * mean_size(x)).collect(),
+            first_met: first_met
+                .iter()
+                .map(|&x| {
+                    let n = (x.max(0.0) * n_max as f64).round() as usize;
+                    [if x <= 1.0 { Some(n) } else { None }; 9]
+                })
+                .collect(),
+        }
+    }
+
+    /// The number of sites or bonds added by each sweep
+    pub fn n_max(&self) -> usize {
+        self.largest.len() - 1
+    }
+
+    pub fn n_runs(&self) -> usize {
+        self.first_met.len()
+    }
+
+    /// The observables averaged over the sweeps once n sites or bonds are added, or `None` if n
+    /// exceeds `n_max()`
+    pub fn at_n(&self, n: usize) -> Option<Observables> {
+        if n > self.n_max() {
+            return None;
+        }
+        Some(self.weighted(n as f64 / self.n_max().max(1) as f64, n, &[1.0]))
+    }
+
+    /// The observables with each site or bond added with probability p, i.e., the observables at
+    /// each number added weighted by the binomial probability of adding that many
+    pub fn at_p(&self, p: f64) -> Observables {
+        let (first, weights) = binomial_weights(self.n_max(), p);
+        self.weighted(p, first, &weights)
+    }
+
+    /// Estimate the threshold for the criterion as the mean fraction of the sites or bonds added
+    /// when the criterion is first met, over the sweeps which meet it.
+    /// Return the estimate and its standard error, or `None` if fewer than two sweeps met the
+    /// criterion or it can be met and then lost (`Criterion::WrapsOne`).
+    pub fn threshold(&self, criterion: Criterion) -> Option<(f64, f64)> {
+        if criterion == Criterion::WrapsOne {
+            return None;
+        }
+        let thresholds = self
+            .first_met
+            .iter()
+            .filter_map(|met| met[index(criterion)])
+            .map(|n| n as f64 / self.n_max() as f64)
+            .collect::<Vec<f64>>();
+        if thresholds.len() < 2 {
+            return None;
+        }
+        let count = thresholds.len() as f64;
+        let mean = thresholds.iter().sum::<f64>() / count;
+        let variance = thresholds.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (count - 1.0);
+        Some((mean, (variance / count).sqrt()))
+    }
+
+    /// Average the observables over the sweeps, weighting the numbers added from `first` on by
+    /// `weights`, which sum to one.  Every other number added has no weight.
+    fn weighted(&self, p: f64, first: usize, weights: &[f64]) -> Observables {
+        let runs = self.n_runs() as f64;
+        // The probability of having added at least n, so a sweep which first met a criterion at
+        // n contributes the chance of having reached it
+        let mut tail = weights.to_vec();
+        for k in (0..tail.len().saturating_sub(1)).rev() {
+            tail[k] += tail[k + 1];
+        }
+        let at_least = |n: usize| {
+            if n < first {
+                1.0
+            } else {
+                tail.get(n - first).cloned().unwrap_or(0.0)
+            }
+        };
+        let mut probabilities = (0..Criterion::ALL.len())
+            .map(|k| {
+                self.first_met
+                    .iter()
+                    .filter_map(|met| met[k])
+                    .map(at_least)
+                    .sum::<f64>()
+                    / runs
+            })
+            .collect::<Vec<f64>>();
+        // Wrapping one way only is met between wrapping either way and wrapping both ways
+        probabilities[index(Criterion::WrapsOne)] = (probabilities[index(Criterion::WrapsEither)]
+            - probabilities[index(Criterion::WrapsBoth)])
+        .max(0.0);
+
+        let dot = |totals: &[f64]| -> f64 {
+            totals[first..]
+                .iter()
+                .zip(weights)
+                .map(|(t, w)| t * w)
+                .sum::<f64>()
+                / runs
+        };
+        Observables {
+            p,
+            largest: dot(&self.largest) / self.n_sites.max(1) as f64,
+            mean_size: dot(&self.mean_size),
+            probabilities,
+        }
+    }
+}
+
+/// The position of the criterion in `Criterion::ALL`
+fn index(criterion: Criterion) -> usize {
+    Criterion::ALL.iter().position(|&c| c == criterion).unwrap()
+}
+
+/// The binomial probabilities of adding each of 0 to `n_max` items, each with probability p.
+/// Return the first number with a weight, then the weights of it and the numbers after it,
+/// leaving out the tails where the weights are negligible.
+fn binomial_weights(n_max: usize, p: f64) -> (usize, Vec<f64>) {
+    if p <= 0.0 {
+        return (0, vec![1.0]);
+    }
+    if p >= 1.0 {
+        return (n_max, vec![1.0]);
+    }
+    // Step out each way from the most likely number by the ratio of consecutive weights,
+    // since the binomial coefficients themselves overflow for large lattices
+    let odds = p / (1.0 - p);
+    let mode = (((n_max + 1) as f64 * p) as usize).min(n_max);
+    let mut above = vec![];
+    let mut weight = 1.0;
+    for n in mode..n_max {
+        weight *= (n_max - n) as f64 / (n + 1) as f64 * odds;
+        if weight < NEGLIGIBLE {
+            break;
+        }
+        above.push(weight);
+    }
+    let mut below = vec![];
+    weight = 1.0;
+    for n in (1..=mode).rev() {
+        weight *= n as f64 / (n_max - n + 1) as f64 / odds;
+        if weight < NEGLIGIBLE {
+            break;
+        }
+        below.push(weight);
+    }
+
+    let first = mode - below.len();
+    let mut weights = below
+        .into_iter()
+        .rev()
+        .chain(std::iter::once(1.0))
+        .chain(above)
+        .collect::<Vec<f64>>();
+    let total: f64 = weights.iter().sum();
+    weights.iter_mut().for_each(|w| *w /= total);
+    (first, weights)
+}
+
+/// The observables of one sweep, by the number of sites or bonds added
+struct Run {
+    largest: Vec<usize>,
+    mean_size: Vec<f64>,
+    first_met: [Option<usize>; 9],
+}
+
+/// Add the sites of the lattice in a random order, joining each to its occupied neighbors
+/// through the open bonds
+fn sweep_sites(lattice: &mut Lattice, uni: &mut Uniform701) -> Run {
+    let n = lattice.box_len;
+    lattice.grid.iter_mut().flatten().for_each(|s| *s = false);
+    let mut order = (0..n)
+        .flat_map(|i| (0..n).map(move |j| (i, j)))
+        .collect::<Vec<Site>>();
+    shuffle(&mut order, uni);
+
+    let mut clusters = Tracker::new(n);
+    let mut run = Run::start(&clusters);
+    for (added, site) in order.into_iter().enumerate() {
+        lattice.grid[site.0][site.1] = true;
+        clusters.occupy(site);
+        for (to, offset) in lattice.neighbors(site) {
+            clusters.join(site, to, offset);
+        }
+        run.record(added + 1, &clusters);
+    }
+    run
+}
+
+/// Open the bonds of the lattice in a random order, joining the clusters at the ends of each
+/// bond whose sites are both occupied
+fn sweep_bonds(lattice: &mut Lattice, uni: &mut Uniform701) -> Run {
+    let n = lattice.box_len;
+    let offsets = lattice.connectivity.bonds();
+    let mut order = (0..n)
+        .flat_map(|i| (0..n).map(move |j| (i, j)))
+        .flat_map(|site| (0..offsets.len()).map(move |k| (site, k)))
+        .filter(|&(site, k)| lattice.bond_end(site, k).is_some())
+        .collect::<Vec<(Site, usize)>>();
+    shuffle(&mut order, uni);
+
+    let mut clusters = Tracker::new(n);
+    for (i, row) in lattice.grid.iter().enumerate() {
+        for (j, &occupied) in row.iter().enumerate() {
+            if occupied {
+                clusters.occupy((i, j));
+            }
+        }
+    }
+    let mut run = Run::start(&clusters);
+    for (added, (site, k)) in order.into_iter().enumerate() {
+        let to = lattice.bond_end(site, k).unwrap();
+        if lattice.grid[site.0][site.1] && lattice.grid[to.0][to.1] {
+            clusters.join(site, to, offsets[k]);
+        }
+        run.record(added + 1, &clusters);
+    }
+    run
+}
+
+/// Put the items in a uniformly random order (the Fisher-Yates shuffle)
+fn shuffle<T>(items: &mut [T], uni: &mut Uniform701) {
+    for i in (1..items.len()).rev() {
+        let j = ((uni.next() * (i + 1) as f64) as usize).min(i);
+        items.swap(i, j);
+    }
+}
+
+impl Run {
+    /// Start recording a sweep from the clusters before anything is added
+    fn start(clusters: &Tracker) -> Self {
+        let mut run = Self {
+            largest: vec![],
+            mean_size: vec![],
+            first_met: [None; 9],
+        };
+        run.record(0, clusters);
+        run
+    }
+
+    /// Record the observables once `added` sites or bonds are added
+    fn record(&mut self, added: usize, clusters: &Tracker) {
+        let outside = clusters.occupied - clusters.largest;
+        self.largest.push(clusters.largest);
+        self.mean_size.push(if outside > 0 {
+            (clusters.sum_of_squares - clusters.largest * clusters.largest) as f64 / outside as f64
+        } else {
+            0.0
+        });
+        for (k, criterion) in Criterion::ALL.iter().enumerate() {
+            if self.first_met[k].is_none() && criterion.is_met_by(&clusters.crossing) {
+                self.first_met[k] = Some(added);
+            }
+        }
+    }
+}
+
+/// The clusters of a lattice as sites are joined, kept with union-find.
+/// Each site also keeps its position relative to its parent, unwrapped from the lattice, so the
+/// extent of each cluster is known, and a bond joining a cluster to itself at a different
+/// unwrapped position shows that the cluster wraps around the lattice.
+struct Tracker {
+    box_len: usize,
+    parent: Vec<usize>,
+    /// The position of each site less the position of its parent
+    offset: Vec<(isize, isize)>,
+    /// The size, smallest and largest position relative to the root, and wrapping of each
+    /// cluster, kept at its root
+    size: Vec<usize>,
+    low: Vec<(isize, isize)>,
+    high: Vec<(isize, isize)>,
+    wraps: Vec<(bool, bool)>,
+    occupied: usize,
+    largest: usize,
+    sum_of_squares: usize,
+    /// How any cluster crosses the lattice
+    crossing: Percolation,
+}
+
+impl Tracker {
+    fn new(box_len: usize) -> Self {
+        let n = box_len * box_len;
+        Self {
+            box_len,
+            parent: (0..n).collect(),
+            offset: vec![(0, 0); n],
+            size: vec![1; n],
+            low: vec![(0, 0); n],
+            high: vec![(0, 0); n],
+            wraps: vec![(false, false); n],
+            occupied: 0,
+            largest: 0,
+            sum_of_squares: 0,
+            crossing: Percolation::default(),
+        }
+    }
+
+    /// Start a cluster of one site
+    fn occupy(&mut self, (i, j): Site) {
+        self.occupied += 1;
+        self.sum_of_squares += 1;
+        self.largest = self.largest.max(1);
+        let root = i * self.box_len + j;
+        self.update_crossing(root);
+    }
+
+    /// Find the root of the cluster holding site i, pointing i straight at it
+    fn find(&mut self, i: usize) -> usize {
+        let parent = self.parent[i];
+        if parent == i {
+            return i;
+        }
+        let root = self.find(parent);
+        self.offset[i] = add(self.offset[i], self.offset[parent]);
+        self.parent[i] = root;
+        root
+    }
+
+    /// The position of site i relative to the root of its cluster
+    fn position(&mut self, i: usize) -> (usize, (isize, isize)) {
+        let root = self.find(i);
+        (root, if i == root { (0, 0) } else { self.offset[i] })
+    }
+
+    /// Join the clusters of two occupied sites, where `to` lies `offset` away from `from`
+    fn join(&mut self, from: Site, to: Site, offset: (isize, isize)) {
+        let (a, at_a) = self.position(from.0 * self.box_len + from.1);
+        let (b, at_b) = self.position(to.0 * self.box_len + to.1);
+        // The position of the root of b's cluster relative to the root of a's
+        let shift = sub(add(at_a, offset), at_b);
+        if a == b {
+            self.wraps[a].0 |= shift.0 != 0;
+            self.wraps[a].1 |= shift.1 != 0;
+            self.update_crossing(a);
+            return;
+        }
+
+        let (big, small, shift) = if self.size[a] >= self.size[b] {
+            (a, b, shift)
+        } else {
+            (b, a, sub((0, 0), shift))
+        };
+        self.sum_of_squares += 2 * self.size[big] * self.size[small];
+        self.parent[small] = big;
+        self.offset[small] = shift;
+        self.size[big] += self.size[small];
+        self.largest = self.largest.max(self.size[big]);
+        let (low, high) = (add(self.low[small], shift), add(self.high[small], shift));
+        self.low[big] = (self.low[big].0.min(low.0), self.low[big].1.min(low.1));
+        self.high[big] = (self.high[big].0.max(high.0), self.high[big].1.max(high.1));
+        self.wraps[big].0 |= self.wraps[small].0;
+        self.wraps[big].1 |= self.wraps[small].1;
+        self.update_crossing(big);
+    }
+
+    /// Add how the cluster with the root crosses the lattice to how any cluster does
+    fn update_crossing(&mut self, root: usize) {
+        let n = self.box_len as isize;
+        self.crossing = self.crossing
+            | Percolation {
+                spans_vertically: self.high[root].0 - self.low[root].0 + 1 >= n,
+                spans_horizontally: self.high[root].1 - self.low[root].1 + 1 >= n,
+                wraps_vertically: self.wraps[root].0,
+                wraps_horizontally: self.wraps[root].1,
+            };
+    }
+}
+
+fn add(a: (isize, isize), b: (isize, isize)) -> (isize, isize) {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: (isize, isize), b: (isize, isize)) -> (isize, isize) {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binomial_weights_sum_to_one() {
+        for &n_max in &[1, 10, 100, 10_000, 1_000_000] {
+            for &p in &[1e-4, 0.1, 0.5, 0.5927, 0.9, 0.9999] {
+                let (first, weights) = binomial_weights(n_max, p);
+                assert!(first + weights.len() <= n_max + 1);
+                assert!(weights.iter().all(|&w| w > 0.0));
+                let total = weights.iter().sum::<f64>();
+                assert!(
+                    (total - 1.0).abs() < 1e-12,
+                    "{} at n = {}, p = {}",
+                    total,
+                    n_max,
+                    p
+                );
+                let mean = (first..)
+                    .zip(&weights)
+                    .map(|(n, w)| n as f64 * w)
+                    .sum::<f64>();
+                assert!((mean - n_max as f64 * p).abs() < 1e-6 * n_max as f64);
+            }
+        }
+        assert_eq!(binomial_weights(50, 0.0), (0, vec![1.0]));
+        assert_eq!(binomial_weights(50, 1.0), (50, vec![1.0]));
+    }
+
+    #[test]
+    fn binomial_weights_match_the_binomial() {
+        let (n_max, p) = (20, 0.3);
+        let (first, weights) = binomial_weights(n_max, p);
+        assert_eq!((first, weights.len()), (0, n_max + 1));
+        let mut choose = 1.0;
+        for (n, w) in weights.iter().enumerate() {
+            let exact = choose * p.powi(n as i32) * (1.0 - p).powi((n_max - n) as i32);
+            assert!((w - exact).abs() < 1e-15, "{} != {} at n = {}", w, exact, n);
+            choose *= (n_max - n) as f64 / (n + 1) as f64;
+        }
+    }
+
+    #[test]
+    fn sweeps_run_from_empty_to_full() {
+        let mut uni = Uniform701::seeded(22);
+        // Before any bond is added, every site is a cluster of its own
+        let sweeps = [
+            (Sweep::Sites { p_bond: 1.0 }, 0.0, 0.5927),
+            (Sweep::Bonds { p_site: 1.0 }, 1.0 / 256.0, 0.5),
+        ];
+        for &(sweep, smallest, threshold) in &sweeps {
+            let experiment =
+                NewmanZiff::run(sweep, 16, Connectivity::Four, true, 40, &mut uni).unwrap();
+            assert_eq!(experiment.n_runs(), 40);
+
+            let empty = experiment.at_p(0.0);
+            assert_eq!(empty.largest, smallest);
+            assert!(Criterion::ALL.iter().all(|&c| empty.probability(c) == 0.0));
+            let full = experiment.at_p(1.0);
+            assert_eq!(full.largest, 1.0);
+            for &criterion in Criterion::ALL.iter().filter(|&&c| c != Criterion::WrapsOne) {
+                assert_eq!(full.probability(criterion), 1.0);
+            }
+            assert_eq!(experiment.at_n(0).unwrap().largest, smallest);
+            assert_eq!(experiment.at_n(experiment.n_max()).unwrap().largest, 1.0);
+            assert!(experiment.at_n(experiment.n_max() + 1).is_none());
+            assert!(experiment.at_n(usize::MAX).is_none());
+
+            // The curves in p rise through the threshold
+            let curve = (0..=20)
+                .map(|k| experiment.at_p(k as f64 / 20.0))
+                .collect::<Vec<Observables>>();
+            for pair in curve.windows(2) {
+                assert!(pair[0].largest <= pair[1].largest + 1e-12);
+                let spans = |o: &Observables| o.probability(Criterion::SpansEither);
+                assert!(spans(&pair[0]) <= spans(&pair[1]) + 1e-12);
+            }
+
+            let (estimate, error) = experiment.threshold(Criterion::WrapsEither).unwrap();
+            assert!((estimate - threshold).abs() < 4.0 * error + 0.03);
+            assert!(experiment.threshold(Criterion::WrapsOne).is_none());
+        }
+        assert!(NewmanZiff::run(
+            Sweep::Sites { p_bond: 1.0 },
+            8,
+            Connectivity::Four,
+            true,
+            0,
+            &mut uni
+        )
+        .is_err());
+    }
+}
@@ -1,90 +1,271 @@
-pub(crate) const ORIGIN_2D: Point2d = Point2d { x: 0.0, y: 0.0 };
-pub(crate) const ORIGIN_3D: Point3d = Point3d {
-    x: 0.0,
-    y: 0.0,
-    z: 0.0,
-};
+use crate::data::random_vec::RandomVec;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::ops::{Add, AddAssign, Div, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
 
-#[derive(Copy, Clone)]
-pub struct Point2d {
-    pub x: f64,
-    pub y: f64,
-}
+pub const ORIGIN_2D: Point2d = Point::origin();
+pub const ORIGIN_3D: Point3d = Point::origin();
 
-#[derive(Copy, Clone)]
-pub struct Point3d {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
+/// A point in `D`-dimensional space, which doubles as the vector from the origin to it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Point<const D: usize> {
+    pub coords: [f64; D],
 }
 
-impl Point2d {
+pub type Point2d = Point<2>;
+pub type Point3d = Point<3>;
+
+impl<const D: usize> Point<D> {
+    pub const fn from_coords(coords: [f64; D]) -> Self {
+        Self { coords }
+    }
+
+    pub const fn origin() -> Self {
+        Self { coords: [0.0; D] }
+    }
+
+    /// Calculate the dot product of this point and that point
+    pub fn dot(&self, that: &Self) -> f64 {
+        self.coords
+            .iter()
+            .zip(&that.coords)
+            .map(|(a, b)| a * b)
+            .sum()
+    }
+
+    /// Calculate the squared magnitude of this point, which avoids the square root
+    pub fn magnitude_squared(&self) -> f64 {
+        self.dot(self)
+    }
+
     /// Calculate the magnitude of this point
     pub fn magnitude(&self) -> f64 {
-        (self.x.powf(2.0) + self.y.powf(2.0)).sqrt()
+        self.magnitude_squared().sqrt()
+    }
+
+    /// Calculate the squared distance between this point and that point
+    pub fn distance_squared_to(&self, that: &Self) -> f64 {
+        (*self - *that).magnitude_squared()
     }
 
-    /// Calculate the 2-dimensional distance between points a and b
+    /// Calculate the distance between this point and that point
     pub fn distance_to(&self, that: &Self) -> f64 {
-        ((self.x - that.x).powf(2.0) + (self.y - that.y).powf(2.0)).sqrt()
+        self.distance_squared_to(that).sqrt()
     }
 
-    pub fn shift_by(mut self, that: &Self) -> Self {
-        self.x += that.x;
-        self.y += that.y;
-        self
+    pub fn shift_by(self, that: &Self) -> Self {
+        self + *that
+    }
+
+    /// Whether every coordinate of this point is within `epsilon` of that point's
+    pub fn approx_eq(&self, that: &Self, epsilon: f64) -> bool {
+        self.coords
+            .iter()
+            .zip(&that.coords)
+            .all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.coords.iter().all(|c| c.is_finite())
+    }
+}
+
+impl Point2d {
+    pub const fn new(x: f64, y: f64) -> Self {
+        Self { coords: [x, y] }
+    }
+
+    pub fn x(&self) -> f64 {
+        self.coords[0]
+    }
+
+    pub fn y(&self) -> f64 {
+        self.coords[1]
+    }
+
+    /// Calculate the z-component of the cross product of this point and that point,
+    /// i.e., the signed area of the parallelogram they span
+    pub fn cross(&self, that: &Self) -> f64 {
+        self.x() * that.y() - self.y() * that.x()
     }
 }
 
 impl Point3d {
-    /// Calculate the magnitude of this point
-    pub fn magnitude(&self) -> f64 {
-        (self.x.powf(2.0) + self.y.powf(2.0) + self.z.powf(2.0)).sqrt()
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { coords: [x, y, z] }
     }
 
-    /// Calculate the 3-dimensional distance between points a and b
-    pub fn distance_to(&self, that: &Self) -> f64 {
-        ((self.x - that.x).powf(2.0) + (self.y - that.y).powf(2.0) + (self.z - that.z).powf(2.0))
-            .sqrt()
+    pub fn x(&self) -> f64 {
+        self.coords[0]
+    }
+
+    pub fn y(&self) -> f64 {
+        self.coords[1]
+    }
+
+    pub fn z(&self) -> f64 {
+        self.coords[2]
+    }
+
+    /// Calculate the cross product of this point and that point
+    pub fn cross(&self, that: &Self) -> Self {
+        Self::new(
+            self.y() * that.z() - self.z() * that.y(),
+            self.z() * that.x() - self.x() * that.z(),
+            self.x() * that.y() - self.y() * that.x(),
+        )
+    }
+}
+
+impl<const D: usize> Default for Point<D> {
+    fn default() -> Self {
+        Self::origin()
+    }
+}
+
+impl<const D: usize> Index<usize> for Point<D> {
+    type Output = f64;
+
+    fn index(&self, i: usize) -> &f64 {
+        &self.coords[i]
+    }
+}
+
+impl<const D: usize> IndexMut<usize> for Point<D> {
+    fn index_mut(&mut self, i: usize) -> &mut f64 {
+        &mut self.coords[i]
+    }
+}
+
+impl<const D: usize> Add for Point<D> {
+    type Output = Self;
+
+    fn add(mut self, that: Self) -> Self {
+        self += that;
+        self
+    }
+}
+
+impl<const D: usize> AddAssign for Point<D> {
+    fn add_assign(&mut self, that: Self) {
+        self.coords
+            .iter_mut()
+            .zip(&that.coords)
+            .for_each(|(a, b)| *a += b);
+    }
+}
+
+impl<const D: usize> Sub for Point<D> {
+    type Output = Self;
+
+    fn sub(mut self, that: Self) -> Self {
+        self -= that;
+        self
+    }
+}
+
+impl<const D: usize> SubAssign for Point<D> {
+    fn sub_assign(&mut self, that: Self) {
+        self.coords
+            .iter_mut()
+            .zip(&that.coords)
+            .for_each(|(a, b)| *a -= b);
+    }
+}
+
+impl<const D: usize> Neg for Point<D> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        self * -1.0
+    }
+}
+
+impl<const D: usize> Mul<f64> for Point<D> {
+    type Output = Self;
+
+    fn mul(mut self, k: f64) -> Self {
+        self *= k;
+        self
+    }
+}
+
+impl<const D: usize> Mul<Point<D>> for f64 {
+    type Output = Point<D>;
+
+    fn mul(self, p: Point<D>) -> Point<D> {
+        p * self
+    }
+}
+
+impl<const D: usize> MulAssign<f64> for Point<D> {
+    fn mul_assign(&mut self, k: f64) {
+        self.coords.iter_mut().for_each(|a| *a *= k);
+    }
+}
+
+impl<const D: usize> Div<f64> for Point<D> {
+    type Output = Self;
+
+    fn div(self, k: f64) -> Self {
+        self * (1.0 / k)
+    }
+}
+
+impl<const D: usize> From<[f64; D]> for Point<D> {
+    fn from(coords: [f64; D]) -> Self {
+        Self { coords }
     }
 }
 
 impl From<(f64, f64)> for Point2d {
     fn from(v: (f64, f64)) -> Self {
-        Self { x: v.0, y: v.1 }
+        Self::new(v.0, v.1)
     }
 }
 
 impl From<Point2d> for (f64, f64) {
     fn from(p: Point2d) -> Self {
-        (p.x, p.y)
-    }
-}
-
-impl std::fmt::Display for Point2d {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({:.2}, {:.2})", self.x, self.y)
+        (p.x(), p.y())
     }
 }
 
 impl From<(f64, f64, f64)> for Point3d {
     fn from(v: (f64, f64, f64)) -> Self {
-        Self {
-            x: v.0,
-            y: v.1,
-            z: v.2,
-        }
+        Self::new(v.0, v.1, v.2)
     }
 }
 
 impl From<Point3d> for (f64, f64, f64) {
     fn from(p: Point3d) -> Self {
-        (p.x, p.y, p.z)
+        (p.x(), p.y(), p.z())
+    }
+}
+
+impl<const D: usize> TryFrom<&RandomVec> for Point<D> {
+    type Error = Box<dyn Error>;
+
+    fn try_from(v: &RandomVec) -> Result<Self, Self::Error> {
+        let coords = <[f64; D]>::try_from(v.get().as_slice()).map_err(|_| {
+            format!(
+                "Cannot convert a vector of dimension {} to a point of dimension {}",
+                v.get().len(),
+                D
+            )
+        })?;
+        Ok(Self { coords })
     }
 }
 
-impl std::fmt::Display for Point3d {
+impl<const D: usize> std::fmt::Display for Point<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({:.2}, {:.2}, {:.2})", self.x, self.y, self.z)
+        write!(f, "(")?;
+        for (i, c) in self.coords.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:.2}", c)?;
+        }
+        write!(f, ")")
     }
 }
@@ -1,77 +1,81 @@
 use crate::data::line::Line2d;
-use crate::data::point::{Point2d, Point3d};
+use crate::data::point::{Point, Point2d, Point3d};
+use crate::data::simulation_box::SimulationBox;
+use crate::data::spatial_index::{CellList, SpatialIndex};
+use crate::rand::sampler::Sampler701;
 use crate::rand::uniform::Uniform701;
+use std::error::Error;
+
+/// The number of consecutive candidates rejected before placing a point is abandoned
+const MAX_ATTEMPTS: usize = 1_000_000;
 
 /// Generate `n` points in a box of size `box_len`x`box_len`, with no constraints.
 pub fn gen_points_in_box(uni: &mut Uniform701, box_len: f64, n: usize) -> Vec<Point2d> {
     (0..n)
-        .map(|_| Point2d {
-            x: uni.next() * box_len,
-            y: uni.next() * box_len,
-        })
+        .map(|_| Point2d::new(uni.next() * box_len, uni.next() * box_len))
         .collect::<Vec<Point2d>>()
 }
 
 /// Generate `n` points in a box of size `box_len`x`box_len`, with the constraint that
 /// no point is closer than `r_min` to any other point.
+/// Return an error if the points cannot be packed into the box.
 pub fn gen_spaced_points_in_box(
     uni: &mut Uniform701,
     box_len: f64,
     n: usize,
     r_min: f64,
-) -> Vec<Point2d> {
-    let mut accepted: Vec<Point2d> = Vec::with_capacity(n);
-
-    for _ in 0..n {
-        let mut rejected = true;
-        while rejected {
-            let next = Point2d {
-                x: uni.next() * box_len,
-                y: uni.next() * box_len,
-            };
-            if accepted
-                .iter()
-                .find(|&&point| point.distance_to(&next) < r_min)
-                .is_none()
-            {
-                accepted.push(next);
-                rejected = false;
-            }
-        }
-    }
-    accepted
+) -> Result<Vec<Point2d>, Box<dyn Error>> {
+    gen_spaced_points_in(&SimulationBox::cube(box_len, false)?, uni, n, r_min)
 }
 
 /// Generate `n` points in a cube of size `box_len`x`box_len`x`box_len`, with the constraint that
 /// no point is closer than `r_min` to any other point.
+/// Return an error if the points cannot be packed into the cube.
 pub fn gen_points_in_cube(
     uni: &mut Uniform701,
     cube_len: f64,
     n: usize,
     r_min: f64,
-) -> Vec<Point3d> {
-    let mut accepted: Vec<Point3d> = Vec::with_capacity(n);
+) -> Result<Vec<Point3d>, Box<dyn Error>> {
+    gen_spaced_points_in(&SimulationBox::cube(cube_len, false)?, uni, n, r_min)
+}
+
+/// Generate `n` points in `sim_box`, with the constraint that no point is closer than `r_min`
+/// to any other point.  Along the periodic axes of the box, the distance is measured to the
+/// nearest periodic image, so points near opposite faces keep their distance too.
+/// Return an error if the points cannot be packed into the box.
+pub fn gen_spaced_points_in<const D: usize>(
+    sim_box: &SimulationBox<D>,
+    uni: &mut Uniform701,
+    n: usize,
+    r_min: f64,
+) -> Result<Vec<Point<D>>, Box<dyn Error>> {
+    // Without a positive minimum distance, every candidate is accepted
+    if r_min.is_nan() || r_min <= 0.0 {
+        return Ok((0..n).map(|_| sim_box.sample(uni)).collect());
+    }
+
+    // The accepted points are kept in a cell list so that each candidate is only compared
+    // against the points near it
+    let mut accepted = CellList::in_box(sim_box, r_min)?;
 
     for _ in 0..n {
-        let mut rejected = true;
-        while rejected {
-            let next = Point3d {
-                x: uni.next() * cube_len,
-                y: uni.next() * cube_len,
-                z: uni.next() * cube_len,
-            };
-            if accepted
-                .iter()
-                .find(|&&point| point.distance_to(&next) < r_min)
-                .is_none()
-            {
-                accepted.push(next);
-                rejected = false;
-            }
-        }
+        let next = (0..MAX_ATTEMPTS)
+            .map(|_| sim_box.sample(uni))
+            .find(|next| !accepted.any_within(next, r_min))
+            .ok_or_else(|| too_dense(accepted.len(), r_min))?;
+        accepted.insert(next);
     }
 
-    accepted
+    Ok((0..accepted.len()).map(|i| accepted.point(i)).collect())
+}
+
+fn too_dense(n_placed: usize, r_min: f64) -> Box<dyn Error> {
+    format!(
+        "Gave up placing a point after {} attempts; {} points were placed with r_min = {}",
+        MAX_ATTEMPTS, n_placed, r_min
+    )
+    .into()
 }
 
 /// Generate lines from the point at index `i` to all other points.
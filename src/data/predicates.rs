@@ -0,0 +1,408 @@
+//! Geometric predicates whose signs are always correct.
+//!
+//! Each predicate evaluates a determinant in plain floating point first, together with a bound
+//! on its rounding error (Shewchuk, "Adaptive Precision Floating-Point Arithmetic and Fast
+//! Robust Geometric Predicates", 1997).  Only when the result is too close to zero to trust is the
+//! determinant evaluated again exactly, as a sum of non-overlapping floating-point components.
+//! The returned values have the correct sign, but their magnitudes are only approximate.
+
+use crate::data::point::{Point2d, Point3d};
+use std::cmp::Ordering;
+use std::ops::{Add, Mul, Sub};
+
+/// Half of the distance between 1 and the next larger double, i.e., the unit roundoff
+const EPSILON: f64 = f64::EPSILON / 2.0;
+const ORIENT2D_BOUND: f64 = (3.0 + 16.0 * EPSILON) * EPSILON;
+const ORIENT3D_BOUND: f64 = (7.0 + 56.0 * EPSILON) * EPSILON;
+const INCIRCLE_BOUND: f64 = (10.0 + 96.0 * EPSILON) * EPSILON;
+const INSPHERE_BOUND: f64 = (16.0 + 224.0 * EPSILON) * EPSILON;
+
+/// Positive if a, b and c turn counter-clockwise, negative if clockwise and zero if they are
+/// collinear.  The magnitude is about twice the area of the triangle abc.
+pub fn orient2d(a: Point2d, b: Point2d, c: Point2d) -> f64 {
+    adaptive(ORIENT2D_BOUND, orient2d_det(a, b, c), || {
+        orient2d_det(a, b, c)
+    })
+}
+
+/// Positive if d lies below the plane through a, b and c, where "below" means that a, b and c
+/// appear counter-clockwise from above.  Negative if d lies above the plane and zero if the
+/// four points are coplanar.  The magnitude is about six times the volume of the tetrahedron.
+pub fn orient3d(a: Point3d, b: Point3d, c: Point3d, d: Point3d) -> f64 {
+    adaptive(ORIENT3D_BOUND, orient3d_det(a, b, c, d), || {
+        orient3d_det(a, b, c, d)
+    })
+}
+
+/// Whether three points lie on a line, which is so exactly when their projections onto each of
+/// the coordinate planes do
+pub fn collinear(a: Point3d, b: Point3d, c: Point3d) -> bool {
+    [(0, 1), (1, 2), (2, 0)].iter().all(|&(u, v)| {
+        let project = |p: Point3d| Point2d::new(p[u], p[v]);
+        orient2d(project(a), project(b), project(c)) == 0.0
+    })
+}
+
+/// Positive if d lies inside the circle through a, b and c, negative if outside and zero if
+/// the four points are cocircular.  The sign is reversed if a, b and c turn clockwise.
+pub fn incircle(a: Point2d, b: Point2d, c: Point2d, d: Point2d) -> f64 {
+    adaptive(INCIRCLE_BOUND, incircle_det(a, b, c, d), || {
+        incircle_det(a, b, c, d)
+    })
+}
+
+/// Positive if e lies inside the sphere through a, b, c and d, negative if outside and zero if
+/// the five points are cospherical.  The sign is reversed if `orient3d(a, b, c, d)` is negative.
+pub fn insphere(a: Point3d, b: Point3d, c: Point3d, d: Point3d, e: Point3d) -> f64 {
+    adaptive(INSPHERE_BOUND, insphere_det(a, b, c, d, e), || {
+        insphere_det(a, b, c, d, e)
+    })
+}
+
+/// Compare the directions from `center` to `a` and to `b` by their angle counter-clockwise from
+/// the positive x-axis, without computing the angles
+pub fn cmp_around(center: Point2d, a: Point2d, b: Point2d) -> Ordering {
+    // Split the directions into those at angles in [0, pi) and those in [pi, 2 pi)
+    let lower_half = |p: Point2d| p.y() < center.y() || (p.y() == center.y() && p.x() < center.x());
+    lower_half(a).cmp(&lower_half(b)).then_with(|| {
+        // Within a half, a comes first if b is counter-clockwise from it
+        0.0.partial_cmp(&orient2d(center, a, b))
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+/// Return the floating-point value of a determinant if its rounding error, at most `bound`
+/// times its permanent, cannot have changed its sign.  Otherwise, evaluate it exactly.
+fn adaptive<F: FnOnce() -> Expansion>(bound: f64, approx: Approx, exact: F) -> f64 {
+    if approx.value.abs() > bound * approx.permanent {
+        approx.value
+    } else {
+        exact().estimate()
+    }
+}
+
+/// The arithmetic needed to evaluate the determinants, which are built from differences of
+/// coordinates using only addition, subtraction and multiplication
+trait Arithmetic: Clone + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> {
+    fn diff(a: f64, b: f64) -> Self;
+}
+
+fn orient2d_det<T: Arithmetic>(a: Point2d, b: Point2d, c: Point2d) -> T {
+    T::diff(a.x(), c.x()) * T::diff(b.y(), c.y()) - T::diff(a.y(), c.y()) * T::diff(b.x(), c.x())
+}
+
+fn orient3d_det<T: Arithmetic>(a: Point3d, b: Point3d, c: Point3d, d: Point3d) -> T {
+    let [ad, bd, cd] = [a, b, c].map(|p| [0, 1, 2].map(|i| T::diff(p[i], d[i])));
+    let cross = |u: &[T; 3], v: &[T; 3]| u[0].clone() * v[1].clone() - v[0].clone() * u[1].clone();
+    ad[2].clone() * cross(&bd, &cd)
+        + bd[2].clone() * cross(&cd, &ad)
+        + cd[2].clone() * cross(&ad, &bd)
+}
+
+fn incircle_det<T: Arithmetic>(a: Point2d, b: Point2d, c: Point2d, d: Point2d) -> T {
+    let [ad, bd, cd] = [a, b, c].map(|p| [0, 1].map(|i| T::diff(p[i], d[i])));
+    let lift = |u: &[T; 2]| u[0].clone() * u[0].clone() + u[1].clone() * u[1].clone();
+    let cross = |u: &[T; 2], v: &[T; 2]| u[0].clone() * v[1].clone() - v[0].clone() * u[1].clone();
+    lift(&ad) * cross(&bd, &cd) + lift(&bd) * cross(&cd, &ad) + lift(&cd) * cross(&ad, &bd)
+}
+
+fn insphere_det<T: Arithmetic>(a: Point3d, b: Point3d, c: Point3d, d: Point3d, e: Point3d) -> T {
+    let [ae, be, ce, de] = [a, b, c, d].map(|p| [0, 1, 2].map(|i| T::diff(p[i], e[i])));
+    let lift = |u: &[T; 3]| {
+        u[0].clone() * u[0].clone() + u[1].clone() * u[1].clone() + u[2].clone() * u[2].clone()
+    };
+    let cross = |u: &[T; 3], v: &[T; 3]| u[0].clone() * v[1].clone() - v[0].clone() * u[1].clone();
+
+    let ab = cross(&ae, &be);
+    let bc = cross(&be, &ce);
+    let cd = cross(&ce, &de);
+    let da = cross(&de, &ae);
+    let ac = cross(&ae, &ce);
+    let bd = cross(&be, &de);
+
+    let abc = ae[2].clone() * bc.clone() - be[2].clone() * ac.clone() + ce[2].clone() * ab.clone();
+    let bcd = be[2].clone() * cd.clone() - ce[2].clone() * bd.clone() + de[2].clone() * bc;
+    let cda = ce[2].clone() * da.clone() + de[2].clone() * ac + ae[2].clone() * cd;
+    let dab = de[2].clone() * ab + ae[2].clone() * bd + be[2].clone() * da;
+
+    (lift(&de) * abc - lift(&ce) * dab) + (lift(&be) * cda - lift(&ae) * bcd)
+}
+
+/// A floating-point value, along with the value the same expression would have if every term
+/// were replaced by its magnitude.  The rounding error of the value is proportional to the latter.
+#[derive(Copy, Clone)]
+struct Approx {
+    value: f64,
+    permanent: f64,
+}
+
+impl Arithmetic for Approx {
+    fn diff(a: f64, b: f64) -> Self {
+        let value = a - b;
+        Self {
+            value,
+            permanent: value.abs(),
+        }
+    }
+}
+
+impl Add for Approx {
+    type Output = Self;
+
+    fn add(self, that: Self) -> Self {
+        Self {
+            value: self.value + that.value,
+            permanent: self.permanent + that.permanent,
+        }
+    }
+}
+
+impl Sub for Approx {
+    type Output = Self;
+
+    fn sub(self, that: Self) -> Self {
+        Self {
+            value: self.value - that.value,
+            permanent: self.permanent + that.permanent,
+        }
+    }
+}
+
+impl Mul for Approx {
+    type Output = Self;
+
+    fn mul(self, that: Self) -> Self {
+        Self {
+            value: self.value * that.value,
+            permanent: self.permanent * that.permanent,
+        }
+    }
+}
+
+/// A number represented exactly as the sum of floating-point components, which are
+/// non-overlapping, non-zero, and in increasing order of magnitude
+#[derive(Clone)]
+struct Expansion {
+    components: Vec<f64>,
+}
+
+impl Arithmetic for Expansion {
+    /// The exact difference of two doubles
+    fn diff(a: f64, b: f64) -> Self {
+        let x = a - b;
+        let b_virtual = a - x;
+        let a_virtual = x + b_virtual;
+        let error = (a - a_virtual) + (b_virtual - b);
+        Self::from_pair(error, x)
+    }
+}
+
+impl Expansion {
+    fn from_pair(small: f64, large: f64) -> Self {
+        Self {
+            components: [small, large]
+                .iter()
+                .cloned()
+                .filter(|&c| c != 0.0)
+                .collect(),
+        }
+    }
+
+    /// Add a double to the expansion, keeping it exact
+    fn grow(&mut self, b: f64) {
+        let mut q = b;
+        let mut grown = Vec::with_capacity(self.components.len() + 1);
+        for &e in &self.components {
+            let (sum, error) = two_sum(q, e);
+            if error != 0.0 {
+                grown.push(error);
+            }
+            q = sum;
+        }
+        if q != 0.0 {
+            grown.push(q);
+        }
+        self.components = grown;
+    }
+
+    /// An approximation of the value with the correct sign, namely its largest component
+    fn estimate(&self) -> f64 {
+        self.components.last().cloned().unwrap_or(0.0)
+    }
+}
+
+/// The rounded sum of two doubles and its rounding error
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let x = a + b;
+    let b_virtual = x - a;
+    let a_virtual = x - b_virtual;
+    (x, (a - a_virtual) + (b - b_virtual))
+}
+
+impl Add for Expansion {
+    type Output = Self;
+
+    fn add(mut self, that: Self) -> Self {
+        that.components.iter().for_each(|&c| self.grow(c));
+        self
+    }
+}
+
+impl Sub for Expansion {
+    type Output = Self;
+
+    fn sub(mut self, that: Self) -> Self {
+        that.components.iter().for_each(|&c| self.grow(-c));
+        self
+    }
+}
+
+impl Mul for Expansion {
+    type Output = Self;
+
+    fn mul(self, that: Self) -> Self {
+        let mut product = Self { components: vec![] };
+        for &a in &self.components {
+            for &b in &that.components {
+                // The rounding error of a product is itself a double
+                let x = a * b;
+                product.grow(a.mul_add(b, -x));
+                product.grow(x);
+            }
+        }
+        product
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The doubles within `steps` ulps either side of 0.5, each with its exact offset from 0.5
+    fn near_half(steps: i64) -> Vec<(f64, f64)> {
+        (-steps..=steps)
+            .map(|k| f64::from_bits((0.5f64.to_bits() as i64 + k) as u64))
+            .map(|x| (x, x - 0.5))
+            .collect()
+    }
+
+    fn sign(x: f64) -> i8 {
+        if x > 0.0 {
+            1
+        } else if x < 0.0 {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// 1 if a point lies outside the circle or sphere about the origin through (0.5, ..., 0.5),
+    /// 0 if on it and -1 if inside, from the exact offsets of the point from (0.5, ..., 0.5).
+    /// The sum of the offsets decides unless it vanishes, when the sum of their squares does.
+    fn outside(offsets: &[f64]) -> i8 {
+        match sign(offsets.iter().sum()) {
+            0 if offsets.iter().any(|&d| d != 0.0) => 1,
+            s => s,
+        }
+    }
+
+    #[test]
+    fn orient2d_is_exact_where_plain_floating_point_is_not() {
+        // Kettner et al., "Classroom Examples of Robustness Problems in Geometric Computations"
+        let (b, c) = (Point2d::new(12.0, 12.0), Point2d::new(24.0, 24.0));
+        let mut wrong = 0;
+        for &(x, dx) in &near_half(64) {
+            for &(y, dy) in &near_half(64) {
+                let a = Point2d::new(x, y);
+                // Twice the area is 12 (y - x) exactly, so a is left of bc when above y = x
+                let expected = sign(dy - dx);
+                assert_eq!(sign(orient2d(a, b, c)), expected, "at {:?}", a);
+                wrong += (sign(orient2d_det::<Approx>(a, b, c).value) != expected) as usize;
+            }
+        }
+        assert!(wrong > 0);
+    }
+
+    #[test]
+    fn orient3d_is_exact_where_plain_floating_point_is_not() {
+        // Three points on the plane x = y, and one near (0.5, 0.5) nudged off it
+        let a = Point3d::from_coords([12.0, 12.0, 0.0]);
+        let b = Point3d::from_coords([24.0, 24.0, 0.0]);
+        let c = Point3d::from_coords([12.0, 12.0, 1.0]);
+        let side = sign(orient3d(a, b, c, Point3d::from_coords([1.0, 0.0, 0.5])));
+        assert_ne!(side, 0);
+        let mut wrong = 0;
+        for &(x, dx) in &near_half(64) {
+            for &(y, dy) in &near_half(64) {
+                let d = Point3d::from_coords([x, y, 0.5]);
+                let expected = side * sign(dx - dy);
+                assert_eq!(sign(orient3d(a, b, c, d)), expected, "at {:?}", d);
+                wrong += (sign(orient3d_det::<Approx>(a, b, c, d).value) != expected) as usize;
+            }
+        }
+        assert!(wrong > 0);
+    }
+
+    #[test]
+    fn collinear_is_exact() {
+        // Two points on the line x = y = z, and one near (0.5, 0.5, 0.5) nudged off it
+        let a = Point3d::from_coords([12.0, 12.0, 12.0]);
+        let b = Point3d::from_coords([24.0, 24.0, 24.0]);
+        for &(x, dx) in &near_half(8) {
+            for &(y, dy) in &near_half(8) {
+                let c = Point3d::from_coords([x, y, 0.5]);
+                assert_eq!(collinear(a, b, c), dx == 0.0 && dy == 0.0, "at {:?}", c);
+            }
+        }
+        assert!(collinear(a, a, b));
+
+        // A line along an axis projects to a single point on one of the coordinate planes
+        let o = Point3d::origin();
+        let up = Point3d::from_coords([0.0, 0.0, 1.0]);
+        assert!(collinear(o, up, Point3d::from_coords([0.0, 0.0, -5.0])));
+        assert!(!collinear(o, up, Point3d::from_coords([1e-300, 0.0, -5.0])));
+    }
+
+    #[test]
+    fn incircle_is_exact_where_plain_floating_point_is_not() {
+        // Three corners of a square, counter-clockwise, and the fourth nudged off their circle
+        let a = Point2d::new(-0.5, 0.5);
+        let b = Point2d::new(-0.5, -0.5);
+        let c = Point2d::new(0.5, -0.5);
+        let mut wrong = 0;
+        for &(x, dx) in &near_half(64) {
+            for &(y, dy) in &near_half(64) {
+                let d = Point2d::new(x, y);
+                let expected = -outside(&[dx, dy]);
+                assert_eq!(sign(incircle(a, b, c, d)), expected, "at {:?}", d);
+                wrong += (sign(incircle_det::<Approx>(a, b, c, d).value) != expected) as usize;
+            }
+        }
+        assert!(wrong > 0);
+    }
+
+    #[test]
+    fn insphere_is_exact_where_plain_floating_point_is_not() {
+        // Four corners of a cube, and the corner opposite the last nudged off their sphere
+        let a = Point3d::from_coords([0.5, -0.5, -0.5]);
+        let b = Point3d::from_coords([-0.5, 0.5, -0.5]);
+        let c = Point3d::from_coords([-0.5, -0.5, 0.5]);
+        let d = Point3d::from_coords([-0.5, -0.5, -0.5]);
+        let orientation = sign(orient3d(a, b, c, d));
+        assert_ne!(orientation, 0);
+        let near = near_half(8);
+        let mut wrong = 0;
+        for &(x, dx) in &near {
+            for &(y, dy) in &near {
+                for &(z, dz) in &near {
+                    let e = Point3d::from_coords([x, y, z]);
+                    let expected = -orientation * outside(&[dx, dy, dz]);
+                    assert_eq!(sign(insphere(a, b, c, d, e)), expected, "at {:?}", e);
+                    let naive = insphere_det::<Approx>(a, b, c, d, e).value;
+                    wrong += (sign(naive) != expected) as usize;
+                }
+            }
+        }
+        assert!(wrong > 0);
+    }
+}
@@ -1,7 +1,8 @@
-use crate::rand::boxmuller::BoxMullerGaussian701;
+use crate::data::point::Point;
+use crate::rand::sampler::Sampler701;
 use crate::rand::uniform::Uniform701;
 
-pub(crate) struct RandomVec {
+pub struct RandomVec {
     v: Vec<f64>,
 }
 
@@ -23,36 +24,25 @@ impl RandomVec {
         }
     }
 
-    pub fn efficient(
+    /// Generate a point uniformly distributed within the unit sphere.
+    /// `gaussian` must be a standard normal source, e.g., `BoxMullerGaussian701::standard`,
+    /// so that the direction of the point is uniformly distributed.
+    pub fn efficient<G: Sampler701 + ?Sized>(
         uniform: &mut Uniform701,
-        gaussian: &mut BoxMullerGaussian701,
+        gaussian: &mut G,
         dim: usize,
-    ) -> Self {
-        Self::efficient_scaled(uniform, gaussian, dim, 1.0, 0.0)
-    }
-
-    pub fn efficient_scaled(
-        uniform: &mut Uniform701,
-        gaussian: &mut BoxMullerGaussian701,
-        dim: usize,
-        scale_by: f64,
-        shift_by: f64,
     ) -> Self {
         Self {
-            v: Self::gen_efficient_vec(uniform, gaussian, dim, scale_by, shift_by),
+            v: Self::gen_efficient_vec(uniform, gaussian, dim),
         }
     }
 
-    fn gen_efficient_vec(
+    fn gen_efficient_vec<G: Sampler701 + ?Sized>(
         uniform: &mut Uniform701,
-        gaussian: &mut BoxMullerGaussian701,
+        gaussian: &mut G,
         dim: usize,
-        scale_by: f64,
-        shift_by: f64,
     ) -> Vec<f64> {
-        let x: Vec<f64> = (0..dim)
-            .map(|_| gaussian.next() * scale_by + shift_by)
-            .collect();
+        let x: Vec<f64> = gaussian.samples().take(dim).collect();
         let mag = x.iter().map(|&xi| xi.powi(2)).sum::<f64>().sqrt();
         let alpha = uniform.next().powf(1.0 / dim as f64);
         x.iter()
@@ -74,3 +64,11 @@ impl RandomVec {
             <= radius
     }
 }
+
+impl<const D: usize> From<Point<D>> for RandomVec {
+    fn from(p: Point<D>) -> Self {
+        Self {
+            v: p.coords.to_vec(),
+        }
+    }
+}
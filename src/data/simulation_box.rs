@@ -0,0 +1,205 @@
+//! A rectangular simulation box whose axes are each either periodic or open
+
+use crate::data::point::Point;
+use crate::rand::sampler::Sampler701;
+use crate::rand::uniform::Uniform701;
+use std::error::Error;
+
+/// A box with one corner at the origin, spanning `0..lengths[i]` along each axis `i`.
+/// Along a periodic axis, a point leaving one face re-enters through the opposite face, so
+/// distances are measured to the nearest periodic image of a point (the minimum image).
+/// Along an open axis, the faces are hard walls and distances are the usual ones.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SimulationBox<const D: usize> {
+    lengths: [f64; D],
+    periodic: [bool; D],
+}
+
+impl<const D: usize> SimulationBox<D> {
+    /// Construct a new box with the supplied side lengths, which must be positive and finite
+    pub fn new(lengths: [f64; D], periodic: [bool; D]) -> Result<Self, Box<dyn Error>> {
+        if let Some(len) = lengths.iter().find(|&&len| !(len.is_finite() && len > 0.0)) {
+            return Err(format!(
+                "The sides of a simulation box must be positive and finite, got {}",
+                len
+            )
+            .into());
+        }
+        Ok(Self { lengths, periodic })
+    }
+
+    /// Construct a new box with every side of length `len`, either all periodic or all open
+    pub fn cube(len: f64, periodic: bool) -> Result<Self, Box<dyn Error>> {
+        Self::new([len; D], [periodic; D])
+    }
+
+    pub fn lengths(&self) -> [f64; D] {
+        self.lengths
+    }
+
+    pub fn is_periodic(&self, axis: usize) -> bool {
+        self.periodic[axis]
+    }
+
+    pub fn volume(&self) -> f64 {
+        self.lengths.iter().product()
+    }
+
+    /// Map the point into the box along the periodic axes.  Open axes are left alone.
+    pub fn wrap(&self, p: &Point<D>) -> Point<D> {
+        let mut wrapped = *p;
+        for axis in 0..D {
+            if self.periodic[axis] {
+                wrapped[axis] = wrapped[axis].rem_euclid(self.lengths[axis]);
+                // rem_euclid can round up to the length itself for tiny negative coordinates
+                if wrapped[axis] >= self.lengths[axis] {
+                    wrapped[axis] = 0.0;
+                }
+            }
+        }
+        wrapped
+    }
+
+    /// Whether the point lies within the box
+    pub fn contains(&self, p: &Point<D>) -> bool {
+        p.coords
+            .iter()
+            .zip(&self.lengths)
+            .all(|(&x, &len)| (0.0..len).contains(&x))
+    }
+
+    /// Get the vector from `from` to the nearest periodic image of `to`
+    pub fn displacement(&self, from: &Point<D>, to: &Point<D>) -> Point<D> {
+        let mut d = *to - *from;
+        for axis in 0..D {
+            if self.periodic[axis] {
+                let len = self.lengths[axis];
+                d[axis] -= len * (d[axis] / len).round();
+            }
+        }
+        d
+    }
+
+    /// Calculate the squared minimum image distance between the two points
+    pub fn distance_squared(&self, a: &Point<D>, b: &Point<D>) -> f64 {
+        self.displacement(a, b).magnitude_squared()
+    }
+
+    /// Calculate the minimum image distance between the two points
+    pub fn distance(&self, a: &Point<D>, b: &Point<D>) -> f64 {
+        self.distance_squared(a, b).sqrt()
+    }
+
+    /// The largest distance at which every pair of points has a single nearest image,
+    /// i.e., half of the shortest periodic side.  Infinite if no axis is periodic.
+    pub fn max_unique_distance(&self) -> f64 {
+        (0..D)
+            .filter(|&axis| self.periodic[axis])
+            .map(|axis| self.lengths[axis] / 2.0)
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    /// Draw a point uniformly distributed within the box
+    pub fn sample(&self, uni: &mut Uniform701) -> Point<D> {
+        let mut p = Point::origin();
+        for axis in 0..D {
+            p[axis] = uni.next() * self.lengths[axis];
+        }
+        p
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p2(x: f64, y: f64) -> Point<2> {
+        Point::from_coords([x, y])
+    }
+
+    #[test]
+    fn displacement_is_to_the_nearest_image() {
+        let sim_box = SimulationBox::cube(10.0, true).unwrap();
+        // Across the faces, rather than through the middle of the box
+        assert_eq!(
+            sim_box.displacement(&p2(1.0, 9.0), &p2(9.0, 1.0)),
+            p2(-2.0, 2.0)
+        );
+        assert_eq!(
+            sim_box.displacement(&p2(9.0, 1.0), &p2(1.0, 9.0)),
+            p2(2.0, -2.0)
+        );
+        assert_eq!(sim_box.distance(&p2(0.5, 5.0), &p2(9.5, 5.0)), 1.0);
+        // Images of points outside the box are found the same way
+        assert_eq!(
+            sim_box.displacement(&p2(1.0, 1.0), &p2(-28.0, 33.0)),
+            p2(1.0, 2.0)
+        );
+        assert_eq!(sim_box.distance_squared(&p2(0.0, 0.0), &p2(5.0, 5.0)), 50.0);
+    }
+
+    #[test]
+    fn open_axes_measure_straight_across() {
+        let sim_box = SimulationBox::new([10.0, 4.0], [true, false]).unwrap();
+        assert_eq!(
+            sim_box.displacement(&p2(1.0, 0.5), &p2(9.0, 3.5)),
+            p2(-2.0, 3.0)
+        );
+        assert_eq!(
+            sim_box.distance(&p2(9.0, 0.0), &p2(1.0, 4.0)),
+            20.0f64.sqrt()
+        );
+        assert_eq!(sim_box.wrap(&p2(-1.0, -1.0)), p2(9.0, -1.0));
+        assert!(!sim_box.contains(&sim_box.wrap(&p2(-1.0, -1.0))));
+        assert_eq!(sim_box.max_unique_distance(), 5.0);
+
+        let open = SimulationBox::<3>::cube(1.0, false).unwrap();
+        assert_eq!(
+            open.displacement(&Point::origin(), &Point::from_coords([5.0; 3]))
+                .coords,
+            [5.0; 3]
+        );
+        assert_eq!(open.max_unique_distance(), f64::INFINITY);
+    }
+
+    #[test]
+    fn wrapped_points_are_inside_the_box() {
+        let sim_box = SimulationBox::cube(10.0, true).unwrap();
+        assert_eq!(sim_box.wrap(&p2(12.5, -2.5)), p2(2.5, 7.5));
+        assert_eq!(sim_box.wrap(&p2(10.0, 20.0)), p2(0.0, 0.0));
+        // rem_euclid rounds a tiny negative coordinate up to the length itself
+        assert_eq!((-1e-17f64).rem_euclid(10.0), 10.0);
+        for &x in &[-0.0, -1e-17, -f64::MIN_POSITIVE, -1e-300] {
+            let wrapped = sim_box.wrap(&p2(x, 5.0));
+            assert!(sim_box.contains(&wrapped), "{} wraps to {}", x, wrapped);
+            assert_eq!(wrapped, p2(0.0, 5.0));
+        }
+        assert_eq!(sim_box.wrap(&p2(-1e-15, 5.0))[0], 10.0 - 1e-15);
+    }
+
+    #[test]
+    fn box_sides_must_be_positive_and_finite() {
+        assert_eq!(
+            SimulationBox::new([2.0, 3.0, 4.0], [false; 3])
+                .unwrap()
+                .volume(),
+            24.0
+        );
+        assert_eq!(
+            SimulationBox::new([6.0, 4.0, 5.0], [true, false, true])
+                .unwrap()
+                .max_unique_distance(),
+            2.5
+        );
+        for &len in &[0.0, -1.0, f64::INFINITY, f64::NAN] {
+            assert!(SimulationBox::new([1.0, len], [true, true]).is_err());
+        }
+    }
+
+    #[test]
+    fn samples_lie_in_the_box() {
+        let sim_box = SimulationBox::new([1.0, 100.0, 0.5], [true, false, true]).unwrap();
+        let mut uni = Uniform701::seeded(10);
+        assert!((0..1000).all(|_| sim_box.contains(&sim_box.sample(&mut uni))));
+    }
+}
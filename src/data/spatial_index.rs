@@ -0,0 +1,628 @@
+//! Spatial indices for finding the points near a location without scanning every point
+
+use crate::data::point::Point;
+use crate::data::simulation_box::SimulationBox;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// A set of points which can be searched by location.
+/// Points are identified by the order in which they were inserted, starting from 0.
+pub trait SpatialIndex<const D: usize> {
+    /// Add a point to the index and return its identifier
+    fn insert(&mut self, p: Point<D>) -> usize;
+
+    /// Get the point with identifier `i`
+    fn point(&self, i: usize) -> Point<D>;
+
+    /// The number of points in the index
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calculate the squared distance between two points, as measured by this index
+    fn distance_squared(&self, a: &Point<D>, b: &Point<D>) -> f64 {
+        a.distance_squared_to(b)
+    }
+
+    /// Get the identifiers of all points strictly closer than `r` to `p`, in no particular order
+    fn within(&self, p: &Point<D>, r: f64) -> Vec<usize>;
+
+    /// Whether any point is strictly closer than `r` to `p`
+    fn any_within(&self, p: &Point<D>, r: f64) -> bool {
+        !self.within(p, r).is_empty()
+    }
+
+    /// Get the identifiers of the `k` points closest to `p`, nearest first
+    fn nearest(&self, p: &Point<D>, k: usize) -> Vec<usize>;
+}
+
+/// Sort the identifiers by their distance from `p` and keep the closest `k`
+fn closest<const D: usize, I: SpatialIndex<D> + ?Sized>(
+    index: &I,
+    p: &Point<D>,
+    mut candidates: Vec<usize>,
+    k: usize,
+) -> Vec<usize> {
+    candidates.sort_unstable_by(|&l, &r| {
+        let dl = index.distance_squared(&index.point(l), p);
+        let dr = index.distance_squared(&index.point(r), p);
+        dl.total_cmp(&dr).then(l.cmp(&r))
+    });
+    candidates.truncate(k);
+    candidates
+}
+
+/// A uniform grid of cells, each listing the points which fall inside it.
+/// Queries only visit the cells overlapping the search radius, so a radius query costs
+/// time proportional to the number of points nearby when the radius is close to the cell size.
+/// Without a simulation box the grid is unbounded; only occupied cells are stored.
+/// Within a simulation box, the grid wraps around the periodic axes and distances are
+/// measured to the nearest periodic image.
+pub struct CellList<const D: usize> {
+    /// The side length of the cells along each axis
+    cell_lens: [f64; D],
+    /// The number of cells along each axis, which only bounds the periodic axes of `sim_box`
+    n_cells: [i64; D],
+    sim_box: Option<SimulationBox<D>>,
+    points: Vec<Point<D>>,
+    cells: HashMap<[i64; D], Vec<usize>>,
+}
+
+impl<const D: usize> CellList<D> {
+    /// Construct a new, empty cell list.  `cell_len` should be about the radius of the
+    /// queries which will be made, and must be positive.
+    pub fn new(cell_len: f64) -> Result<Self, Box<dyn Error>> {
+        if cell_len.is_nan() || cell_len <= 0.0 {
+            return Err(format!(
+                "The cells of a cell list must have a positive size, got {}",
+                cell_len
+            )
+            .into());
+        }
+        Ok(Self {
+            cell_lens: [cell_len; D],
+            n_cells: [i64::MAX; D],
+            sim_box: None,
+            points: vec![],
+            cells: HashMap::new(),
+        })
+    }
+
+    /// Construct a new, empty cell list for points within `sim_box`.
+    /// Along the periodic axes, the cells are stretched slightly so that a whole number of them
+    /// fit in the box.
+    pub fn in_box(sim_box: &SimulationBox<D>, cell_len: f64) -> Result<Self, Box<dyn Error>> {
+        let mut index = Self::new(cell_len)?;
+        for axis in 0..D {
+            if sim_box.is_periodic(axis) {
+                let len = sim_box.lengths()[axis];
+                let n = (len / cell_len).floor().max(1.0);
+                index.n_cells[axis] = n as i64;
+                index.cell_lens[axis] = len / n;
+            }
+        }
+        index.sim_box = Some(*sim_box);
+        Ok(index)
+    }
+
+    /// Construct a new cell list holding the supplied points.
+    /// The identifiers of the points are their indices in `points`.
+    pub fn with_points(cell_len: f64, points: &[Point<D>]) -> Result<Self, Box<dyn Error>> {
+        let mut index = Self::new(cell_len)?;
+        points.iter().for_each(|&p| {
+            index.insert(p);
+        });
+        Ok(index)
+    }
+
+    /// Construct a new cell list holding the supplied points within `sim_box`.
+    /// The identifiers of the points are their indices in `points`.
+    pub fn with_points_in_box(
+        sim_box: &SimulationBox<D>,
+        cell_len: f64,
+        points: &[Point<D>],
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut index = Self::in_box(sim_box, cell_len)?;
+        points.iter().for_each(|&p| {
+            index.insert(p);
+        });
+        Ok(index)
+    }
+
+    fn wrap(&self, p: &Point<D>) -> Point<D> {
+        match &self.sim_box {
+            Some(sim_box) => sim_box.wrap(p),
+            None => *p,
+        }
+    }
+
+    /// Get the cell holding `p`, which is assumed to be wrapped into the box
+    fn cell_of(&self, p: &Point<D>) -> [i64; D] {
+        let mut cell = self.unwrapped_cell_of(p);
+        // Clamp, since a coordinate just below the length of the box may round up
+        cell.iter_mut()
+            .zip(&self.n_cells)
+            .for_each(|(c, &n)| *c = (*c).min(n - 1));
+        cell
+    }
+
+    /// Get the cell holding `p` as if the grid carried on past the faces of the box
+    fn unwrapped_cell_of(&self, p: &Point<D>) -> [i64; D] {
+        let mut cell = [0; D];
+        for axis in 0..D {
+            cell[axis] = (p[axis] / self.cell_lens[axis]).floor() as i64;
+        }
+        cell
+    }
+
+    /// Visit every point in the cells overlapping the cube of half-width `r` centered on `p`
+    fn for_each_near<F: FnMut(usize) -> bool>(&self, p: &Point<D>, r: f64, mut f: F) {
+        if r.is_nan() || r <= 0.0 {
+            return;
+        }
+        let p = self.wrap(p);
+        let mut lo = self.unwrapped_cell_of(&(p - Point::from_coords([r; D])));
+        let mut hi = self.unwrapped_cell_of(&(p + Point::from_coords([r; D])));
+        for axis in 0..D {
+            // A search wider than a periodic axis would visit some cells twice
+            if hi[axis].saturating_sub(lo[axis]) >= self.n_cells[axis] {
+                lo[axis] = 0;
+                hi[axis] = self.n_cells[axis] - 1;
+            }
+        }
+
+        // If the cube spans more cells than there are occupied cells, visit every point
+        let n_spanned = lo
+            .iter()
+            .zip(&hi)
+            .map(|(&l, &h)| h as f64 - l as f64 + 1.0)
+            .product::<f64>();
+        if n_spanned > self.cells.len() as f64 {
+            for i in 0..self.points.len() {
+                if !f(i) {
+                    return;
+                }
+            }
+            return;
+        }
+
+        // Step through the cells like an odometer, with the first axis turning fastest
+        let mut cell = lo;
+        loop {
+            let mut wrapped = cell;
+            for axis in 0..D {
+                if self.n_cells[axis] != i64::MAX {
+                    wrapped[axis] = cell[axis].rem_euclid(self.n_cells[axis]);
+                }
+            }
+            if let Some(members) = self.cells.get(&wrapped) {
+                for &i in members {
+                    if !f(i) {
+                        return;
+                    }
+                }
+            }
+            let mut axis = 0;
+            loop {
+                if axis == D {
+                    return;
+                }
+                if cell[axis] < hi[axis] {
+                    cell[axis] += 1;
+                    break;
+                }
+                cell[axis] = lo[axis];
+                axis += 1;
+            }
+        }
+    }
+}
+
+impl<const D: usize> SpatialIndex<D> for CellList<D> {
+    fn insert(&mut self, p: Point<D>) -> usize {
+        let i = self.points.len();
+        self.points.push(p);
+        let cell = self.cell_of(&self.wrap(&p));
+        self.cells.entry(cell).or_default().push(i);
+        i
+    }
+
+    fn point(&self, i: usize) -> Point<D> {
+        self.points[i]
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn distance_squared(&self, a: &Point<D>, b: &Point<D>) -> f64 {
+        match &self.sim_box {
+            Some(sim_box) => sim_box.distance_squared(a, b),
+            None => a.distance_squared_to(b),
+        }
+    }
+
+    fn within(&self, p: &Point<D>, r: f64) -> Vec<usize> {
+        let r2 = r * r;
+        let mut found = vec![];
+        self.for_each_near(p, r, |i| {
+            if self.distance_squared(&self.points[i], p) < r2 {
+                found.push(i);
+            }
+            true
+        });
+        found
+    }
+
+    fn any_within(&self, p: &Point<D>, r: f64) -> bool {
+        let r2 = r * r;
+        let mut any = false;
+        self.for_each_near(p, r, |i| {
+            any = self.distance_squared(&self.points[i], p) < r2;
+            !any
+        });
+        any
+    }
+
+    fn nearest(&self, p: &Point<D>, k: usize) -> Vec<usize> {
+        if k >= self.len() {
+            return closest(self, p, (0..self.len()).collect(), k);
+        }
+        // Grow the search radius until it holds at least k points
+        let mut r = self.cell_lens.iter().cloned().fold(f64::INFINITY, f64::min);
+        loop {
+            let found = self.within(p, r);
+            if found.len() >= k {
+                return closest(self, p, found, k);
+            }
+            if r.is_infinite() {
+                return closest(self, p, (0..self.len()).collect(), k);
+            }
+            r *= 2.0;
+        }
+    }
+}
+
+struct KdNode {
+    /// The identifier of the point which splits this node
+    point: usize,
+    /// The axis along which this node splits its children
+    axis: usize,
+    /// The subtree of points below the splitting point along `axis`
+    left: Option<usize>,
+    /// The subtree of points at or above the splitting point along `axis`
+    right: Option<usize>,
+}
+
+/// A k-d tree, which splits space along each axis in turn.
+/// Trees built from a complete set of points with `KdTree::with_points` are balanced;
+/// points inserted afterwards are added at the leaves without rebalancing.
+pub struct KdTree<const D: usize> {
+    points: Vec<Point<D>>,
+    nodes: Vec<KdNode>,
+    root: Option<usize>,
+}
+
+impl<const D: usize> KdTree<D> {
+    pub fn new() -> Self {
+        Self {
+            points: vec![],
+            nodes: vec![],
+            root: None,
+        }
+    }
+
+    /// Construct a new, balanced tree holding the supplied points.
+    /// The identifiers of the points are their indices in `points`.
+    pub fn with_points(points: &[Point<D>]) -> Self {
+        let mut tree = Self {
+            points: points.to_vec(),
+            nodes: Vec::with_capacity(points.len()),
+            root: None,
+        };
+        let mut ids = (0..points.len()).collect::<Vec<usize>>();
+        tree.root = tree.build(&mut ids, 0);
+        tree
+    }
+
+    /// Split `ids` at the median along `axis` and build the subtrees on either side
+    fn build(&mut self, ids: &mut [usize], axis: usize) -> Option<usize> {
+        if ids.is_empty() {
+            return None;
+        }
+        let points = &self.points;
+        ids.sort_unstable_by(|&l, &r| points[l][axis].total_cmp(&points[r][axis]));
+        // Points equal to the median along the axis belong on the right, as they do on insertion
+        let mut mid = ids.len() / 2;
+        while mid > 0 && points[ids[mid - 1]][axis] >= points[ids[mid]][axis] {
+            mid -= 1;
+        }
+
+        let (left, rest) = ids.split_at_mut(mid);
+        let (median, right) = rest.split_at_mut(1);
+        let next_axis = (axis + 1) % D;
+        let node = KdNode {
+            point: median[0],
+            axis,
+            left: self.build(left, next_axis),
+            right: self.build(right, next_axis),
+        };
+        self.nodes.push(node);
+        Some(self.nodes.len() - 1)
+    }
+
+    fn visit_within(&self, node: Option<usize>, p: &Point<D>, r: f64, found: &mut Vec<usize>) {
+        if let Some(n) = node {
+            let node = &self.nodes[n];
+            let q = self.points[node.point];
+            if q.distance_squared_to(p) < r * r {
+                found.push(node.point);
+            }
+            let diff = p[node.axis] - q[node.axis];
+            if diff - r < 0.0 {
+                self.visit_within(node.left, p, r, found);
+            }
+            if diff + r >= 0.0 {
+                self.visit_within(node.right, p, r, found);
+            }
+        }
+    }
+
+    /// Descend the nearer side first, keeping the `k` closest points seen in `best`,
+    /// sorted by squared distance.  The far side is only visited if it could hold a closer point.
+    fn visit_nearest(
+        &self,
+        node: Option<usize>,
+        p: &Point<D>,
+        k: usize,
+        best: &mut Vec<(f64, usize)>,
+    ) {
+        if let Some(n) = node {
+            let node = &self.nodes[n];
+            let q = self.points[node.point];
+            let d2 = q.distance_squared_to(p);
+            if best.len() < k || d2 < best[best.len() - 1].0 {
+                let at = best.partition_point(|&(d, _)| d <= d2);
+                best.insert(at, (d2, node.point));
+                best.truncate(k);
+            }
+            let diff = p[node.axis] - q[node.axis];
+            let (near, far) = if diff < 0.0 {
+                (node.left, node.right)
+            } else {
+                (node.right, node.left)
+            };
+            self.visit_nearest(near, p, k, best);
+            if best.len() < k || diff * diff < best[best.len() - 1].0 {
+                self.visit_nearest(far, p, k, best);
+            }
+        }
+    }
+}
+
+impl<const D: usize> Default for KdTree<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const D: usize> SpatialIndex<D> for KdTree<D> {
+    fn insert(&mut self, p: Point<D>) -> usize {
+        let i = self.points.len();
+        self.points.push(p);
+
+        // Walk down to the leaf under which the point belongs
+        let mut parent = None;
+        let mut axis = 0;
+        let mut next = self.root;
+        while let Some(n) = next {
+            let node = &self.nodes[n];
+            let go_left = p[node.axis] < self.points[node.point][node.axis];
+            parent = Some((n, go_left));
+            axis = (node.axis + 1) % D;
+            next = if go_left { node.left } else { node.right };
+        }
+
+        self.nodes.push(KdNode {
+            point: i,
+            axis,
+            left: None,
+            right: None,
+        });
+        let new_node = Some(self.nodes.len() - 1);
+        match parent {
+            None => self.root = new_node,
+            Some((n, true)) => self.nodes[n].left = new_node,
+            Some((n, false)) => self.nodes[n].right = new_node,
+        }
+        i
+    }
+
+    fn point(&self, i: usize) -> Point<D> {
+        self.points[i]
+    }
+
+    fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    fn within(&self, p: &Point<D>, r: f64) -> Vec<usize> {
+        let mut found = vec![];
+        self.visit_within(self.root, p, r, &mut found);
+        found
+    }
+
+    fn nearest(&self, p: &Point<D>, k: usize) -> Vec<usize> {
+        let mut best = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.visit_nearest(self.root, p, k, &mut best);
+        }
+        best.into_iter().map(|(_, i)| i).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::test_support::grid;
+    use crate::rand::uniform::Uniform701;
+
+    /// The points strictly closer than `r` to `p`, found by checking every point
+    fn brute_within<const D: usize, I: SpatialIndex<D>>(
+        index: &I,
+        p: &Point<D>,
+        r: f64,
+    ) -> Vec<usize> {
+        (0..index.len())
+            .filter(|&i| index.distance_squared(&index.point(i), p) < r * r)
+            .collect()
+    }
+
+    /// The squared distances to the `k` points closest to `p`, found by sorting every point
+    fn brute_nearest<const D: usize, I: SpatialIndex<D>>(
+        index: &I,
+        p: &Point<D>,
+        k: usize,
+    ) -> Vec<f64> {
+        let mut distances = (0..index.len())
+            .map(|i| index.distance_squared(&index.point(i), p))
+            .collect::<Vec<f64>>();
+        distances.sort_unstable_by(f64::total_cmp);
+        distances.truncate(k);
+        distances
+    }
+
+    /// Check every query against a scan of every point.  Points at the same distance may come
+    /// back in any order, so the nearest points are compared by their distances.
+    fn assert_matches_brute_force<const D: usize, I: SpatialIndex<D>>(
+        index: &I,
+        queries: &[Point<D>],
+        radii: &[f64],
+    ) {
+        for p in queries {
+            for &r in radii {
+                let mut found = index.within(p, r);
+                found.sort_unstable();
+                assert_eq!(found, brute_within(index, p, r), "within {} of {}", r, p);
+                assert_eq!(index.any_within(p, r), !found.is_empty());
+            }
+            for &k in &[0, 1, 2, 7, index.len(), index.len() + 3] {
+                let mut nearest = index.nearest(p, k);
+                let distances = nearest
+                    .iter()
+                    .map(|&i| index.distance_squared(&index.point(i), p))
+                    .collect::<Vec<f64>>();
+                assert_eq!(distances, brute_nearest(index, p, k), "{} nearest {}", k, p);
+                nearest.sort_unstable();
+                nearest.dedup();
+                assert_eq!(nearest.len(), k.min(index.len()));
+            }
+        }
+    }
+
+    /// Uniform points in the box, then the points of a unit grid, which lie on the faces of the
+    /// cells of unit size, and a repeat of a few of each
+    fn points_in<const D: usize>(sim_box: &SimulationBox<D>, seed: u64, n: usize) -> Vec<Point<D>> {
+        let mut uni = Uniform701::seeded(seed);
+        let k = sim_box
+            .lengths()
+            .iter()
+            .cloned()
+            .fold(f64::INFINITY, f64::min) as usize;
+        let mut points = (0..n)
+            .map(|_| sim_box.sample(&mut uni))
+            .collect::<Vec<Point<D>>>();
+        points.extend(grid(k, 0.0));
+        points.extend_from_within(..5);
+        points.extend_from_within(n..n + 5);
+        points
+    }
+
+    #[test]
+    fn queries_in_open_space_match_brute_force() {
+        let sim_box = SimulationBox::cube(10.0, false).unwrap();
+        let points = points_in::<2>(&sim_box, 9, 200);
+        let mut queries = grid(11, 0.0);
+        queries.extend(points_in::<2>(&sim_box, 10, 20));
+        queries.extend([[-3.0, 12.5], [10.0, -0.5], [50.0, 50.0]].map(Point::from_coords));
+        let radii = [0.5, 1.0, 2.0, 3.7, 25.0];
+
+        assert_matches_brute_force(
+            &CellList::with_points(1.0, &points).unwrap(),
+            &queries,
+            &radii,
+        );
+        assert_matches_brute_force(&KdTree::with_points(&points), &queries, &radii);
+        let mut cells = CellList::new(2.5).unwrap();
+        let mut tree = KdTree::new();
+        for &p in &points {
+            assert_eq!(cells.insert(p), tree.insert(p));
+        }
+        assert_matches_brute_force(&cells, &queries, &radii);
+        assert_matches_brute_force(&tree, &queries, &radii);
+    }
+
+    #[test]
+    fn queries_in_a_periodic_box_match_brute_force() {
+        // Queries outside the box are wrapped into it, and radii over half the box reach every
+        // cell, some through more than one face
+        let sim_box = SimulationBox::cube(10.0, true).unwrap();
+        let points = points_in::<2>(&sim_box, 11, 200);
+        let mut queries = grid(11, 0.0);
+        queries.extend([[-0.5, 3.0], [-10.0, 25.0], [9.999, 0.001]].map(Point::from_coords));
+        let radii = [0.5, 1.0, 2.5, 4.0, 6.0, 8.0];
+        for &cell_len in &[1.0, 3.0, 20.0] {
+            let index = CellList::with_points_in_box(&sim_box, cell_len, &points).unwrap();
+            assert_matches_brute_force(&index, &queries, &radii);
+        }
+    }
+
+    #[test]
+    fn queries_in_a_box_periodic_along_some_axes_match_brute_force() {
+        let sim_box = SimulationBox::new([6.0, 8.0, 10.0], [true, false, true]).unwrap();
+        let points = points_in::<3>(&sim_box, 12, 300);
+        let mut uni = Uniform701::seeded(13);
+        let mut queries = grid(4, 0.0);
+        queries.extend((0..20).map(|_| sim_box.sample(&mut uni)));
+        queries.push(Point::from_coords([-1.0, -1.0, 11.0]));
+        let radii = [1.0, 1.5, 3.0, 5.0];
+        for &cell_len in &[1.0, 2.2] {
+            let index = CellList::with_points_in_box(&sim_box, cell_len, &points).unwrap();
+            assert_matches_brute_force(&index, &queries, &radii);
+        }
+    }
+
+    #[test]
+    fn nearest_widens_the_search_until_it_holds_k_points() {
+        // Cells far smaller than the spacing of the points, so the search doubles many times
+        let sim_box = SimulationBox::cube(100.0, false).unwrap();
+        let mut uni = Uniform701::seeded(14);
+        let points = (0..20)
+            .map(|_| sim_box.sample(&mut uni))
+            .collect::<Vec<Point<2>>>();
+        let queries = [[50.0, 50.0], [500.0, -500.0]].map(Point::from_coords);
+        assert_matches_brute_force(
+            &CellList::with_points(0.01, &points).unwrap(),
+            &queries,
+            &[],
+        );
+        assert_matches_brute_force(&KdTree::with_points(&points), &queries, &[]);
+    }
+
+    #[test]
+    fn empty_indices_find_nothing() {
+        let p = Point::from_coords([1.0, 2.0]);
+        let cells = CellList::<2>::new(1.0).unwrap();
+        assert!(cells.is_empty());
+        assert!(cells.within(&p, 10.0).is_empty());
+        assert!(cells.nearest(&p, 3).is_empty());
+        let tree = KdTree::<2>::default();
+        assert!(tree.within(&p, 10.0).is_empty());
+        assert!(tree.nearest(&p, 3).is_empty());
+
+        assert!(CellList::<2>::new(0.0).is_err());
+        assert!(CellList::<2>::new(f64::NAN).is_err());
+    }
+}
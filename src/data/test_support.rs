@@ -0,0 +1,62 @@
+//! Site sets and checks shared by the tests of the meshes, hulls and tessellations
+
+use crate::data::point::{Point, Point2d, Point3d};
+use crate::data::points_in_grid::{gen_points_in_box, gen_points_in_cube};
+use crate::rand::uniform::Uniform701;
+
+/// `n` sites drawn uniformly from the square of side `box_len`
+pub(crate) fn random_sites_2d(seed: u64, box_len: f64, n: usize) -> Vec<Point2d> {
+    gen_points_in_box(&mut Uniform701::seeded(seed), box_len, n)
+}
+
+/// `n` sites drawn uniformly from the cube of side `box_len`
+pub(crate) fn random_sites_3d(seed: u64, box_len: f64, n: usize) -> Vec<Point3d> {
+    gen_points_in_cube(&mut Uniform701::seeded(seed), box_len, n, 0.0).unwrap()
+}
+
+/// The sites followed by a second copy of the first `n_repeats` of them
+pub(crate) fn with_repeats<P: Copy>(sites: &[P], n_repeats: usize) -> Vec<P> {
+    [sites, &sites[..n_repeats]].concat()
+}
+
+/// The `k^D` sites of a grid with unit spacing, shifted by `offset` along every axis and
+/// ordered with the first axis varying fastest
+pub(crate) fn grid<const D: usize>(k: usize, offset: f64) -> Vec<Point<D>> {
+    (0..k.pow(D as u32))
+        .map(|s| {
+            let mut coordinates = [0.0; D];
+            let mut rest = s;
+            for c in coordinates.iter_mut() {
+                *c = (rest % k) as f64 + offset;
+                rest /= k;
+            }
+            Point::from(coordinates)
+        })
+        .collect()
+}
+
+/// Assert that `actual` is within `tolerance` of `expected`
+pub(crate) fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+/// Assert that no site is nearer any of the vertices than site `own`, as for the corners of
+/// its Voronoi cell
+pub(crate) fn assert_no_site_nearer<const D: usize>(
+    sites: &[Point<D>],
+    own: usize,
+    vertices: &[Point<D>],
+    tolerance: f64,
+) {
+    for v in vertices {
+        let distance = v.distance_to(&sites[own]);
+        assert!(sites
+            .iter()
+            .all(|s| distance <= v.distance_to(s) + tolerance));
+    }
+}
@@ -0,0 +1,60 @@
+//! Disjoint sets of the whole numbers below some bound, merged with union by size
+
+/// A partition of `0..n` into disjoint sets, each named by one of its elements (its root).
+/// Finding the root of an element and merging two sets both take nearly constant time.
+pub struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    /// Put each of `0..n` in a set of its own
+    pub fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    /// The number of elements across all the sets
+    pub fn len(&self) -> usize {
+        self.parent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+
+    /// Find the root of the set holding `i`, pointing each element on the way at its grandparent
+    /// so that later searches are shorter
+    pub fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    /// Merge the sets holding `a` and `b` by hanging the smaller under the larger.
+    /// Return the root of the merged set.
+    pub fn union(&mut self, a: usize, b: usize) -> usize {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return a;
+        }
+        let (big, small) = if self.size[a] < self.size[b] {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        self.parent[small] = big;
+        self.size[big] += self.size[small];
+        big
+    }
+
+    /// The number of elements in the set holding `i`
+    pub fn size(&mut self, i: usize) -> usize {
+        let root = self.find(i);
+        self.size[root]
+    }
+}
@@ -1,55 +1,383 @@
+//! Voronoi diagrams of sites in the plane, built as the dual of their Delaunay triangulation
+
+use crate::data::delaunay::{delaunay_2d, Triangulation};
 use crate::data::line::Line2d;
 use crate::data::point::Point2d;
+use std::error::Error;
+
+/// The region of the plane closer to one site than to any other, clipped to the bounds of
+/// the diagram
+pub struct VoronoiCell {
+    /// The corners of the cell, counter-clockwise
+    vertices: Vec<Point2d>,
+    /// The site across each side of the cell, or `None` for the bounds.
+    /// Side `i` runs from vertex `i` to vertex `i + 1`, wrapping around at the end.
+    across: Vec<Option<usize>>,
+}
+
+impl VoronoiCell {
+    /// The corners of the cell, counter-clockwise.  Empty if the cell lies outside the bounds.
+    pub fn vertices(&self) -> &[Point2d] {
+        &self.vertices
+    }
+
+    /// The sides of the cell, counter-clockwise, each paired with the site across it,
+    /// or `None` if the side lies along the bounds
+    pub fn sides(&self) -> Vec<(Line2d, Option<usize>)> {
+        (0..self.vertices.len())
+            .map(|i| {
+                let src = self.vertices[i];
+                let dst = self.vertices[(i + 1) % self.vertices.len()];
+                (Line2d { src, dst }, self.across[i])
+            })
+            .collect()
+    }
+
+    /// The sites whose cells share a side with this one, in ascending order
+    pub fn neighbors(&self) -> Vec<usize> {
+        let mut neighbors = self
+            .across
+            .iter()
+            .filter_map(|&j| j)
+            .collect::<Vec<usize>>();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// Whether the cell was cut short by the bounds of the diagram
+    pub fn touches_bounds(&self) -> bool {
+        self.across.contains(&None)
+    }
+
+    /// Calculate the area of the cell with the shoelace formula
+    pub fn area(&self) -> f64 {
+        self.sides()
+            .iter()
+            .map(|(side, _)| side.src.cross(&side.dst))
+            .sum::<f64>()
+            / 2.0
+    }
+
+    pub fn perimeter(&self) -> f64 {
+        self.sides().iter().map(|(side, _)| side.length()).sum()
+    }
+}
 
-pub fn voronoi_701(sites: &[Point2d], delaunay: &[Vec<usize>]) -> Vec<Line2d> {
-    let n_sites = sites.len();
+/// The Voronoi cells of a set of sites within a rectangle
+pub struct VoronoiDiagram {
+    cells: Vec<VoronoiCell>,
+}
+
+impl VoronoiDiagram {
+    /// Compute the Voronoi diagram of the sites, clipping the cells to the rectangle with
+    /// corners `min` and `max`.
+    /// Return an error if the sites cannot be triangulated, e.g., if they all lie on a line.
+    pub fn new(sites: &[Point2d], min: Point2d, max: Point2d) -> Result<Self, Box<dyn Error>> {
+        if !(min.x() < max.x() && min.y() < max.y()) {
+            return Err(format!("The bounds {} to {} enclose no area", min, max).into());
+        }
+        let triangulation = delaunay_2d(sites)?;
+        Ok(Self::from_triangulation(sites, &triangulation, min, max))
+    }
+
+    /// Compute the Voronoi diagram of the sites from their Delaunay triangulation, clipping the
+    /// cells to the rectangle with corners `min` and `max`
+    pub fn from_triangulation(
+        sites: &[Point2d],
+        triangulation: &Triangulation,
+        min: Point2d,
+        max: Point2d,
+    ) -> Self {
+        let triangles = triangulation.triangles();
+        let neighbors = triangulation.neighbors();
+        let centers = triangles
+            .iter()
+            .map(|t| circumcenter(sites[t[0]], sites[t[1]], sites[t[2]]))
+            .collect::<Vec<Point2d>>();
 
-    let mut ret = vec![];
+        // A triangle touching each site, and the corner of the triangle at which the site sits
+        let mut touching = vec![None; sites.len()];
+        for (t, triangle) in triangles.iter().enumerate() {
+            for (k, &v) in triangle.iter().enumerate() {
+                touching[v] = Some((t, k));
+            }
+        }
+
+        // Far enough that a ray leaving any circumcenter reaches past the bounds
+        let reach = centers
+            .iter()
+            .chain(&[min, max])
+            .map(|c| (*c - min).magnitude().max((*c - max).magnitude()))
+            .fold(max.distance_to(&min), f64::max)
+            * 2.0;
 
-    for i in 0..n_sites {
-        let mut spokes = delaunay[i]
+        let cells = touching
             .iter()
-            .map(|&j| {
-                let src = sites[i];
-                let dst = sites[j];
-                Line2d { src, dst }
+            .enumerate()
+            .map(|(v, &start)| {
+                let (polygon, across) = match start {
+                    Some(start) => {
+                        let ring = triangles_around(triangles, neighbors, v, start);
+                        cell_polygon(sites, triangles, neighbors, &centers, v, &ring, reach)
+                    }
+                    // A repeated site has no triangles and no cell of its own
+                    None => (vec![], vec![]),
+                };
+                clip(polygon, across, min, max)
             })
-            .collect::<Vec<Line2d>>();
-        spokes.sort_unstable_by(|&l, &r| l.angle().partial_cmp(&r.angle()).unwrap());
+            .collect();
 
-        let mut bounding_lines = spokes
+        Self { cells }
+    }
+
+    /// The cell of each site, in the same order as the sites
+    pub fn cells(&self) -> &[VoronoiCell] {
+        &self.cells
+    }
+
+    /// Every side of every cell, with the sides shared by two cells listed once
+    // `Option::is_none_or` would need Rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn edges(&self) -> Vec<Line2d> {
+        self.cells
             .iter()
-            .map(|&line| line.perpendicular_bisector())
-            .collect::<Vec<Line2d>>();
-
-        let mut to_remove = vec![];
-        for j in 0..bounding_lines.len() {
-            let k = if j + 1 == bounding_lines.len() {
-                0
-            } else {
-                j + 1
-            };
-            let intersection = bounding_lines[j].intersection(&bounding_lines[k]).unwrap();
-            bounding_lines[j].dst = intersection;
-            bounding_lines[k].src = intersection;
-            let len_j = bounding_lines[j].length();
-            let len_k = bounding_lines[k].length();
-            if len_j > 2.0 {
-                to_remove.push(j);
+            .enumerate()
+            .flat_map(|(i, cell)| {
+                cell.sides()
+                    .into_iter()
+                    .filter(move |&(_, across)| across.map_or(true, |j| j > i))
+                    .map(|(side, _)| side)
+            })
+            .collect()
+    }
+}
+
+/// List the triangles around site `v` counter-clockwise, as (triangle, corner of `v`) pairs.
+/// For a site on the hull, the list starts with the triangle following the hull clockwise
+/// from the site and ends with the one preceding it.
+fn triangles_around(
+    triangles: &[[usize; 3]],
+    neighbors: &[[Option<usize>; 3]],
+    v: usize,
+    start: (usize, usize),
+) -> Vec<(usize, usize)> {
+    let corner_of = |t: usize| (t, triangles[t].iter().position(|&u| u == v).unwrap());
+
+    // Back up clockwise until the hull stops the walk, or the walk comes back around
+    let mut first = start;
+    while let Some(t) = neighbors[first.0][first.1] {
+        if t == start.0 {
+            break;
+        }
+        first = corner_of(t);
+    }
+
+    let mut ring = vec![first];
+    let mut at = first;
+    while let Some(t) = neighbors[at.0][(at.1 + 2) % 3] {
+        if t == first.0 {
+            break;
+        }
+        at = corner_of(t);
+        ring.push(at);
+    }
+    ring
+}
+
+/// Build the polygon of the cell of site `v` from the circumcenters of the triangles around it.
+/// The cell of a site on the hull is unbounded, so its two sides which lie along hull edges are
+/// cut off at a distance `reach` and joined up outside the bounds.
+/// Return the corners of the polygon counter-clockwise, and the site across each side.
+fn cell_polygon(
+    sites: &[Point2d],
+    triangles: &[[usize; 3]],
+    neighbors: &[[Option<usize>; 3]],
+    centers: &[Point2d],
+    v: usize,
+    ring: &[(usize, usize)],
+    reach: f64,
+) -> (Vec<Point2d>, Vec<Option<usize>>) {
+    let mut polygon = ring
+        .iter()
+        .map(|&(t, _)| centers[t])
+        .collect::<Vec<Point2d>>();
+    // The side from the circumcenter of each triangle to the next crosses the edge from
+    // the site to the triangle's last corner
+    let mut across = ring
+        .iter()
+        .map(|&(t, k)| Some(triangles[t][(k + 2) % 3]))
+        .collect::<Vec<Option<usize>>>();
+
+    let (first, k_first) = ring[0];
+    let (last, k_last) = ring[ring.len() - 1];
+    if neighbors[first][k_first].is_none() {
+        // The hull edges leaving and entering the site, whose outward normals the rays follow
+        let site = sites[v];
+        let before = sites[triangles[first][(k_first + 1) % 3]] - site;
+        let after = site - sites[triangles[last][(k_last + 2) % 3]];
+        let outward = |edge: Point2d| Point2d::new(edge.y(), -edge.x()) / edge.magnitude();
+        let (out_first, out_last) = (outward(before), outward(after));
+        // Go around through the middle direction, so that no side cuts back across the bounds
+        let middle = out_first + out_last;
+        let middle = if middle.magnitude() > 0.0 {
+            middle / middle.magnitude()
+        } else {
+            Point2d::new(-out_first.y(), out_first.x())
+        };
+
+        polygon.push(centers[last] + out_last * reach);
+        polygon.push(centers[last] + middle * reach);
+        polygon.push(centers[first] + out_first * reach);
+        across.push(None);
+        across.push(None);
+        across.push(Some(triangles[first][(k_first + 1) % 3]));
+    }
+    (polygon, across)
+}
+
+/// Clip the polygon to the rectangle with corners `min` and `max`, one side at a time
+/// (the Sutherland-Hodgman algorithm), keeping track of the site across each side.
+/// Sides which are new from the clipping lie along the rectangle.
+fn clip(
+    polygon: Vec<Point2d>,
+    across: Vec<Option<usize>>,
+    min: Point2d,
+    max: Point2d,
+) -> VoronoiCell {
+    let mut sides = polygon.into_iter().zip(across).collect::<Vec<_>>();
+
+    // Each side of the rectangle keeps the points where the coordinate along `axis`,
+    // multiplied by `sign`, is at most `limit`
+    let bounds = [
+        (0, -1.0, -min.x()),
+        (0, 1.0, max.x()),
+        (1, -1.0, -min.y()),
+        (1, 1.0, max.y()),
+    ];
+    for &(axis, sign, limit) in &bounds {
+        let outside = |p: Point2d| sign * p[axis] - limit;
+        let mut clipped = vec![];
+        for i in 0..sides.len() {
+            let (src, label) = sides[i];
+            let dst = sides[(i + 1) % sides.len()].0;
+            let (d_src, d_dst) = (outside(src), outside(dst));
+            if d_src <= 0.0 {
+                clipped.push((src, label));
             }
-            if len_k > 2.0 {
-                to_remove.push(k);
+            if (d_src <= 0.0) != (d_dst <= 0.0) {
+                let crossing = src + (dst - src) * (d_src / (d_src - d_dst));
+                // Leaving the rectangle, the next side runs along its boundary
+                clipped.push((crossing, if d_src <= 0.0 { None } else { label }));
             }
         }
-        to_remove.sort_unstable();
-        to_remove.dedup();
-        to_remove.reverse();
-        for j in to_remove {
-            bounding_lines.remove(j);
+        sides = clipped;
+    }
+
+    // Rounding can leave corners doubled where a side was clipped to nothing
+    let mut i = 0;
+    while sides.len() > 1 && i < sides.len() {
+        if sides[i].0 == sides[(i + 1) % sides.len()].0 {
+            sides.remove(i);
+        } else {
+            i += 1;
         }
+    }
+
+    let (vertices, across) = sides.into_iter().unzip();
+    VoronoiCell { vertices, across }
+}
 
-        ret.append(&mut bounding_lines);
+/// Calculate the center of the circle passing through a, b and c, which must not be collinear.
+/// Working relative to a keeps the terms small.
+fn circumcenter(a: Point2d, b: Point2d, c: Point2d) -> Point2d {
+    let (ab, ac) = (b - a, c - a);
+    let d = 2.0 * ab.cross(&ac);
+    let (ab2, ac2) = (ab.magnitude_squared(), ac.magnitude_squared());
+    a + Point2d::new(ac.y() * ab2 - ab.y() * ac2, ab.x() * ac2 - ac.x() * ab2) / d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::predicates::orient2d;
+    use crate::data::test_support::{
+        assert_close, assert_no_site_nearer, grid, random_sites_2d, with_repeats,
+    };
+
+    const TOLERANCE: f64 = 1e-9;
+
+    /// Check that the cells tile the bounds, that each holds its site and lies no nearer any
+    /// other site, and that their neighbors are mutual and joined to them in the triangulation
+    fn assert_tiles(sites: &[Point2d], diagram: &VoronoiDiagram, min: Point2d, max: Point2d) {
+        let cells = diagram.cells();
+        assert_eq!(cells.len(), sites.len());
+        let total = cells.iter().map(VoronoiCell::area).sum::<f64>();
+        let bounds = (max.x() - min.x()) * (max.y() - min.y());
+        assert_close(total, bounds, TOLERANCE * bounds);
+
+        let adjacency = delaunay_2d(sites).unwrap().adjacency();
+        for (i, cell) in cells
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.vertices().is_empty())
+        {
+            assert!(cell.area() > 0.0);
+            for (side, _) in cell.sides() {
+                assert!(orient2d(side.src, side.dst, sites[i]) > 0.0);
+            }
+            assert_no_site_nearer(sites, i, cell.vertices(), TOLERANCE);
+            for j in cell.neighbors() {
+                assert!(cells[j].neighbors().contains(&i));
+                assert!(adjacency[i].contains(&j));
+            }
+        }
+    }
+
+    #[test]
+    fn cells_of_random_sites_tile_the_box() {
+        let (min, max) = (Point2d::new(0.0, 0.0), Point2d::new(10.0, 10.0));
+        for &n in &[3, 50, 500] {
+            let sites = random_sites_2d(13, 10.0, n);
+            let diagram = VoronoiDiagram::new(&sites, min, max).unwrap();
+            assert_tiles(&sites, &diagram, min, max);
+        }
     }
 
-    ret
+    #[test]
+    fn cells_of_a_grid_are_squares() {
+        // Each four neighboring sites of the grid are cocircular
+        for &k in &[2, 5] {
+            let sites = grid(k, 0.5);
+            let (min, max) = (Point2d::new(0.0, 0.0), Point2d::new(k as f64, k as f64));
+            let diagram = VoronoiDiagram::new(&sites, min, max).unwrap();
+            assert_tiles(&sites, &diagram, min, max);
+            for cell in diagram.cells() {
+                assert_close(cell.area(), 1.0, TOLERANCE);
+                assert_close(cell.perimeter(), 4.0, TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_sites_have_no_cell() {
+        let (min, max) = (Point2d::new(0.0, 0.0), Point2d::new(10.0, 10.0));
+        let sites = with_repeats(&random_sites_2d(14, 10.0, 20), 5);
+        let diagram = VoronoiDiagram::new(&sites, min, max).unwrap();
+        assert_tiles(&sites, &diagram, min, max);
+        let empty = diagram.cells().iter().filter(|c| c.vertices().is_empty());
+        assert_eq!(empty.count(), 5);
+    }
+
+    #[test]
+    fn sites_on_a_line_or_empty_bounds_have_no_diagram() {
+        let (min, max) = (Point2d::new(0.0, 0.0), Point2d::new(10.0, 10.0));
+        let line = (0..5)
+            .map(|i| Point2d::new(i as f64, 2.0 * i as f64))
+            .collect::<Vec<Point2d>>();
+        assert!(VoronoiDiagram::new(&line, min, max).is_err());
+        let sites = [(1.0, 1.0), (2.0, 1.0), (1.0, 2.0)].map(|(x, y)| Point2d::new(x, y));
+        assert!(VoronoiDiagram::new(&sites, max, min).is_err());
+        assert!(VoronoiDiagram::new(&sites, min, Point2d::new(10.0, 0.0)).is_err());
+    }
 }
@@ -0,0 +1,457 @@
+//! Voronoi tessellations of sites in space, with each cell cut down from the bounding box by the
+//! planes halfway to the site's natural neighbors
+
+use crate::data::delaunay_3d::{delaunay_3d, Tetrahedralization};
+use crate::data::neighbors::NearestNeighborMap;
+use crate::data::point::Point3d;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+/// A face of a Voronoi cell
+pub struct VoronoiFace {
+    /// The indices of the corners of the face among the vertices of its cell,
+    /// counter-clockwise seen from outside the cell
+    corners: Vec<usize>,
+    /// The site across the face, or `None` if the face lies along the bounds
+    across: Option<usize>,
+}
+
+impl VoronoiFace {
+    /// The indices of the corners of the face among the vertices of its cell,
+    /// counter-clockwise seen from outside the cell
+    pub fn corners(&self) -> &[usize] {
+        &self.corners
+    }
+
+    /// The site whose cell lies across the face, or `None` if the face lies along the bounds
+    pub fn across(&self) -> Option<usize> {
+        self.across
+    }
+}
+
+/// The region of space closer to one site than to any other, clipped to the bounds of
+/// the tessellation
+pub struct VoronoiCell3d {
+    vertices: Vec<Point3d>,
+    faces: Vec<VoronoiFace>,
+}
+
+impl VoronoiCell3d {
+    /// The corners of the cell.  Empty if the cell lies outside the bounds.
+    pub fn vertices(&self) -> &[Point3d] {
+        &self.vertices
+    }
+
+    pub fn faces(&self) -> &[VoronoiFace] {
+        &self.faces
+    }
+
+    /// The sites whose cells share a face with this one, in ascending order
+    pub fn neighbors(&self) -> Vec<usize> {
+        let mut neighbors = self
+            .faces
+            .iter()
+            .filter_map(|face| face.across)
+            .collect::<Vec<usize>>();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// Whether the cell was cut short by the bounds of the tessellation
+    pub fn touches_bounds(&self) -> bool {
+        self.faces.iter().any(|face| face.across.is_none())
+    }
+
+    /// Calculate the area of a face of the cell
+    pub fn face_area(&self, face: &VoronoiFace) -> f64 {
+        self.face_vector(&face.corners).magnitude()
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        self.faces.iter().map(|face| self.face_area(face)).sum()
+    }
+
+    /// Calculate the volume of the cell by splitting it into tetrahedra, each joining a triangle
+    /// of a face to the center of the vertices
+    pub fn volume(&self) -> f64 {
+        let center = self.center();
+        self.faces
+            .iter()
+            .map(|face| {
+                let fan = self.fan(&face.corners);
+                fan.map(|(a, b, c)| (a - center).dot(&(b - center).cross(&(c - center))))
+                    .sum::<f64>()
+            })
+            .sum::<f64>()
+            / 6.0
+    }
+
+    /// The mean of the vertices, which lies inside the cell
+    fn center(&self) -> Point3d {
+        self.vertices
+            .iter()
+            .fold(Point3d::default(), |sum, &v| sum + v)
+            / self.vertices.len().max(1) as f64
+    }
+
+    /// Split a face into triangles sharing its first corner
+    fn fan<'a>(
+        &'a self,
+        corners: &'a [usize],
+    ) -> impl Iterator<Item = (Point3d, Point3d, Point3d)> + 'a {
+        (1..corners.len().saturating_sub(1)).map(move |k| {
+            (
+                self.vertices[corners[0]],
+                self.vertices[corners[k]],
+                self.vertices[corners[k + 1]],
+            )
+        })
+    }
+
+    /// The normal of a face pointing out of the cell, whose length is the area of the face
+    fn face_vector(&self, corners: &[usize]) -> Point3d {
+        self.fan(corners)
+            .fold(Point3d::default(), |sum, (a, b, c)| {
+                sum + (b - a).cross(&(c - a))
+            })
+            / 2.0
+    }
+
+    /// The box with corners `min` and `max`
+    fn cuboid(min: Point3d, max: Point3d) -> Self {
+        // Vertex i takes its x from max if bit 0 of i is set, its y if bit 1 is and its z if
+        // bit 2 is
+        let vertices = (0..8)
+            .map(|i| {
+                Point3d::from_coords([0, 1, 2].map(|axis| {
+                    if i & (1 << axis) == 0 {
+                        min[axis]
+                    } else {
+                        max[axis]
+                    }
+                }))
+            })
+            .collect();
+        let faces = [
+            [0, 4, 6, 2],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 6, 7, 3],
+            [0, 2, 3, 1],
+            [4, 5, 7, 6],
+        ]
+        .iter()
+        .map(|corners| VoronoiFace {
+            corners: corners.to_vec(),
+            across: None,
+        })
+        .collect();
+        Self { vertices, faces }
+    }
+
+    /// Cut away the part of the cell where `(p - on) . normal` is positive, closing the cut with
+    /// a new face across from site `across`
+    fn clip(&mut self, on: Point3d, normal: Point3d, across: usize) {
+        let side = self
+            .vertices
+            .iter()
+            .map(|&v| (v - on).dot(&normal))
+            .collect::<Vec<f64>>();
+        if side.iter().all(|&d| d <= 0.0) {
+            return;
+        }
+        if side.iter().all(|&d| d >= 0.0) {
+            self.vertices.clear();
+            self.faces.clear();
+            return;
+        }
+
+        // Keep the vertices on or below the plane, and add one where each edge crosses it.
+        // These are the corners of the face closing the cut.
+        let mut vertices = vec![];
+        let mut cap = vec![];
+        let mut renumbered = vec![None; self.vertices.len()];
+        for (i, &v) in self.vertices.iter().enumerate() {
+            if side[i] <= 0.0 {
+                if side[i] == 0.0 {
+                    cap.push(vertices.len());
+                }
+                renumbered[i] = Some(vertices.len());
+                vertices.push(v);
+            }
+        }
+        let mut crossings = HashMap::new();
+        let mut crossing = |a: usize, b: usize, vertices: &mut Vec<Point3d>| {
+            let (below, above) = if side[a] < 0.0 { (a, b) } else { (b, a) };
+            *crossings.entry((below, above)).or_insert_with(|| {
+                let (p, q) = (self.vertices[below], self.vertices[above]);
+                vertices.push(p + (q - p) * (side[below] / (side[below] - side[above])));
+                cap.push(vertices.len() - 1);
+                vertices.len() - 1
+            })
+        };
+
+        let mut faces = vec![];
+        for face in &self.faces {
+            let mut corners = vec![];
+            for (k, &a) in face.corners.iter().enumerate() {
+                let b = face.corners[(k + 1) % face.corners.len()];
+                if let Some(r) = renumbered[a] {
+                    corners.push(r);
+                }
+                if (side[a] < 0.0 && side[b] > 0.0) || (side[a] > 0.0 && side[b] < 0.0) {
+                    corners.push(crossing(a, b, &mut vertices));
+                }
+            }
+            if corners.len() >= 3 {
+                faces.push(VoronoiFace {
+                    corners,
+                    across: face.across,
+                });
+            }
+        }
+
+        // The new face is the part of the plane inside the cell, so its corners go
+        // counter-clockwise around the normal
+        if cap.len() >= 3 {
+            let center = cap
+                .iter()
+                .fold(Point3d::default(), |sum, &k| sum + vertices[k])
+                / cap.len() as f64;
+            let u = perpendicular_to(normal);
+            let w = normal.cross(&u);
+            let angle = |k: usize| {
+                let p = vertices[k] - center;
+                p.dot(&w).atan2(p.dot(&u))
+            };
+            cap.sort_unstable_by(|&a, &b| angle(a).total_cmp(&angle(b)));
+            faces.push(VoronoiFace {
+                corners: cap,
+                across: Some(across),
+            });
+        }
+
+        self.vertices = vertices;
+        self.faces = faces;
+    }
+}
+
+/// A vector perpendicular to `v`, found by crossing it with the axis it is least aligned with
+fn perpendicular_to(v: Point3d) -> Point3d {
+    let axis = (0..3)
+        .min_by(|&a, &b| v[a].abs().total_cmp(&v[b].abs()))
+        .unwrap();
+    let mut unit = Point3d::default();
+    unit[axis] = 1.0;
+    v.cross(&unit)
+}
+
+/// The Voronoi cells of a set of sites within a box
+pub struct VoronoiDiagram3d {
+    cells: Vec<VoronoiCell3d>,
+}
+
+impl VoronoiDiagram3d {
+    /// Compute the Voronoi tessellation of the sites, clipping the cells to the box with
+    /// corners `min` and `max`.
+    /// Return an error if the sites cannot be tetrahedralized, e.g., if they all lie in a plane.
+    pub fn new(sites: &[Point3d], min: Point3d, max: Point3d) -> Result<Self, Box<dyn Error>> {
+        if !(0..3).all(|axis| min[axis] < max[axis]) {
+            return Err(format!("The bounds {} to {} enclose no volume", min, max).into());
+        }
+        let tetrahedralization = delaunay_3d(sites)?;
+        Ok(Self::from_tetrahedralization(
+            sites,
+            &tetrahedralization,
+            min,
+            max,
+        ))
+    }
+
+    /// Compute the Voronoi tessellation of the sites from their Delaunay tetrahedralization,
+    /// clipping the cells to the box with corners `min` and `max`.
+    /// The cell of each site starts as the whole box, and is cut by the plane halfway to each
+    /// site joined to it by an edge of the tetrahedralization.
+    pub fn from_tetrahedralization(
+        sites: &[Point3d],
+        tetrahedralization: &Tetrahedralization,
+        min: Point3d,
+        max: Point3d,
+    ) -> Self {
+        let mut meshed = vec![false; sites.len()];
+        tetrahedralization
+            .tetrahedra()
+            .iter()
+            .flatten()
+            .for_each(|&i| meshed[i] = true);
+
+        let cells = tetrahedralization
+            .adjacency()
+            .iter()
+            .enumerate()
+            .map(|(i, neighbors)| {
+                // A repeated site is left out of the mesh, and has no cell of its own
+                if !meshed[i] {
+                    return VoronoiCell3d {
+                        vertices: vec![],
+                        faces: vec![],
+                    };
+                }
+                let mut cell = VoronoiCell3d::cuboid(min, max);
+                for &j in neighbors {
+                    cell.clip((sites[i] + sites[j]) / 2.0, sites[j] - sites[i], j);
+                }
+                cell
+            })
+            .collect();
+
+        Self { cells }
+    }
+
+    /// The cell of each site, in the same order as the sites
+    pub fn cells(&self) -> &[VoronoiCell3d] {
+        &self.cells
+    }
+
+    /// Map each site to the sites whose cells share a face with its cell.
+    /// Unlike the natural neighbors, this leaves out neighbors whose shared face lies outside
+    /// the bounds.
+    pub fn neighbor_map(&self) -> NearestNeighborMap {
+        NearestNeighborMap {
+            neighbors: self.cells.iter().map(VoronoiCell3d::neighbors).collect(),
+        }
+    }
+
+    /// Write the cells as a Wavefront OBJ mesh, with one object per non-empty cell,
+    /// named after the index of its site
+    pub fn print_obj(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        // Vertices are numbered from 1 across the whole file
+        let mut offset = 1;
+        for (i, cell) in self.cells.iter().enumerate() {
+            if cell.vertices.is_empty() {
+                continue;
+            }
+            of.write_all(format!("o cell_{}\n", i).as_ref())?;
+            for v in &cell.vertices {
+                of.write_all(format!("v {} {} {}\n", v.x(), v.y(), v.z()).as_ref())?;
+            }
+            for face in &cell.faces {
+                of.write_all(
+                    format!(
+                        "f {}\n",
+                        face.corners
+                            .iter()
+                            .map(|&k| (k + offset).to_string())
+                            .collect::<Vec<String>>()
+                            .join(" ")
+                    )
+                    .as_ref(),
+                )?;
+            }
+            offset += cell.vertices.len();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::test_support::{
+        assert_close, assert_no_site_nearer, grid, random_sites_3d, with_repeats,
+    };
+
+    const TOLERANCE: f64 = 1e-9;
+
+    /// Check that the cells fill the bounds, that each lies no nearer any other site and has
+    /// its faces turned away from its site, and that each face shared with a neighbor has the
+    /// same area seen from either side
+    fn assert_fills(sites: &[Point3d], diagram: &VoronoiDiagram3d, min: Point3d, max: Point3d) {
+        let cells = diagram.cells();
+        assert_eq!(cells.len(), sites.len());
+        let total = cells.iter().map(VoronoiCell3d::volume).sum::<f64>();
+        let bounds = (0..3).map(|axis| max[axis] - min[axis]).product::<f64>();
+        assert_close(total, bounds, TOLERANCE * bounds);
+
+        let adjacency = delaunay_3d(sites).unwrap().adjacency();
+        let shared = |cell: &VoronoiCell3d, j: usize| {
+            cell.faces()
+                .iter()
+                .filter(|face| face.across() == Some(j))
+                .map(|face| cell.face_area(face))
+                .sum::<f64>()
+        };
+        for (i, cell) in cells
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| !c.vertices().is_empty())
+        {
+            assert!(cell.volume() > 0.0);
+            assert_no_site_nearer(sites, i, cell.vertices(), TOLERANCE);
+            for face in cell.faces() {
+                let outward = cell.face_vector(face.corners());
+                let corner = cell.vertices()[face.corners()[0]];
+                assert!(outward.dot(&(corner - sites[i])) > 0.0);
+            }
+            for j in cell.neighbors() {
+                assert!(adjacency[i].contains(&j));
+                assert_close(shared(cell, j), shared(&cells[j], i), TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn cells_of_random_sites_fill_the_box() {
+        let (min, max) = (Point3d::new(0.0, 0.0, 0.0), Point3d::new(10.0, 10.0, 10.0));
+        for &n in &[4, 30, 200] {
+            let sites = random_sites_3d(16, 10.0, n);
+            let diagram = VoronoiDiagram3d::new(&sites, min, max).unwrap();
+            assert_fills(&sites, &diagram, min, max);
+        }
+    }
+
+    #[test]
+    fn cells_of_a_grid_are_cubes() {
+        // Each eight neighboring sites of the grid lie on one sphere
+        let sites = grid(3, 0.5);
+        let (min, max) = (Point3d::new(0.0, 0.0, 0.0), Point3d::new(3.0, 3.0, 3.0));
+        let diagram = VoronoiDiagram3d::new(&sites, min, max).unwrap();
+        assert_fills(&sites, &diagram, min, max);
+        for cell in diagram.cells() {
+            assert_close(cell.volume(), 1.0, TOLERANCE);
+            assert_close(cell.surface_area(), 6.0, TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn repeated_sites_have_no_cell() {
+        let (min, max) = (Point3d::new(0.0, 0.0, 0.0), Point3d::new(10.0, 10.0, 10.0));
+        let sites = with_repeats(&random_sites_3d(17, 10.0, 30), 5);
+        let diagram = VoronoiDiagram3d::new(&sites, min, max).unwrap();
+        assert_fills(&sites, &diagram, min, max);
+        let empty = diagram.cells().iter().filter(|c| c.vertices().is_empty());
+        assert_eq!(empty.count(), 5);
+    }
+
+    #[test]
+    fn sites_in_a_plane_or_empty_bounds_have_no_tessellation() {
+        let (min, max) = (Point3d::new(0.0, 0.0, 0.0), Point3d::new(10.0, 10.0, 10.0));
+        let plane = (0..9)
+            .map(|i| Point3d::new((i % 3) as f64, (i / 3) as f64, 1.0))
+            .collect::<Vec<Point3d>>();
+        assert!(VoronoiDiagram3d::new(&plane, min, max).is_err());
+        let sites = [
+            (1.0, 1.0, 1.0),
+            (2.0, 1.0, 1.0),
+            (1.0, 2.0, 1.0),
+            (1.0, 1.0, 2.0),
+        ]
+        .map(|(x, y, z)| Point3d::new(x, y, z));
+        assert!(VoronoiDiagram3d::new(&sites, max, min).is_err());
+        assert!(VoronoiDiagram3d::new(&sites, min, Point3d::new(10.0, 10.0, 0.0)).is_err());
+    }
+}
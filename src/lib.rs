@@ -0,0 +1,16 @@
+//! Random number generators, point processes and the spatial structures built on them:
+//! nearest neighbor maps, Delaunay triangulations, Voronoi diagrams, convex hulls and
+//! percolation on a lattice.
+//!
+//! - [`rand`] holds the random number generators, all of which implement
+//!   [`rand::sampler::Sampler701`].
+//! - [`data`] holds the geometric types and the algorithms which operate on them.
+//! - [`stats`] holds histograms and goodness-of-fit tests.
+//!
+//! Functions which can fail on their input return a `Result` rather than panicking.  The few
+//! `expect`s left state invariants which the algorithms keep whatever their input.
+
+pub mod data;
+pub mod rand;
+pub mod stats;
+pub mod util;
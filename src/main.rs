@@ -1,77 +1,141 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
-
 mod assignment;
-mod data;
-mod rand;
-mod util;
+mod output;
 
-#[allow(unused_imports)]
-use plotters::prelude::*;
 use simple_logger::SimpleLogger;
 use std::error::Error;
+use std::path::PathBuf;
 
-#[allow(unused_imports)]
-use crate::assignment::assignment1;
-#[allow(unused_imports)]
-use crate::assignment::assignment2;
-#[allow(unused_imports)]
-use crate::assignment::assignment3;
-#[allow(unused_imports)]
-use crate::assignment::assignment4;
-#[allow(unused_imports)]
-use crate::assignment::assignment5;
-#[allow(unused_imports)]
-use crate::assignment::assignment6;
-#[allow(unused_imports)]
-use crate::assignment::assignment7;
-#[allow(unused_imports)]
-use crate::assignment::project_d;
+use crate::assignment::assignment1::{self, DistributionsArgs};
+use crate::assignment::assignment2::{self, UniformArgs};
+use crate::assignment::assignment3::{self, SphereArgs};
+use crate::assignment::assignment4::{self, NeighborsArgs};
+use crate::assignment::assignment5::{self, DelaunayArgs};
+use crate::assignment::assignment6::{self, VoronoiArgs};
+use crate::assignment::assignment7::{self, HullArgs};
+use crate::assignment::project_d::{self, PercolationArgs};
+use crate::output::Output;
+use clap::{CommandFactory, Parser, Subcommand};
+use cos_701::rand::uniform::Uniform701;
 use log::LevelFilter::Warn;
 
+/// Run the experiments of COS 701.  Without a subcommand, every experiment is run.
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    /// The seed of the random number generators.  Pass the seed of a previous run to reproduce it.
+    #[clap(long, global = true)]
+    seed: Option<u64>,
+    /// The directory under which each experiment writes its results
+    #[clap(long, global = true, default_value = "output")]
+    output_dir: PathBuf,
+    /// Override the size of every image, e.g., 1920x1080
+    #[clap(long, global = true, value_parser = parse_image_size)]
+    image_size: Option<(u32, u32)>,
+    /// List the available experiments
+    #[clap(long)]
+    list: bool,
+    #[clap(subcommand)]
+    experiment: Option<Experiment>,
+}
+
+#[derive(Subcommand)]
+enum Experiment {
+    /// Assignment 1: histograms and goodness-of-fit of the random number generators
+    Distributions(DistributionsArgs),
+    /// Assignment 2: uniformly distributed points in a box and a cube
+    Uniform(UniformArgs),
+    /// Assignment 3: points in a hypersphere
+    Sphere(SphereArgs),
+    /// Assignment 4: first and second nearest neighbors
+    Neighbors(NeighborsArgs),
+    /// Assignment 5: Delaunay triangulation
+    Delaunay(DelaunayArgs),
+    /// Assignment 6: Voronoi diagrams
+    Voronoi(VoronoiArgs),
+    /// Assignment 7: convex hulls
+    Hull(HullArgs),
+    /// Project D: site, bond and mixed percolation
+    Percolation(PercolationArgs),
+    /// Run every experiment with its default parameters
+    All,
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     SimpleLogger::new()
         .with_module_level("voronoi", Warn)
         .init()?;
 
-    log::info!("Hello world");
+    let cli = Cli::parse();
 
-    ensure_output_dirs_exist()?;
+    if cli.list {
+        Cli::command().get_subcommands().for_each(|cmd| {
+            println!(
+                "{:<14}{}",
+                cmd.get_name(),
+                cmd.get_about().unwrap_or_default()
+            )
+        });
+        return Ok(());
+    }
 
-    assignment1::do_assignment_1()?;
-    assignment2::do_assignment_2()?;
-    assignment3::do_assignment_3()?;
-    assignment4::do_assignment_4()?;
-    assignment5::do_assignment_5()?;
-    assignment6::do_assignment_6()?;
-    assignment7::do_assignment_7()?;
-    project_d::do_project_d()?;
+    let seed = match cli.seed {
+        Some(seed) => seed,
+        None => Uniform701::new().seed(),
+    };
+    let output = Output::new(cli.output_dir, cli.image_size, seed);
 
-    // scratch()?;
-
-    Ok(())
+    run(&output, cli.experiment.unwrap_or(Experiment::All))
 }
 
-fn ensure_output_dirs_exist() -> Result<(), Box<dyn Error>> {
-    log::info!("Creating output directories");
-
-    vec![
-        // "output/scratch",
-        "output/assignment1",
-        "output/assignment2",
-        "output/assignment3",
-        "output/assignment4",
-        "output/assignment5",
-        "output/assignment6",
-        "output/assignment7",
-        "output/projectD",
-    ]
-    .iter()
-    .try_for_each(std::fs::create_dir_all)?;
-
-    Ok(())
+/// Run the supplied experiment, writing its results to its own subdirectory of `output`
+fn run(output: &Output, experiment: Experiment) -> Result<(), Box<dyn Error>> {
+    match experiment {
+        Experiment::Distributions(args) => {
+            assignment1::do_assignment_1(&output.for_experiment("assignment1")?, &args)
+        }
+        Experiment::Uniform(args) => {
+            assignment2::do_assignment_2(&output.for_experiment("assignment2")?, &args)
+        }
+        Experiment::Sphere(args) => {
+            assignment3::do_assignment_3(&output.for_experiment("assignment3")?, &args)
+        }
+        Experiment::Neighbors(args) => {
+            assignment4::do_assignment_4(&output.for_experiment("assignment4")?, &args)
+        }
+        Experiment::Delaunay(args) => {
+            assignment5::do_assignment_5(&output.for_experiment("assignment5")?, &args)
+        }
+        Experiment::Voronoi(args) => {
+            assignment6::do_assignment_6(&output.for_experiment("assignment6")?, &args)
+        }
+        Experiment::Hull(args) => {
+            assignment7::do_assignment_7(&output.for_experiment("assignment7")?, &args)
+        }
+        Experiment::Percolation(args) => {
+            project_d::do_project_d(&output.for_experiment("projectD")?, &args)
+        }
+        Experiment::All => vec![
+            Experiment::Distributions(DistributionsArgs::parse_from([""])),
+            Experiment::Uniform(UniformArgs::parse_from([""])),
+            Experiment::Sphere(SphereArgs::parse_from([""])),
+            Experiment::Neighbors(NeighborsArgs::parse_from([""])),
+            Experiment::Delaunay(DelaunayArgs::parse_from([""])),
+            Experiment::Voronoi(VoronoiArgs::parse_from([""])),
+            Experiment::Hull(HullArgs::parse_from([""])),
+            Experiment::Percolation(PercolationArgs::parse_from([""])),
+        ]
+        .into_iter()
+        .try_for_each(|experiment| run(output, experiment)),
+    }
 }
 
-// fn scratch() -> Result<(), Box<dyn Error>> {
-//     Ok(())
-// }
+/// Parse an image size of the form `WIDTHxHEIGHT`
+fn parse_image_size(arg: &str) -> Result<(u32, u32), String> {
+    let (width, height) = arg
+        .split_once('x')
+        .ok_or_else(|| format!("Expected WIDTHxHEIGHT, got '{}'", arg))?;
+    Ok((
+        width.parse().map_err(|e| format!("Bad width: {}", e))?,
+        height.parse().map_err(|e| format!("Bad height: {}", e))?,
+    ))
+}
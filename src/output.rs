@@ -0,0 +1,57 @@
+//! Where and how the experiments write their results
+
+use cos_701::util;
+use std::error::Error;
+use std::path::PathBuf;
+
+/// The settings shared by every file an experiment writes
+#[derive(Clone)]
+pub struct Output {
+    /// The directory the files are written to
+    dir: PathBuf,
+    /// When set, overrides the default size of every image
+    image_size: Option<(u32, u32)>,
+    /// The seed of the run, written next to every file
+    seed: u64,
+}
+
+impl Output {
+    pub fn new(dir: PathBuf, image_size: Option<(u32, u32)>, seed: u64) -> Self {
+        Self {
+            dir,
+            image_size,
+            seed,
+        }
+    }
+
+    /// Get the settings for an experiment which writes to the subdirectory `name`,
+    /// creating the subdirectory if it does not exist.
+    pub fn for_experiment(&self, name: &str) -> Result<Self, Box<dyn Error>> {
+        let dir = self.dir.join(name);
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            image_size: self.image_size,
+            seed: self.seed,
+        })
+    }
+
+    /// Get the path of `file` within the output directory
+    pub fn path(&self, file: &str) -> String {
+        self.dir.join(file).to_string_lossy().into_owned()
+    }
+
+    /// Get the size of an image, which is `default` unless overridden
+    pub fn image_size(&self, default: (u32, u32)) -> (u32, u32) {
+        self.image_size.unwrap_or(default)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Write the seed of the run next to `file`
+    pub fn write_seed(&self, file: &str) -> Result<(), Box<dyn Error>> {
+        util::write_seed(&self.path(file), self.seed)
+    }
+}
@@ -1,44 +1,84 @@
+use crate::rand::sampler::Sampler701;
 use crate::rand::uniform::Uniform701;
+use crate::util;
 use std::f64::consts::PI;
 
 pub struct BoxMullerGaussian701 {
     /// The uniform random source
     uniform: Uniform701,
+    /// The mean of the generated distribution
+    mu: f64,
+    /// The standard deviation of the generated distribution
+    sigma: f64,
     /// The second value generated by the algorithm,
     /// or None if two new values need to be generated,
     stashed_second_value: Option<f64>,
 }
 
 impl BoxMullerGaussian701 {
-    /// Construct a new BoxMullerGaussian701
-    pub fn new(uniform: Uniform701) -> Self {
+    /// Construct a new BoxMullerGaussian701 with mean `mu` and standard deviation `sigma`
+    pub fn new(uniform: Uniform701, mu: f64, sigma: f64) -> Self {
         Self {
             uniform,
+            mu,
+            sigma,
             stashed_second_value: None,
         }
     }
 
-    /// Get the next random number normally distributed with mean 0 and deviation...  actually, I don't know...
-    pub fn next(&mut self) -> f64 {
+    /// Construct a new BoxMullerGaussian701 with mean 0 and standard deviation 1
+    pub fn standard(uniform: Uniform701) -> Self {
+        Self::new(uniform, 0.0, 1.0)
+    }
+
+    pub fn mu(&self) -> f64 {
+        self.mu
+    }
+
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+}
+
+impl Sampler701 for BoxMullerGaussian701 {
+    /// Get the next random number normally distributed with mean mu and deviation sigma
+    fn next(&mut self) -> f64 {
         let (ret_val, to_stash) = match self.stashed_second_value {
             Some(previously_stashed) => (previously_stashed, None),
             None => {
-                // Generate two new uniform random variables
-                let u1 = self.uniform.next();
+                // Generate two new uniform random variables.
+                // u1 is in (0, 1] so that its logarithm is finite.
+                let u1 = 1.0 - self.uniform.next();
                 let u2 = self.uniform.next();
 
                 // Calculate the common values
                 let r = (-2.0 * u1.ln()).sqrt();
                 let t = 2.0 * PI * u2;
 
-                // Generate two new gaussian random variables
-                let x = (r * t.cos()) / 10.0 + 0.5;
-                let y = (r * t.sin()) / 10.0 + 0.5;
+                // Generate two new standard gaussian random variables
+                let x = r * t.cos();
+                let y = r * t.sin();
 
                 (x, Some(y))
             }
         };
         self.stashed_second_value = to_stash;
-        ret_val
+        ret_val * self.sigma + self.mu
+    }
+
+    fn pdf(&self, x: f64) -> Option<f64> {
+        Some(util::normal_pdf(x, self.mu, self.sigma))
+    }
+
+    fn cdf(&self, x: f64) -> Option<f64> {
+        Some(util::normal_cdf(x, self.mu, self.sigma))
+    }
+
+    fn mean(&self) -> Option<f64> {
+        Some(self.mu)
+    }
+
+    fn variance(&self) -> Option<f64> {
+        Some(self.sigma.powi(2))
     }
 }
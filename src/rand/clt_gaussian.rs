@@ -1,4 +1,6 @@
+use crate::rand::sampler::Sampler701;
 use crate::rand::uniform::Uniform701;
+use crate::util;
 
 pub struct CentralLimitTheoremGaussian701 {
     /// The uniform random source
@@ -12,9 +14,48 @@ impl CentralLimitTheoremGaussian701 {
         Self { uniform, n }
     }
 
+    /// Evaluate the alternating Irwin-Hall sum, sum_k (-1)^k C(n, k) (s - k)^power,
+    /// for the sum `s` of `n` uniform random variables
+    fn irwin_hall_sum(&self, s: f64, power: i32) -> f64 {
+        let n = self.n;
+        (0..=n.min(s.floor() as usize))
+            .map(|k| {
+                let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                sign * util::binomial(n, k) * (s - k as f64).powi(power)
+            })
+            .sum()
+    }
+}
+
+/// The mean of `n` uniform random variables follows the Bates distribution,
+/// which approaches a gaussian as `n` grows.
+impl Sampler701 for CentralLimitTheoremGaussian701 {
     /// Get the next random number normally distributed
-    pub fn next(&mut self) -> f64 {
+    fn next(&mut self) -> f64 {
         let n = self.n;
         (0..n).map(|_| self.uniform.next()).sum::<f64>() / n as f64
     }
+
+    fn pdf(&self, x: f64) -> Option<f64> {
+        if !(0.0..=1.0).contains(&x) {
+            return Some(0.0);
+        }
+        let n = self.n;
+        let s = x * n as f64;
+        Some(n as f64 * self.irwin_hall_sum(s, n as i32 - 1) / util::gamma(n))
+    }
+
+    fn cdf(&self, x: f64) -> Option<f64> {
+        let n = self.n;
+        let s = x.clamp(0.0, 1.0) * n as f64;
+        Some(self.irwin_hall_sum(s, n as i32) / util::gamma(n + 1))
+    }
+
+    fn mean(&self) -> Option<f64> {
+        Some(0.5)
+    }
+
+    fn variance(&self) -> Option<f64> {
+        Some(1.0 / (12.0 * self.n as f64))
+    }
 }
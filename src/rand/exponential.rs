@@ -1,4 +1,6 @@
+use crate::rand::sampler::Sampler701;
 use crate::rand::uniform::Uniform701;
+use crate::util;
 
 pub struct Exponential701 {
     uniform: Uniform701,
@@ -11,17 +13,71 @@ impl Exponential701 {
         Self { uniform, a, b }
     }
 
-    pub fn next(&mut self) -> f64 {
+    pub fn a(&self) -> f64 {
+        self.a
+    }
+    pub fn b(&self) -> f64 {
+        self.b
+    }
+
+    /// The largest value this generator can produce, where the cdf reaches 1.
+    /// This is infinite when `a == b`, i.e., the usual exponential distribution.
+    pub fn upper_bound(&self) -> f64 {
+        -(1.0 - self.b / self.a).ln() / self.b
+    }
+
+    /// Calculate the integral of t^k * a * e^(-bt) over the support, i.e., the k-th raw moment
+    fn raw_moment(&self, k: i32) -> f64 {
+        let a = self.a;
+        let b = self.b;
+        let t = self.upper_bound();
+        // Remaining mass of e^(-bt) beyond the upper bound, 0 when the support is unbounded
+        let tail = 1.0 - b / a;
+        let truncation = if tail == 0.0 {
+            0.0
+        } else {
+            // The finite support cuts off sum_j k!/j! * t^j / b^(k-j+1) for j in 0..=k
+            tail * (0..=k)
+                .map(|j| {
+                    util::gamma(k as usize + 1) / util::gamma(j as usize + 1) * t.powi(j)
+                        / b.powi(k - j + 1)
+                })
+                .sum::<f64>()
+        };
+        a * (util::gamma(k as usize + 1) / b.powi(k + 1) - truncation)
+    }
+}
+
+impl Sampler701 for Exponential701 {
+    /// Get the next random number with density a * e^(-bx)
+    fn next(&mut self) -> f64 {
         let x = self.uniform.next();
         let a = self.a;
         let b = self.b;
         -(-(b / a) * x + 1.0).ln() / b
     }
 
-    pub fn a(&self) -> f64 {
-        self.a
+    fn pdf(&self, x: f64) -> Option<f64> {
+        Some(if x < 0.0 || x >= self.upper_bound() {
+            0.0
+        } else {
+            self.a * (-self.b * x).exp()
+        })
     }
-    pub fn b(&self) -> f64 {
-        self.b
+
+    fn cdf(&self, x: f64) -> Option<f64> {
+        Some(if x < 0.0 {
+            0.0
+        } else {
+            (self.a / self.b * (1.0 - (-self.b * x).exp())).min(1.0)
+        })
+    }
+
+    fn mean(&self) -> Option<f64> {
+        Some(self.raw_moment(1))
+    }
+
+    fn variance(&self) -> Option<f64> {
+        Some(self.raw_moment(2) - self.raw_moment(1).powi(2))
     }
 }
@@ -1,4 +1,6 @@
+use crate::rand::sampler::Sampler701;
 use crate::rand::uniform::Uniform701;
+use std::f64::consts::PI;
 
 pub struct InverseTransform701 {
     uniform: Uniform701,
@@ -10,12 +12,40 @@ impl InverseTransform701 {
         Self { uniform, sigma }
     }
 
-    pub fn next(&mut self) -> f64 {
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+}
+
+/// Inverting the cdf of the Rayleigh distribution
+impl Sampler701 for InverseTransform701 {
+    fn next(&mut self) -> f64 {
         let y = self.uniform.next();
         (-2.0 * self.sigma.powf(2.0) * (1.0 - y).ln()).sqrt()
     }
 
-    pub fn sigma(&self) -> f64 {
-        self.sigma
+    fn pdf(&self, x: f64) -> Option<f64> {
+        let s2 = self.sigma.powi(2);
+        Some(if x < 0.0 {
+            0.0
+        } else {
+            (x / s2) * (-x.powi(2) / (2.0 * s2)).exp()
+        })
+    }
+
+    fn cdf(&self, x: f64) -> Option<f64> {
+        Some(if x < 0.0 {
+            0.0
+        } else {
+            1.0 - (-x.powi(2) / (2.0 * self.sigma.powi(2))).exp()
+        })
+    }
+
+    fn mean(&self) -> Option<f64> {
+        Some(self.sigma * (PI / 2.0).sqrt())
+    }
+
+    fn variance(&self) -> Option<f64> {
+        Some((4.0 - PI) / 2.0 * self.sigma.powi(2))
     }
 }
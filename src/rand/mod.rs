@@ -1,5 +1,7 @@
-pub(crate) mod boxmuller;
-pub(crate) mod clt_gaussian;
-pub(crate) mod exponential;
-pub(crate) mod inverse_transform;
-pub(crate) mod uniform;
+pub mod boxmuller;
+pub mod clt_gaussian;
+pub mod exponential;
+pub mod inverse_transform;
+pub mod polar;
+pub mod sampler;
+pub mod uniform;
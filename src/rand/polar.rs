@@ -0,0 +1,85 @@
+use crate::rand::sampler::Sampler701;
+use crate::rand::uniform::Uniform701;
+use crate::util;
+
+/// The Marsaglia polar method, a variant of Box-Müller which avoids the trigonometric functions
+/// by rejecting uniform points that fall outside of the unit circle.
+pub struct MarsagliaPolarGaussian701 {
+    /// The uniform random source
+    uniform: Uniform701,
+    /// The mean of the generated distribution
+    mu: f64,
+    /// The standard deviation of the generated distribution
+    sigma: f64,
+    /// The second value generated by the algorithm,
+    /// or None if two new values need to be generated,
+    stashed_second_value: Option<f64>,
+}
+
+impl MarsagliaPolarGaussian701 {
+    /// Construct a new MarsagliaPolarGaussian701 with mean `mu` and standard deviation `sigma`
+    pub fn new(uniform: Uniform701, mu: f64, sigma: f64) -> Self {
+        Self {
+            uniform,
+            mu,
+            sigma,
+            stashed_second_value: None,
+        }
+    }
+
+    /// Construct a new MarsagliaPolarGaussian701 with mean 0 and standard deviation 1
+    pub fn standard(uniform: Uniform701) -> Self {
+        Self::new(uniform, 0.0, 1.0)
+    }
+
+    pub fn mu(&self) -> f64 {
+        self.mu
+    }
+
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+}
+
+impl Sampler701 for MarsagliaPolarGaussian701 {
+    /// Get the next random number normally distributed with mean mu and deviation sigma
+    fn next(&mut self) -> f64 {
+        let (ret_val, to_stash) = match self.stashed_second_value {
+            Some(previously_stashed) => (previously_stashed, None),
+            None => {
+                // Draw points in the square [-1, 1) x [-1, 1) until one is inside the unit circle
+                let (u, v, s) = loop {
+                    let u = self.uniform.next() * 2.0 - 1.0;
+                    let v = self.uniform.next() * 2.0 - 1.0;
+                    let s = u * u + v * v;
+                    if s > 0.0 && s < 1.0 {
+                        break (u, v, s);
+                    }
+                };
+
+                // Generate two new standard gaussian random variables
+                let f = (-2.0 * s.ln() / s).sqrt();
+
+                (u * f, Some(v * f))
+            }
+        };
+        self.stashed_second_value = to_stash;
+        ret_val * self.sigma + self.mu
+    }
+
+    fn pdf(&self, x: f64) -> Option<f64> {
+        Some(util::normal_pdf(x, self.mu, self.sigma))
+    }
+
+    fn cdf(&self, x: f64) -> Option<f64> {
+        Some(util::normal_cdf(x, self.mu, self.sigma))
+    }
+
+    fn mean(&self) -> Option<f64> {
+        Some(self.mu)
+    }
+
+    fn variance(&self) -> Option<f64> {
+        Some(self.sigma.powi(2))
+    }
+}
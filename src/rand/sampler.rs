@@ -0,0 +1,102 @@
+use std::cell::RefCell;
+
+/// The common interface of every random number generator in the COS701 project.
+/// Only `next` is required; the analytic properties of the distribution are optional
+/// and return None when they are not known in closed form.
+pub trait Sampler701 {
+    /// Get the next random number from this generator
+    fn next(&mut self) -> f64;
+
+    /// The probability density function of the generated distribution, evaluated at `x`
+    fn pdf(&self, _x: f64) -> Option<f64> {
+        None
+    }
+
+    /// The cumulative distribution function of the generated distribution, evaluated at `x`
+    fn cdf(&self, _x: f64) -> Option<f64> {
+        None
+    }
+
+    /// The mean of the generated distribution
+    fn mean(&self) -> Option<f64> {
+        None
+    }
+
+    /// The variance of the generated distribution
+    fn variance(&self) -> Option<f64> {
+        None
+    }
+
+    /// Borrow this generator as an endless iterator of samples
+    fn samples(&mut self) -> Samples<'_, Self> {
+        Samples { sampler: self }
+    }
+
+    /// Wrap this generator so it can be used wherever a `rand::distributions::Distribution`
+    /// is expected
+    fn into_distribution(self) -> SamplerDistribution<Self>
+    where
+        Self: Sized,
+    {
+        SamplerDistribution {
+            sampler: RefCell::new(self),
+        }
+    }
+}
+
+/// An endless iterator over the samples of a `Sampler701`
+pub struct Samples<'a, S: Sampler701 + ?Sized> {
+    sampler: &'a mut S,
+}
+
+impl<'a, S: Sampler701 + ?Sized> Iterator for Samples<'a, S> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        Some(self.sampler.next())
+    }
+}
+
+/// Adapts a `Sampler701` to `rand::distributions::Distribution`.
+/// Every generator owns its own uniform source, so the `rng` supplied to `sample` is not used;
+/// seed the generator's `Uniform701` instead to get reproducible results.
+pub struct SamplerDistribution<S: Sampler701> {
+    sampler: RefCell<S>,
+}
+
+impl<S: Sampler701> SamplerDistribution<S> {
+    /// Unwrap the underlying generator
+    pub fn into_inner(self) -> S {
+        self.sampler.into_inner()
+    }
+}
+
+impl<S: Sampler701> rand::distributions::Distribution<f64> for SamplerDistribution<S> {
+    fn sample<R: rand::Rng + ?Sized>(&self, _rng: &mut R) -> f64 {
+        self.sampler.borrow_mut().next()
+    }
+}
+
+/// The sample mean and the (unbiased) sample variance of `samples`
+pub fn sample_moments(samples: &[f64]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let (sum, sum_sq) = samples
+        .iter()
+        .fold((0.0, 0.0), |(sum, sum_sq), x| (sum + x, sum_sq + x * x));
+    let mean = sum / n;
+    let variance = (sum_sq - n * mean * mean) / (n - 1.0);
+    (mean, variance)
+}
+
+/// Log the sample moments of `samples`, drawn from `sampler`, next to the analytic ones
+pub fn log_moments<S: Sampler701 + ?Sized>(name: &str, sampler: &S, samples: &[f64]) {
+    let (mean, variance) = sample_moments(samples);
+    log::info!(
+        "{}: sample mean = {:.5} (expected {:?}), sample variance = {:.5} (expected {:?})",
+        name,
+        mean,
+        sampler.mean(),
+        variance,
+        sampler.variance()
+    );
+}
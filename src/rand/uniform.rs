@@ -1,24 +1,81 @@
+use crate::rand::sampler::Sampler701;
 use rand::distributions::Distribution;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 
 pub struct Uniform701 {
-    /// The system random number generator
-    rng: rand::rngs::ThreadRng,
+    /// The random number generator backing this source
+    rng: Box<dyn RngCore>,
+    /// The seed used to initialize `rng`
+    seed: u64,
     /// The system Uniform random distribution
     dst: rand::distributions::Uniform<f64>,
 }
 
 /// The Uniform random data source for all of the COS701 project
 impl Uniform701 {
-    /// Construct a new Uniform701
+    /// Construct a new Uniform701 with a random seed.
+    /// The seed is logged so that the run can be reproduced with `Uniform701::seeded`.
     pub fn new() -> Self {
+        Self::seeded(rand::random())
+    }
+
+    /// Construct a new Uniform701 backed by the standard generator, initialized with `seed`.
+    /// Two instances constructed with the same seed produce the same sequence.
+    pub fn seeded(seed: u64) -> Self {
+        log::info!("Using seed {}", seed);
+        Self::with_rng(StdRng::seed_from_u64(seed), seed)
+    }
+
+    /// Construct a new Uniform701 backed by the supplied generator, e.g., a ChaCha or PCG stream.
+    /// `seed` should be the seed used to initialize `rng`; it is only recorded, not applied.
+    pub fn with_rng<R: RngCore + 'static>(rng: R, seed: u64) -> Self {
         Self {
-            rng: rand::thread_rng(),
+            rng: Box::new(rng),
+            seed,
             dst: rand::distributions::Uniform::from(0f64..1f64),
         }
     }
 
+    /// Construct a new, independent Uniform701 whose seed is drawn from this one.
+    /// Forking in a fixed order keeps every derived stream reproducible from the original seed.
+    pub fn fork(&mut self) -> Self {
+        let seed = self.rng.next_u64();
+        log::debug!("Forking seed {} from seed {}", seed, self.seed);
+        Self::with_rng(StdRng::seed_from_u64(seed), seed)
+    }
+
+    /// Get the seed used to initialize this source
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Default for Uniform701 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sampler701 for Uniform701 {
     /// Get the next random number uniformly distributed between 0..1
-    pub fn next(&mut self) -> f64 {
+    fn next(&mut self) -> f64 {
         self.dst.sample(&mut self.rng)
     }
+
+    fn pdf(&self, x: f64) -> Option<f64> {
+        Some(if (0.0..1.0).contains(&x) { 1.0 } else { 0.0 })
+    }
+
+    fn cdf(&self, x: f64) -> Option<f64> {
+        Some(x.clamp(0.0, 1.0))
+    }
+
+    fn mean(&self) -> Option<f64> {
+        Some(0.5)
+    }
+
+    fn variance(&self) -> Option<f64> {
+        Some(1.0 / 12.0)
+    }
 }
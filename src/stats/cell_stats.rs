@@ -0,0 +1,266 @@
+//! Statistics of the sizes and shapes of the cells of Voronoi diagrams
+
+use crate::data::voronoi::VoronoiDiagram;
+use crate::stats::fit::LinearFit;
+use crate::stats::histogram::Histogram;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+/// The cells with a given number of sides
+#[derive(Copy, Clone, Debug)]
+pub struct SidesRow {
+    pub sides: usize,
+    pub cells: u64,
+    /// The fraction of all cells which have this many sides
+    pub fraction: f64,
+    /// The mean area of these cells relative to the mean area of all cells
+    pub mean_relative_area: f64,
+    /// The mean number of sides of the neighbors of these cells, i.e., m(n) of Aboav-Weaire's law,
+    /// or `None` if none of these cells has only interior neighbors
+    pub mean_neighbor_sides: Option<f64>,
+}
+
+/// Statistics of the interior cells of one or more Voronoi diagrams, i.e., those cells not cut
+/// short by the bounds of their diagram
+#[derive(Clone, Debug, Default)]
+pub struct CellStatistics {
+    /// The area of each interior cell, relative to the mean area of the cells of its diagram
+    relative_areas: Vec<f64>,
+    /// The number of sides of each interior cell
+    sides: Vec<usize>,
+    /// The number of sides of each interior cell whose neighbors are all interior,
+    /// paired with the mean number of sides of its neighbors
+    neighbor_sides: Vec<(usize, f64)>,
+}
+
+impl CellStatistics {
+    pub fn from_diagram(diagram: &VoronoiDiagram) -> Self {
+        let cells = diagram.cells();
+        let interior = cells
+            .iter()
+            .map(|cell| !cell.vertices().is_empty() && !cell.touches_bounds())
+            .collect::<Vec<bool>>();
+
+        let areas = cells.iter().map(|cell| cell.area()).collect::<Vec<f64>>();
+        let n_nonempty = cells.iter().filter(|c| !c.vertices().is_empty()).count();
+        let mean_area = areas.iter().sum::<f64>() / n_nonempty.max(1) as f64;
+
+        let mut stats = Self::default();
+        for (i, cell) in cells.iter().enumerate().filter(|&(i, _)| interior[i]) {
+            let n = cell.vertices().len();
+            stats.relative_areas.push(areas[i] / mean_area);
+            stats.sides.push(n);
+
+            let neighbors = cell.neighbors();
+            if neighbors.iter().all(|&j| interior[j]) {
+                let total = neighbors
+                    .iter()
+                    .map(|&j| cells[j].vertices().len())
+                    .sum::<usize>();
+                stats
+                    .neighbor_sides
+                    .push((n, total as f64 / neighbors.len() as f64));
+            }
+        }
+        stats
+    }
+
+    /// Pool the cells of another set of diagrams with these
+    pub fn merge(&mut self, that: &Self) {
+        self.relative_areas.extend(&that.relative_areas);
+        self.sides.extend(&that.sides);
+        self.neighbor_sides.extend(&that.neighbor_sides);
+    }
+
+    /// The number of interior cells
+    pub fn n_cells(&self) -> usize {
+        self.sides.len()
+    }
+
+    /// The area of each interior cell, relative to the mean area of the cells of its diagram
+    pub fn relative_areas(&self) -> &[f64] {
+        &self.relative_areas
+    }
+
+    /// Bin the relative areas of the cells into `n_bins` equal bins spanning `0..max`
+    pub fn area_histogram(&self, max: f64, n_bins: usize) -> Result<Histogram, Box<dyn Error>> {
+        let mut histogram = Histogram::uniform(0.0..max, n_bins)?;
+        histogram.fill_all(self.relative_areas.iter().cloned());
+        Ok(histogram)
+    }
+
+    /// The mean number of sides of a cell, which is 6 for a large diagram by Euler's formula
+    pub fn mean_sides(&self) -> f64 {
+        self.sides.iter().sum::<usize>() as f64 / self.n_cells() as f64
+    }
+
+    /// The variance of the number of sides of a cell, mu_2
+    pub fn sides_variance(&self) -> f64 {
+        let mean = self.mean_sides();
+        self.sides
+            .iter()
+            .map(|&n| (n as f64 - mean).powi(2))
+            .sum::<f64>()
+            / self.n_cells() as f64
+    }
+
+    /// Tabulate the cells by their number of sides, from the fewest sides to the most
+    pub fn by_sides(&self) -> Vec<SidesRow> {
+        let max_sides = self.sides.iter().cloned().max().unwrap_or(0);
+        let mut cells = vec![0; max_sides + 1];
+        let mut areas = vec![0.0; max_sides + 1];
+        for (&n, &area) in self.sides.iter().zip(&self.relative_areas) {
+            cells[n] += 1;
+            areas[n] += area;
+        }
+        let mut neighbor_cells = vec![0; max_sides + 1];
+        let mut neighbor_sides = vec![0.0; max_sides + 1];
+        for &(n, m) in &self.neighbor_sides {
+            neighbor_cells[n] += 1;
+            neighbor_sides[n] += m;
+        }
+
+        (0..=max_sides)
+            .filter(|&n| cells[n] > 0)
+            .map(|n| SidesRow {
+                sides: n,
+                cells: cells[n],
+                fraction: cells[n] as f64 / self.n_cells() as f64,
+                mean_relative_area: areas[n] / cells[n] as f64,
+                mean_neighbor_sides: if neighbor_cells[n] > 0 {
+                    Some(neighbor_sides[n] / neighbor_cells[n] as f64)
+                } else {
+                    None
+                },
+            })
+            .collect()
+    }
+
+    /// Fit Aboav-Weaire's law, n m(n) = (6 - a) n + 6 a + mu_2, where m(n) is the mean number
+    /// of sides of the neighbors of cells with n sides.  The slope of the fit is 6 - a, and its
+    /// intercept may be compared with 6 a + `sides_variance()`.
+    /// Each cell with only interior neighbors is weighted equally.
+    pub fn aboav_weaire(&self) -> Result<LinearFit, Box<dyn Error>> {
+        LinearFit::least_squares(
+            &self
+                .neighbor_sides
+                .iter()
+                .map(|&(n, m)| (n as f64, n as f64 * m))
+                .collect::<Vec<(f64, f64)>>(),
+        )
+    }
+
+    /// Fit Lewis's law, that the mean relative area of cells with n sides grows linearly with n.
+    /// Each cell is weighted equally.
+    pub fn lewis(&self) -> Result<LinearFit, Box<dyn Error>> {
+        LinearFit::least_squares(
+            &self
+                .sides
+                .iter()
+                .zip(&self.relative_areas)
+                .map(|(&n, &area)| (n as f64, area))
+                .collect::<Vec<(f64, f64)>>(),
+        )
+    }
+
+    /// Write the table of cells by their number of sides
+    pub fn print_csv(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        of.write_all("Sides, Cells, Fraction, Mean Relative Area, Mean Neighbor Sides\n".as_ref())?;
+        for row in self.by_sides() {
+            of.write_all(
+                format!(
+                    "{}, {}, {}, {}, {}\n",
+                    row.sides,
+                    row.cells,
+                    row.fraction,
+                    row.mean_relative_area,
+                    row.mean_neighbor_sides
+                        .map_or_else(String::new, |m| m.to_string())
+                )
+                .as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::point::Point2d;
+    use crate::rand::sampler::Sampler701;
+    use crate::rand::uniform::Uniform701;
+
+    #[test]
+    fn a_hexagonal_lattice_has_only_hexagons() {
+        // A triangular lattice of sites, whose interior cells are regular hexagons of area √3/2
+        let sites = (0..100)
+            .map(|s| {
+                let (i, j) = (s % 10, s / 10);
+                Point2d::new(i as f64 + 0.5 * (j % 2) as f64, j as f64 * 0.75f64.sqrt())
+            })
+            .collect::<Vec<Point2d>>();
+        let diagram =
+            VoronoiDiagram::new(&sites, Point2d::new(-0.4, -1.0), Point2d::new(9.9, 8.8)).unwrap();
+        let stats = CellStatistics::from_diagram(&diagram);
+
+        // The bounds cut the cells of the outermost sites, including the pentagons at the ends of
+        // the shifted rows, leaving 8 rows of 8 hexagons
+        assert_eq!(stats.n_cells(), 64);
+        assert_eq!((stats.mean_sides(), stats.sides_variance()), (6.0, 0.0));
+        let hexagon = 0.75f64.sqrt();
+        let mean_area = diagram.cells().iter().map(|c| c.area()).sum::<f64>() / 100.0;
+        for &area in stats.relative_areas() {
+            assert!((area - hexagon / mean_area).abs() < 1e-9);
+        }
+
+        let rows = stats.by_sides();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            (rows[0].sides, rows[0].cells, rows[0].fraction),
+            (6, 64, 1.0)
+        );
+        // Only the 6 rows of 6 hexagons whose neighbors are all interior enter m(n)
+        assert_eq!(rows[0].mean_neighbor_sides, Some(6.0));
+        assert_eq!(stats.neighbor_sides.len(), 36);
+
+        // Every cell has the same number of sides, so neither law can be fit
+        assert!(stats.aboav_weaire().is_err());
+        assert!(stats.lewis().is_err());
+    }
+
+    #[test]
+    fn random_cells_follow_the_laws() {
+        let mut uni = Uniform701::seeded(14);
+        let mut stats = CellStatistics::default();
+        for _ in 0..4 {
+            let sites = (0..500)
+                .map(|_| Point2d::new(10.0 * uni.next(), 10.0 * uni.next()))
+                .collect::<Vec<Point2d>>();
+            let diagram =
+                VoronoiDiagram::new(&sites, Point2d::new(0.0, 0.0), Point2d::new(10.0, 10.0))
+                    .unwrap();
+            stats.merge(&CellStatistics::from_diagram(&diagram));
+        }
+
+        let rows = stats.by_sides();
+        assert_eq!(
+            rows.iter().map(|r| r.cells).sum::<u64>(),
+            stats.n_cells() as u64
+        );
+        assert!((rows.iter().map(|r| r.fraction).sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!(rows.windows(2).all(|w| w[0].sides < w[1].sides));
+        assert!(rows[0].sides >= 3);
+        // Cells with more sides are larger
+        assert!(rows.first().unwrap().mean_relative_area < rows.last().unwrap().mean_relative_area);
+        assert!((stats.mean_sides() - 6.0).abs() < 0.1);
+
+        // Aboav-Weaire's a is about 0.7 for Poisson-Voronoi cells
+        let aboav_weaire = stats.aboav_weaire().unwrap();
+        assert!(aboav_weaire.slope > 5.0 && aboav_weaire.slope < 5.6);
+        let lewis = stats.lewis().unwrap();
+        assert!(lewis.slope > 0.0);
+    }
+}
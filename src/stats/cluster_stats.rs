@@ -0,0 +1,495 @@
+//! Statistics of the clusters of a lattice: their sizes and shapes, the number of clusters of
+//! each size, and the fractal dimension of a cluster
+
+use crate::data::lattice::{ClusterLabels, Connectivity, Criterion, Lattice};
+use crate::stats::fit::LinearFit;
+use crate::stats::histogram::Histogram;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+/// A position on the lattice, unwrapped from a periodic lattice
+pub type Position = (isize, isize);
+
+/// The size and shape of a cluster
+#[derive(Copy, Clone, Debug)]
+pub struct ClusterShape {
+    pub size: usize,
+    /// The mean row and column of the sites
+    pub center: (f64, f64),
+    /// The root mean square distance of the sites from the center
+    pub radius_of_gyration: f64,
+    /// The smallest row and column of the sites
+    pub min: Position,
+    /// The largest row and column of the sites
+    pub max: Position,
+    /// The number of empty sites neighboring the cluster
+    pub perimeter: usize,
+    /// The number of those empty sites which can be reached from outside the cluster without
+    /// crossing it, i.e., the perimeter less the edges of the holes in the cluster
+    pub hull: usize,
+}
+
+impl ClusterShape {
+    /// The number of rows and columns the cluster covers
+    pub fn extent(&self) -> (usize, usize) {
+        (
+            (self.max.0 - self.min.0 + 1) as usize,
+            (self.max.1 - self.min.1 + 1) as usize,
+        )
+    }
+}
+
+/// A fit of the fractal dimension of a cluster as the slope of a line on a log-log scale
+#[derive(Clone, Debug)]
+pub struct FractalFit {
+    /// The points fit, as (log of the length scale, log of the amount of the cluster)
+    pub points: Vec<(f64, f64)>,
+    pub fit: LinearFit,
+}
+
+impl FractalFit {
+    pub fn dimension(&self) -> f64 {
+        self.fit.slope
+    }
+
+    fn new(points: Vec<(f64, f64)>) -> Result<Self, Box<dyn Error>> {
+        if points.len() < 3 {
+            return Err(format!(
+                "A fractal dimension needs at least 3 length scales, got {}",
+                points.len()
+            )
+            .into());
+        }
+        let fit = LinearFit::least_squares(&points)?;
+        Ok(Self { points, fit })
+    }
+}
+
+/// The positions and shapes of the clusters of a lattice
+pub struct ClusterGeometry {
+    /// The positions of the sites of each cluster, indexed by label
+    positions: Vec<Vec<Position>>,
+    shapes: Vec<ClusterShape>,
+}
+
+impl ClusterGeometry {
+    /// Find the positions and shapes of the labelled clusters of the lattice.
+    /// On a periodic lattice, each cluster is walked from its first site through its bonds to
+    /// unwrap its positions.  A cluster which wraps around the lattice cannot be unwrapped
+    /// consistently, so its shape is that of one of its copies cut open along some bonds.
+    pub fn new(lattice: &Lattice, labels: &ClusterLabels) -> Self {
+        let positions = unwrap(lattice, labels);
+        let shapes = positions
+            .iter()
+            .map(|sites| shape(lattice, sites))
+            .collect();
+        Self { positions, shapes }
+    }
+
+    /// The shape of each cluster, indexed by label
+    pub fn shapes(&self) -> &[ClusterShape] {
+        &self.shapes
+    }
+
+    /// The positions of the sites of the cluster with the label
+    pub fn positions(&self, label: usize) -> &[Position] {
+        &self.positions[label]
+    }
+
+    /// Fit the fractal dimension of the cluster with the label from how the number of its sites
+    /// within r of its center grows with r (the mass-radius relation), for r growing by factors
+    /// of the square root of 2 from 2 up to its radius of gyration, within which the cluster
+    /// is not cut short by its own edges.
+    /// Return an error if the cluster is too small to give at least 3 radii.
+    pub fn mass_radius(&self, label: usize) -> Result<FractalFit, Box<dyn Error>> {
+        let shape = &self.shapes[label];
+        let (ci, cj) = shape.center;
+        let mut distances = self.positions[label]
+            .iter()
+            .map(|&(i, j)| ((i as f64 - ci).powi(2) + (j as f64 - cj).powi(2)).sqrt())
+            .collect::<Vec<f64>>();
+        distances.sort_unstable_by(f64::total_cmp);
+
+        let points = (0..)
+            .map(|k| 2.0 * 2.0_f64.sqrt().powi(k))
+            .take_while(|&r| r <= shape.radius_of_gyration)
+            .map(|r| {
+                let mass = distances.partition_point(|&d| d <= r);
+                (r.ln(), (mass.max(1) as f64).ln())
+            })
+            .collect();
+        FractalFit::new(points)
+    }
+
+    /// Fit the fractal dimension of the cluster with the label by counting the boxes of side
+    /// 2, 4, 8, ..., up to half of its extent, which hold a site of the cluster.  Boxes of side 1
+    /// are left out since they see the lattice rather than the fractal.  The points fit are
+    /// (log of the inverse of the side, log of the boxes counted).
+    /// Return an error if the cluster is too small to give at least 3 box sizes.
+    pub fn box_counting(&self, label: usize) -> Result<FractalFit, Box<dyn Error>> {
+        let shape = &self.shapes[label];
+        let (rows, cols) = shape.extent();
+        let points = (0..)
+            .map(|k| 2_isize << k)
+            .take_while(|&side| 2 * side as usize <= rows.max(cols))
+            .map(|side| {
+                let boxes = self.positions[label]
+                    .iter()
+                    .map(|&(i, j)| {
+                        (
+                            (i - shape.min.0).div_euclid(side),
+                            (j - shape.min.1).div_euclid(side),
+                        )
+                    })
+                    .collect::<HashSet<Position>>();
+                ((1.0 / side as f64).ln(), (boxes.len() as f64).ln())
+            })
+            .collect();
+        FractalFit::new(points)
+    }
+
+    pub fn print_csv(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        of.write_all(
+            "Label, Size, Center Row, Center Column, Radius of Gyration, Min Row, Min Column, \
+             Max Row, Max Column, Perimeter, Hull\n"
+                .as_ref(),
+        )?;
+        for (label, s) in self.shapes.iter().enumerate() {
+            of.write_all(
+                format!(
+                    "{}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}\n",
+                    label,
+                    s.size,
+                    s.center.0,
+                    s.center.1,
+                    s.radius_of_gyration,
+                    s.min.0,
+                    s.min.1,
+                    s.max.0,
+                    s.max.1,
+                    s.perimeter,
+                    s.hull
+                )
+                .as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Give each site of each cluster its position, walking each cluster from its first site so
+/// that the positions of neighbors differ by the offset of the bond between them
+fn unwrap(lattice: &Lattice, labels: &ClusterLabels) -> Vec<Vec<Position>> {
+    let n = lattice.box_len;
+    let mut positions = labels
+        .sizes()
+        .iter()
+        .map(|&size| Vec::with_capacity(size))
+        .collect::<Vec<Vec<Position>>>();
+    let mut visited = vec![false; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            let label = match labels.label((i, j)) {
+                Some(label) if !visited[i * n + j] => label,
+                _ => continue,
+            };
+            visited[i * n + j] = true;
+            let mut to_visit = vec![((i, j), (i as isize, j as isize))];
+            while let Some((site, at)) = to_visit.pop() {
+                positions[label].push(at);
+                for ((a, b), (di, dj)) in lattice.neighbors(site) {
+                    if !visited[a * n + b] {
+                        visited[a * n + b] = true;
+                        to_visit.push(((a, b), (at.0 + di, at.1 + dj)));
+                    }
+                }
+            }
+        }
+    }
+    positions
+}
+
+/// Find the shape of the cluster with sites at the positions
+fn shape(lattice: &Lattice, positions: &[Position]) -> ClusterShape {
+    let size = positions.len();
+    let center = positions.iter().fold((0.0, 0.0), |(si, sj), &(i, j)| {
+        (si + i as f64 / size as f64, sj + j as f64 / size as f64)
+    });
+    let radius_of_gyration = (positions
+        .iter()
+        .map(|&(i, j)| (i as f64 - center.0).powi(2) + (j as f64 - center.1).powi(2))
+        .sum::<f64>()
+        / size as f64)
+        .sqrt();
+    let min = positions
+        .iter()
+        .fold((isize::MAX, isize::MAX), |m, &(i, j)| {
+            (m.0.min(i), m.1.min(j))
+        });
+    let max = positions
+        .iter()
+        .fold((isize::MIN, isize::MIN), |m, &(i, j)| {
+            (m.0.max(i), m.1.max(j))
+        });
+
+    // The empty sites next to the cluster, by every offset to a neighbor
+    let cluster = positions.iter().cloned().collect::<HashSet<Position>>();
+    let offsets = lattice
+        .connectivity
+        .bonds()
+        .iter()
+        .flat_map(|&(di, dj)| [(di, dj), (-di, -dj)])
+        .collect::<Vec<Position>>();
+    let n = lattice.box_len as isize;
+    let perimeter = positions
+        .iter()
+        .flat_map(|&(i, j)| offsets.iter().map(move |&(di, dj)| (i + di, j + dj)))
+        .filter(|&(i, j)| {
+            let on_lattice = lattice.periodic || (0..n).contains(&i) && (0..n).contains(&j);
+            on_lattice && !lattice.grid[i.rem_euclid(n) as usize][j.rem_euclid(n) as usize]
+        })
+        .collect::<HashSet<Position>>();
+
+    let hull = outside(lattice.connectivity, &cluster, min, max)
+        .intersection(&perimeter)
+        .count();
+
+    ClusterShape {
+        size,
+        center,
+        radius_of_gyration,
+        min,
+        max,
+        perimeter: perimeter.len(),
+        hull,
+    }
+}
+
+/// The positions around the cluster which can be reached from beyond its bounds without
+/// crossing it, within its bounds widened by the longest bond.
+/// The outside moves by the connectivity dual to the cluster's, so that a cluster joined only
+/// through its edges leaves gaps at its corners, and one also joined through its corners does
+/// not.
+fn outside(
+    connectivity: Connectivity,
+    cluster: &HashSet<Position>,
+    min: Position,
+    max: Position,
+) -> HashSet<Position> {
+    let pad = 2;
+    let (lo, hi) = ((min.0 - pad, min.1 - pad), (max.0 + pad, max.1 + pad));
+    let moves: &[Position] = match connectivity {
+        Connectivity::Four => &[
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ],
+        _ => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
+    };
+
+    let mut reached = HashSet::new();
+    let mut to_visit = vec![lo];
+    reached.insert(lo);
+    while let Some((i, j)) = to_visit.pop() {
+        for &(di, dj) in moves {
+            let next = (i + di, j + dj);
+            if next.0 < lo.0 || next.1 < lo.1 || next.0 > hi.0 || next.1 > hi.1 {
+                continue;
+            }
+            if !cluster.contains(&next) && reached.insert(next) {
+                to_visit.push(next);
+            }
+        }
+    }
+    reached
+}
+
+/// The number of clusters of each size per site, n_s, counted over many lattices of the same
+/// size in bins whose widths grow geometrically
+pub struct ClusterNumbers {
+    histogram: Histogram,
+    /// The number of sites over all the lattices counted
+    n_sites: usize,
+}
+
+impl ClusterNumbers {
+    /// Construct an empty count of clusters of up to `max_size` sites, in at most `n_bins` bins.
+    /// The edges of the bins are whole numbers, so some of the smallest bins may be merged.
+    pub fn new(max_size: usize, n_bins: usize) -> Result<Self, Box<dyn Error>> {
+        let top = (max_size + 1) as f64;
+        let mut edges = (0..=n_bins)
+            .map(|k| top.powf(k as f64 / n_bins.max(1) as f64).round())
+            .collect::<Vec<f64>>();
+        edges.dedup();
+        Ok(Self {
+            histogram: Histogram::with_edges(edges)?,
+            n_sites: 0,
+        })
+    }
+
+    /// Count the clusters of a lattice, leaving out those which meet the criterion, i.e., the
+    /// cluster standing in for the infinite cluster
+    pub fn add(&mut self, labels: &ClusterLabels, excluding: Criterion) {
+        self.n_sites += labels.labels().len().pow(2);
+        self.histogram.fill_all(
+            labels
+                .sizes()
+                .iter()
+                .zip(labels.percolation())
+                .filter(|(_, crossing)| !excluding.is_met_by(crossing))
+                .map(|(&size, _)| size as f64),
+        );
+    }
+
+    /// The mean of n_s over the sizes in each bin, with the geometric mean of the smallest and
+    /// largest size in the bin
+    pub fn n_s(&self) -> Vec<(f64, f64)> {
+        self.histogram
+            .bins()
+            .map(|(bin, count)| {
+                let sizes = bin.end - bin.start;
+                (
+                    (bin.start * (bin.end - 1.0)).sqrt(),
+                    count as f64 / (sizes * self.n_sites.max(1) as f64),
+                )
+            })
+            .collect()
+    }
+
+    pub fn print_csv(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        of.write_all("Smallest Size, Largest Size, Clusters, s, n_s\n".as_ref())?;
+        for ((bin, count), (s, n_s)) in self.histogram.bins().zip(self.n_s()) {
+            of.write_all(
+                format!(
+                    "{}, {}, {}, {}, {}\n",
+                    bin.start,
+                    bin.end - 1.0,
+                    count,
+                    s,
+                    n_s
+                )
+                .as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn geometry_of(rows: &[&str], connectivity: Connectivity, periodic: bool) -> ClusterGeometry {
+        let lattice = Lattice::drawn(rows, connectivity, periodic);
+        ClusterGeometry::new(&lattice, &lattice.label_clusters())
+    }
+
+    #[test]
+    fn the_hull_leaves_out_the_holes() {
+        let ring = [
+            ".......", ".#####.", ".#...#.", ".#...#.", ".#...#.", ".#####.", ".......",
+        ];
+        let shape = geometry_of(&ring, Connectivity::Four, false).shapes()[0];
+        assert_eq!((shape.size, shape.center), (16, (3.0, 3.0)));
+        assert_eq!(
+            (shape.min, shape.max, shape.extent()),
+            ((1, 1), (5, 5), (5, 5))
+        );
+        // 4 corners 8 from the center squared, and 3 sites along each side 5, 4 and 5 from it
+        assert_eq!(shape.radius_of_gyration, 5.5f64.sqrt());
+        // 5 empty sites along each side outside, and all but the middle of the hole inside
+        assert_eq!((shape.perimeter, shape.hull), (28, 20));
+
+        // Joined through the corners, the ring also touches the empty sites at its corners and
+        // the middle of the hole is still out of reach
+        let shape = geometry_of(&ring, Connectivity::Eight, false).shapes()[0];
+        assert_eq!((shape.perimeter, shape.hull), (32, 24));
+
+        // A ring joined only through its edges leaks through a corner it is missing
+        let mut leaky = ring;
+        leaky[1] = "..####.";
+        let shape = geometry_of(&leaky, Connectivity::Four, false).shapes()[0];
+        assert_eq!(shape.size, 15);
+        assert_eq!(shape.hull, shape.perimeter);
+    }
+
+    #[test]
+    fn clusters_are_unwrapped_across_periodic_edges() {
+        let rows = [".....", ".....", "##..#", ".....", "....."];
+        let geometry = geometry_of(&rows, Connectivity::Four, true);
+        assert_eq!(geometry.shapes().len(), 1);
+        let mut positions = geometry.positions(0).to_vec();
+        positions.sort_unstable();
+        assert_eq!(positions, [(2, -1), (2, 0), (2, 1)]);
+        let shape = geometry.shapes()[0];
+        assert_eq!(
+            (shape.min, shape.max, shape.extent()),
+            ((2, -1), (2, 1), (1, 3))
+        );
+        assert_eq!(shape.center, (2.0, 0.0));
+        assert_eq!((shape.perimeter, shape.hull), (8, 8));
+
+        let open = geometry_of(&rows, Connectivity::Four, false);
+        let extents = open
+            .shapes()
+            .iter()
+            .map(ClusterShape::extent)
+            .collect::<Vec<(usize, usize)>>();
+        assert_eq!(extents, [(1, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn a_filled_square_has_dimension_2() {
+        let row = "#".repeat(64);
+        let rows = vec![row.as_str(); 64];
+        let geometry = geometry_of(&rows, Connectivity::Four, false);
+        assert_eq!(geometry.shapes()[0].hull, 0);
+
+        let box_counting = geometry.box_counting(0).unwrap();
+        // Boxes of side 2 to 32
+        assert_eq!(box_counting.points.len(), 5);
+        assert!((box_counting.dimension() - 2.0).abs() < 1e-12);
+        let mass_radius = geometry.mass_radius(0).unwrap();
+        assert!((mass_radius.dimension() - 2.0).abs() < 0.1);
+
+        // A single site is too small to fit
+        let single = geometry_of(&["#.", ".."], Connectivity::Four, false);
+        assert!(single.box_counting(0).is_err());
+        assert!(single.mass_radius(0).is_err());
+    }
+
+    #[test]
+    fn cluster_numbers_are_per_site_and_size() {
+        let mut numbers = ClusterNumbers::new(16, 4).unwrap();
+        // Bins of [1, 2), [2, 4), [4, 8) and [8, 17)
+        let rows = ["#.##", "....", "###.", "...#"];
+        let labels = Lattice::drawn(&rows, Connectivity::Four, false).label_clusters();
+        assert_eq!(labels.sizes(), &[1, 2, 3, 1]);
+        numbers.add(&labels, Criterion::SpansEither);
+        assert_eq!(
+            numbers.n_s(),
+            [
+                (1.0, 2.0 / 16.0),
+                (6.0f64.sqrt(), 2.0 / 32.0),
+                (28.0f64.sqrt(), 0.0),
+                (128.0f64.sqrt(), 0.0)
+            ]
+        );
+
+        // The spanning row is left out, but its sites are counted
+        let rows = ["....", "####", "....", "#..."];
+        let labels = Lattice::drawn(&rows, Connectivity::Four, false).label_clusters();
+        numbers.add(&labels, Criterion::SpansEither);
+        let n_s = numbers.n_s().iter().map(|&(_, n)| n).collect::<Vec<f64>>();
+        assert_eq!(n_s, [3.0 / 32.0, 2.0 / 64.0, 0.0, 0.0]);
+    }
+}
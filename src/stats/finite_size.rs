@@ -0,0 +1,573 @@
+//! Finite-size scaling of percolation: the threshold from where the curves of lattices of
+//! different sizes cross, and the critical exponents from how the observables at the threshold
+//! grow with the size of the lattice and from collapsing the curves onto each other
+
+use crate::data::lattice::{Connectivity, Criterion};
+use crate::data::newman_ziff::{NewmanZiff, Observables, Sweep};
+use crate::rand::uniform::Uniform701;
+use crate::stats::fit::LinearFit;
+use std::error::Error;
+
+/// The number of values of p at which the curves are compared when looking for their crossing
+const N_SCAN: usize = 400;
+/// The number of scaled values at which the curves are compared when collapsing them
+const N_COLLAPSE: usize = 41;
+/// The number of steps taken each way from the best exponent to find its uncertainty
+const N_STEPS: usize = 50;
+/// The most times the bracket around an exponent is widened before giving up on it
+const N_WIDEN: usize = 10;
+/// The step used to estimate the slope of the percolation probability
+const SLOPE_STEP: f64 = 1e-3;
+
+/// An estimated value and its uncertainty
+#[derive(Copy, Clone, Debug)]
+pub struct Estimate {
+    pub value: f64,
+    pub error: f64,
+}
+
+impl std::fmt::Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.4} +/- {:.4}", self.value, self.error)
+    }
+}
+
+/// The critical exponents which set how the observables scale with the size of the lattice
+#[derive(Copy, Clone, Debug)]
+pub struct Exponents {
+    /// The correlation length exponent: the curves narrow as L^(-1/nu)
+    pub nu: Estimate,
+    /// The fraction of the sites in the largest cluster at the threshold falls as L^(-beta/nu)
+    pub beta_over_nu: Estimate,
+    /// The mean cluster size at the threshold grows as L^(gamma/nu)
+    pub gamma_over_nu: Estimate,
+}
+
+impl Exponents {
+    /// The exact exponents of percolation in two dimensions
+    pub const EXACT_2D: [(&'static str, f64); 3] = [
+        ("nu", 4.0 / 3.0),
+        ("beta/nu", 5.0 / 48.0),
+        ("gamma/nu", 43.0 / 24.0),
+    ];
+}
+
+/// The observables which are scaled, each with the exponent of L it is multiplied by
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scaled {
+    /// The probability of some cluster meeting the criterion, which does not scale
+    Probability,
+    /// The fraction of the sites in the largest cluster, times L^(beta/nu)
+    Largest,
+    /// The mean cluster size, times L^(-gamma/nu)
+    MeanSize,
+}
+
+impl Scaled {
+    pub const ALL: [Self; 3] = [Self::Probability, Self::Largest, Self::MeanSize];
+}
+
+impl std::fmt::Display for Scaled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Probability => "Percolation probability",
+            Self::Largest => "Largest cluster",
+            Self::MeanSize => "Mean cluster size",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Newman-Ziff experiments on lattices of several sizes, all judged by one criterion
+pub struct FiniteSizeScaling {
+    criterion: Criterion,
+    experiments: Vec<(usize, NewmanZiff)>,
+}
+
+impl FiniteSizeScaling {
+    /// Run `n_sweeps` Newman-Ziff sweeps on a lattice of each size.
+    /// Return an error if there are fewer than three distinct sizes, since the exponents are
+    /// fit from how the observables change between them.
+    pub fn run(
+        sweep: Sweep,
+        sizes: &[usize],
+        connectivity: Connectivity,
+        periodic: bool,
+        n_sweeps: usize,
+        criterion: Criterion,
+        uni: &mut Uniform701,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut sizes = sizes.to_vec();
+        sizes.sort_unstable();
+        sizes.dedup();
+        if sizes.len() < 3 || sizes[0] < 2 {
+            return Err(format!(
+                "Finite-size scaling needs at least three lattice sizes of at least 2, got {:?}",
+                sizes
+            )
+            .into());
+        }
+        let experiments = sizes
+            .into_iter()
+            .map(|l| {
+                log::debug!("Sweeping lattices of size {}", l);
+                Ok((
+                    l,
+                    NewmanZiff::run(sweep, l, connectivity, periodic, n_sweeps, uni)?,
+                ))
+            })
+            .collect::<Result<Vec<(usize, NewmanZiff)>, Box<dyn Error>>>()?;
+        Ok(Self {
+            criterion,
+            experiments,
+        })
+    }
+
+    pub fn sizes(&self) -> Vec<usize> {
+        self.experiments.iter().map(|&(l, _)| l).collect()
+    }
+
+    pub fn criterion(&self) -> Criterion {
+        self.criterion
+    }
+
+    /// The observables of the lattice of each size at p
+    pub fn at_p(&self, p: f64) -> Vec<(usize, Observables)> {
+        self.experiments
+            .iter()
+            .map(|(l, experiment)| (*l, experiment.at_p(p)))
+            .collect()
+    }
+
+    /// Find where the percolation probabilities of each pair of consecutive sizes cross.
+    /// Below the threshold the larger lattice is less likely to percolate, and above it more.
+    /// Pairs whose curves do not cross are left out.
+    pub fn crossings(&self) -> Vec<(usize, usize, f64)> {
+        self.experiments
+            .windows(2)
+            .filter_map(|pair| {
+                let (small, large) = (&pair[0], &pair[1]);
+                let gap = |p: f64| {
+                    large.1.at_p(p).probability(self.criterion)
+                        - small.1.at_p(p).probability(self.criterion)
+                };
+                // Of the steps where the gap changes sign from below to above, take the one
+                // where the curves are closest to one half, away from the flat ends
+                let step = (1..N_SCAN)
+                    .map(|k| (k as f64 / N_SCAN as f64, (k + 1) as f64 / N_SCAN as f64))
+                    .filter(|&(lo, hi)| gap(lo) < 0.0 && gap(hi) >= 0.0)
+                    .min_by(|a, b| {
+                        let off =
+                            |p: f64| (small.1.at_p(p).probability(self.criterion) - 0.5).abs();
+                        off(a.0).total_cmp(&off(b.0))
+                    })?;
+                let (mut lo, mut hi) = step;
+                for _ in 0..40 {
+                    let mid = (lo + hi) / 2.0;
+                    if gap(mid) < 0.0 {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                Some((small.0, large.0, (lo + hi) / 2.0))
+            })
+            .collect()
+    }
+
+    /// Estimate the threshold as the mean of the crossings, with its standard error.
+    /// Return an error if fewer than two pairs of curves cross.
+    pub fn threshold(&self) -> Result<Estimate, Box<dyn Error>> {
+        let crossings = self
+            .crossings()
+            .iter()
+            .map(|&(_, _, p)| p)
+            .collect::<Vec<f64>>();
+        if crossings.len() < 2 {
+            return Err(format!(
+                "Only {} pairs of the curves of '{}' cross",
+                crossings.len(),
+                self.criterion
+            )
+            .into());
+        }
+        let n = crossings.len() as f64;
+        let mean = crossings.iter().sum::<f64>() / n;
+        let variance = crossings.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        Ok(Estimate {
+            value: mean,
+            error: (variance / n).sqrt(),
+        })
+    }
+
+    /// Fit the exponents from how the observables at the threshold change with L, on a log-log
+    /// scale: the slope of the percolation probability grows as L^(1/nu), the largest cluster
+    /// shrinks as L^(-beta/nu) and the mean cluster size grows as L^(gamma/nu).
+    /// The uncertainties are the standard errors of the slopes of the fits.
+    /// Return an error if any of the observables is not positive, so has no logarithm, or the
+    /// percolation probability does not steepen as L grows.
+    pub fn fit_exponents(&self, p_c: f64) -> Result<Exponents, Box<dyn Error>> {
+        let log_log =
+            |name: &str, value: &dyn Fn(&NewmanZiff) -> f64| -> Result<LinearFit, Box<dyn Error>> {
+                let points = self
+                    .experiments
+                    .iter()
+                    .map(|(l, experiment)| {
+                        let v = value(experiment);
+                        if v > 0.0 && v.is_finite() {
+                            Ok(((*l as f64).ln(), v.ln()))
+                        } else {
+                            Err(format!(
+                                "The {} of the lattice of size {} at p = {} is {}, which has no \
+                             logarithm",
+                                name, l, p_c, v
+                            )
+                            .into())
+                        }
+                    })
+                    .collect::<Result<Vec<(f64, f64)>, Box<dyn Error>>>()?;
+                LinearFit::least_squares(&points)
+            };
+        let slope = log_log("slope of the percolation probability", &|experiment| {
+            (experiment
+                .at_p(p_c + SLOPE_STEP)
+                .probability(self.criterion)
+                - experiment
+                    .at_p(p_c - SLOPE_STEP)
+                    .probability(self.criterion))
+                / (2.0 * SLOPE_STEP)
+        })?;
+        if slope.slope <= 0.0 {
+            return Err(format!(
+                "The percolation probability does not steepen as L grows at p = {}",
+                p_c
+            )
+            .into());
+        }
+        let largest = log_log("largest cluster", &|experiment| {
+            experiment.at_p(p_c).largest
+        })?;
+        let mean_size = log_log("mean cluster size", &|experiment| {
+            experiment.at_p(p_c).mean_size
+        })?;
+
+        Ok(Exponents {
+            nu: Estimate {
+                value: 1.0 / slope.slope,
+                error: slope.slope_error / slope.slope.powi(2),
+            },
+            beta_over_nu: Estimate {
+                value: -largest.slope,
+                error: largest.slope_error,
+            },
+            gamma_over_nu: Estimate {
+                value: mean_size.slope,
+                error: mean_size.slope_error,
+            },
+        })
+    }
+
+    /// Refine the exponents by collapsing the curves of every size onto one, starting from
+    /// `initial`.  Each exponent is chosen to minimize the scatter between the curves
+    /// (see `scatter`), first nu from the percolation probability, then beta/nu and gamma/nu
+    /// with nu fixed.  The uncertainty of each is half the range of exponents over which the
+    /// scatter stays within twice its smallest value.
+    /// Return an error if the scatter has no minimum near any of the initial exponents
+    /// (see `minimize`).
+    pub fn fit_collapse(
+        &self,
+        p_c: f64,
+        initial: &Exponents,
+        x_max: f64,
+    ) -> Result<Exponents, Box<dyn Error>> {
+        let nu = minimize(initial.nu.value, |nu| {
+            self.scatter(Scaled::Probability, p_c, nu, 0.0, x_max)
+        })?;
+        let beta_over_nu = minimize(initial.beta_over_nu.value, |b| {
+            self.scatter(Scaled::Largest, p_c, nu.value, b, x_max)
+        })?;
+        let gamma_over_nu = minimize(initial.gamma_over_nu.value, |g| {
+            self.scatter(Scaled::MeanSize, p_c, nu.value, g, x_max)
+        })?;
+        Ok(Exponents {
+            nu,
+            beta_over_nu,
+            gamma_over_nu,
+        })
+    }
+
+    /// The curve of each size with p scaled to x = (p - p_c) L^(1/nu) for x in
+    /// [-x_max, x_max], and the observable scaled by L to the power set by `exponent`
+    /// (see `Scaled`)
+    pub fn collapse(
+        &self,
+        scaled: Scaled,
+        p_c: f64,
+        nu: f64,
+        exponent: f64,
+        x_max: f64,
+    ) -> Vec<(usize, Vec<(f64, f64)>)> {
+        self.experiments
+            .iter()
+            .map(|(l, experiment)| {
+                let l_f = *l as f64;
+                let points = (0..N_COLLAPSE)
+                    .map(|k| -x_max + 2.0 * x_max * k as f64 / (N_COLLAPSE - 1) as f64)
+                    .map(|x| {
+                        let p = (p_c + x * l_f.powf(-1.0 / nu)).clamp(0.0, 1.0);
+                        let at = experiment.at_p(p);
+                        let y = match scaled {
+                            Scaled::Probability => at.probability(self.criterion),
+                            Scaled::Largest => at.largest * l_f.powf(exponent),
+                            Scaled::MeanSize => at.mean_size * l_f.powf(-exponent),
+                        };
+                        (x, y)
+                    })
+                    .collect();
+                (*l, points)
+            })
+            .collect()
+    }
+
+    /// How far the collapsed curves are from lying on one another: the variance between the
+    /// sizes at each scaled p, summed, relative to the sum of the squared means.
+    /// Being relative, it cannot be made small just by scaling every curve towards zero.
+    pub fn scatter(&self, scaled: Scaled, p_c: f64, nu: f64, exponent: f64, x_max: f64) -> f64 {
+        let curves = self.collapse(scaled, p_c, nu, exponent, x_max);
+        let n = curves.len() as f64;
+        let (mut variance, mut squared) = (0.0, 0.0);
+        for k in 0..N_COLLAPSE {
+            let ys = curves.iter().map(|(_, c)| c[k].1).collect::<Vec<f64>>();
+            let mean = ys.iter().sum::<f64>() / n;
+            variance += ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / n;
+            squared += mean * mean;
+        }
+        variance / squared.max(f64::MIN_POSITIVE)
+    }
+}
+
+/// Minimize the scatter over an exponent near `start`, then measure how far the exponent can
+/// move before the scatter doubles.
+/// The bracket [start / 2, 2 start] is widened, halving or doubling the end where the scatter
+/// is no larger than at the least point inside, until the scatter rises towards both ends.
+/// It is then narrowed by golden-section search.  The ends keep the sign of `start`.
+/// Return an error if `start` is zero, the scatter does not rise both ways within `N_WIDEN`
+/// widenings, or the search ends at an end of the bracket.
+fn minimize<F: Fn(f64) -> f64>(start: f64, scatter: F) -> Result<Estimate, Box<dyn Error>> {
+    if start == 0.0 || !start.is_finite() {
+        return Err(format!("Cannot bracket an exponent starting from {}", start).into());
+    }
+    let (shrink, grow) = if start > 0.0 { (0.5, 2.0) } else { (2.0, 0.5) };
+    let (mut a, mut b) = (start * shrink, start * grow);
+    // The least scatter found inside the bracket, first at `start` and then at the old ends
+    let (mut f_a, mut f_inside, mut f_b) = (scatter(a), scatter(start), scatter(b));
+    let mut widened = 0;
+    while f_a <= f_inside || f_b <= f_inside {
+        if widened == N_WIDEN {
+            return Err(format!(
+                "The scatter does not rise both ways from any exponent in [{}, {}]",
+                a, b
+            )
+            .into());
+        }
+        if f_a <= f_inside {
+            f_inside = f_a;
+            a *= shrink;
+            f_a = scatter(a);
+        } else {
+            f_inside = f_b;
+            b *= grow;
+            f_b = scatter(b);
+        }
+        widened += 1;
+    }
+    let (lo, hi) = (a, b);
+
+    let ratio = (5.0_f64.sqrt() - 1.0) / 2.0;
+    let mut c = b - ratio * (b - a);
+    let mut d = a + ratio * (b - a);
+    let (mut f_c, mut f_d) = (scatter(c), scatter(d));
+    for _ in 0..40 {
+        if f_c < f_d {
+            b = d;
+            d = c;
+            f_d = f_c;
+            c = b - ratio * (b - a);
+            f_c = scatter(c);
+        } else {
+            a = c;
+            c = d;
+            f_c = f_d;
+            d = a + ratio * (b - a);
+            f_d = scatter(d);
+        }
+    }
+    let best = (a + b) / 2.0;
+    if (best - lo).min(hi - best) < 1e-6 * (hi - lo) {
+        return Err(format!(
+            "The scatter is least at {}, an end of the bracket [{}, {}]",
+            best, lo, hi
+        )
+        .into());
+    }
+    let least = scatter(best);
+
+    // Step out each way until the scatter doubles, or the search range is left
+    let reach = |direction: f64| {
+        let step = start.abs().max(f64::MIN_POSITIVE) / N_STEPS as f64;
+        (1..=N_STEPS)
+            .map(|k| best + direction * k as f64 * step)
+            .find(|&x| scatter(x) > 2.0 * least)
+            .unwrap_or(best + direction * start.abs())
+    };
+    Ok(Estimate {
+        value: best,
+        error: (reach(1.0) - reach(-1.0)) / 2.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const P_C: f64 = 0.5;
+    const BETA_OVER_NU: f64 = 5.0 / 48.0;
+    const GAMMA_OVER_NU: f64 = 43.0 / 24.0;
+    const SIZES: [usize; 4] = [4, 8, 16, 32];
+
+    /// Curves which scale exactly with the exponents, about a threshold of one half: each
+    /// lattice percolates at a fraction drawn from a logistic distribution of width L^(-1/nu),
+    /// and its largest cluster and mean cluster size are L^(-beta/nu) e^(x/2) and
+    /// L^(gamma/nu) / (1 + x^2), with x = (p - p_c) L^(1/nu)
+    fn scaling_with(sizes: &[usize], nu: f64) -> FiniteSizeScaling {
+        let n_runs = 200;
+        let experiments = sizes
+            .iter()
+            .map(|&l| {
+                let l_f = l as f64;
+                let width = l_f.powf(-1.0 / nu);
+                let first_met = (0..n_runs)
+                    .map(|r| (r as f64 + 0.5) / n_runs as f64)
+                    .map(|u| P_C + width * (u / (1.0 - u)).ln())
+                    .collect::<Vec<f64>>();
+                let x = move |p: f64| (p - P_C) / width;
+                let experiment = NewmanZiff::from_curves(
+                    2000,
+                    &first_met,
+                    |p| l_f.powf(-BETA_OVER_NU) * (x(p) / 2.0).exp(),
+                    |p| l_f.powf(GAMMA_OVER_NU) / (1.0 + x(p).powi(2)),
+                );
+                (l, experiment)
+            })
+            .collect();
+        FiniteSizeScaling {
+            criterion: Criterion::SpansEither,
+            experiments,
+        }
+    }
+
+    #[test]
+    fn curves_cross_at_the_threshold() {
+        let scaling = scaling_with(&SIZES, 4.0 / 3.0);
+        let crossings = scaling.crossings();
+        assert_eq!(crossings.len(), 3);
+        for (&(small, large, p), pair) in crossings.iter().zip(SIZES.windows(2)) {
+            assert_eq!((small, large), (pair[0], pair[1]));
+            assert!(
+                (p - P_C).abs() < 2e-3,
+                "{} and {} cross at {}",
+                small,
+                large,
+                p
+            );
+        }
+        assert!((scaling.threshold().unwrap().value - P_C).abs() < 2e-3);
+
+        // Curves which do not narrow as L grows lie on one another, so never cross
+        let scaling = scaling_with(&SIZES, f64::INFINITY);
+        assert!(scaling.crossings().is_empty());
+        assert!(scaling.threshold().is_err());
+    }
+
+    #[test]
+    fn fit_exponents_recovers_the_power_laws() {
+        let exponents = scaling_with(&SIZES, 4.0 / 3.0).fit_exponents(P_C).unwrap();
+        assert!((1.0 / exponents.nu.value - 0.75).abs() < 0.02);
+        assert!((exponents.beta_over_nu.value - BETA_OVER_NU).abs() < 0.01);
+        assert!((exponents.gamma_over_nu.value - GAMMA_OVER_NU).abs() < 0.01);
+
+        // Curves which widen as L grows have no positive nu
+        assert!(scaling_with(&SIZES, -4.0 / 3.0).fit_exponents(P_C).is_err());
+        // A lattice which never percolates has a slope of zero, which has no logarithm
+        let mut scaling = scaling_with(&SIZES, 4.0 / 3.0);
+        scaling.experiments[1].1 = NewmanZiff::from_curves(2000, &[2.0; 4], |_| 0.0, |_| 1.0);
+        let error = scaling.fit_exponents(P_C).unwrap_err();
+        assert!(error.to_string().contains("no logarithm"), "{}", error);
+    }
+
+    #[test]
+    fn collapse_is_best_at_the_generating_exponents() {
+        let scaling = scaling_with(&SIZES, 4.0 / 3.0);
+        let nu = 4.0 / 3.0;
+        let at_nu = scaling.scatter(Scaled::Probability, P_C, nu, 0.0, 1.0);
+        for &off in &[0.8, 1.2] {
+            assert!(at_nu < scaling.scatter(Scaled::Probability, P_C, off * nu, 0.0, 1.0));
+        }
+
+        let initial = Exponents {
+            nu: Estimate {
+                value: 1.0,
+                error: 0.0,
+            },
+            beta_over_nu: Estimate {
+                value: 0.2,
+                error: 0.0,
+            },
+            gamma_over_nu: Estimate {
+                value: 1.5,
+                error: 0.0,
+            },
+        };
+        let collapsed = scaling.fit_collapse(P_C, &initial, 1.0).unwrap();
+        assert!((collapsed.nu.value - nu).abs() < 0.03, "{}", collapsed.nu);
+        assert!((collapsed.beta_over_nu.value - BETA_OVER_NU).abs() < 0.01);
+        assert!((collapsed.gamma_over_nu.value - GAMMA_OVER_NU).abs() < 0.01);
+        assert!(collapsed.nu.error > 0.0);
+    }
+
+    #[test]
+    fn minimize_brackets_the_least_scatter() {
+        let parabola = |x: f64| (x - 1.3).powi(2) + 1.0;
+        let estimate = minimize(1.0, parabola).unwrap();
+        assert!((estimate.value - 1.3).abs() < 1e-6);
+        // The scatter doubles 1 either side, which the steps of 0.02 reach from above
+        assert!((estimate.error - 1.0).abs() < 0.02);
+
+        // A minimum far from the start is reached by widening the bracket, on either side of 0
+        assert!((minimize(1.0, |x| (x - 20.0).powi(2)).unwrap().value - 20.0).abs() < 1e-6);
+        assert!((minimize(-1.0, |x| (x + 0.01).powi(2)).unwrap().value + 0.01).abs() < 1e-6);
+
+        // A scatter which keeps falling has no minimum to bracket
+        assert!(minimize(1.0, |x| -x).is_err());
+        assert!(minimize(1.0, |x| x).is_err());
+        assert!(minimize(0.0, parabola).is_err());
+    }
+
+    #[test]
+    fn scaling_needs_three_sizes() {
+        let mut uni = Uniform701::seeded(23);
+        let sweep = Sweep::Sites { p_bond: 1.0 };
+        for sizes in &[&[4, 8][..], &[4, 8, 8, 4], &[1, 4, 8]] {
+            assert!(FiniteSizeScaling::run(
+                sweep,
+                sizes,
+                Connectivity::Four,
+                true,
+                10,
+                Criterion::SpansEither,
+                &mut uni
+            )
+            .is_err());
+        }
+    }
+}
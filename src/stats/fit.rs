@@ -0,0 +1,156 @@
+//! Least-squares fits of straight lines to data
+
+use std::error::Error;
+
+/// The line y = slope * x + intercept which best fits a set of points
+#[derive(Copy, Clone, Debug)]
+pub struct LinearFit {
+    pub slope: f64,
+    pub intercept: f64,
+    /// The fraction of the (weighted) variance of y explained by the line
+    pub r_squared: f64,
+    /// The standard error of the slope, from the scatter of the points about the line.
+    /// Infinite if there are only two points.
+    pub slope_error: f64,
+}
+
+impl LinearFit {
+    /// Fit a line to the `(x, y)` points, weighting each equally
+    pub fn least_squares(points: &[(f64, f64)]) -> Result<Self, Box<dyn Error>> {
+        Self::weighted_least_squares(
+            &points
+                .iter()
+                .map(|&(x, y)| (x, y, 1.0))
+                .collect::<Vec<(f64, f64, f64)>>(),
+        )
+    }
+
+    /// Fit a line to the `(x, y, weight)` points, minimizing the weighted sum of squared
+    /// residuals.  Return an error if the points with positive weight do not span at least
+    /// two distinct values of x.
+    pub fn weighted_least_squares(points: &[(f64, f64, f64)]) -> Result<Self, Box<dyn Error>> {
+        let total: f64 = points.iter().map(|&(_, _, w)| w).sum();
+        if total.is_nan() || total <= 0.0 {
+            return Err("A line cannot be fit without points of positive weight".into());
+        }
+        let mean_x = points.iter().map(|&(x, _, w)| w * x).sum::<f64>() / total;
+        let mean_y = points.iter().map(|&(_, y, w)| w * y).sum::<f64>() / total;
+
+        let s_xx: f64 = points
+            .iter()
+            .map(|&(x, _, w)| w * (x - mean_x).powi(2))
+            .sum();
+        let s_xy: f64 = points
+            .iter()
+            .map(|&(x, y, w)| w * (x - mean_x) * (y - mean_y))
+            .sum();
+        let s_yy: f64 = points
+            .iter()
+            .map(|&(_, y, w)| w * (y - mean_y).powi(2))
+            .sum();
+        if s_xx <= 0.0 {
+            return Err("A line cannot be fit to points which share a single x".into());
+        }
+
+        let slope = s_xy / s_xx;
+        // The residual variance has two fewer degrees of freedom than there are points
+        let n = points.iter().filter(|&&(_, _, w)| w > 0.0).count() as f64;
+        let residual = (s_yy - slope * s_xy).max(0.0) / total * n / (n - 2.0);
+        Ok(Self {
+            slope,
+            intercept: mean_y - slope * mean_x,
+            r_squared: if s_yy > 0.0 {
+                s_xy * s_xy / (s_xx * s_yy)
+            } else {
+                1.0
+            },
+            slope_error: if n > 2.0 {
+                (residual / (s_xx / total * n)).sqrt()
+            } else {
+                f64::INFINITY
+            },
+        })
+    }
+
+    /// Evaluate the line at x
+    pub fn at(&self, x: f64) -> f64 {
+        self.slope * x + self.intercept
+    }
+}
+
+impl std::fmt::Display for LinearFit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "y = {:.4} x + {:.4} (R^2 = {:.4})",
+            self.slope, self.intercept, self.r_squared
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_on_a_line_are_fit_exactly() {
+        let points = (0..6)
+            .map(|x| (x as f64, 2.0 * x as f64 + 1.0))
+            .collect::<Vec<(f64, f64)>>();
+        let fit = LinearFit::least_squares(&points).unwrap();
+        assert_eq!((fit.slope, fit.intercept, fit.r_squared), (2.0, 1.0, 1.0));
+        assert_eq!(fit.slope_error, 0.0);
+        assert_eq!(fit.at(-3.0), -5.0);
+
+        // Two points fix the line, but leave nothing to estimate its error from
+        let fit = LinearFit::least_squares(&points[2..4]).unwrap();
+        assert_eq!((fit.slope, fit.intercept), (2.0, 1.0));
+        assert_eq!(fit.slope_error, f64::INFINITY);
+
+        // A point of no weight is ignored, however far it is from the line
+        let mut weighted = points
+            .iter()
+            .map(|&(x, y)| (x, y, 0.5))
+            .collect::<Vec<(f64, f64, f64)>>();
+        weighted.push((2.0, 100.0, 0.0));
+        let fit = LinearFit::weighted_least_squares(&weighted).unwrap();
+        assert_eq!((fit.slope, fit.intercept, fit.slope_error), (2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn the_slope_error_follows_the_scatter() {
+        // Noise of ±0.5 alternating about y = 3 x - 2
+        let points = (0..11)
+            .map(|x| (x as f64, 3.0 * x as f64 - 2.0 + 0.5 - (x % 2) as f64))
+            .collect::<Vec<(f64, f64)>>();
+        let fit = LinearFit::least_squares(&points).unwrap();
+        assert!((fit.slope - 3.0).abs() < 0.1);
+        assert!(fit.r_squared < 1.0 && fit.r_squared > 0.99);
+
+        // The textbook standard error, sqrt(sum of squared residuals / (n - 2) / S_xx)
+        let residuals = points
+            .iter()
+            .map(|&(x, y)| (y - fit.at(x)).powi(2))
+            .sum::<f64>();
+        let s_xx = points.iter().map(|&(x, _)| (x - 5.0).powi(2)).sum::<f64>();
+        let expected = (residuals / 9.0 / s_xx).sqrt();
+        assert!((fit.slope_error - expected).abs() < 1e-12 * expected);
+
+        // Doubling the noise doubles the error
+        let noisier = points
+            .iter()
+            .map(|&(x, y)| (x, 2.0 * y - (3.0 * x - 2.0)))
+            .collect::<Vec<(f64, f64)>>();
+        let noisier_fit = LinearFit::least_squares(&noisier).unwrap();
+        assert!((noisier_fit.slope_error - 2.0 * expected).abs() < 1e-12 * expected);
+    }
+
+    #[test]
+    fn a_line_needs_two_distinct_x() {
+        assert!(LinearFit::least_squares(&[]).is_err());
+        assert!(LinearFit::least_squares(&[(1.0, 2.0)]).is_err());
+        assert!(LinearFit::least_squares(&[(1.0, 2.0), (1.0, 3.0), (1.0, 4.0)]).is_err());
+        assert!(LinearFit::weighted_least_squares(&[(0.0, 0.0, 0.0), (1.0, 1.0, 0.0)]).is_err());
+        assert!(LinearFit::weighted_least_squares(&[(0.0, 0.0, 1.0), (1.0, 1.0, 0.0)]).is_err());
+    }
+}
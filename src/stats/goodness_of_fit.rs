@@ -0,0 +1,375 @@
+//! Goodness-of-fit tests of samples against an analytic cumulative distribution function
+
+use crate::rand::sampler::Sampler701;
+use crate::stats::histogram::Histogram;
+use crate::util;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::ops::Range;
+
+/// The smallest expected count a chi-square bin may have before it is merged with its neighbor
+const MIN_EXPECTED: f64 = 5.0;
+
+/// The outcome of a single goodness-of-fit test
+#[derive(Copy, Clone, Debug)]
+pub struct TestResult {
+    /// The test statistic
+    pub statistic: f64,
+    /// The probability of a statistic at least this extreme if the samples follow the distribution
+    pub p_value: f64,
+}
+
+impl TestResult {
+    /// Whether the hypothesis that the samples follow the distribution is rejected at level `alpha`
+    pub fn rejects(&self, alpha: f64) -> bool {
+        self.p_value < alpha
+    }
+}
+
+impl std::fmt::Display for TestResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.5} (p = {:.4})", self.statistic, self.p_value)
+    }
+}
+
+/// Pearson's chi-square test.  The bins of `histogram`, including its underflow and overflow,
+/// are compared against the counts expected from `cdf`.
+/// Adjacent bins are merged until every bin expects at least `MIN_EXPECTED` samples.
+pub fn chi_square<F: Fn(f64) -> f64>(histogram: &Histogram, cdf: F) -> TestResult {
+    let n = histogram.total() as f64;
+
+    let mut observed = vec![histogram.underflow()];
+    observed.extend_from_slice(histogram.counts());
+    observed.push(histogram.overflow());
+    let mut cumulative = histogram
+        .edges()
+        .iter()
+        .map(|&e| cdf(e))
+        .collect::<Vec<f64>>();
+    cumulative.insert(0, 0.0);
+    cumulative.push(1.0);
+    let expected = cumulative
+        .windows(2)
+        .map(|w| (w[1] - w[0]) * n)
+        .collect::<Vec<f64>>();
+
+    chi_square_counts(&observed, &expected)
+}
+
+/// Pearson's chi-square test for precomputed `observed` and `expected` bin counts.
+/// Adjacent bins are merged until every bin expects at least `MIN_EXPECTED` samples.
+pub fn chi_square_counts(observed: &[u64], expected: &[f64]) -> TestResult {
+    let mut merged: Vec<(f64, f64)> = vec![];
+    let mut pending = (0.0, 0.0);
+    for (&o, &e) in observed.iter().zip(expected) {
+        pending = (pending.0 + o as f64, pending.1 + e);
+        if pending.1 >= MIN_EXPECTED {
+            merged.push(pending);
+            pending = (0.0, 0.0);
+        }
+    }
+    // Fold whatever is left over into the last bin
+    match merged.last_mut() {
+        Some(last) => {
+            last.0 += pending.0;
+            last.1 += pending.1;
+        }
+        None => merged.push(pending),
+    }
+
+    let statistic = merged
+        .iter()
+        .filter(|&&(_, e)| e > 0.0)
+        .map(|&(o, e)| (o - e).powi(2) / e)
+        .sum::<f64>();
+    let dof = merged.len().saturating_sub(1).max(1);
+
+    TestResult {
+        statistic,
+        p_value: util::gamma_q(dof as f64 / 2.0, statistic / 2.0),
+    }
+}
+
+/// The Kolmogorov-Smirnov test, comparing the empirical distribution of the samples to `cdf`.
+/// The statistic is the largest distance between the two distribution functions.
+pub fn kolmogorov_smirnov<F: Fn(f64) -> f64>(samples: &[f64], cdf: F) -> TestResult {
+    let sorted = sorted(samples);
+    let n = sorted.len() as f64;
+
+    let statistic = sorted
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| {
+            let f = cdf(x);
+            f64::max(f - i as f64 / n, (i + 1) as f64 / n - f)
+        })
+        .fold(0.0, f64::max);
+
+    let sqrt_n = n.sqrt();
+    TestResult {
+        statistic,
+        p_value: kolmogorov_q((sqrt_n + 0.12 + 0.11 / sqrt_n) * statistic),
+    }
+}
+
+/// The Anderson-Darling test, which weights the tails of the distribution more heavily
+/// than the Kolmogorov-Smirnov test does.
+pub fn anderson_darling<F: Fn(f64) -> f64>(samples: &[f64], cdf: F) -> TestResult {
+    let sorted = sorted(samples);
+    let n = sorted.len();
+
+    // Clamp so that samples outside of the support give a large, finite statistic
+    let u = sorted
+        .iter()
+        .map(|&x| cdf(x).clamp(1e-300, 1.0 - 1e-16))
+        .collect::<Vec<f64>>();
+    let s = (0..n)
+        .map(|i| (2 * i + 1) as f64 * (u[i].ln() + (1.0 - u[n - 1 - i]).ln()))
+        .sum::<f64>();
+    let statistic = -(n as f64) - s / n as f64;
+
+    TestResult {
+        statistic,
+        p_value: 1.0 - anderson_darling_cdf(n, statistic),
+    }
+}
+
+/// The results of all of the goodness-of-fit tests for one generator
+pub struct GoodnessOfFit {
+    pub name: String,
+    pub n: usize,
+    pub chi_square: TestResult,
+    pub kolmogorov_smirnov: TestResult,
+    pub anderson_darling: TestResult,
+}
+
+impl GoodnessOfFit {
+    /// Draw `n` samples from `sampler` and test them against its analytic cdf.
+    /// The chi-square test uses `n_bins` equal bins spanning `range`.
+    /// Return an error if the cdf of the sampler is not known.
+    pub fn test<S: Sampler701 + ?Sized>(
+        name: &str,
+        sampler: &mut S,
+        n: usize,
+        range: Range<f64>,
+        n_bins: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        if sampler.cdf(0.0).is_none() {
+            return Err(format!("The cdf of '{}' is not known", name).into());
+        }
+
+        let samples = sampler.samples().take(n).collect::<Vec<f64>>();
+        let cdf = |x| sampler.cdf(x).unwrap_or(f64::NAN);
+        let mut histogram = Histogram::uniform(range, n_bins)?;
+        histogram.fill_all(samples.iter().copied());
+
+        let result = Self {
+            name: name.to_owned(),
+            n,
+            chi_square: chi_square(&histogram, cdf),
+            kolmogorov_smirnov: kolmogorov_smirnov(&samples, cdf),
+            anderson_darling: anderson_darling(&samples, cdf),
+        };
+        log::info!("{}", result);
+        Ok(result)
+    }
+
+    /// Whether any of the tests reject the distribution at level `alpha`
+    pub fn rejects(&self, alpha: f64) -> bool {
+        self.chi_square.rejects(alpha)
+            || self.kolmogorov_smirnov.rejects(alpha)
+            || self.anderson_darling.rejects(alpha)
+    }
+
+    /// Write a table of the supplied results
+    pub fn print_csv(results: &[Self], to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        of.write_all(
+            "Generator, Samples, Chi-square, p, Kolmogorov-Smirnov, p, Anderson-Darling, p\n"
+                .as_ref(),
+        )?;
+        for r in results {
+            of.write_all(
+                format!(
+                    "{}, {}, {:.5}, {:.4}, {:.5}, {:.4}, {:.5}, {:.4}\n",
+                    r.name,
+                    r.n,
+                    r.chi_square.statistic,
+                    r.chi_square.p_value,
+                    r.kolmogorov_smirnov.statistic,
+                    r.kolmogorov_smirnov.p_value,
+                    r.anderson_darling.statistic,
+                    r.anderson_darling.p_value,
+                )
+                .as_ref(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for GoodnessOfFit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, n = {}: chi-square = {}, Kolmogorov-Smirnov = {}, Anderson-Darling = {}",
+            self.name, self.n, self.chi_square, self.kolmogorov_smirnov, self.anderson_darling
+        )
+    }
+}
+
+fn sorted(samples: &[f64]) -> Vec<f64> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable_by(|l, r| l.total_cmp(r));
+    sorted
+}
+
+/// The complement of the Kolmogorov distribution, i.e., the probability that the scaled
+/// Kolmogorov-Smirnov statistic exceeds `lambda`.  From Numerical Recipes.
+fn kolmogorov_q(lambda: f64) -> f64 {
+    let a2 = -2.0 * lambda.powi(2);
+    let mut fac = 2.0;
+    let mut sum = 0.0;
+    let mut term_before = 0.0;
+    for j in 1..=100 {
+        let term = fac * (a2 * (j * j) as f64).exp();
+        sum += term;
+        if term.abs() <= 0.001 * term_before || term.abs() <= 1e-8 * sum {
+            return sum.clamp(0.0, 1.0);
+        }
+        fac = -fac;
+        term_before = term.abs();
+    }
+    // The series failed to converge, which only happens as lambda approaches 0
+    1.0
+}
+
+/// The cumulative distribution of the Anderson-Darling statistic `z` for `n` samples.
+/// From Marsaglia & Marsaglia, "Evaluating the Anderson-Darling Distribution", 2004.
+fn anderson_darling_cdf(n: usize, z: f64) -> f64 {
+    let x = if z <= 0.0 {
+        0.0
+    } else if z < 2.0 {
+        (-1.233_714_1 / z).exp() / z.sqrt()
+            * (2.000_12
+                + (0.247_105
+                    - (0.064_982_1 - (0.034_796_2 - (0.011_672 - 0.001_686_91 * z) * z) * z) * z)
+                    * z)
+    } else {
+        (-(1.077_6
+            - (2.306_95 - (0.434_24 - (0.082_433 - (0.008_056 - 0.000_314_6 * z) * z) * z) * z)
+                * z)
+            .exp())
+        .exp()
+    };
+
+    // Correct the asymptotic distribution for a finite number of samples
+    let n = n as f64;
+    let err = if x > 0.8 {
+        (-130.213_7
+            + (745.233_7 - (1_705.091 - (1_950.646 - (1_116.360 - 255.784_4 * x) * x) * x) * x) * x)
+            / n
+    } else {
+        let c = 0.012_65 + 0.175_7 / n;
+        if x < c {
+            let t = x / c;
+            let t = t.sqrt() * (1.0 - t) * (49.0 * t - 102.0);
+            t * (0.003_7 / (n * n) + 0.000_78 / n + 0.000_06) / n
+        } else {
+            let t = (x - c) / (0.8 - c);
+            let t = -0.000_226_33
+                + (6.540_34 - (14.653_8 - (14.458 - (8.259 - 1.918_64 * t) * t) * t) * t) * t;
+            t * (0.042_13 + 0.013_65 / n) / n
+        }
+    };
+
+    (x + err).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::boxmuller::BoxMullerGaussian701;
+    use crate::rand::clt_gaussian::CentralLimitTheoremGaussian701;
+    use crate::rand::exponential::Exponential701;
+    use crate::rand::inverse_transform::InverseTransform701;
+    use crate::rand::polar::MarsagliaPolarGaussian701;
+    use crate::rand::uniform::Uniform701;
+
+    const N: usize = 20_000;
+    const ALPHA: f64 = 0.001;
+
+    fn assert_fits<S: Sampler701>(name: &str, sampler: &mut S, range: Range<f64>) {
+        let result = GoodnessOfFit::test(name, sampler, N, range, 50).unwrap();
+        assert!(!result.rejects(ALPHA), "{}", result);
+    }
+
+    #[test]
+    fn uniform_fits() {
+        assert_fits("Uniform", &mut Uniform701::seeded(1), 0.0..1.0);
+    }
+
+    #[test]
+    fn exponential_fits() {
+        let mut exp = Exponential701::new(Uniform701::seeded(2), 1.0, 1.0);
+        assert_fits("Exponential", &mut exp, 0.0..5.0);
+    }
+
+    #[test]
+    fn inverse_transform_fits() {
+        let mut rayleigh = InverseTransform701::new(Uniform701::seeded(3), 1.0);
+        assert_fits("Rayleigh", &mut rayleigh, 0.0..5.0);
+    }
+
+    #[test]
+    fn box_muller_fits() {
+        let mut gaussian = BoxMullerGaussian701::new(Uniform701::seeded(4), 0.5, 0.1);
+        assert_fits("Box-Müller", &mut gaussian, 0.0..1.0);
+    }
+
+    #[test]
+    fn polar_fits() {
+        let mut gaussian = MarsagliaPolarGaussian701::new(Uniform701::seeded(5), 0.5, 0.1);
+        assert_fits("Marsaglia polar", &mut gaussian, 0.0..1.0);
+    }
+
+    #[test]
+    fn central_limit_theorem_fits() {
+        let mut clt = CentralLimitTheoremGaussian701::new(Uniform701::seeded(6), 8);
+        assert_fits("Central limit theorem", &mut clt, 0.0..1.0);
+    }
+
+    #[test]
+    fn mismatched_cdf_is_rejected() {
+        let mut exp = Exponential701::new(Uniform701::seeded(7), 1.0, 1.0);
+        let samples = exp.samples().take(N).collect::<Vec<f64>>();
+        let wrong = |x: f64| 1.0 - (-1.1 * x.max(0.0)).exp();
+        let mut histogram = Histogram::uniform(0.0..5.0, 50).unwrap();
+        histogram.fill_all(samples.iter().copied());
+
+        assert!(chi_square(&histogram, wrong).rejects(ALPHA));
+        assert!(kolmogorov_smirnov(&samples, wrong).rejects(ALPHA));
+        assert!(anderson_darling(&samples, wrong).rejects(ALPHA));
+    }
+
+    #[test]
+    fn kolmogorov_q_matches_table() {
+        for &(lambda, q) in &[
+            (0.5, 0.963_945),
+            (1.0, 0.270_000),
+            (1.358, 0.050_027),
+            (1.628, 0.009_976),
+        ] {
+            assert!((kolmogorov_q(lambda) - q).abs() < 1e-5, "Q({})", lambda);
+        }
+    }
+
+    #[test]
+    fn anderson_darling_cdf_matches_critical_values() {
+        // The asymptotic critical values at the 10%, 5% and 1% levels
+        for &(z, p) in &[(1.933, 0.90), (2.492, 0.95), (3.857, 0.99)] {
+            assert!((anderson_darling_cdf(1000, z) - p).abs() < 1e-3, "A({})", z);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! A histogram of real-valued samples with arbitrary, ascending bin edges
+
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::ops::Range;
+
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    /// The `n + 1` ascending edges of the `n` bins.  Bin `i` covers `edges[i]..edges[i + 1]`.
+    edges: Vec<f64>,
+    /// The number of samples in each bin
+    counts: Vec<u64>,
+    /// The number of samples below the first edge
+    underflow: u64,
+    /// The number of samples at or above the last edge, or that are not a number
+    overflow: u64,
+}
+
+impl Histogram {
+    /// Construct a new, empty histogram with `n_bins` bins of equal width spanning `range`
+    pub fn uniform(range: Range<f64>, n_bins: usize) -> Result<Self, Box<dyn Error>> {
+        let width = (range.end - range.start) / n_bins as f64;
+        let mut edges = (0..n_bins)
+            .map(|i| range.start + i as f64 * width)
+            .collect::<Vec<f64>>();
+        // Stepping n times from the start can round short of or past the end
+        edges.push(range.end);
+        Self::with_edges(edges)
+    }
+
+    /// Construct a new, empty histogram with `n_bins` bins spanning `range` whose widths grow
+    /// geometrically, i.e., they are of equal width on a log scale.  `range` must be positive.
+    pub fn logarithmic(range: Range<f64>, n_bins: usize) -> Result<Self, Box<dyn Error>> {
+        if range.start <= 0.0 {
+            return Err("Logarithmic bins must have positive edges".into());
+        }
+        let ratio = (range.end / range.start).powf(1.0 / n_bins as f64);
+        let mut edges = (0..n_bins)
+            .map(|i| range.start * ratio.powi(i as i32))
+            .collect::<Vec<f64>>();
+        edges.push(range.end);
+        Self::with_edges(edges)
+    }
+
+    /// Construct a new, empty histogram with the supplied bin edges, which must be ascending
+    pub fn with_edges(edges: Vec<f64>) -> Result<Self, Box<dyn Error>> {
+        if edges.len() < 2 {
+            return Err("A histogram needs at least one bin".into());
+        }
+        if !edges.windows(2).all(|w| w[0] < w[1]) {
+            return Err("Histogram edges must be strictly ascending".into());
+        }
+        Ok(Self {
+            counts: vec![0; edges.len() - 1],
+            edges,
+            underflow: 0,
+            overflow: 0,
+        })
+    }
+
+    /// Count one sample
+    pub fn fill(&mut self, x: f64) {
+        if x < self.edges[0] {
+            self.underflow += 1;
+        } else if x >= self.edges[self.edges.len() - 1] || x.is_nan() {
+            self.overflow += 1;
+        } else {
+            // The first edge greater than x closes the bin containing x
+            let i = self.edges.partition_point(|&e| e <= x);
+            self.counts[i - 1] += 1;
+        }
+    }
+
+    /// Count every sample in `samples`
+    pub fn fill_all<I: IntoIterator<Item = f64>>(&mut self, samples: I) {
+        samples.into_iter().for_each(|x| self.fill(x));
+    }
+
+    /// Add the counts of `that` to this histogram, e.g., to combine the results of parallel runs.
+    /// Both histograms must have the same edges.
+    pub fn merge(&mut self, that: &Self) -> Result<(), Box<dyn Error>> {
+        if self.edges != that.edges {
+            return Err("Cannot merge histograms with different bin edges".into());
+        }
+        self.counts
+            .iter_mut()
+            .zip(&that.counts)
+            .for_each(|(l, r)| *l += r);
+        self.underflow += that.underflow;
+        self.overflow += that.overflow;
+        Ok(())
+    }
+
+    pub fn edges(&self) -> &[f64] {
+        &self.edges
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    pub fn underflow(&self) -> u64 {
+        self.underflow
+    }
+
+    pub fn overflow(&self) -> u64 {
+        self.overflow
+    }
+
+    pub fn n_bins(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// The number of samples counted, including those outside of the bins
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum::<u64>() + self.underflow + self.overflow
+    }
+
+    /// The range covered by bin `i`
+    pub fn bin(&self, i: usize) -> Range<f64> {
+        self.edges[i]..self.edges[i + 1]
+    }
+
+    pub fn bin_center(&self, i: usize) -> f64 {
+        (self.edges[i] + self.edges[i + 1]) / 2.0
+    }
+
+    pub fn bin_width(&self, i: usize) -> f64 {
+        self.edges[i + 1] - self.edges[i]
+    }
+
+    /// Iterate over the range and count of each bin
+    pub fn bins(&self) -> impl Iterator<Item = (Range<f64>, u64)> + '_ {
+        (0..self.n_bins()).map(move |i| (self.bin(i), self.counts[i]))
+    }
+
+    /// Normalize the counts to a probability density, i.e., divide each count by the total number
+    /// of samples and by the bin width.  Samples outside of the bins count towards the total,
+    /// so the density integrates to the fraction of samples within the bins.
+    pub fn density(&self) -> Vec<f64> {
+        let total = self.total() as f64;
+        (0..self.n_bins())
+            .map(|i| {
+                if total == 0.0 {
+                    0.0
+                } else {
+                    self.counts[i] as f64 / (total * self.bin_width(i))
+                }
+            })
+            .collect()
+    }
+
+    pub fn print_csv(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        of.write_all("Lower Edge, Upper Edge, Count, Density\n".as_ref())?;
+        of.write_all(format!("-inf, {}, {}, \n", self.edges[0], self.underflow).as_ref())?;
+        for (i, density) in self.density().iter().enumerate() {
+            of.write_all(
+                format!(
+                    "{}, {}, {}, {}\n",
+                    self.edges[i],
+                    self.edges[i + 1],
+                    self.counts[i],
+                    density
+                )
+                .as_ref(),
+            )?;
+        }
+        of.write_all(
+            format!(
+                "{}, inf, {}, \n",
+                self.edges[self.edges.len() - 1],
+                self.overflow
+            )
+            .as_ref(),
+        )?;
+        Ok(())
+    }
+
+    pub fn print_json(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let join = |v: &mut dyn Iterator<Item = String>| v.collect::<Vec<String>>().join(", ");
+        let mut of = File::create(to_file)?;
+        of.write_all(
+            format!(
+                "{{\n  \"edges\": [{}],\n  \"counts\": [{}],\n  \"density\": [{}],\n  \
+                 \"underflow\": {},\n  \"overflow\": {},\n  \"total\": {}\n}}\n",
+                join(&mut self.edges.iter().map(|v| json_number(*v))),
+                join(&mut self.counts.iter().map(|v| v.to_string())),
+                join(&mut self.density().iter().map(|v| json_number(*v))),
+                self.underflow,
+                self.overflow,
+                self.total(),
+            )
+            .as_ref(),
+        )?;
+        Ok(())
+    }
+}
+
+/// Format a number for JSON, which has no representation for infinities or NaN
+fn json_number(v: f64) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        "null".to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_edges_end_at_the_range() {
+        // Stepping three widths of 0.3 from 0 gives 0.8999999999999999
+        let histogram = Histogram::uniform(0.0..0.9, 3).unwrap();
+        assert_eq!(histogram.n_bins(), 3);
+        assert_eq!(histogram.edges()[0], 0.0);
+        assert_eq!(histogram.edges()[3], 0.9);
+        for i in 0..3 {
+            assert!((histogram.bin_width(i) - 0.3).abs() < 1e-15);
+        }
+
+        let mut histogram = histogram;
+        histogram.fill(0.8999999999999999);
+        histogram.fill(0.9);
+        assert_eq!(histogram.counts(), &[0, 0, 1]);
+        assert_eq!(histogram.overflow(), 1);
+    }
+
+    #[test]
+    fn logarithmic_edges_grow_geometrically() {
+        let mut histogram = Histogram::logarithmic(1.0..1000.0, 3).unwrap();
+        assert_eq!(histogram.edges()[0], 1.0);
+        // The cube of the ratio is 999.9999999999994
+        assert_eq!(histogram.edges()[3], 1000.0);
+        for (i, &edge) in histogram.edges().iter().enumerate().take(3) {
+            assert!((edge - 10.0f64.powi(i as i32)).abs() < 1e-12 * edge);
+        }
+        histogram.fill_all(vec![0.5, 1.0, 9.0, 50.0, 999.9999999999995, 1000.0]);
+        assert_eq!(histogram.counts(), &[2, 1, 1]);
+        assert_eq!((histogram.underflow(), histogram.overflow()), (1, 1));
+
+        assert!(Histogram::logarithmic(0.0..10.0, 3).is_err());
+        assert!(Histogram::logarithmic(-1.0..10.0, 3).is_err());
+    }
+
+    #[test]
+    fn samples_outside_the_edges_are_counted_apart() {
+        let mut histogram = Histogram::with_edges(vec![-1.0, 0.0, 2.0, 5.0]).unwrap();
+        histogram.fill_all(vec![
+            f64::NEG_INFINITY,
+            -1.5,
+            -1.0,
+            -0.5,
+            0.0,
+            4.999,
+            5.0,
+            f64::INFINITY,
+            f64::NAN,
+        ]);
+        // A sample on an edge falls in the bin above it, and NaN above every bin
+        assert_eq!(histogram.counts(), &[2, 1, 1]);
+        assert_eq!(histogram.underflow(), 2);
+        assert_eq!(histogram.overflow(), 3);
+        assert_eq!(histogram.total(), 9);
+        assert_eq!(histogram.bin(1), 0.0..2.0);
+        assert_eq!(histogram.bin_center(2), 3.5);
+    }
+
+    #[test]
+    fn edges_must_ascend() {
+        assert!(Histogram::with_edges(vec![]).is_err());
+        assert!(Histogram::with_edges(vec![1.0]).is_err());
+        assert!(Histogram::with_edges(vec![0.0, 1.0, 1.0]).is_err());
+        assert!(Histogram::with_edges(vec![0.0, 2.0, 1.0]).is_err());
+        assert!(Histogram::with_edges(vec![0.0, f64::NAN]).is_err());
+        assert!(Histogram::uniform(1.0..0.0, 4).is_err());
+        assert!(Histogram::uniform(0.0..1.0, 0).is_err());
+    }
+
+    #[test]
+    fn merge_needs_the_same_edges() {
+        let mut left = Histogram::uniform(0.0..1.0, 2).unwrap();
+        left.fill_all(vec![-1.0, 0.25, 0.75]);
+        let mut right = Histogram::uniform(0.0..1.0, 2).unwrap();
+        right.fill_all(vec![0.25, 2.0]);
+        left.merge(&right).unwrap();
+        assert_eq!(left.counts(), &[2, 1]);
+        assert_eq!((left.underflow(), left.overflow()), (1, 1));
+
+        let other = Histogram::uniform(0.0..1.0, 4).unwrap();
+        assert!(left.merge(&other).is_err());
+        let shifted = Histogram::uniform(0.0..2.0, 2).unwrap();
+        assert!(left.merge(&shifted).is_err());
+        assert_eq!(left.total(), 5);
+    }
+
+    #[test]
+    fn density_integrates_to_the_fraction_in_the_bins() {
+        let mut histogram = Histogram::with_edges(vec![0.0, 1.0, 3.0]).unwrap();
+        assert_eq!(histogram.density(), &[0.0, 0.0]);
+        histogram.fill_all(vec![0.5, 1.5, 2.5, 2.5, 7.0]);
+        assert_eq!(histogram.density(), &[0.2, 0.3]);
+        let integral = (0..2)
+            .map(|i| histogram.density()[i] * histogram.bin_width(i))
+            .sum::<f64>();
+        assert!((integral - 0.8).abs() < 1e-15);
+    }
+
+    #[test]
+    fn exports_every_bin_and_the_outliers() {
+        let mut histogram = Histogram::with_edges(vec![0.0, 1.0, 3.0]).unwrap();
+        histogram.fill_all(vec![-1.0, 0.5, 1.5, 2.5, 2.5]);
+
+        let csv = std::env::temp_dir().join("cos_701_histogram_test.csv");
+        histogram.print_csv(csv.to_str().unwrap()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&csv).unwrap(),
+            "Lower Edge, Upper Edge, Count, Density\n\
+             -inf, 0, 1, \n\
+             0, 1, 1, 0.2\n\
+             1, 3, 3, 0.3\n\
+             3, inf, 0, \n"
+        );
+
+        let json = std::env::temp_dir().join("cos_701_histogram_test.json");
+        histogram.print_json(json.to_str().unwrap()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&json).unwrap(),
+            "{\n  \"edges\": [0, 1, 3],\n  \"counts\": [1, 3],\n  \"density\": [0.2, 0.3],\n  \
+             \"underflow\": 1,\n  \"overflow\": 0,\n  \"total\": 5\n}\n"
+        );
+        assert_eq!(json_number(f64::NAN), "null");
+        assert_eq!(json_number(f64::NEG_INFINITY), "null");
+    }
+}
@@ -0,0 +1,7 @@
+pub mod cell_stats;
+pub mod cluster_stats;
+pub mod finite_size;
+pub mod fit;
+pub mod goodness_of_fit;
+pub mod histogram;
+pub mod rdf;
@@ -0,0 +1,143 @@
+//! The radial distribution function, g(r), of a set of points
+
+use crate::data::point::Point;
+use crate::data::simulation_box::SimulationBox;
+use crate::data::spatial_index::{CellList, SpatialIndex};
+use crate::stats::histogram::Histogram;
+use crate::util;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+/// The density of pairs of points at each distance, relative to that of uniformly distributed
+/// points at the same overall density.  Uniform points have g(r) = 1, while points kept apart
+/// by a minimum distance have g(r) = 0 below it.
+pub struct RadialDistribution {
+    /// The number of pairs at each distance
+    pairs: Histogram,
+    g: Vec<f64>,
+}
+
+impl RadialDistribution {
+    /// Compute g(r) for the supplied points in `sim_box`, using `n_bins` equal bins spanning
+    /// `0..r_max`.  Each pair is counted once, at its minimum image distance.
+    /// In a periodic box, `r_max` may not exceed half of the shortest periodic side.
+    /// Along open axes, the shells around points near the walls are partly empty,
+    /// so g(r) falls below 1 as r grows.
+    pub fn compute<const D: usize>(
+        sim_box: &SimulationBox<D>,
+        points: &[Point<D>],
+        r_max: f64,
+        n_bins: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        if points.len() < 2 {
+            return Err("The radial distribution needs at least 2 points".into());
+        }
+        if r_max > sim_box.max_unique_distance() {
+            return Err(format!(
+                "r_max = {} exceeds half of the shortest periodic side, {}",
+                r_max,
+                sim_box.max_unique_distance()
+            )
+            .into());
+        }
+
+        let mut pairs = Histogram::uniform(0.0..r_max, n_bins)?;
+        let index = CellList::with_points_in_box(sim_box, r_max, points)?;
+        for (i, p) in points.iter().enumerate() {
+            index
+                .within(p, r_max)
+                .into_iter()
+                .filter(|&j| j > i)
+                .for_each(|j| pairs.fill(sim_box.distance(p, &points[j])));
+        }
+
+        // The number of pairs expected in each shell if the points were uniformly distributed
+        let n = points.len() as f64;
+        let pair_density = n * (n - 1.0) / 2.0 / sim_box.volume();
+        let g = pairs
+            .bins()
+            .map(|(bin, count)| {
+                let shell = util::ball_volume(D, bin.end) - util::ball_volume(D, bin.start);
+                count as f64 / (pair_density * shell)
+            })
+            .collect();
+
+        Ok(Self { pairs, g })
+    }
+
+    /// The center of each bin
+    pub fn r(&self) -> Vec<f64> {
+        (0..self.pairs.n_bins())
+            .map(|i| self.pairs.bin_center(i))
+            .collect()
+    }
+
+    pub fn g(&self) -> &[f64] {
+        &self.g
+    }
+
+    pub fn pairs(&self) -> &Histogram {
+        &self.pairs
+    }
+
+    pub fn print_csv(&self, to_file: &str) -> Result<(), Box<dyn Error>> {
+        let mut of = File::create(to_file)?;
+        of.write_all("r, g(r), Pairs\n".as_ref())?;
+        for ((r, g), (_, count)) in self.r().iter().zip(&self.g).zip(self.pairs.bins()) {
+            of.write_all(format!("{}, {}, {}\n", r, g, count).as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rand::uniform::Uniform701;
+
+    #[test]
+    fn uniform_points_have_g_near_1() {
+        let sim_box = SimulationBox::cube(20.0, true).unwrap();
+        let mut uni = Uniform701::seeded(10);
+        let points = (0..2000)
+            .map(|_| sim_box.sample(&mut uni))
+            .collect::<Vec<Point<2>>>();
+        let rdf = RadialDistribution::compute(&sim_box, &points, 5.0, 10).unwrap();
+        // Even the innermost shell expects about 3900 pairs
+        for (r, g) in rdf.r().iter().zip(rdf.g()) {
+            assert!((g - 1.0).abs() < 0.1, "g({}) = {}", r, g);
+        }
+
+        // Every pair within r_max is counted once
+        let close = (0..points.len())
+            .flat_map(|i| (i + 1..points.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| sim_box.distance_squared(&points[i], &points[j]) < 25.0)
+            .count();
+        assert_eq!(rdf.pairs().total(), close as u64);
+        assert_eq!(rdf.pairs().overflow(), 0);
+    }
+
+    #[test]
+    fn a_grid_has_no_pairs_closer_than_its_spacing() {
+        let sim_box = SimulationBox::cube(10.0, true).unwrap();
+        let points = (0..100)
+            .map(|s| Point::from_coords([(s % 10) as f64, (s / 10) as f64]))
+            .collect::<Vec<Point<2>>>();
+        let rdf = RadialDistribution::compute(&sim_box, &points, 5.0, 10).unwrap();
+        assert_eq!(rdf.r()[0], 0.25);
+        assert_eq!(&rdf.g()[..2], &[0.0, 0.0]);
+        // Each point has 4 neighbors at 1 and 4 at √2, counting those across the faces, and
+        // each pair is counted once
+        assert_eq!(&rdf.pairs().counts()[2..4], &[400, 0]);
+    }
+
+    #[test]
+    fn r_max_must_have_a_single_image() {
+        let sim_box = SimulationBox::new([10.0, 4.0], [false, true]).unwrap();
+        let points = [[1.0, 1.0], [2.0, 2.0]].map(Point::from_coords);
+        assert!(RadialDistribution::compute(&sim_box, &points, 2.0, 4).is_ok());
+        assert!(RadialDistribution::compute(&sim_box, &points, 2.5, 4).is_err());
+        assert!(RadialDistribution::compute(&sim_box, &points[..1], 1.0, 4).is_err());
+    }
+}
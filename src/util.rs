@@ -1,6 +1,6 @@
 //! Various utility functions
 
-use crate::data::point::Point2d;
+use std::error::Error;
 use std::f64::consts::PI;
 
 /// Constant string to begin a latex document in standalone mode
@@ -15,6 +15,13 @@ pub const TEX_PREFIX: &str = "\\documentclass{standalone}
 /// Constant string to end a latex document
 pub const TEX_SUFFIX: &str = "\\end{document}";
 
+/// Write the seed used to generate the output at `path` to a file next to it, named `path.seed`,
+/// so that the output can be regenerated bit-for-bit.
+pub fn write_seed(path: &str, seed: u64) -> Result<(), Box<dyn Error>> {
+    std::fs::write(format!("{}.seed", path), format!("{}\n", seed))?;
+    Ok(())
+}
+
 /// Calculate the gamma for half of the supplied positive whole number.
 /// If n is 1, i.e., gamma(1/2), return PI.sqrt().
 /// If n is 2, i.e., gamma(2/2), return 1.  Otherwise,
@@ -50,36 +57,167 @@ pub fn gamma(n: usize) -> f64 {
     }
 }
 
-/// Calculate a circle passing through points a, b, and c.
-/// Return the center of the circle and the radius.
-#[allow(clippy::many_single_char_names)]
-pub fn circle_through(p1: Point2d, p2: Point2d, p3: Point2d) -> (Point2d, f64) {
-    let (x1, y1) = p1.into();
-    let (x2, y2) = p2.into();
-    let (x3, y3) = p3.into();
-
-    let x12 = x1.powf(2.0);
-    let y12 = y1.powf(2.0);
-    let x22 = x2.powf(2.0);
-    let y22 = y2.powf(2.0);
-    let x32 = x3.powf(2.0);
-    let y32 = y3.powf(2.0);
-
-    let a = x1 * (y2 - y3) - y1 * (x2 - x3) + x2 * y3 - x3 * y2;
-    let b = (x12 + y12) * (y3 - y2) + (x22 + y22) * (y1 - y3) + (x32 + y32) * (y2 - y1);
-    let c = (x12 + y12) * (x2 - x3) + (x22 + y22) * (x3 - x1) + (x32 + y32) * (x1 - x2);
-    let d = (x12 + y12) * (x3 * y2)
-        + (x22 + y22) * (x1 * y3 - x3 * y1)
-        + (x32 + y32) * (x2 * y1 - x1 * y2);
-
-    let zx = -b / (2.0 * a);
-    let zy = -c / (2.0 * a);
-    let r = ((zx - x1).powf(2.0) + (zy - y1).powf(2.0)).sqrt();
-
-    ((zx, zy).into(), r)
+/// Calculate the volume of a ball of radius `r` in `dim` dimensions,
+/// e.g., the area of a circle in 2 dimensions
+pub fn ball_volume(dim: usize, r: f64) -> f64 {
+    PI.powf(dim as f64 / 2.0) * r.powi(dim as i32) / gamma_half(dim + 2)
+}
+
+/// Calculate the binomial coefficient, n choose k
+pub fn binomial(n: usize, k: usize) -> f64 {
+    if k > n {
+        0.0
+    } else {
+        (0..k.min(n - k)).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+    }
+}
+
+/// Calculate the complementary error function, erfc(x) = 1 - erf(x).
+/// Uses the Chebyshev approximation from Numerical Recipes, with fractional error below 1.2e-7.
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let ans = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0.0 {
+        ans
+    } else {
+        2.0 - ans
+    }
+}
+
+/// Calculate the error function
+pub fn erf(x: f64) -> f64 {
+    1.0 - erfc(x)
+}
+
+/// The cumulative distribution function of the normal distribution with mean `mu`
+/// and standard deviation `sigma`
+pub fn normal_cdf(x: f64, mu: f64, sigma: f64) -> f64 {
+    0.5 * erfc(-(x - mu) / (sigma * std::f64::consts::SQRT_2))
 }
 
-/// Determine whether the supplied point is within the circle centered at center and with radius r
-pub fn point_in_circle(point: Point2d, center: Point2d, r: f64) -> bool {
-    point.distance_to(&center) < r
+/// The probability density function of the normal distribution with mean `mu`
+/// and standard deviation `sigma`
+pub fn normal_pdf(x: f64, mu: f64, sigma: f64) -> f64 {
+    (-(x - mu).powi(2) / (2.0 * sigma.powi(2))).exp() / (2.0 * PI * sigma.powi(2)).sqrt()
+}
+
+/// Calculate the natural logarithm of the gamma function for positive, real `x`.
+/// Uses the Lanczos approximation from Numerical Recipes.
+pub fn ln_gamma(x: f64) -> f64 {
+    const COF: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let ser = COF
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |ser, (j, c)| {
+            ser + c / (x + 1.0 + j as f64)
+        });
+    -tmp + (2.506_628_274_631_000_5 * ser / x).ln()
+}
+
+/// Calculate the regularized upper incomplete gamma function, Q(a, x) = 1 - P(a, x).
+/// Uses the series expansion for x < a + 1 and the continued fraction otherwise.
+pub fn gamma_q(a: f64, x: f64) -> f64 {
+    const MAX_ITER: usize = 1_000;
+    const EPS: f64 = 1e-14;
+    const FP_MIN: f64 = 1e-300;
+
+    if x <= 0.0 {
+        1.0
+    } else if x < a + 1.0 {
+        // Series representation of P(a, x)
+        let mut ap = a;
+        let mut del = 1.0 / a;
+        let mut sum = del;
+        for _ in 0..MAX_ITER {
+            ap += 1.0;
+            del *= x / ap;
+            sum += del;
+            if del.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+        1.0 - sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+    } else {
+        // Continued fraction representation of Q(a, x), by the modified Lentz method
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / FP_MIN;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..MAX_ITER {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < FP_MIN {
+                d = FP_MIN;
+            }
+            c = b + an / c;
+            if c.abs() < FP_MIN {
+                c = FP_MIN;
+            }
+            d = 1.0 / d;
+            let del = d * c;
+            h *= del;
+            if (del - 1.0).abs() < EPS {
+                break;
+            }
+        }
+        (-x + a * x.ln() - ln_gamma(a)).exp() * h
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erfc_matches_table() {
+        for &(x, y) in &[
+            (0.0, 1.0),
+            (0.5, 0.479_500_122_186_953_5),
+            (1.0, 0.157_299_207_050_285_1),
+            (2.0, 0.004_677_734_981_047_265),
+            (-1.0, 1.842_700_792_949_715),
+        ] {
+            assert!((erfc(x) - y).abs() <= 1.2e-7 * y, "erfc({})", x);
+        }
+    }
+
+    #[test]
+    fn gamma_q_matches_chi_square_table() {
+        // Q(k/2, x/2) is the probability of a chi-square statistic with k degrees of freedom
+        // exceeding x
+        for &(k, x, p) in &[
+            (1.0, 3.841_459, 0.05),
+            (1.0, 6.634_897, 0.01),
+            (10.0, 18.307_04, 0.05),
+            (2.0, 4.0, (-2.0_f64).exp()),
+        ] {
+            assert!(
+                (gamma_q(k / 2.0, x / 2.0) - p).abs() < 1e-6,
+                "Q({}, {})",
+                k,
+                x
+            );
+        }
+    }
 }